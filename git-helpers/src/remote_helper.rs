@@ -16,8 +16,9 @@ use librad::{
         url::LocalUrl,
     },
     keys::{PublicKey, SecretKey},
+    peer::PeerId,
     profile::Profile,
-    signer::{BoxedSigner, SomeSigner},
+    signer::{external::External, BoxedSigner, SomeSigner},
 };
 use radicle_keystore::{
     crypto::{self, Pwhash},
@@ -28,6 +29,18 @@ use radicle_keystore::{
 // FIXME: this should be defined elsewhere to be consistent between applications
 const SECRET_KEY_FILE: &str = "librad.key";
 
+/// Git config key selecting how to sign: `keystore` (the default),
+/// `ssh-agent`, or `external`.
+const CONFIG_SIGNER: &str = "rad.signer";
+/// Git config key holding the [`PeerId`] to sign as, required if the signer
+/// is not `keystore`.
+const CONFIG_SIGNING_KEY: &str = "rad.signingKey";
+/// Git config key holding the command line of the `external` signer.
+///
+/// Arguments containing whitespace can be quoted like in a shell, see
+/// [`split_command`].
+const CONFIG_SIGNER_COMMAND: &str = "rad.signerCommand";
+
 pub fn run() -> anyhow::Result<()> {
     let url = {
         let args = env::args().skip(1).take(2).collect::<Vec<_>>();
@@ -80,6 +93,99 @@ pub fn run() -> anyhow::Result<()> {
 }
 
 fn get_signer(git_dir: &Path, keys_dir: &Path, url: &LocalUrl) -> anyhow::Result<BoxedSigner> {
+    let config = git2::Repository::open(git_dir)?.config()?;
+    match config.get_string(CONFIG_SIGNER).ok().as_deref() {
+        None | Some("keystore") => get_keystore_signer(git_dir, keys_dir, url),
+
+        #[cfg(unix)]
+        Some("ssh-agent") => {
+            let key = get_signing_key(&config)?;
+            let signer = librad::signer::ssh_agent::SshAgent::from_env(key)?;
+            Ok(SomeSigner { signer }.into())
+        },
+
+        Some("external") => {
+            let key = get_signing_key(&config)?;
+            let cmd = split_command(&config.get_string(CONFIG_SIGNER_COMMAND)?)?;
+            let (program, args) = cmd
+                .split_first()
+                .ok_or_else(|| anyhow::anyhow!("`{}` is empty", CONFIG_SIGNER_COMMAND))?;
+            let signer = External::new(program, args, key);
+            Ok(SomeSigner { signer }.into())
+        },
+
+        Some(other) => Err(anyhow::anyhow!(
+            "unsupported `{}`: {}",
+            CONFIG_SIGNER,
+            other
+        )),
+    }
+}
+
+/// Split the command line `cmd` into words, like a POSIX shell would.
+///
+/// Words are separated by whitespace. Single quotes preserve everything up to
+/// the closing quote, within double quotes a backslash only escapes `"`, `\`,
+/// `$` and `` ` ``, and outside of quotes a backslash escapes any character.
+/// No expansions are performed.
+fn split_command(cmd: &str) -> anyhow::Result<Vec<String>> {
+    let unterminated = || anyhow::anyhow!("unterminated quote in `{}`", CONFIG_SIGNER_COMMAND);
+
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            },
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(unterminated)? {
+                            c @ '"' | c @ '\\' | c @ '$' | c @ '`' => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c)
+                            },
+                        },
+                        c => word.push(c),
+                    }
+                }
+            },
+            '\\' => {
+                let c = chars.next().ok_or_else(|| {
+                    anyhow::anyhow!("trailing backslash in `{}`", CONFIG_SIGNER_COMMAND)
+                })?;
+                word.get_or_insert_with(String::new).push(c)
+            },
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+fn get_signing_key(config: &git2::Config) -> anyhow::Result<PublicKey> {
+    let peer_id = config.get_string(CONFIG_SIGNING_KEY)?.parse::<PeerId>()?;
+    Ok(*peer_id.as_public_key())
+}
+
+fn get_keystore_signer(
+    git_dir: &Path,
+    keys_dir: &Path,
+    url: &LocalUrl,
+) -> anyhow::Result<BoxedSigner> {
     let mut cred = credential::Git::new(git_dir);
     let pass = cred.get(url)?;
     let file = keys_dir.join(SECRET_KEY_FILE);
@@ -92,3 +198,60 @@ fn get_signer(git_dir: &Path, keys_dir: &Path, url: &LocalUrl) -> anyhow::Result
 
     Ok(SomeSigner { signer: key }.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use librad::{git::Urn, git_ext as ext, keys::Signature, signer::Signer as _};
+    use tempfile::tempdir;
+
+    #[test]
+    fn split_quoted_command() {
+        assert_eq!(
+            split_command(r#"sign  --key 'my key' "a \"b\" \c" d\ e ''"#).unwrap(),
+            vec!["sign", "--key", "my key", r#"a "b" \c"#, "d e", ""]
+        );
+        assert!(split_command("sign 'key").is_err());
+        assert!(split_command(r#"sign "key"#).is_err());
+        assert!(split_command("sign \\").is_err());
+        assert!(split_command("  ").unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn external_signer_from_config() {
+        let tmp = tempdir().unwrap();
+        let repo = git2::Repository::init(tmp.path().join("repo")).unwrap();
+        let url = LocalUrl::from(Urn::new(ext::Oid::from(git2::Oid::zero())));
+
+        let key = SecretKey::new();
+        let sig_file = tmp.path().join("the signature");
+        fs::write(&sig_file, &<[u8; 64]>::from(key.sign(b"cheyenne"))[..]).unwrap();
+
+        let mut config = repo.config().unwrap();
+        config.set_str(CONFIG_SIGNER, "external").unwrap();
+        config
+            .set_str(CONFIG_SIGNING_KEY, &PeerId::from(key.public()).to_string())
+            .unwrap();
+        config
+            .set_str(
+                CONFIG_SIGNER_COMMAND,
+                &format!(
+                    "sh -c 'cat >/dev/null && cat \"$0\"' '{}'",
+                    sig_file.display()
+                ),
+            )
+            .unwrap();
+
+        let signer = get_signer(repo.path(), tmp.path(), &url).unwrap();
+        assert_eq!(signer.peer_id(), PeerId::from(key.public()));
+        let sig = signer.sign_blocking(b"cheyenne").unwrap();
+        assert!(key.public().verify(&Signature::from(sig), b"cheyenne"));
+
+        config.set_str(CONFIG_SIGNER, "gpg").unwrap();
+        assert!(get_signer(repo.path(), tmp.path(), &url).is_err());
+    }
+}
@@ -41,3 +41,13 @@ impl<A> DerefMut for WithTmpDir<A> {
         &mut self.inner
     }
 }
+
+impl<A, B> AsRef<B> for WithTmpDir<A>
+where
+    A: AsRef<B>,
+    B: ?Sized,
+{
+    fn as_ref(&self) -> &B {
+        self.inner.as_ref()
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Offline transfer of a namespace using [git bundles][bundle].
+//!
+//! [`export`] writes the refs of a namespace to a bundle file, accompanied by a
+//! [`Manifest`] which is signed by the exporting peer. [`import`] verifies the
+//! manifest, and then replicates from the bundle as if it was the monorepo of
+//! the exporting peer, ie. applying the same verification as
+//! [`replication::replicate`].
+//!
+//! [bundle]: https://git-scm.com/docs/git-bundle
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use git_ext as ext;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    fetch,
+    identities::local::LocalIdentity,
+    replication::{self, ReplicateResult},
+    storage::{self, Storage},
+    types::RefsCategory,
+};
+use crate::{
+    internal::canonical::{Cjson, CjsonError},
+    keys::Signature,
+    peer::PeerId,
+    signer::Signer as _,
+};
+
+pub use crate::identities::git::Urn;
+
+/// The file extension of the [`Manifest`], appended to the path of the bundle.
+pub const MANIFEST_EXTENSION: &str = "manifest";
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("namespace `{0}` does not exist")]
+    NoSuchUrn(Urn),
+
+    #[error("the bundle was exported by ourselves")]
+    SelfImport,
+
+    #[error("invalid manifest signature")]
+    InvalidSignature,
+
+    #[error(
+        "ref `{name}` in bundle does not match the manifest: expected {expected:?}, got {actual:?}"
+    )]
+    Mismatch {
+        name: ext::RefLike,
+        expected: Option<ext::Oid>,
+        actual: Option<ext::Oid>,
+    },
+
+    #[error("`git bundle` exited with {0}")]
+    Bundle(ExitStatus),
+
+    #[error("signer error")]
+    Sign(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error(transparent)]
+    Replication(#[from] replication::Error),
+
+    #[error(transparent)]
+    Store(#[from] storage::Error),
+
+    #[error(transparent)]
+    Cjson(#[from] CjsonError),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Ref(#[from] ext::name::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Describes the contents of a bundle.
+///
+/// The [`Manifest::signature`] is made by [`Manifest::origin`] over the
+/// canonical JSON form of the other fields.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The namespace contained in the bundle.
+    pub urn: Urn,
+    /// The peer which exported the bundle.
+    pub origin: PeerId,
+    /// The refs contained in the bundle, and their targets.
+    pub refs: BTreeMap<ext::RefLike, ext::Oid>,
+    pub signature: Signature,
+}
+
+#[derive(Serialize)]
+struct Unsigned<'a> {
+    urn: &'a Urn,
+    origin: &'a PeerId,
+    refs: &'a BTreeMap<ext::RefLike, ext::Oid>,
+}
+
+impl Manifest {
+    /// The path of the manifest belonging to the bundle at `bundle`.
+    pub fn path_for(bundle: &Path) -> PathBuf {
+        let mut path = bundle.as_os_str().to_owned();
+        path.push(".");
+        path.push(MANIFEST_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// Check that the [`Manifest::signature`] was made by
+    /// [`Manifest::origin`].
+    pub fn verify(&self) -> Result<(), Error> {
+        if self
+            .signature
+            .verify(&self.canonical_form()?, &*self.origin)
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidSignature)
+        }
+    }
+
+    fn canonical_form(&self) -> Result<Vec<u8>, CjsonError> {
+        Cjson(Unsigned {
+            urn: &self.urn,
+            origin: &self.origin,
+            refs: &self.refs,
+        })
+        .canonical_form()
+    }
+}
+
+/// Export the namespace `urn` to a bundle at `path`.
+///
+/// The bundle contains the identity branches and `rad/signed_refs` of all
+/// peers, our own heads, tags and notes, and those of the remote `peers`. If
+/// `peers` is `None`, the heads, tags and notes of all remote peers are
+/// exported.
+///
+/// The signed [`Manifest`] is written to [`Manifest::path_for`] `path`, and
+/// also returned.
+#[tracing::instrument(skip(storage, peers), err)]
+pub fn export(
+    storage: &Storage,
+    urn: &Urn,
+    peers: Option<&BTreeSet<PeerId>>,
+    path: &Path,
+) -> Result<Manifest, Error> {
+    let urn = Urn::new(urn.id);
+    if !storage.has_urn(&urn)? {
+        return Err(Error::NoSuchUrn(urn));
+    }
+
+    let namespace = reflike!("refs/namespaces").join(&urn);
+    let remotes = namespace.join(reflike!("refs/remotes"));
+    let mut refs = BTreeMap::new();
+    for reference in storage.as_raw().references_glob(
+        namespace
+            .with_pattern_suffix(refspec_pattern!("*"))
+            .as_str(),
+    )? {
+        let reference = reference?;
+        let name = match reference.name() {
+            Some(name) => ext::RefLike::try_from(name)?,
+            None => continue,
+        };
+        if let Ok(remote) = name.strip_prefix(&remotes) {
+            let mut components = remote.as_str().splitn(3, '/');
+            let peer = components.next().and_then(|peer| peer.parse().ok());
+            let category = components.next().and_then(RefsCategory::parse);
+            let selected = match (peer, peers) {
+                (_, None) => true,
+                (Some(peer), Some(peers)) => peers.contains(&peer),
+                (None, Some(_)) => false,
+            };
+            if !selected && !matches!(category, Some(RefsCategory::Rad)) {
+                continue;
+            }
+        }
+        let target = storage.as_raw().refname_to_id(name.as_str())?;
+        refs.insert(name, target.into());
+    }
+
+    let origin = *storage.peer_id();
+    let canonical = Cjson(Unsigned {
+        urn: &urn,
+        origin: &origin,
+        refs: &refs,
+    })
+    .canonical_form()?;
+    let signature = futures::executor::block_on(storage.signer().sign(&canonical))
+        .map_err(|err| Error::Sign(Box::new(err)))?
+        .into();
+    let manifest = Manifest {
+        urn,
+        origin,
+        refs,
+        signature,
+    };
+
+    bundle_create(storage, &manifest.refs, path)?;
+    fs::write(Manifest::path_for(path), serde_json::to_vec(&manifest)?)?;
+
+    Ok(manifest)
+}
+
+/// Import the bundle at `path`, which was created by [`export`].
+///
+/// The [`Manifest`] is expected at [`Manifest::path_for`] `path`. After
+/// verifying the manifest, and that the bundle contains exactly the refs it
+/// describes, the namespace is replicated from the bundle like
+/// [`replication::replicate`] would from the [`Manifest::origin`].
+#[tracing::instrument(skip(storage, config, whoami), err)]
+pub fn import(
+    storage: &Storage,
+    config: replication::Config,
+    whoami: Option<LocalIdentity>,
+    path: &Path,
+) -> Result<ReplicateResult, Error> {
+    let manifest: Manifest = serde_json::from_slice(&fs::read(Manifest::path_for(path))?)?;
+    manifest.verify()?;
+    if &manifest.origin == storage.peer_id() {
+        return Err(Error::SelfImport);
+    }
+
+    let scratch = tempfile::tempdir()?;
+    let repo = git2::Repository::init_bare(scratch.path())?;
+    repo.odb()?
+        .add_disk_alternate(&storage.path().join("objects").to_string_lossy())?;
+    bundle_unbundle(scratch.path(), path)?;
+    check_refs(&repo, &manifest)?;
+
+    let mut fetcher = fetch::DefaultFetcher::from_path(
+        storage,
+        manifest.urn.clone(),
+        manifest.origin,
+        scratch.path(),
+    )
+    .map_err(storage::Error::from)?;
+    Ok(replication::replicate_with(
+        storage,
+        &mut fetcher,
+        config,
+        whoami,
+        manifest.urn,
+        manifest.origin,
+    )?)
+}
+
+/// Check that `repo` contains exactly the refs listed in the `manifest`.
+fn check_refs(repo: &git2::Repository, manifest: &Manifest) -> Result<(), Error> {
+    let mut actual = BTreeMap::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+            actual.insert(ext::RefLike::try_from(name)?, ext::Oid::from(target));
+        }
+    }
+
+    let names = manifest
+        .refs
+        .keys()
+        .chain(actual.keys())
+        .collect::<BTreeSet<_>>();
+    for name in names {
+        let expected = manifest.refs.get(name).copied();
+        let actual = actual.get(name).copied();
+        if expected != actual {
+            return Err(Error::Mismatch {
+                name: name.clone(),
+                expected,
+                actual,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn bundle_create(
+    storage: &Storage,
+    refs: &BTreeMap<ext::RefLike, ext::Oid>,
+    path: &Path,
+) -> Result<(), Error> {
+    let mut child = git(storage.path())
+        .args(&["bundle", "create", "--quiet"])
+        .arg(path)
+        .arg("--stdin")
+        .stdin(Stdio::piped())
+        .spawn()?;
+    {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        for name in refs.keys() {
+            writeln!(stdin, "{}", name)?;
+        }
+    }
+    let status = child.wait()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Bundle(status))
+    }
+}
+
+fn bundle_unbundle(repo: &Path, bundle: &Path) -> Result<(), Error> {
+    let status = git(repo)
+        .args(&["bundle", "verify", "--quiet"])
+        .arg(bundle)
+        .status()?;
+    if !status.success() {
+        return Err(Error::Bundle(status));
+    }
+
+    let status = git(repo)
+        .args(&["fetch", "--quiet"])
+        .arg(bundle)
+        .arg("refs/*:refs/*")
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Bundle(status))
+    }
+}
+
+fn git(dir: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.envs(::std::env::vars().filter(|(key, _)| key.starts_with("GIT_TRACE")))
+        .current_dir(dir);
+    cmd
+}
@@ -11,6 +11,7 @@ use std::{
     convert::TryFrom,
     net::SocketAddr,
     ops::Deref,
+    path::Path,
 };
 
 use git_ext as ext;
@@ -18,8 +19,9 @@ use multihash::Multihash;
 
 use super::{
     p2p::url::GitUrl,
-    refs::Refs,
+    refs::{self, Refs},
     storage::{self, Storage},
+    tracking,
     types::{
         reference::{Reference, RefsCategory},
         AsRemote,
@@ -52,8 +54,8 @@ pub const FIVE_GB: usize = ONE_KB * ONE_KB * ONE_KB * 5;
 /// The default values are [`FIVE_KB`], [`FIVE_GB`], respectively.
 #[derive(Clone, Copy, Debug)]
 pub struct Limit {
-    /// Limit the amount of data we fetch using [`Fetchspecs::PeekAll`] and
-    /// [`Fetchspecs::Peek`].
+    /// Limit the amount of data we fetch using [`Fetchspecs::PeekAll`],
+    /// [`Fetchspecs::Peek`] and [`Fetchspecs::Delegates`].
     pub peek: usize,
     /// Limit the amount of data we fetch using [`Fetchspecs::Replicate`].
     pub data: usize,
@@ -79,12 +81,46 @@ pub enum Fetchspecs<P, R> {
     /// Only request the branches necessary for identity verification.
     Peek { remotes: BTreeSet<P>, limit: Limit },
 
+    /// Only request the identity branches of the given delegate namespaces,
+    /// as advertised by the remote peer.
+    ///
+    /// This is used to obtain the members of an org, which are not included
+    /// in the namespace of a project delegating to the org.
+    Delegates {
+        delegates: BTreeSet<Urn<R>>,
+        limit: Limit,
+    },
+
     /// Request the remote heads matching the signed refs of the respective
     /// tracked peers, as well as top-level delegates found in the identity
     /// document.
+    ///
+    /// Only the signed refs allowed by the tracking [`tracking::Policy`] of the
+    /// respective peer are requested. Peers without an entry in `policies`
+    /// are subject to the default policy.
+    ///
+    /// For tracked peers whose policy follows remotes, the `rad/signed_refs`
+    /// of the peers they track are requested, up to `depth` levels of their
+    /// tracking graph.
     Replicate {
         tracked_sigrefs: BTreeMap<P, Refs>,
+        policies: BTreeMap<P, tracking::Policy>,
         delegates: BTreeSet<Urn<R>>,
+        depth: usize,
+        limit: Limit,
+    },
+
+    /// Only request the `rad/signed_refs` of the given peers.
+    SignedRefs { remotes: BTreeSet<P>, limit: Limit },
+
+    /// Only request the remote heads matching the signed refs of the
+    /// respective peers, as far as their tracking [`tracking::Policy`]
+    /// allows.
+    ///
+    /// Unlike [`Self::Replicate`], no `rad` refs are requested.
+    SignedHeads {
+        tracked_sigrefs: BTreeMap<P, Refs>,
+        policies: BTreeMap<P, tracking::Policy>,
         limit: Limit,
     },
 }
@@ -93,6 +129,7 @@ impl<P, R> Fetchspecs<P, R>
 where
     P: Clone + Ord + PartialEq + 'static,
     for<'a> &'a P: AsRemote + Into<ext::RefLike>,
+    PeerId: Into<P>,
 
     R: HasProtocol + Clone + 'static,
     for<'a> &'a R: Into<Multihash>,
@@ -112,11 +149,34 @@ where
                 all
             },
             Self::Peek { remotes, .. } => refspecs::peek(urn, &remote_peer, remotes),
+            Self::Delegates { delegates, .. } => {
+                let remote = Some(remote_peer.clone()).into_iter().collect();
+                delegates
+                    .iter()
+                    .flat_map(|delegate_urn| refspecs::peek(delegate_urn, &remote_peer, &remote))
+                    .collect()
+            },
             Self::Replicate {
                 tracked_sigrefs,
+                policies,
                 delegates,
+                depth,
                 ..
-            } => refspecs::replicate(urn, &remote_peer, remote_heads, tracked_sigrefs, delegates),
+            } => refspecs::replicate(
+                urn,
+                &remote_peer,
+                remote_heads,
+                tracked_sigrefs,
+                policies,
+                delegates,
+                *depth,
+            ),
+            Self::SignedRefs { remotes, .. } => refspecs::signed_refs(urn, &remote_peer, remotes),
+            Self::SignedHeads {
+                tracked_sigrefs,
+                policies,
+                ..
+            } => refspecs::signed_heads(urn, &remote_peer, remote_heads, tracked_sigrefs, policies),
         }
     }
 
@@ -124,7 +184,10 @@ where
         match self {
             Fetchspecs::PeekAll { limit } => limit.peek,
             Fetchspecs::Peek { limit, .. } => limit.peek,
+            Fetchspecs::Delegates { limit, .. } => limit.peek,
             Fetchspecs::Replicate { limit, .. } => limit.data,
+            Fetchspecs::SignedRefs { limit, .. } => limit.peek,
+            Fetchspecs::SignedHeads { limit, .. } => limit.data,
         }
     }
 }
@@ -132,6 +195,10 @@ where
 pub mod refspecs {
     use super::*;
 
+    lazy_static! {
+        static ref DEFAULT_POLICY: tracking::Policy = tracking::Policy::default();
+    }
+
     pub fn all<P, R>(urn: &Urn<R>) -> Vec<Fetchspec>
     where
         P: Clone + 'static,
@@ -260,28 +327,41 @@ pub mod refspecs {
         remote_peer: &P,
         remote_heads: &RemoteHeads,
         tracked_sigrefs: &BTreeMap<P, Refs>,
+        policies: &BTreeMap<P, tracking::Policy>,
         delegates: &BTreeSet<Urn<R>>,
+        depth: usize,
     ) -> Vec<Fetchspec>
     where
         P: Clone + Ord + PartialEq + 'static,
         for<'a> &'a P: AsRemote + Into<ext::RefLike>,
+        PeerId: Into<P>,
 
         R: HasProtocol + Clone + 'static,
         for<'a> &'a R: Into<Multihash>,
     {
-        let namespace = Namespace::from(urn);
-        let mut signed = tracked_sigrefs
+        let mut signed = signed_heads(urn, remote_peer, remote_heads, tracked_sigrefs, policies);
+
+        // Get the signed_refs of the peers tracked by the tracked peers, so
+        // they can be verified before tracking them transitively
+        let transitive = tracked_sigrefs
             .iter()
-            .flat_map(|(tracked_peer, refs)| {
-                sigrefs(
-                    namespace.clone(),
-                    remote_peer,
-                    remote_heads,
-                    tracked_peer,
-                    refs,
-                )
+            .filter(|(tracked_peer, _)| {
+                policies
+                    .get(tracked_peer)
+                    .unwrap_or(&DEFAULT_POLICY)
+                    .follow_remotes
             })
-            .collect::<Vec<_>>();
+            .flat_map(|(_, refs)| {
+                refs.remotes
+                    .clone()
+                    .cutoff(depth)
+                    .flatten()
+                    .map(|peer| (*peer).into())
+                    .collect::<Vec<P>>()
+            })
+            .filter(|peer| !tracked_sigrefs.contains_key(peer) && peer != remote_peer)
+            .collect::<BTreeSet<_>>();
+        let mut transitive = signed_refs(urn, remote_peer, &transitive);
 
         // Peek at the remote peer
         let mut peek_remote = peek(
@@ -313,11 +393,42 @@ pub mod refspecs {
             .flatten()
             .collect::<Vec<_>>();
 
+        signed.append(&mut transitive);
         signed.append(&mut peek_remote);
         signed.append(&mut delegates);
         signed
     }
 
+    pub fn signed_heads<P, R>(
+        urn: &Urn<R>,
+        remote_peer: &P,
+        remote_heads: &RemoteHeads,
+        tracked_sigrefs: &BTreeMap<P, Refs>,
+        policies: &BTreeMap<P, tracking::Policy>,
+    ) -> Vec<Fetchspec>
+    where
+        P: Clone + Ord + PartialEq + 'static,
+        for<'a> &'a P: AsRemote + Into<ext::RefLike>,
+
+        R: HasProtocol + Clone + 'static,
+        for<'a> &'a R: Into<Multihash>,
+    {
+        let namespace = Namespace::from(urn);
+        tracked_sigrefs
+            .iter()
+            .flat_map(|(tracked_peer, refs)| {
+                sigrefs(
+                    namespace.clone(),
+                    remote_peer,
+                    remote_heads,
+                    tracked_peer,
+                    refs,
+                    policies.get(tracked_peer).unwrap_or(&DEFAULT_POLICY),
+                )
+            })
+            .collect()
+    }
+
     fn remote_glob<R>(
         r: Reference<Namespace<R>, ext::RefspecPattern, ext::RefLike>,
     ) -> ext::RefspecPattern
@@ -347,6 +458,7 @@ pub mod refspecs {
         remote_heads: &'a RemoteHeads,
         tracked_peer: &'a P,
         refs: &'a Refs,
+        policy: &'a tracking::Policy,
     ) -> impl Iterator<Item = Fetchspec> + 'a
     where
         P: Clone + PartialEq,
@@ -356,16 +468,18 @@ pub mod refspecs {
         for<'b> &'b R: Into<Multihash>,
     {
         refs.iter_categorised()
+            .filter(move |((name, _), category)| policy.allows(*category, name))
             .map({
                 let namespace = namespace.clone();
                 move |(x, category)| {
                     (
                         x,
+                        category,
                         namespaced(&namespace, remote_peer, tracked_peer, x.0.clone(), category),
                     )
                 }
             })
-            .filter_map(move |((name, target), namespaced_name)| {
+            .filter_map(move |((name, target), category, namespaced_name)| {
                 // Only include the advertised ref if its target OID
                 // is the same as the signed one.
                 let targets_match = {
@@ -393,11 +507,12 @@ pub mod refspecs {
                 };
 
                 targets_match.then_some({
-                    let dst = Reference::head(
-                        namespace.clone(),
-                        tracked_peer.clone(),
-                        name.clone().into(),
-                    );
+                    let dst = Reference {
+                        remote: Some(tracked_peer.clone()),
+                        category,
+                        name: name.clone().into(),
+                        namespace: Some(namespace.clone()),
+                    };
                     let src = if tracked_peer == remote_peer {
                         dst.clone().with_remote(None)
                     } else {
@@ -503,7 +618,11 @@ impl CanFetch for Storage {
 }
 
 pub struct FetchResult {
+    /// The new targets of the refs updated by the fetch.
     pub updated_tips: BTreeMap<ext::RefLike, ext::Oid>,
+    /// The targets of the refs in [`Self::updated_tips`] before the fetch, if
+    /// they existed.
+    pub previous_tips: BTreeMap<ext::RefLike, ext::Oid>,
 }
 
 /// Types which can process [`Fetchspecs`], and update the local [`Storage`]
@@ -538,15 +657,63 @@ impl<'a> DefaultFetcher<'a> {
     where
         Addrs: IntoIterator<Item = SocketAddr>,
     {
-        let mut remote = storage.as_raw().remote_anonymous(
+        Self::with_repo(
+            storage.as_raw(),
+            PeerId::from_signer(storage.signer()),
+            urn,
+            remote_peer,
+            addr_hints,
+        )
+    }
+
+    /// Create a [`DefaultFetcher`] which fetches into `repo` instead of the
+    /// [`Storage`] of `local_peer`.
+    ///
+    /// This allows to fetch into a scratch repository, eg. to inspect what the
+    /// `remote_peer` has to offer before committing to it.
+    pub(super) fn with_repo<Addrs>(
+        repo: &'a git2::Repository,
+        local_peer: PeerId,
+        urn: git::Urn,
+        remote_peer: PeerId,
+        addr_hints: Addrs,
+    ) -> Result<Self, git2::Error>
+    where
+        Addrs: IntoIterator<Item = SocketAddr>,
+    {
+        let remote = repo.remote_anonymous(
             &GitUrl {
-                local_peer: PeerId::from_signer(storage.signer()),
+                local_peer,
                 remote_peer,
                 repo: urn.id,
                 addr_hints: addr_hints.into_iter().collect(),
             }
             .to_string(),
         )?;
+        Self::connect(remote, urn, remote_peer)
+    }
+
+    /// Create a [`DefaultFetcher`] which fetches from the repository at `path`
+    /// instead of the network.
+    ///
+    /// The repository is expected to have the same layout as the monorepo of
+    /// `remote_peer`, eg. because it was created from a bundle exported by
+    /// `remote_peer`.
+    pub(super) fn from_path(
+        storage: &'a Storage,
+        urn: git::Urn,
+        remote_peer: PeerId,
+        path: &Path,
+    ) -> Result<Self, git2::Error> {
+        let remote = storage.as_raw().remote_anonymous(&path.to_string_lossy())?;
+        Self::connect(remote, urn, remote_peer)
+    }
+
+    fn connect(
+        mut remote: git2::Remote<'a>,
+        urn: git::Urn,
+        remote_peer: PeerId,
+    ) -> Result<Self, git2::Error> {
         remote.connect(git2::Direction::Fetch)?;
         let remote_heads = remote
             .list()?
@@ -572,12 +739,18 @@ impl<'a> DefaultFetcher<'a> {
         })
     }
 
+    /// The refs advertised by the remote peer.
+    pub fn remote_heads(&self) -> &RemoteHeads {
+        &self.remote_heads
+    }
+
     #[tracing::instrument(skip(self), err)]
     pub fn fetch(
         &mut self,
         fetchspecs: Fetchspecs<PeerId, git::Revision>,
     ) -> Result<FetchResult, git2::Error> {
         let mut updated_tips = BTreeMap::new();
+        let mut previous_tips = BTreeMap::new();
         {
             let limit = fetchspecs.fetch_limit();
             let refspecs = fetchspecs
@@ -609,6 +782,9 @@ impl<'a> DefaultFetcher<'a> {
                 tracing::debug!("Fetch: updating tip {}: {} -> {}", name, old, new);
                 match ext::RefLike::try_from(name) {
                     Ok(refname) => {
+                        if !old.is_zero() {
+                            previous_tips.insert(refname.clone(), old.into());
+                        }
                         updated_tips.insert(refname, new.into());
                     },
                     Err(e) => tracing::warn!("invalid refname `{}`: {}", name, e),
@@ -630,7 +806,10 @@ impl<'a> DefaultFetcher<'a> {
             )?;
         }
 
-        Ok(FetchResult { updated_tips })
+        Ok(FetchResult {
+            updated_tips,
+            previous_tips,
+        })
     }
 }
 
@@ -828,7 +1007,9 @@ mod tests {
 
         let specs = Fetchspecs::Replicate {
             tracked_sigrefs,
+            policies: Default::default(),
             delegates,
+            depth: refs::DEFAULT_TRACKING_GRAPH_DEPTH,
             limit: Default::default(),
         }
         .refspecs(&*PROJECT_URN, TOLA.clone(), &remote_heads);
@@ -955,4 +1136,75 @@ mod tests {
             .collect::<BTreeSet<String>>()
         )
     }
+
+    #[test]
+    fn replicate_signed_refs_up_to_depth() {
+        use crate::{
+            git::refs::{Refs, Remotes},
+            keys::SecretKey,
+        };
+
+        let carol = PeerId::from(SecretKey::new());
+        let dave = PeerId::from(SecretKey::new());
+
+        // Lolek tracks carol, who tracks dave
+        let mut remotes = Some(carol).into_iter().collect::<Remotes<PeerId>>();
+        remotes.insert(carol, Box::new(Some(dave).into_iter().collect()));
+        let tracked_sigrefs = Some((
+            LOLEK.clone(),
+            Refs {
+                heads: Default::default(),
+                rad: Default::default(),
+                tags: Default::default(),
+                notes: Default::default(),
+                remotes,
+            },
+        ))
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+
+        let signed_refs_of = |depth| {
+            Fetchspecs::Replicate {
+                tracked_sigrefs: tracked_sigrefs.clone(),
+                policies: Default::default(),
+                delegates: BTreeSet::new(),
+                depth,
+                limit: Default::default(),
+            }
+            .refspecs(&*PROJECT_URN, TOLA.clone(), &Default::default())
+            .into_iter()
+            .map(|spec| spec.to_string())
+            .filter(|spec| spec.ends_with("rad/signed_refs"))
+            .collect::<BTreeSet<String>>()
+        };
+        let signed_refs = |peer: &PeerId| {
+            let name = PROJECT_NAMESPACE
+                .join(reflike!("refs/remotes"))
+                .join(peer)
+                .join(reflike!("rad/signed_refs"));
+            format!("{}:{}", name, name)
+        };
+        let tola = format!(
+            "{}:{}",
+            PROJECT_NAMESPACE.join(reflike!("refs/rad/signed_refs")),
+            PROJECT_NAMESPACE.join(reflike!("refs/remotes/tola/rad/signed_refs"))
+        );
+
+        assert_eq!(
+            signed_refs_of(0),
+            Some(tola.clone()).into_iter().collect::<BTreeSet<_>>()
+        );
+        assert_eq!(
+            signed_refs_of(1),
+            vec![tola.clone(), signed_refs(&carol)]
+                .into_iter()
+                .collect::<BTreeSet<_>>()
+        );
+        assert_eq!(
+            signed_refs_of(2),
+            vec![tola, signed_refs(&carol), signed_refs(&dave)]
+                .into_iter()
+                .collect::<BTreeSet<_>>()
+        );
+    }
 }
@@ -5,9 +5,13 @@
 
 pub mod any;
 pub mod error;
+pub mod fork;
 pub mod local;
+pub mod org;
 pub mod person;
 pub mod project;
+pub mod proposal;
+pub mod query;
 pub mod relations;
 
 pub(super) mod common;
@@ -9,15 +9,12 @@ use git_ext::is_not_found_err;
 
 use super::{
     super::{
-        storage::{self, glob, Storage},
+        storage::{self, glob, read::ReadIdentities, ReadOnly},
         types::Reference,
     },
     error::Error,
 };
-use crate::identities::{
-    self,
-    git::{Identities, SomeIdentity},
-};
+use crate::identities::{self, git::SomeIdentity};
 
 pub use identities::git::Urn;
 
@@ -27,7 +24,11 @@ pub use identities::git::Urn;
 /// tip of the branch it resolves to. If that branch is not found, `None` is
 /// returned.
 #[tracing::instrument(level = "debug", skip(storage), err)]
-pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<SomeIdentity>, Error> {
+pub fn get<S>(storage: &S, urn: &Urn) -> Result<Option<SomeIdentity>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
     let branch = Reference::try_from(urn)?;
     tracing::trace!(
         "trying to resolve unknown identity at {} from {}",
@@ -37,7 +38,7 @@ pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<SomeIdentity>, Error>
     match storage.reference(&branch) {
         Ok(Some(reference)) => {
             let tip = reference.peel_to_commit()?.id();
-            Ok(Some(identities(&storage).some_identity(tip)?))
+            Ok(Some(identities(storage).some_identity(tip)?))
         },
 
         Ok(None) => Ok(None),
@@ -48,9 +49,13 @@ pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<SomeIdentity>, Error>
 
 /// List all identities found in `storage`.
 #[tracing::instrument(level = "debug", skip(storage), err)]
-pub fn list<'a>(
-    storage: &'a Storage,
-) -> Result<impl Iterator<Item = Result<SomeIdentity, Error>> + 'a, Error> {
+pub fn list<'a, S>(
+    storage: &'a S,
+) -> Result<impl Iterator<Item = Result<SomeIdentity, Error>> + 'a, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
     lazy_static! {
         static ref GLOB: glob::RefspecMatcher =
             refspec_pattern!("refs/namespaces/*/refs/rad/id").into();
@@ -69,6 +74,6 @@ pub fn list<'a>(
     Ok(iter)
 }
 
-fn identities(storage: &Storage) -> Identities<!> {
+fn identities(storage: &ReadOnly) -> ReadIdentities<!> {
     storage.identities()
 }
@@ -6,9 +6,12 @@
 use git_ext::is_exists_err;
 use std_ext::result::ResultExt as _;
 
-use super::super::{
-    storage::Storage,
-    types::{Force, Namespace, Reference},
+use super::{
+    super::{
+        storage::{transaction, Storage, Transaction},
+        types::{Force, Namespace, Reference},
+    },
+    query,
 };
 use crate::identities::git::Urn;
 
@@ -35,7 +38,9 @@ impl<'a> IdRef<'a> {
                 &format!("Initial rad/id for {}", self.0),
             )
             .and(Ok(()))
-            .or_matches(is_exists_err, || Ok(()))
+            .or_matches(is_exists_err, || Ok(()))?;
+        update_index(storage, self.0);
+        Ok(())
     }
 
     pub fn update(
@@ -44,8 +49,54 @@ impl<'a> IdRef<'a> {
         target: impl AsRef<git2::Oid>,
         msg: &str,
     ) -> Result<(), git2::Error> {
-        Reference::rad_id(Namespace::from(self.0))
-            .create(storage.as_raw(), *target.as_ref(), Force::True, msg)
-            .and(Ok(()))
+        Reference::rad_id(Namespace::from(self.0)).create(
+            storage.as_raw(),
+            *target.as_ref(),
+            Force::True,
+            msg,
+        )?;
+        update_index(storage, self.0);
+        Ok(())
+    }
+
+    /// Like [`IdRef::create`], but as part of the given [`Transaction`].
+    pub fn create_in(
+        &self,
+        tx: &mut Transaction,
+        target: impl AsRef<git2::Oid>,
+    ) -> Result<(), transaction::Error> {
+        tx.set_target(
+            &Reference::rad_id(Namespace::from(self.0)),
+            *target.as_ref(),
+            Force::False,
+            &format!("Initial rad/id for {}", self.0),
+        )
+        .or_matches(|e| matches!(e, transaction::Error::Exists(_)), || Ok(()))
+    }
+
+    /// Like [`IdRef::update`], but as part of the given [`Transaction`].
+    pub fn update_in(
+        &self,
+        tx: &mut Transaction,
+        target: impl AsRef<git2::Oid>,
+        msg: &str,
+    ) -> Result<(), transaction::Error> {
+        tx.set_target(
+            &Reference::rad_id(Namespace::from(self.0)),
+            *target.as_ref(),
+            Force::True,
+            msg,
+        )
+    }
+}
+
+/// Keep the namespace [`query::Index`] up-to-date after `rad/id` of `urn` was
+/// changed, if there is an index.
+///
+/// Failing to do so is not fatal, as the index is eventually brought up-to-date
+/// by [`query::Index::refresh`].
+pub fn update_index(storage: &Storage, urn: &Urn) {
+    if let Err(e) = query::update_index(storage, urn) {
+        tracing::warn!(err = %e, "failed to update namespace index");
     }
 }
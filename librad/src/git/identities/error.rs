@@ -3,6 +3,8 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+use std::io;
+
 use thiserror::Error;
 
 use super::{
@@ -11,7 +13,7 @@ use super::{
 };
 use crate::identities::{
     self,
-    git::{Urn, VerificationError},
+    git::{Revision, Urn, VerificationError},
 };
 
 #[derive(Debug, Error)]
@@ -20,6 +22,18 @@ pub enum Error {
     #[error("the URN {0} does not exist")]
     NotFound(Urn),
 
+    #[error("the proposal does not change the current revision")]
+    EmptyProposal,
+
+    #[error("no proposal for revision {0}")]
+    NoProposal(Revision),
+
+    #[error("the proposal of revision {0} does not replace the current revision")]
+    StaleProposal(Revision),
+
+    #[error("revision {0} was proposed, and needs to be signed by a quorum of delegates")]
+    Proposed(Revision),
+
     #[error("malformed URN")]
     Ref(#[from] reference::FromUrnError),
 
@@ -50,6 +64,12 @@ pub enum Error {
     #[error(transparent)]
     Store(#[from] identities::git::error::Store),
 
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
     #[error(transparent)]
     Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
 }
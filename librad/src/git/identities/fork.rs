@@ -0,0 +1,123 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Resolving forks between identity histories.
+//!
+//! Replication refuses to proceed if the `rad/id` of a delegate has diverged
+//! from ours (see [`super::person::is_fork`] and
+//! [`super::project::is_fork`]). [`super::person::explain_fork`] and
+//! [`super::project::explain_fork`] show how the histories diverged, which can
+//! then be resolved by either:
+//!
+//! * adopting their history ([`super::person::adopt_fork`],
+//!   [`super::project::adopt_fork`])
+//! * keeping ours, and ignoring their current history ([`keep_mine`])
+//! * merging both histories ([`super::person::merge_fork`],
+//!   [`super::project::merge_fork`])
+//!
+//! after which replication can be resumed. Keeping ours and merging record the
+//! current tip of theirs as resolved (see [`is_ignored`]), while adopting
+//! theirs ends the fork altogether. The records are removed once theirs has
+//! moved past the resolved tip (see [`prune`]).
+
+use std::convert::TryFrom;
+
+use git_ext as ext;
+
+use super::{
+    super::{
+        storage::{self, backend::ReadRefs as _, ReadOnly, Storage},
+        types::Namespace,
+    },
+    error::Error,
+};
+use crate::identities::git::{Revision, Urn};
+
+pub use crate::identities::git::fork::{Branch, Commit, Fork};
+
+/// Keep our history of `mine`, and ignore the fork `theirs`.
+///
+/// Only the current tip of `theirs` is ignored: if it changes, the fork will be
+/// detected again. The decision is recorded locally, and not published.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn keep_mine(storage: &Storage, mine: &Urn, theirs: &Urn) -> Result<(), Error> {
+    let tip = storage
+        .tip(theirs)?
+        .ok_or_else(|| Error::NotFound(theirs.clone()))?;
+    ignore(
+        storage,
+        mine,
+        tip,
+        &format!("keeping {} over fork {}", mine, theirs),
+    )
+}
+
+/// Whether the current tip of `theirs` was ignored by [`keep_mine`], or merged
+/// into `mine` by [`super::person::merge_fork`] or
+/// [`super::project::merge_fork`].
+pub fn is_ignored<S>(storage: &S, mine: &Urn, theirs: &Urn) -> Result<bool, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    match storage.tip(theirs)? {
+        None => Ok(false),
+        Some(tip) => Ok(storage.ref_target(&ignored(mine, &tip))?.is_some()),
+    }
+}
+
+/// Remove the records of resolved forks of `mine` which `theirs` has moved
+/// past, ie. whose tip is an ancestor of the current tip of `theirs`.
+///
+/// Returns the number of records removed.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn prune(storage: &Storage, mine: &Urn, theirs: &Urn) -> Result<usize, Error> {
+    let tip = match storage.tip(theirs)? {
+        None => return Ok(0),
+        Some(tip) => tip,
+    };
+
+    let repo = storage.as_raw();
+    let mut moved_past = Vec::new();
+    for reference in storage.references_glob(storage::glob::RefspecMatcher::from(
+        forks(mine).with_pattern_suffix(refspec_pattern!("*")),
+    ))? {
+        let reference = reference?;
+        if let (Some(name), Some(ignored)) = (reference.name(), reference.target()) {
+            if ignored != *tip && repo.graph_descendant_of(*tip, ignored)? {
+                moved_past.push(name.to_owned());
+            }
+        }
+    }
+    for name in &moved_past {
+        repo.find_reference(name)?.delete()?;
+    }
+
+    Ok(moved_past.len())
+}
+
+/// Record that the fork of `mine` at `tip` was resolved, such that
+/// [`is_ignored`] holds while it remains the tip.
+pub(super) fn ignore(storage: &Storage, mine: &Urn, tip: ext::Oid, msg: &str) -> Result<(), Error> {
+    storage
+        .as_raw()
+        .reference(ignored(mine, &tip).as_str(), *tip, true, msg)?;
+
+    Ok(())
+}
+
+/// `refs/namespaces/<mine>/refs/forks/<tip>`
+///
+/// Note that this is outside of the categories covered by `rad/signed_refs`.
+fn ignored(mine: &Urn, tip: &ext::Oid) -> ext::RefLike {
+    forks(mine).join(ext::RefLike::try_from(tip.to_string()).unwrap())
+}
+
+/// `refs/namespaces/<mine>/refs/forks`
+fn forks(mine: &Urn) -> ext::RefLike {
+    reflike!("refs/namespaces")
+        .join(Namespace::<Revision>::from(mine))
+        .join(reflike!("refs/forks"))
+}
@@ -0,0 +1,155 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{convert::TryFrom, fmt::Debug};
+
+use git_ext::is_not_found_err;
+use nonempty::NonEmpty;
+
+use super::{
+    super::{
+        refs::Refs as Sigrefs,
+        storage::{self, read::ReadIdentities, ReadOnly, Storage},
+        types::{namespace, Reference},
+    },
+    error::Error,
+    local::LocalIdentity,
+    project::ProjectRefs,
+};
+use crate::identities::{
+    self,
+    git::{Identities, IndirectDelegation, Org, Revision, VerifiedOrg, Verifying},
+};
+
+pub use identities::{git::Urn, payload::OrgPayload};
+
+type Namespace = namespace::Namespace<Revision>;
+
+/// Read an [`Org`] from the tip of the ref [`Urn::path`] points to.
+///
+/// If the ref is not found, `None` is returned.
+#[tracing::instrument(level = "trace", skip(storage), err)]
+pub fn get<S>(storage: &S, urn: &Urn) -> Result<Option<Org>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    match storage.reference(&Reference::try_from(urn)?) {
+        Ok(Some(reference)) => {
+            let tip = reference.peel_to_commit()?.id();
+            Ok(Some(read_identities(storage).get(tip)?))
+        },
+
+        Ok(None) => Ok(None),
+        Err(storage::Error::Git(e)) if is_not_found_err(&e) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read and verify the [`Org`] pointed to by `urn`.
+///
+/// If the ref pointed to by [`Urn::path`] is not found, `None` is returned.
+///
+/// # Caveats
+///
+/// The members of the org are resolved from their top-level `rad/id` in
+/// `storage`, so the result reflects the membership as of the latest revision
+/// of each member we know about.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn verify<S>(storage: &S, urn: &Urn) -> Result<Option<VerifiedOrg>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    match storage.reference(&Reference::try_from(urn)?) {
+        Ok(Some(reference)) => {
+            let tip = reference.peel_to_commit()?.id();
+            let lookup = |urn| {
+                storage
+                    .reference_oid(&Reference::rad_id(Namespace::from(urn)))
+                    .map(git2::Oid::from)
+            };
+            read_identities(storage)
+                .verify(tip, lookup)
+                .map(Some)
+                .map_err(|e| Error::Verify(e.into()))
+        },
+
+        Ok(None) => Ok(None),
+        Err(storage::Error::Git(e)) if is_not_found_err(&e) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Create a new [`Org`].
+///
+/// The `delegations` may only refer to keys and persons, orgs can not be
+/// members of other orgs.
+#[tracing::instrument(level = "debug", skip(storage, whoami), err)]
+pub fn create<P>(
+    storage: &Storage,
+    whoami: LocalIdentity,
+    payload: P,
+    delegations: IndirectDelegation,
+) -> Result<Org, Error>
+where
+    P: Into<OrgPayload> + Debug,
+{
+    let org = identities(storage).create(payload.into(), delegations, storage.signer())?;
+    let urn = org.urn();
+    ProjectRefs::Create(&org).apply(storage)?;
+    whoami.link(storage, &urn)?;
+    Sigrefs::update(storage, &urn)?;
+
+    Ok(org)
+}
+
+/// Update the [`Org`] at `urn`.
+///
+/// Projects delegating to the org pick up the new membership the next time
+/// they are verified.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn update<L, P, D>(
+    storage: &Storage,
+    urn: &Urn,
+    whoami: L,
+    payload: P,
+    delegations: D,
+) -> Result<Org, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+    P: Into<Option<OrgPayload>> + Debug,
+    D: Into<Option<IndirectDelegation>> + Debug,
+{
+    let prev = get(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let prev = Verifying::from(prev).signed()?;
+    let next = identities(storage).update(prev, payload, delegations, storage.signer())?;
+
+    ProjectRefs::Update(&next, "update").apply(storage)?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, urn)?;
+    }
+    Sigrefs::update(storage, urn)?;
+
+    Ok(next)
+}
+
+/// Given a list of orgs -- assumed to be the same org -- return the latest
+/// revision tip.
+pub fn latest_tip<S>(storage: &S, orgs: NonEmpty<Org>) -> Result<git2::Oid, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    Ok(read_identities(storage).latest_tip(orgs)?)
+}
+
+fn identities(storage: &Storage) -> Identities<Org> {
+    storage.identities()
+}
+
+fn read_identities(storage: &ReadOnly) -> ReadIdentities<Org> {
+    storage.identities()
+}
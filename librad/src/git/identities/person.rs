@@ -3,7 +3,7 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{convert::TryFrom, fmt::Debug};
+use std::{collections::BTreeMap, convert::TryFrom, fmt::Debug};
 
 use nonempty::NonEmpty;
 use radicle_git_ext::{is_not_found_err, OneLevel};
@@ -11,21 +11,25 @@ use radicle_git_ext::{is_not_found_err, OneLevel};
 use super::{
     super::{
         refs::Refs,
-        storage::{self, Storage},
+        storage::{self, read::ReadIdentities, ReadOnly, Storage},
         types::Reference,
     },
     common,
     error::Error,
+    fork,
     local::LocalIdentity,
 };
 use crate::{
     identities::{
         self,
         delegation,
-        git::{Identities, Verifying},
+        git::{history, Identities, Verifying},
+        quorum,
         urn,
     },
+    keys::PublicKey,
     peer::PeerId,
+    signer::Signer,
 };
 
 pub use identities::{
@@ -37,11 +41,15 @@ pub use identities::{
 ///
 /// If the ref is not found, `None` is returned.
 #[tracing::instrument(level = "trace", skip(storage), err)]
-pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<Person>, Error> {
+pub fn get<S>(storage: &S, urn: &Urn) -> Result<Option<Person>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
     match storage.reference(&Reference::try_from(urn)?) {
         Ok(Some(reference)) => {
             let tip = reference.peel_to_commit()?.id();
-            Ok(Some(identities(storage).get(tip)?))
+            Ok(Some(read_identities(storage).get(tip)?))
         },
 
         Ok(None) => Ok(None),
@@ -61,13 +69,17 @@ pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<Person>, Error> {
 /// function cannot be used to assert that the state after an [`update`] is
 /// valid.
 #[tracing::instrument(level = "debug", skip(storage), err)]
-pub fn verify(storage: &Storage, urn: &Urn) -> Result<Option<VerifiedPerson>, Error> {
+pub fn verify<S>(storage: &S, urn: &Urn) -> Result<Option<VerifiedPerson>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
     let branch = Reference::try_from(urn)?;
     tracing::debug!("verifying {} from {}", urn, branch);
     match storage.reference(&branch) {
         Ok(Some(reference)) => {
             let tip = reference.peel_to_commit()?.id();
-            identities(storage)
+            read_identities(storage)
                 .verify(tip)
                 .map(Some)
                 .map_err(|e| Error::Verify(e.into()))
@@ -79,6 +91,27 @@ pub fn verify(storage: &Storage, urn: &Urn) -> Result<Option<VerifiedPerson>, Er
     }
 }
 
+/// Build the [`history::History`] of the [`Person`] pointed to by `urn`.
+///
+/// If the ref pointed to by [`Urn::path`] is not found, `None` is returned.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn history<S>(storage: &S, urn: &Urn) -> Result<Option<history::History>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    match storage.reference(&Reference::try_from(urn)?) {
+        Ok(Some(reference)) => {
+            let tip = reference.peel_to_commit()?.id();
+            Ok(Some(read_identities(storage).history(tip)?))
+        },
+
+        Ok(None) => Ok(None),
+        Err(storage::Error::Git(e)) if is_not_found_err(&e) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
 /// Create a new [`Person`].
 ///
 /// The `delegations` must include the [`Storage`]'s [`crate::signer::Signer`]
@@ -137,6 +170,125 @@ where
     Ok(next)
 }
 
+/// Update the [`quorum::Policy`], and optionally the delegations, of the
+/// [`Person`] at `urn`.
+///
+/// See [`Identities::update_quorum`].
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn update_quorum<L, D>(
+    storage: &Storage,
+    urn: &Urn,
+    whoami: L,
+    delegations: D,
+    quorum: Option<quorum::Policy>,
+) -> Result<Person, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+    D: Into<Option<delegation::Direct>> + Debug,
+{
+    let prev = get(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let prev = Verifying::from(prev).signed()?;
+    let next = identities(storage).update_quorum(prev, delegations, quorum, storage.signer())?;
+
+    common::IdRef::from(urn).update(storage, next.content_id, "update")?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, urn)?;
+    }
+    Refs::update(storage, urn)?;
+
+    Ok(next)
+}
+
+/// Update the expiry times of the delegations of the [`Person`] at `urn`.
+///
+/// See [`Identities::update_expiry`].
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn update_expiry<L>(
+    storage: &Storage,
+    urn: &Urn,
+    whoami: L,
+    not_after: BTreeMap<PublicKey, i64>,
+) -> Result<Person, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+{
+    let prev = get(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let prev = Verifying::from(prev).signed()?;
+    let next = identities(storage).update_expiry(prev, not_after, storage.signer())?;
+
+    common::IdRef::from(urn).update(storage, next.content_id, "update")?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, urn)?;
+    }
+    Refs::update(storage, urn)?;
+
+    Ok(next)
+}
+
+/// Revoke `key` from the [`Person`] at `urn`, optionally replacing it by
+/// `replacement`.
+///
+/// The result is signed by the [`Storage`]'s [`crate::signer::Signer`], and
+/// may need to be confirmed by other delegates in order to become valid. See
+/// [`Identities::revoke`].
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn revoke<L>(
+    storage: &Storage,
+    urn: &Urn,
+    whoami: L,
+    key: PublicKey,
+    replacement: Option<PublicKey>,
+    reason: Option<String>,
+) -> Result<Person, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+{
+    let prev = get(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let prev = Verifying::from(prev).signed()?;
+    let next = identities(storage).revoke(prev, key, replacement, reason, storage.signer())?;
+
+    common::IdRef::from(urn).update(storage, next.content_id, "revoke")?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, urn)?;
+    }
+    Refs::update(storage, urn)?;
+
+    Ok(next)
+}
+
+/// Rotate the key of the [`Storage`]'s [`crate::signer::Signer`] in the
+/// [`Person`] at `urn` to the key of `new`.
+///
+/// The old key is revoked, and the result is signed by both keys. See
+/// [`Identities::rotate`].
+///
+/// Note that this only updates the identity: the [`Storage`] must be reopened
+/// with `new` in order to use the new key.
+#[tracing::instrument(level = "debug", skip(storage, new), err)]
+pub fn rotate<L, S>(
+    storage: &Storage,
+    urn: &Urn,
+    whoami: L,
+    new: &S,
+    reason: Option<String>,
+) -> Result<Person, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+    S: Signer,
+{
+    let prev = get(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let prev = Verifying::from(prev).signed()?;
+    let next = identities(storage).rotate(prev, storage.signer(), new, reason)?;
+
+    common::IdRef::from(urn).update(storage, next.content_id, "rotate")?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, urn)?;
+    }
+    Refs::update(storage, urn)?;
+
+    Ok(next)
+}
+
 /// Merge and sign the [`Person`] state as seen by `from`.
 #[tracing::instrument(level = "debug", skip(storage), err)]
 pub fn merge(storage: &Storage, urn: &Urn, from: PeerId) -> Result<Person, Error> {
@@ -168,24 +320,124 @@ pub fn merge(storage: &Storage, urn: &Urn, from: PeerId) -> Result<Person, Error
 ///
 ///   * If the `left` project could not be found
 ///   * If the `right` project could not be found
-pub fn is_fork(storage: &Storage, left: &Urn, right: &Urn) -> Result<bool, Error> {
+pub fn is_fork<S>(storage: &S, left: &Urn, right: &Urn) -> Result<bool, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
     let left = verify(storage, left)?.ok_or_else(|| Error::NotFound(left.clone()))?;
     let right = verify(storage, right)?.ok_or_else(|| Error::NotFound(right.clone()))?;
-    let verified = verified(&storage);
+    let verified = verified(storage);
     Ok(verified.is_fork(&left, &right)?)
 }
 
+/// Explain how `mine` and `theirs` have diverged, where [`is_fork`] is `true`.
+///
+/// See [`fork`] for how to resolve the fork.
+pub fn explain_fork<S>(storage: &S, mine: &Urn, theirs: &Urn) -> Result<fork::Fork, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let mine = verify(storage, mine)?.ok_or_else(|| Error::NotFound(mine.clone()))?;
+    let theirs = verify(storage, theirs)?.ok_or_else(|| Error::NotFound(theirs.clone()))?;
+    Ok(read_identities(storage).fork(*mine.content_id, *theirs.content_id)?)
+}
+
+/// Resolve a fork by making the verified state of `theirs` our `rad/id` of
+/// `mine`.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn adopt_fork<L>(
+    storage: &Storage,
+    mine: &Urn,
+    theirs: &Urn,
+    whoami: L,
+) -> Result<Person, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+{
+    let next = verify(storage, theirs)?
+        .ok_or_else(|| Error::NotFound(theirs.clone()))?
+        .into_inner();
+
+    common::IdRef::from(mine).update(
+        storage,
+        next.content_id,
+        &format!("adopt fork {}", theirs),
+    )?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, mine)?;
+    }
+    Refs::update(storage, mine)?;
+
+    Ok(next)
+}
+
+/// Resolve a fork by merging `theirs` into `mine`.
+///
+/// Our `rad/id` is updated to the union of the delegations of both sides
+/// (excluding keys we revoked), and then linked to `theirs` using
+/// [`Identities::merge_fork`]. The result is signed by the [`Storage`]'s
+/// [`crate::signer::Signer`], and must be confirmed by a quorum of the
+/// delegates of both sides (see [`merge`]) before they can adopt it.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn merge_fork<L>(
+    storage: &Storage,
+    mine: &Urn,
+    theirs: &Urn,
+    whoami: L,
+) -> Result<Person, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+{
+    let ours = get(storage, mine)?.ok_or_else(|| Error::NotFound(mine.clone()))?;
+    let their_id = get(storage, theirs)?.ok_or_else(|| Error::NotFound(theirs.clone()))?;
+
+    let delegations = ours
+        .delegations()
+        .iter()
+        .chain(their_id.delegations().iter())
+        .filter(|key| !ours.doc.revocations.contains_key(*key))
+        .copied()
+        .collect::<delegation::Direct>();
+    let ours = Verifying::from(ours).signed()?;
+    let ours = identities(storage).update(ours, None, delegations, storage.signer())?;
+    let their_tip = their_id.content_id;
+    let next = identities(storage).merge_fork(
+        Verifying::from(ours).signed()?,
+        Verifying::from(their_id).signed()?,
+        storage.signer(),
+    )?;
+
+    common::IdRef::from(mine).update(storage, next.content_id, "merge fork")?;
+    fork::ignore(storage, mine, their_tip, &format!("merged fork {}", theirs))?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, mine)?;
+    }
+    Refs::update(storage, mine)?;
+
+    Ok(next)
+}
+
 /// Given a list persons -- assumed to be the same person -- return the latest
 /// revision tip.
-pub fn latest_tip(storage: &Storage, persons: NonEmpty<Person>) -> Result<git2::Oid, Error> {
+pub fn latest_tip<S>(storage: &S, persons: NonEmpty<Person>) -> Result<git2::Oid, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
     // FIXME: Should we ensure that all the projects have the same URN?
-    Ok(identities(storage).latest_tip(persons)?)
+    Ok(read_identities(storage).latest_tip(persons)?)
 }
 
 fn identities(storage: &Storage) -> Identities<Person> {
     storage.identities()
 }
 
-fn verified(storage: &Storage) -> Identities<VerifiedPerson> {
+fn read_identities(storage: &ReadOnly) -> ReadIdentities<Person> {
+    storage.identities()
+}
+
+fn verified(storage: &ReadOnly) -> ReadIdentities<VerifiedPerson> {
     storage.identities()
 }
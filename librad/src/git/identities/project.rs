@@ -3,7 +3,11 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{convert::TryFrom, fmt::Debug};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt::Debug,
+};
 
 use either::Either;
 use git_ext::{is_not_found_err, OneLevel};
@@ -12,19 +16,35 @@ use nonempty::NonEmpty;
 use super::{
     super::{
         refs::Refs as Sigrefs,
-        storage::{self, Storage},
+        storage::{self, read::ReadIdentities, ReadOnly, Storage},
         types::{namespace, reference, Force, Reference, Single, SymbolicRef},
     },
     common,
     error::Error,
+    fork,
     local::LocalIdentity,
+    proposal,
 };
 use crate::{
     identities::{
         self,
-        git::{Identities, IndirectDelegation, Project, Revision, VerifiedProject, Verifying},
+        git::{
+            history,
+            Delegate,
+            Doc,
+            Identities,
+            Identity,
+            IndirectDelegation,
+            Project,
+            Revision,
+            VerifiedProject,
+            Verifying,
+        },
+        quorum,
         urn,
     },
+    internal::canonical::Cstring,
+    keys::PublicKey,
     peer::PeerId,
 };
 
@@ -36,11 +56,15 @@ type Namespace = namespace::Namespace<Revision>;
 ///
 /// If the ref is not found, `None` is returned.
 #[tracing::instrument(level = "trace", skip(storage), err)]
-pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<Project>, Error> {
+pub fn get<S>(storage: &S, urn: &Urn) -> Result<Option<Project>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
     match storage.reference(&Reference::try_from(urn)?) {
         Ok(Some(reference)) => {
             let tip = reference.peel_to_commit()?.id();
-            Ok(Some(identities(storage).get(tip)?))
+            Ok(Some(read_identities(storage).get(tip)?))
         },
 
         Ok(None) => Ok(None),
@@ -60,15 +84,20 @@ pub fn get(storage: &Storage, urn: &Urn) -> Result<Option<Project>, Error> {
 /// function cannot be used to assert that the state after an [`update`] is
 /// valid.
 #[tracing::instrument(level = "debug", skip(storage), err)]
-pub fn verify(storage: &Storage, urn: &Urn) -> Result<Option<VerifiedProject>, Error> {
+pub fn verify<S>(storage: &S, urn: &Urn) -> Result<Option<VerifiedProject>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
     match storage.reference(&Reference::try_from(urn)?) {
         Ok(Some(reference)) => {
             let tip = reference.peel_to_commit()?.id();
             let lookup = |urn| {
-                let refname = Reference::rad_id(Namespace::from(urn)).to_string();
-                storage.as_raw().refname_to_id(&refname)
+                storage
+                    .reference_oid(&Reference::rad_id(Namespace::from(urn)))
+                    .map(git2::Oid::from)
             };
-            identities(storage)
+            read_identities(storage)
                 .verify(tip, lookup)
                 .map(Some)
                 .map_err(|e| Error::Verify(e.into()))
@@ -80,6 +109,152 @@ pub fn verify(storage: &Storage, urn: &Urn) -> Result<Option<VerifiedProject>, E
     }
 }
 
+/// A [`VerifiedProject`] along with its resolved indirect delegates, see
+/// [`view`].
+#[derive(Clone, Debug)]
+pub struct View {
+    pub project: VerifiedProject,
+    pub maintainers: Vec<Maintainer>,
+}
+
+/// An indirect delegate of a [`View`]ed project.
+#[derive(Clone, Debug)]
+pub struct Maintainer {
+    /// The latest verified revision of the delegate, as used to verify the
+    /// project.
+    pub delegate: Delegate,
+    /// The name of the person or org.
+    pub name: Cstring,
+    /// `true` if the delegate is stored under its own [`Urn`], ie. it has been
+    /// replicated (or created) locally.
+    pub replicated: bool,
+    /// `true` if the project's `rad/self` is the delegate.
+    pub is_self: bool,
+}
+
+impl Maintainer {
+    pub fn urn(&self) -> Urn {
+        self.delegate.urn()
+    }
+
+    /// The keys of the delegate.
+    pub fn keys(&self) -> impl Iterator<Item = &PublicKey> {
+        self.delegate.delegations().iter()
+    }
+}
+
+/// Read and verify the [`Project`] pointed to by `urn`, and resolve its
+/// indirect delegates.
+///
+/// Unlike [`verify`], delegates which have not been replicated under their own
+/// [`Urn`] are resolved from the `rad/ids/*` of the project as replicated from
+/// its remotes.
+///
+/// If the ref pointed to by [`Urn::path`] is not found, `None` is returned.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn view<S>(storage: &S, urn: &Urn) -> Result<Option<View>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let tip = match storage.reference(&Reference::try_from(urn)?)? {
+        Some(reference) => reference.peel_to_commit()?.id(),
+        None => return Ok(None),
+    };
+    let namespace = Namespace::from(urn);
+    let lookup = |delegate: Urn| -> Result<git2::Oid, storage::Error> {
+        match storage.reference_oid(&Reference::rad_id(Namespace::from(&delegate))) {
+            Err(storage::Error::Git(e)) if is_not_found_err(&e) => {
+                replicated_delegate(storage, &namespace, &delegate)?.ok_or(storage::Error::Git(e))
+            },
+            res => res,
+        }
+        .map(git2::Oid::from)
+    };
+    let project = read_identities(storage)
+        .verify(tip, lookup)
+        .map_err(|e| Error::Verify(e.into()))?;
+
+    let rad_self = storage
+        .reference(&Reference::rad_self(namespace.clone(), None))?
+        .and_then(|reference| reference.symbolic_target().map(ToOwned::to_owned));
+    let maintainers = project
+        .delegations()
+        .iter()
+        .indirect()
+        .map(|delegate| -> Result<Maintainer, Error> {
+            let urn = delegate.urn();
+            let rad_id = Reference::rad_id(Namespace::from(&urn)).to_string();
+            Ok(Maintainer {
+                name: delegate.doc.payload.name().clone(),
+                replicated: storage.has_urn(&urn)?,
+                is_self: rad_self.as_ref() == Some(&rad_id),
+                delegate: delegate.clone(),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Some(View {
+        project,
+        maintainers,
+    }))
+}
+
+/// Find the tip of `delegate` in `refs/namespaces/<namespace>/refs/remotes/*/
+/// rad/ids/<delegate>`.
+///
+/// If several remotes have a copy of the delegate, the most recent one is
+/// returned.
+fn replicated_delegate(
+    storage: &ReadOnly,
+    namespace: &Namespace,
+    delegate: &Urn,
+) -> Result<Option<git_ext::Oid>, storage::Error> {
+    let pattern = reflike!("refs/namespaces")
+        .join(namespace)
+        .with_pattern_suffix(refspec_pattern!("refs/remotes/*/rad/ids"))
+        .append(git_ext::RefLike::try_from(delegate.encode_id()).unwrap());
+
+    let mut latest: Option<git2::Oid> = None;
+    for reference in storage.references_glob(storage::glob::RefspecMatcher::from(pattern))? {
+        let tip = reference?.peel_to_commit()?.id();
+        latest = match latest {
+            Some(latest) if !storage.as_raw().graph_descendant_of(tip, latest)? => Some(latest),
+            _ => Some(tip),
+        };
+    }
+
+    Ok(latest.map(git_ext::Oid::from))
+}
+
+/// Build the [`history::History`] of the [`Project`] pointed to by `urn`.
+///
+/// Indirect delegations are resolved to their latest heads, as for [`verify`].
+///
+/// If the ref pointed to by [`Urn::path`] is not found, `None` is returned.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn history<S>(storage: &S, urn: &Urn) -> Result<Option<history::History>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    match storage.reference(&Reference::try_from(urn)?) {
+        Ok(Some(reference)) => {
+            let tip = reference.peel_to_commit()?.id();
+            let lookup = |urn| {
+                storage
+                    .reference_oid(&Reference::rad_id(Namespace::from(urn)))
+                    .map(git2::Oid::from)
+            };
+            Ok(Some(read_identities(storage).history(tip, lookup)?))
+        },
+
+        Ok(None) => Ok(None),
+        Err(storage::Error::Git(e)) if is_not_found_err(&e) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
 /// Create a new [`Project`].
 #[tracing::instrument(level = "debug", skip(storage, whoami), err)]
 pub fn create<P>(
@@ -127,6 +302,61 @@ where
     Ok(next)
 }
 
+/// Update the [`quorum::Policy`], and optionally the delegations, of the
+/// [`Project`] at `urn`.
+///
+/// See [`Identities::update_quorum`].
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn update_quorum<L, D>(
+    storage: &Storage,
+    urn: &Urn,
+    whoami: L,
+    delegations: D,
+    quorum: Option<quorum::Policy>,
+) -> Result<Project, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+    D: Into<Option<IndirectDelegation>> + Debug,
+{
+    let prev = get(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let prev = Verifying::from(prev).signed()?;
+    let next = identities(storage).update_quorum(prev, delegations, quorum, storage.signer())?;
+
+    ProjectRefs::Update(&next, "update").apply(storage)?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, urn)?;
+    }
+    Sigrefs::update(storage, urn)?;
+
+    Ok(next)
+}
+
+/// Update the expiry times of the delegations of the [`Project`] at `urn`.
+///
+/// See [`Identities::update_expiry`].
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn update_expiry<L>(
+    storage: &Storage,
+    urn: &Urn,
+    whoami: L,
+    not_after: BTreeMap<PublicKey, i64>,
+) -> Result<Project, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+{
+    let prev = get(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let prev = Verifying::from(prev).signed()?;
+    let next = identities(storage).update_expiry(prev, not_after, storage.signer())?;
+
+    ProjectRefs::Update(&next, "update").apply(storage)?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, urn)?;
+    }
+    Sigrefs::update(storage, urn)?;
+
+    Ok(next)
+}
+
 /// Merge and sign the [`Project`] state as seen by `from`.
 #[tracing::instrument(level = "debug", skip(storage), err)]
 pub fn merge(storage: &Storage, urn: &Urn, from: PeerId) -> Result<Project, Error> {
@@ -158,43 +388,180 @@ pub fn merge(storage: &Storage, urn: &Urn, from: PeerId) -> Result<Project, Erro
 ///
 ///   * If the `left` project could not be found
 ///   * If the `right` project could not be found
-pub fn is_fork(storage: &Storage, left: &Urn, right: &Urn) -> Result<bool, Error> {
+pub fn is_fork<S>(storage: &S, left: &Urn, right: &Urn) -> Result<bool, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
     let left = verify(storage, left)?.ok_or_else(|| Error::NotFound(left.clone()))?;
     let right = verify(storage, right)?.ok_or_else(|| Error::NotFound(right.clone()))?;
-    let verified = verified(&storage);
+    let verified = verified(storage);
     Ok(verified.is_fork(&left, &right)?)
 }
 
+/// Explain how `mine` and `theirs` have diverged, where [`is_fork`] is `true`.
+///
+/// See [`fork`] for how to resolve the fork.
+pub fn explain_fork<S>(storage: &S, mine: &Urn, theirs: &Urn) -> Result<fork::Fork, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let mine = verify(storage, mine)?.ok_or_else(|| Error::NotFound(mine.clone()))?;
+    let theirs = verify(storage, theirs)?.ok_or_else(|| Error::NotFound(theirs.clone()))?;
+    Ok(read_identities(storage).fork(*mine.content_id, *theirs.content_id)?)
+}
+
+/// Resolve a fork by making the verified state of `theirs` our `rad/id` of
+/// `mine`.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn adopt_fork<L>(
+    storage: &Storage,
+    mine: &Urn,
+    theirs: &Urn,
+    whoami: L,
+) -> Result<Project, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+{
+    let next = verify(storage, theirs)?
+        .ok_or_else(|| Error::NotFound(theirs.clone()))?
+        .into_inner();
+
+    ProjectRefs::Update(&next, &format!("adopt fork {}", theirs)).apply(storage)?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, mine)?;
+    }
+    Sigrefs::update(storage, mine)?;
+
+    Ok(next)
+}
+
+/// Resolve a fork by merging `theirs` into `mine`.
+///
+/// Our `rad/id` is updated to include the delegations of `theirs` which don't
+/// overlap with ours, and then linked to `theirs` using
+/// [`Identities::merge_fork`]. The result is signed by the [`Storage`]'s
+/// [`crate::signer::Signer`], and must be confirmed by a quorum of the
+/// delegates of both sides (see [`merge`]) before they can adopt it.
+///
+/// Adding the delegations is [`proposal::propose`]d like any other update. If
+/// our signature alone does not make a quorum, [`Error::Proposed`] is
+/// returned: once the proposal is signed by enough delegates and
+/// [`proposal::finalise`]d, the merge can be retried.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn merge_fork<L>(
+    storage: &Storage,
+    mine: &Urn,
+    theirs: &Urn,
+    whoami: L,
+) -> Result<Project, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+{
+    let ours = get(storage, mine)?.ok_or_else(|| Error::NotFound(mine.clone()))?;
+    let their_id = get(storage, theirs)?.ok_or_else(|| Error::NotFound(theirs.clone()))?;
+
+    let delegations = {
+        let keys = identities::git::delegation_keys(ours.delegations());
+        let roots = ours
+            .delegations()
+            .iter()
+            .indirect()
+            .map(|id| &id.root)
+            .collect::<BTreeSet<_>>();
+        let missing = their_id.delegations().iter().filter(|d| match d {
+            Either::Left(key) => !keys.contains(key),
+            Either::Right(id) => {
+                !roots.contains(&id.root) && id.delegations().iter().all(|key| !keys.contains(key))
+            },
+        });
+        IndirectDelegation::try_from_iter(
+            ours.delegations()
+                .iter()
+                .chain(missing)
+                .map(|d| d.map_left(|key| *key).map_right(|id| id.clone())),
+        )
+        .map_err(|e| Error::Verify(identities::git::error::VerifyProject::from(e).into()))?
+    };
+    let ours = match proposal::propose(storage, mine, None, delegations) {
+        Err(Error::EmptyProposal) => ours,
+        Err(e) => return Err(e),
+        Ok(proposed) => {
+            let revision = proposed.project.revision;
+            match proposal::finalise(storage, mine, None, &revision, None) {
+                Err(Error::Verification(e))
+                    if matches!(
+                        e,
+                        identities::git::VerificationError::Quorum
+                            | identities::git::VerificationError::ParentQuorum
+                    ) =>
+                {
+                    return Err(Error::Proposed(revision))
+                },
+                res => res?,
+            }
+        },
+    };
+    let their_tip = their_id.content_id;
+    let next = identities(storage).merge_fork(
+        Verifying::from(ours).signed()?,
+        Verifying::from(their_id).signed()?,
+        storage.signer(),
+    )?;
+
+    ProjectRefs::Update(&next, "merge fork").apply(storage)?;
+    fork::ignore(storage, mine, their_tip, &format!("merged fork {}", theirs))?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, mine)?;
+    }
+    Sigrefs::update(storage, mine)?;
+
+    Ok(next)
+}
+
 /// Given a list projects -- assumed to be the same project -- return the latest
 /// revision tip.
-pub fn latest_tip(storage: &Storage, projects: NonEmpty<Project>) -> Result<git2::Oid, Error> {
+pub fn latest_tip<S>(storage: &S, projects: NonEmpty<Project>) -> Result<git2::Oid, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
     // FIXME: Should we ensure that all the projects have the same URN?
-    Ok(identities(storage).latest_tip(projects)?)
+    Ok(read_identities(storage).latest_tip(projects)?)
 }
 
-enum ProjectRefs<'a> {
-    Create(&'a Project),
-    Update(&'a Project, &'a str),
+/// The `rad/id` and `rad/ids/*` refs of an identity with
+/// [`IndirectDelegation`]s, ie. a [`Project`] or an [`identities::git::Org`].
+pub(super) enum ProjectRefs<'a, P = ProjectPayload> {
+    Create(&'a Identity<Doc<P, IndirectDelegation>>),
+    Update(&'a Identity<Doc<P, IndirectDelegation>>, &'a str),
 }
 
-impl<'a> ProjectRefs<'a> {
+impl<'a, P: 'a> ProjectRefs<'a, P> {
+    /// Update the `rad/id` and the `rad/ids/*` of the identity in a single
+    /// [`storage::Transaction`].
     pub fn apply(&self, storage: &Storage) -> Result<(), Error> {
+        let mut tx = storage.transaction()?;
         for symref in self.delegates() {
-            symref.create(storage.as_raw())?;
+            symref.create_in(&mut tx).map_err(storage::Error::from)?;
         }
         match self {
             Self::Create(project) => {
-                common::IdRef::from(&project.urn()).create(storage, project.content_id)
+                common::IdRef::from(&project.urn()).create_in(&mut tx, project.content_id)
             },
             Self::Update(project, msg) => {
-                common::IdRef::from(&project.urn()).update(storage, project.content_id, msg)
+                common::IdRef::from(&project.urn()).update_in(&mut tx, project.content_id, msg)
             },
-        }?;
+        }
+        .map_err(storage::Error::from)?;
+        tx.commit().map_err(storage::Error::from)?;
+        common::update_index(storage, &self.project().urn());
 
         Ok(())
     }
 
-    fn project(&self) -> &Project {
+    fn project(&self) -> &Identity<Doc<P, IndirectDelegation>> {
         match self {
             Self::Create(project) => project,
             Self::Update(project, _) => project,
@@ -229,6 +596,10 @@ fn identities(storage: &Storage) -> Identities<Project> {
     storage.identities()
 }
 
-fn verified(storage: &Storage) -> Identities<VerifiedProject> {
+fn read_identities(storage: &ReadOnly) -> ReadIdentities<Project> {
+    storage.identities()
+}
+
+fn verified(storage: &ReadOnly) -> ReadIdentities<VerifiedProject> {
     storage.identities()
 }
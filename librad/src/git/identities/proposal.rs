@@ -0,0 +1,286 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Multi-party updates of [`Project`]s.
+//!
+//! An update of a project with several delegates needs to be signed by a
+//! quorum of them. The workflow is:
+//!
+//! 1. One delegate [`propose`]s a new revision. It is signed by them, and
+//!    stored under `rad/ids/proposals/<revision>` -- `rad/id` is left as is.
+//! 2. The proposal is replicated along with the other `rad/ids/*` refs. Other
+//!    delegates can inspect it using [`list`] and [`get`], and [`sign`] it.
+//! 3. Once the signatures reach quorum, anyone can [`finalise`] the proposal,
+//!    which makes it the new `rad/id`.
+
+use std::fmt::Debug;
+
+use git_ext::is_not_found_err;
+
+use super::{
+    super::{
+        refs::Refs as Sigrefs,
+        storage::{read::ReadIdentities, ReadOnly, Storage},
+        types::{Force, Namespace, Reference},
+    },
+    error::Error,
+    local::LocalIdentity,
+    project::{self, ProjectRefs},
+};
+use crate::{
+    identities::{
+        git::{Identities, IndirectDelegation, Project, Revision, Urn, Verifying},
+        payload::ProjectPayload,
+    },
+    peer::PeerId,
+};
+
+/// A proposed update of a [`Project`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proposal {
+    /// The peer the proposal was replicated from, or `None` if it is our own
+    /// copy.
+    pub peer: Option<PeerId>,
+    /// The proposed revision, signed by the delegates which signed it so far.
+    pub project: Project,
+}
+
+impl Proposal {
+    /// Whether the signatures form a quorum of the proposed delegations.
+    ///
+    /// Note that [`finalise`] additionally requires a quorum of the current
+    /// delegations.
+    pub fn is_quorum(&self) -> bool {
+        Verifying::from(self.project.clone())
+            .signed()
+            .and_then(|signed| signed.quorum())
+            .is_ok()
+    }
+}
+
+/// Propose an update of the [`Project`] at `urn`, signed by the [`Storage`]'s
+/// [`crate::signer::Signer`].
+///
+/// If the same revision was proposed before, the existing proposal is
+/// returned.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn propose<P, D>(
+    storage: &Storage,
+    urn: &Urn,
+    payload: P,
+    delegations: D,
+) -> Result<Proposal, Error>
+where
+    P: Into<Option<ProjectPayload>> + Debug,
+    D: Into<Option<IndirectDelegation>> + Debug,
+{
+    let prev = project::get(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let prev_revision = prev.revision;
+    let prev = Verifying::from(prev).signed()?;
+    let next = identities(storage).update(prev, payload, delegations, storage.signer())?;
+    if next.revision == prev_revision {
+        return Err(Error::EmptyProposal);
+    }
+
+    if let Some(existing) = get(storage, urn, &next.revision, None)? {
+        return Ok(existing);
+    }
+    Reference::rad_proposal(Namespace::from(urn), None, &next.revision).create(
+        storage.as_raw(),
+        *next.content_id,
+        Force::False,
+        &format!("proposed revision {}", next.revision),
+    )?;
+    Sigrefs::update(storage, urn)?;
+
+    Ok(Proposal {
+        peer: None,
+        project: next,
+    })
+}
+
+/// Read the proposal of `revision` for the [`Project`] at `urn`, as seen by
+/// `peer` (or ourselves, if `None`).
+pub fn get<S>(
+    storage: &S,
+    urn: &Urn,
+    revision: &Revision,
+    peer: Option<PeerId>,
+) -> Result<Option<Proposal>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    match storage.reference(&Reference::rad_proposal(
+        Namespace::from(urn),
+        peer,
+        revision,
+    ))? {
+        None => Ok(None),
+        Some(reference) => {
+            let tip = reference.peel_to_commit()?.id();
+            Ok(Some(Proposal {
+                peer,
+                project: read_identities(storage).get(tip)?,
+            }))
+        },
+    }
+}
+
+/// List all proposals for the [`Project`] at `urn`, both our own and the ones
+/// replicated from other peers.
+pub fn list<S>(storage: &S, urn: &Urn) -> Result<Vec<Proposal>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let namespace = Namespace::from(urn);
+    let remotes = reflike!("refs/namespaces")
+        .join(&namespace)
+        .join(reflike!("refs/remotes"));
+    let remotes_glob = globset::Glob::new(&format!("{}/*/rad/ids/proposals/*", remotes))
+        .unwrap()
+        .compile_matcher();
+    let remotes_prefix = format!("{}/", remotes);
+
+    let mut proposals = Vec::new();
+    let ours = storage.references(&Reference::rad_proposals(namespace, None))?;
+    for reference in ours.chain(storage.references_glob(remotes_glob)?) {
+        let reference = reference?;
+        let peer = reference
+            .name()
+            .and_then(|name| name.strip_prefix(&remotes_prefix))
+            .and_then(|name| name.splitn(2, '/').next())
+            .and_then(|peer| peer.parse().ok());
+        let tip = reference.peel_to_commit()?.id();
+        proposals.push(Proposal {
+            peer,
+            project: read_identities(storage).get(tip)?,
+        });
+    }
+
+    Ok(proposals)
+}
+
+/// Sign the proposal of `revision` as seen by `from`.
+///
+/// If we have a copy of the proposal already, it is merged with the one from
+/// `from`, such that our copy carries the signatures of both.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn sign(
+    storage: &Storage,
+    urn: &Urn,
+    revision: &Revision,
+    from: PeerId,
+) -> Result<Proposal, Error> {
+    let theirs = get(storage, urn, revision, Some(from))?
+        .ok_or(Error::NoProposal(*revision))?
+        .project;
+    let theirs = Verifying::from(theirs).signed()?;
+    let next = match get(storage, urn, revision, None)? {
+        None => identities(storage).create_from(theirs, storage.signer())?,
+        Some(ours) => {
+            let ours = Verifying::from(ours.project).signed()?;
+            identities(storage).update_from(ours, theirs, storage.signer())?
+        },
+    };
+
+    Reference::rad_proposal(Namespace::from(urn), None, revision).create(
+        storage.as_raw(),
+        *next.content_id,
+        Force::True,
+        &format!("signed revision {} from {}", revision, from),
+    )?;
+    Sigrefs::update(storage, urn)?;
+
+    Ok(Proposal {
+        peer: None,
+        project: next,
+    })
+}
+
+/// Make the proposal of `revision`, as seen by `from` (or ourselves, if
+/// `None`), the new `rad/id` of the [`Project`] at `urn`.
+///
+/// Our copy of the proposal is removed.
+///
+/// # Errors
+///
+/// * If the proposal does not verify against the current `rad/id`, eg. because
+///   it does not reach quorum
+/// * If the proposal does not replace the current revision, or `rad/id` has
+///   moved since the proposal was made
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn finalise<L>(
+    storage: &Storage,
+    urn: &Urn,
+    whoami: L,
+    revision: &Revision,
+    from: Option<PeerId>,
+) -> Result<Project, Error>
+where
+    L: Into<Option<LocalIdentity>> + Debug,
+{
+    let current = project::verify(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let tip = project::get(storage, urn)?
+        .ok_or_else(|| Error::NotFound(urn.clone()))?
+        .content_id;
+    let proposal = get(storage, urn, revision, from)?
+        .ok_or(Error::NoProposal(*revision))?
+        .project;
+
+    let fast_forward = proposal.content_id == tip
+        || storage
+            .as_raw()
+            .graph_descendant_of(*proposal.content_id, *tip)?;
+    if proposal.doc.replaces != Some(current.revision) || !fast_forward {
+        return Err(Error::StaleProposal(*revision));
+    }
+    Verifying::from(proposal.clone())
+        .signed()?
+        .quorum()?
+        .verified(Some(&current))?;
+
+    ProjectRefs::Update(&proposal, &format!("finalised revision {}", revision)).apply(storage)?;
+    withdraw_ref(storage, urn, revision)?;
+    if let Some(local_id) = whoami.into() {
+        local_id.link(storage, urn)?;
+    }
+    Sigrefs::update(storage, urn)?;
+
+    Ok(proposal)
+}
+
+/// Remove our copy of the proposal of `revision`.
+///
+/// Returns `false` if there was no such proposal.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn withdraw(storage: &Storage, urn: &Urn, revision: &Revision) -> Result<bool, Error> {
+    let removed = withdraw_ref(storage, urn, revision)?;
+    if removed {
+        Sigrefs::update(storage, urn)?;
+    }
+
+    Ok(removed)
+}
+
+fn withdraw_ref(storage: &Storage, urn: &Urn, revision: &Revision) -> Result<bool, Error> {
+    match Reference::rad_proposal(Namespace::from(urn), None, revision).find(storage.as_raw()) {
+        Ok(mut reference) => {
+            reference.delete()?;
+            Ok(true)
+        },
+        Err(e) if is_not_found_err(&e) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn identities(storage: &Storage) -> Identities<Project> {
+    storage.identities()
+}
+
+fn read_identities(storage: &ReadOnly) -> ReadIdentities<Project> {
+    storage.identities()
+}
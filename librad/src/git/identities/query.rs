@@ -0,0 +1,451 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Paginated and filtered listing of the identities in a storage.
+//!
+//! Listing only requires a [`ReadOnly`] handle.
+//!
+//! [`list`] walks the namespaces of the storage in [`Urn`] order, loading the
+//! identities until a [`Page`] is filled. For storages with many namespaces,
+//! an [`Index`] can be maintained on disk, which avoids loading the
+//! identities on every query.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fs,
+    io,
+    path::PathBuf,
+};
+
+use either::Either;
+use git_ext as ext;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    super::storage::{
+        backend::{ReadObjects as _, ReadRefs as _},
+        glob,
+        ReadOnly,
+    },
+    any,
+    error::Error,
+};
+use crate::{
+    identities::{self, git::SomeIdentity, payload::SomePayload},
+    keys::PublicKey,
+};
+
+pub use crate::identities::git::Urn;
+
+/// The number of entries in a [`Page`] by default.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// The file name of the [`Index`], relative to [`ReadOnly::path`].
+const INDEX_FILE: &str = "rad-namespaces.json";
+
+lazy_static! {
+    static ref RAD_ID_GLOB: glob::RefspecMatcher =
+        refspec_pattern!("refs/namespaces/*/refs/rad/id").into();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Person,
+    Project,
+    Org,
+}
+
+/// Summary of an identity, as returned by [`list`] and [`Index::list`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub urn: Urn,
+    pub kind: Kind,
+    pub name: String,
+    /// The keys of the delegates. For projects and orgs, this includes the
+    /// keys of the delegate persons.
+    pub delegates: BTreeSet<PublicKey>,
+    /// The commit time (in seconds since the epoch) of the identity's tip.
+    pub updated: i64,
+    /// The tip of the `rad/id` branch.
+    pub tip: ext::Oid,
+}
+
+impl Entry {
+    fn load(storage: &ReadOnly, urn: &Urn, tip: ext::Oid) -> Result<Option<Self>, Error> {
+        let identity = match any::get(storage, urn)? {
+            None => return Ok(None),
+            Some(identity) => identity,
+        };
+        let (kind, name, delegates) = match identity {
+            SomeIdentity::Person(person) => (
+                Kind::Person,
+                person.subject().name.to_string(),
+                person.delegations().iter().copied().collect(),
+            ),
+            SomeIdentity::Project(project) => (
+                Kind::Project,
+                project.subject().name.to_string(),
+                project
+                    .delegations()
+                    .iter()
+                    .flat_map(|delegate| match delegate {
+                        Either::Left(key) => vec![*key],
+                        Either::Right(person) => person.delegations().iter().copied().collect(),
+                    })
+                    .collect(),
+            ),
+            SomeIdentity::Org(org) => (
+                Kind::Org,
+                org.subject().name.to_string(),
+                identities::git::delegation_keys(org.delegations())
+                    .into_iter()
+                    .copied()
+                    .collect(),
+            ),
+        };
+        let updated = storage
+            .read_commit(tip)?
+            .ok_or_else(|| Error::NotFound(urn.clone()))?
+            .time;
+
+        Ok(Some(Self {
+            urn: urn.clone(),
+            kind,
+            name,
+            delegates,
+            updated,
+            tip,
+        }))
+    }
+}
+
+/// Criteria an [`Entry`] must satisfy to be listed.
+///
+/// All criteria which are set must be satisfied.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub kind: Option<Kind>,
+    /// Only identities whose [`Urn`] (as in `rad:git:<id>`) starts with this
+    /// prefix.
+    pub urn_prefix: Option<String>,
+    /// Only identities this key is a delegate of.
+    pub delegate: Option<PublicKey>,
+    /// Only identities whose name starts with this prefix.
+    pub name_prefix: Option<String>,
+    /// Only identities updated at or after this time (in seconds since the
+    /// epoch).
+    pub updated_since: Option<i64>,
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        self.matches_urn(&entry.urn)
+            && self.matches_kind(entry.kind)
+            && self
+                .delegate
+                .as_ref()
+                .map_or(true, |key| entry.delegates.contains(key))
+            && self
+                .name_prefix
+                .as_ref()
+                .map_or(true, |prefix| entry.name.starts_with(prefix.as_str()))
+            && self
+                .updated_since
+                .map_or(true, |since| entry.updated >= since)
+    }
+
+    fn matches_urn(&self, urn: &Urn) -> bool {
+        self.urn_prefix
+            .as_ref()
+            .map_or(true, |prefix| urn.to_string().starts_with(prefix.as_str()))
+    }
+
+    fn matches_kind(&self, kind: Kind) -> bool {
+        self.kind.map_or(true, |want| want == kind)
+    }
+}
+
+/// Which part of the results to return.
+#[derive(Clone, Debug)]
+pub struct Page {
+    /// Only return entries after this [`Urn`], as returned in
+    /// [`Listing::next`].
+    pub after: Option<Urn>,
+    /// The maximum number of entries to return.
+    pub limit: usize,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self {
+            after: None,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+/// One [`Page`] of results.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Listing {
+    pub entries: Vec<Entry>,
+    /// If there are more results, the value to pass as [`Page::after`] to
+    /// obtain the next page.
+    pub next: Option<Urn>,
+}
+
+/// List the identities in `storage` matching `filter`.
+///
+/// The [`Filter::urn_prefix`] and [`Filter::kind`] are checked before loading
+/// an identity, so only the identities on the requested `page` which match them
+/// are loaded. This may still be expensive for restrictive filters on the other
+/// criteria. See [`Index`] for an alternative.
+#[tracing::instrument(level = "debug", skip(storage), err)]
+pub fn list<S>(storage: &S, filter: &Filter, page: &Page) -> Result<Listing, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let mut entries = Vec::new();
+    for (urn, tip) in namespaces(storage)? {
+        if page.after.as_ref().map_or(false, |after| &urn <= after) {
+            continue;
+        }
+        if !filter.matches_urn(&urn) {
+            continue;
+        }
+        if filter.kind.is_some() {
+            match peek_kind(storage, tip)? {
+                Some(kind) if !filter.matches_kind(kind) => continue,
+                _ => {},
+            }
+        }
+        if let Some(entry) = Entry::load(storage, &urn, tip)? {
+            if !filter.matches(&entry) {
+                continue;
+            }
+            if entries.len() == page.limit {
+                return Ok(Listing {
+                    next: entries.last().map(|entry: &Entry| entry.urn.clone()),
+                    entries,
+                });
+            }
+            entries.push(entry)
+        }
+    }
+
+    Ok(Listing {
+        entries,
+        next: None,
+    })
+}
+
+/// An on-disk index of the identities in a storage.
+///
+/// The index is stored next to the storage, and is optional: it is only
+/// maintained once it was created using [`Index::create`]. When namespaces
+/// change, it is updated using [`update_index`] (which replication and the
+/// functions creating or updating identities in [`super`] do), and any changes
+/// made by other means are picked up by [`Index::refresh`], which only reloads
+/// the identities whose tip changed.
+///
+/// # Concurrency
+///
+/// Every write goes to a temporary file of its own, which then atomically
+/// replaces the index file, so readers never observe a partially written index.
+/// Concurrent updates from different storage handles may still overwrite each
+/// other. This is benign, as the lost updates are detected by the next
+/// [`Index::refresh`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    entries: BTreeMap<Urn, Entry>,
+}
+
+impl Index {
+    /// Build the index for `storage`, and write it to disk.
+    pub fn create<S>(storage: &S) -> Result<Self, Error>
+    where
+        S: AsRef<ReadOnly>,
+    {
+        let storage = storage.as_ref();
+        let mut this = Self::default();
+        this.refresh(storage)?;
+        this.save(storage)?;
+        Ok(this)
+    }
+
+    /// Read the index of `storage`, if it exists.
+    ///
+    /// Note that the index may be outdated, see [`Index::refresh`].
+    pub fn open<S>(storage: &S) -> Result<Option<Self>, Error>
+    where
+        S: AsRef<ReadOnly>,
+    {
+        let storage = storage.as_ref();
+        match fs::read(index_path(storage)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove the index of `storage`, so it is no longer maintained.
+    pub fn remove<S>(storage: &S) -> Result<(), Error>
+    where
+        S: AsRef<ReadOnly>,
+    {
+        let storage = storage.as_ref();
+        match fs::remove_file(index_path(storage)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Bring the index up-to-date with `storage`, and write it to disk.
+    ///
+    /// Only identities whose tip is not the indexed one are loaded.
+    pub fn refresh<S>(&mut self, storage: &S) -> Result<(), Error>
+    where
+        S: AsRef<ReadOnly>,
+    {
+        let storage = storage.as_ref();
+        let current = namespaces(storage)?;
+        let mut changed = false;
+
+        let before = self.entries.len();
+        self.entries.retain(|urn, _| current.contains_key(urn));
+        changed |= before != self.entries.len();
+
+        for (urn, tip) in current {
+            if self.entries.get(&urn).map(|entry| entry.tip) != Some(tip) {
+                self.load(storage, urn, tip)?;
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.save(storage)?;
+        }
+        Ok(())
+    }
+
+    /// Update the entry for `urn`, and write the index to disk.
+    pub fn update<S>(&mut self, storage: &S, urn: &Urn) -> Result<(), Error>
+    where
+        S: AsRef<ReadOnly>,
+    {
+        let storage = storage.as_ref();
+        let urn = Urn::new(urn.id);
+        match storage.tip(&urn)? {
+            None => {
+                self.entries.remove(&urn);
+            },
+            Some(tip) => self.load(storage, urn, tip)?,
+        }
+        self.save(storage)
+    }
+
+    /// Like [`list`], but using the index instead of loading the identities.
+    pub fn list(&self, filter: &Filter, page: &Page) -> Listing {
+        use std::ops::Bound::{Excluded, Unbounded};
+
+        let lower = page.after.clone().map_or(Unbounded, Excluded);
+        let mut matching = self
+            .entries
+            .range((lower, Unbounded))
+            .map(|(_, entry)| entry)
+            .filter(|entry| filter.matches(entry));
+        let entries = matching
+            .by_ref()
+            .take(page.limit)
+            .cloned()
+            .collect::<Vec<_>>();
+        let next = matching
+            .next()
+            .and(entries.last().map(|entry| entry.urn.clone()));
+
+        Listing { entries, next }
+    }
+
+    fn load(&mut self, storage: &ReadOnly, urn: Urn, tip: ext::Oid) -> Result<(), Error> {
+        match Entry::load(storage, &urn, tip)? {
+            None => self.entries.remove(&urn),
+            Some(entry) => self.entries.insert(urn, entry),
+        };
+        Ok(())
+    }
+
+    fn save(&self, storage: &ReadOnly) -> Result<(), Error> {
+        let mut tmp = tempfile::NamedTempFile::new_in(storage.path())?;
+        serde_json::to_writer(&mut tmp, self)?;
+        tmp.persist(index_path(storage)).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
+/// Update the [`Index`] entry for `urn`, if the index of `storage` exists.
+pub fn update_index<S>(storage: &S, urn: &Urn) -> Result<(), Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    match Index::open(storage)? {
+        None => Ok(()),
+        Some(mut index) => index.update(storage, urn),
+    }
+}
+
+/// Determine the [`Kind`] of the identity at `tip`, without loading it.
+///
+/// Only the payload of the identity document is parsed, so this is much cheaper
+/// than [`Entry::load`]. `None` is returned if the document could not be read,
+/// in which case it is up to [`Entry::load`] to reject the identity.
+fn peek_kind(storage: &ReadOnly, tip: ext::Oid) -> Result<Option<Kind>, Error> {
+    #[derive(Deserialize)]
+    struct Doc {
+        payload: SomePayload,
+    }
+
+    let commit = match storage.read_commit(tip)? {
+        None => return Ok(None),
+        Some(commit) => commit,
+    };
+    let doc = storage.read_tree(commit.tree)?.and_then(|entries| {
+        entries
+            .into_iter()
+            .find(|entry| entry.kind == Some(git2::ObjectType::Blob))
+    });
+    let blob = match doc {
+        None => return Ok(None),
+        Some(doc) => storage.read_blob(doc.oid)?,
+    };
+
+    Ok(blob
+        .and_then(|blob| serde_json::from_slice::<Doc>(&blob).ok())
+        .map(|doc| match doc.payload {
+            SomePayload::Person(_) => Kind::Person,
+            SomePayload::Project(_) => Kind::Project,
+            SomePayload::Org(_) => Kind::Org,
+        }))
+}
+
+/// The namespaces in `storage` with a `rad/id` branch, and the tip of it.
+fn namespaces(storage: &ReadOnly) -> Result<BTreeMap<Urn, ext::Oid>, Error> {
+    let mut namespaces = BTreeMap::new();
+    for name in storage.reference_names_glob(RAD_ID_GLOB.clone())? {
+        let name = name?;
+        if let Ok(urn) = Urn::try_from(name.clone()) {
+            if let Some(tip) = storage.ref_target(&name)? {
+                namespaces.insert(Urn::new(urn.id), tip);
+            }
+        }
+    }
+    Ok(namespaces)
+}
+
+fn index_path(storage: &ReadOnly) -> PathBuf {
+    storage.path().join(INDEX_FILE)
+}
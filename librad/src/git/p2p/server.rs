@@ -14,6 +14,7 @@
 //! [`git-daemon`]: https://git-scm.com/docs/git-daemon
 
 use std::{
+    convert::TryFrom,
     fmt::Debug,
     io,
     path::{Path, PathBuf},
@@ -25,12 +26,19 @@ use futures::{
     io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
 };
 use git2::transport::Service;
-use git_ext::{into_io_err, RefLike, References, UPLOAD_PACK_HEADER};
+use git_ext::{RefLike, UPLOAD_PACK_HEADER};
+use globset::{Glob, GlobSetBuilder};
 use tokio::process::{self, Command};
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
 use super::{
     super::{
+        storage::{
+            self,
+            pool::{self, ReadOnlyConfig},
+            ReadOnly,
+            ReadOnlyPool,
+        },
         types::namespace::{AsNamespace, Namespace},
         Urn,
     },
@@ -41,12 +49,17 @@ use crate::paths::Paths;
 #[derive(Clone)]
 pub struct GitServer {
     monorepo: PathBuf,
+    storage: ReadOnlyPool,
 }
 
 impl GitServer {
     pub fn new(paths: &Paths) -> Self {
         Self {
             monorepo: paths.git_dir().to_path_buf(),
+            storage: ReadOnlyPool::new(
+                ReadOnlyConfig::new(paths.clone()),
+                num_cpus::get_physical(),
+            ),
         }
     }
 }
@@ -78,7 +91,17 @@ impl GitServer {
                 },
                 Service::UploadPackLs => {
                     tracing::info!("upload pack ls");
-                    UploadPack::advertise(&self.monorepo, Namespace::from(repo))?
+                    let namespace = RefLike::from(Namespace::from(repo));
+                    let ids = {
+                        let namespace = namespace.clone();
+                        pool::with_storage(&self.storage, move |storage| {
+                            delegate_ids(storage, &namespace)
+                        })
+                        .await
+                        .map_err(into_io_err)?
+                        .map_err(into_io_err)?
+                    };
+                    UploadPack::advertise(&self.monorepo, namespace, ids)?
                         .run(recv, send)
                         .await?;
                     tracing::info!("upload pack ls done");
@@ -105,7 +128,7 @@ enum UploadPack {
 
 impl UploadPack {
     #[tracing::instrument(level = "debug", err)]
-    fn advertise<N>(repo_path: &Path, namespace: N) -> io::Result<Self>
+    fn advertise<N>(repo_path: &Path, namespace: N, ids: Vec<String>) -> io::Result<Self>
     where
         N: AsNamespace + Clone + Debug,
     {
@@ -119,23 +142,9 @@ impl UploadPack {
                 reflike!("refs/namespaces").join(&namespace)
             ));
 
-        // FIXME: we should probably keep one git2::Repository around, but
-        // `GitServer` needs to be `Sync`
-        let repo = git2::Repository::open_bare(repo_path).map_err(into_io_err)?;
-        let mut refs = References::from_globs(
-            &repo,
-            &[
-                format!("refs/namespaces/{}/refs/rad/ids/*", namespace),
-                format!("refs/namespaces/{}/refs/remotes/**/rad/ids/*", namespace),
-            ],
-        )
-        .map_err(into_io_err)?;
-
-        for id_ref in refs.names() {
-            if let Some(id) = id_ref.ok().and_then(|name| name.split('/').next_back()) {
-                git.arg("-c")
-                    .arg(format!("uploadpack.hiderefs=!refs/namespaces/{}", id));
-            }
+        for id in ids {
+            git.arg("-c")
+                .arg(format!("uploadpack.hiderefs=!refs/namespaces/{}", id));
         }
 
         git_tracing(&mut git);
@@ -224,6 +233,56 @@ impl UploadPack {
     }
 }
 
+/// The namespaces of the identities `namespace` delegates to, whose refs need
+/// to be advertised alongside `namespace`.
+///
+/// This includes the members of orgs `namespace` delegates to. Orgs can not
+/// be members of other orgs, so we don't need to look any further.
+fn delegate_ids(storage: &ReadOnly, namespace: &RefLike) -> Result<Vec<String>, storage::Error> {
+    let mut ids = rad_ids(storage, namespace)?;
+    for id in ids.clone() {
+        // Taken from a ref name, so this is a valid `RefLike`
+        if let Ok(namespace) = RefLike::try_from(id.as_str()) {
+            ids.extend(rad_ids(storage, &namespace)?);
+        }
+    }
+    ids.sort();
+    ids.dedup();
+
+    Ok(ids)
+}
+
+/// The namespaces referred to by the `rad/ids/*` of `namespace`, including the
+/// ones of its remotes.
+fn rad_ids(storage: &ReadOnly, namespace: &RefLike) -> Result<Vec<String>, storage::Error> {
+    let globs = {
+        let mut builder = GlobSetBuilder::new();
+        for glob in &[
+            format!("refs/namespaces/{}/refs/rad/ids/*", namespace),
+            format!("refs/namespaces/{}/refs/remotes/**/rad/ids/*", namespace),
+        ] {
+            builder.add(Glob::new(glob).expect("namespace globs are valid"));
+        }
+        builder.build().expect("namespace globs are valid")
+    };
+
+    let mut ids = Vec::new();
+    for name in storage.reference_names_glob(globs)? {
+        if let Some(id) = name?.as_str().split('/').next_back() {
+            ids.push(id.to_owned());
+        }
+    }
+
+    Ok(ids)
+}
+
+fn into_io_err<E>(e: E) -> io::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
 fn git_tracing(git: &mut Command) {
     git.envs(::std::env::vars().filter(|(key, _)| key.starts_with("GIT_TRACE")));
 }
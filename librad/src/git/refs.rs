@@ -4,7 +4,7 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 use std::{
-    collections::{btree_map, BTreeMap},
+    collections::{btree_map, BTreeMap, BTreeSet},
     convert::TryFrom,
     fmt::{self, Debug},
     iter::FromIterator,
@@ -23,9 +23,9 @@ use serde::{
 use thiserror::Error;
 
 use super::{
-    storage::{self, Storage},
+    storage::{self, backend, glob, Storage},
     tracking,
-    types::{Namespace, Reference, RefsCategory},
+    types::{Many, Namespace, One, Reference, RefsCategory},
 };
 use crate::{
     internal::canonical::{Cjson, CjsonError},
@@ -37,9 +37,29 @@ use crate::{
 pub use crate::identities::git::Urn;
 pub use git_ext::Oid;
 
-/// The depth of the tracking graph (ie. [`Remotes`]) to retain per peer.
-// TODO(kim): bubble up as parameter
-pub const TRACKING_GRAPH_DEPTH: usize = 3;
+/// The default depth of the tracking graph (ie. [`Remotes`]) to retain per
+/// peer.
+///
+/// See [`storage::config::Config::tracking_graph_depth`].
+pub const DEFAULT_TRACKING_GRAPH_DEPTH: usize = 3;
+
+/// The peers in the tracking graph of `urn`, ie. the tracked peers and the
+/// peers they track, up to the depth configured for the `storage` (see
+/// [`storage::config::Config::tracking_graph_depth`]).
+///
+/// This is the set of peers whose remote tracking branches are retained in the
+/// context of `urn`.
+pub fn tracking_graph(storage: &Storage, urn: &Urn) -> Result<BTreeSet<PeerId>, stored::Error> {
+    let depth = storage
+        .config()?
+        .tracking_graph_depth()
+        .map_err(storage::Error::from)?;
+    Ok(Refs::compute(storage, urn, depth)?
+        .remotes
+        .flatten()
+        .copied()
+        .collect())
+}
 
 /// The transitive tracking graph.
 // **NOTE**: A recursion limit of 128 is imposed by `serde_json` when deserialising.
@@ -170,7 +190,7 @@ pub mod signing {
 pub mod stored {
     use super::*;
 
-    pub(super) const BLOB_PATH: &str = "refs"; // `Path::new` ain't no const fn :(
+    pub(crate) const BLOB_PATH: &str = "refs"; // `Path::new` ain't no const fn :(
 
     #[derive(Debug, Error)]
     #[non_exhaustive]
@@ -196,9 +216,21 @@ pub mod stored {
         #[error(transparent)]
         Store(#[from] storage::Error),
 
+        #[error("storage backend error")]
+        Backend(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
         #[error(transparent)]
         Git(#[from] git2::Error),
     }
+
+    impl Error {
+        pub(super) fn backend<E>(e: E) -> Self
+        where
+            E: std::error::Error + Send + Sync + 'static,
+        {
+            Self::Backend(Box::new(e))
+        }
+    }
 }
 
 /// The published state of a local repository.
@@ -225,53 +257,73 @@ pub struct Refs {
 
 impl Refs {
     /// Compute the [`Refs`] from the current storage state at [`Urn`].
+    ///
+    /// The transitive [`Remotes`] of tracked peers are retained up to `depth`
+    /// levels, and omitted entirely if their [`tracking::Policy`] doesn't
+    /// follow remotes.
     #[tracing::instrument(level = "debug", skip(storage), err)]
-    pub fn compute(storage: &Storage, urn: &Urn) -> Result<Self, stored::Error> {
+    pub fn compute<B>(storage: &B, urn: &Urn, depth: usize) -> Result<Self, stored::Error>
+    where
+        B: backend::ReadRefs + backend::ReadObjects + backend::ReadConfig,
+    {
         let namespace = Namespace::from(urn);
         let namespace_prefix = format!("refs/namespaces/{}/", namespace);
 
-        fn peeled(r: Result<git2::Reference, storage::Error>) -> Option<(String, git2::Oid)> {
-            r.ok().and_then(|head| {
-                head.name()
-                    .and_then(|name| head.target().map(|target| (name.to_owned(), target)))
-            })
-        }
-
-        let refined = |(name, oid): (String, git2::Oid)|
-             -> Result<(reference::OneLevel, Oid), stored::Error>
-        {
-            let name = reference::RefLike::try_from(
-                name.strip_prefix(&namespace_prefix).unwrap_or(&name)
-            )?;
-            Ok((reference::OneLevel::from(name), oid.into()))
+        // Direct refs matching `refs`, relative to the namespace
+        let peeled = |refs: Reference<Many>| -> Result<Vec<(String, Oid)>, stored::Error> {
+            let glob = glob::RefspecMatcher::from(reference::RefspecPattern::from(&refs));
+            let mut peeled = Vec::new();
+            for name in storage.ref_names(&glob).map_err(stored::Error::backend)? {
+                if storage
+                    .symbolic_ref_target(&name)
+                    .map_err(stored::Error::backend)?
+                    .is_some()
+                {
+                    continue;
+                }
+                if let Some(target) = storage.ref_target(&name).map_err(stored::Error::backend)? {
+                    peeled.push((name.as_str().to_owned(), target))
+                }
+            }
+            Ok(peeled)
         };
 
-        let heads = storage
-            .references(&Reference::heads(namespace.clone(), None))?
-            .filter_map(peeled)
+        let refined =
+            |(name, oid): (String, Oid)| -> Result<(reference::OneLevel, Oid), stored::Error> {
+                let name = reference::RefLike::try_from(
+                    name.strip_prefix(&namespace_prefix).unwrap_or(&name),
+                )?;
+                Ok((reference::OneLevel::from(name), oid))
+            };
+
+        let heads = peeled(Reference::heads(namespace.clone(), None))?
+            .into_iter()
             .map(refined)
             .collect::<Result<_, _>>()?;
-        let rad = storage
-            .references(&Reference::rads(namespace.clone(), None))?
-            .filter_map(peeled)
+        let rad = peeled(Reference::rads(namespace.clone(), None))?
+            .into_iter()
             .filter(|(name, _)| !name.ends_with("rad/signed_refs"))
             .map(refined)
             .collect::<Result<_, _>>()?;
-        let tags = storage
-            .references(&Reference::tags(namespace.clone(), None))?
-            .filter_map(peeled)
+        let tags = peeled(Reference::tags(namespace.clone(), None))?
+            .into_iter()
             .map(refined)
             .collect::<Result<_, _>>()?;
-        let notes = storage
-            .references(&Reference::notes(namespace, None))?
-            .filter_map(peeled)
+        let notes = peeled(Reference::notes(namespace.clone(), None))?
+            .into_iter()
             .map(refined)
             .collect::<Result<_, _>>()?;
 
         let mut remotes = tracking::tracked(storage, urn)?.collect::<Remotes<PeerId>>();
         for (peer, tracked) in remotes.iter_mut() {
-            if let Some(refs) = Self::load(storage, urn, *peer)? {
-                *tracked = Box::new(refs.remotes.cutoff(TRACKING_GRAPH_DEPTH));
+            let follow_remotes =
+                tracking::policy(storage, urn, *peer)?.map_or(true, |policy| policy.follow_remotes);
+            if !follow_remotes {
+                continue;
+            }
+            let signed_refs = Reference::rad_signed_refs(namespace.clone(), *peer);
+            if let Some(refs) = Self::load_signed(storage, &signed_refs, peer)? {
+                *tracked = Box::new(refs.remotes.cutoff(depth));
             }
         }
 
@@ -299,19 +351,44 @@ impl Refs {
     {
         let peer = peer.into();
         let signer = peer.unwrap_or_else(|| PeerId::from_signer(storage.signer()));
+        let branch = Reference::rad_signed_refs(Namespace::from(urn), peer);
 
-        let blob_ref = Reference::rad_signed_refs(Namespace::from(urn), peer);
+        Self::load_signed(storage, &branch, &signer)
+    }
+
+    /// Load the [`Refs`] stored at the `rad/signed_refs` `branch`, and verify
+    /// they were signed by `signer`.
+    ///
+    /// If the blob where the signed [`Refs`] are expected to be stored is not
+    /// found, `None` is returned.
+    #[tracing::instrument(skip(backend), err)]
+    pub fn load_signed<B>(
+        backend: &B,
+        branch: &Reference<One>,
+        signer: &PeerId,
+    ) -> Result<Option<Self>, stored::Error>
+    where
+        B: backend::ReadRefs + backend::ReadObjects,
+    {
         let blob_path = Path::new(stored::BLOB_PATH);
 
         tracing::debug!(
             "loading signed_refs from {} {}",
-            &blob_ref,
+            &branch,
             blob_path.display()
         );
 
-        let maybe_blob = storage.blob(&blob_ref, &blob_path)?;
+        let maybe_blob = match backend
+            .ref_target(&reference::RefLike::from(branch))
+            .map_err(stored::Error::backend)?
+        {
+            None => None,
+            Some(tip) => backend
+                .read_blob_at(tip, blob_path)
+                .map_err(stored::Error::backend)?,
+        };
         maybe_blob
-            .map(|blob| Signed::from_json(blob.content(), &signer).map(|signed| signed.refs))
+            .map(|blob| Signed::from_json(&blob, signer).map(|signed| signed.refs))
             .transpose()
             .map_err(stored::Error::from)
     }
@@ -322,12 +399,19 @@ impl Refs {
     /// If the result of [`Self::compute`] is the same as the alread-stored
     /// [`Refs`], no commit is made and `None` is returned. Otherwise, the
     /// new and persisted [`Refs`] are returned in a `Some`.
+    ///
+    /// The depth of the tracking graph to retain per peer is read from the
+    /// storage config, see [`storage::config::Config::tracking_graph_depth`].
     #[tracing::instrument(skip(storage), err)]
     pub fn update(storage: &Storage, urn: &Urn) -> Result<Option<Self>, stored::Error> {
         let branch = Reference::rad_signed_refs(Namespace::from(urn), None);
         tracing::debug!("updating signed refs for {}", branch);
 
-        let signed_refs = Self::compute(storage, urn)?.sign(storage.signer())?;
+        let depth = storage
+            .config()?
+            .tracking_graph_depth()
+            .map_err(storage::Error::from)?;
+        let signed_refs = Self::compute(storage, urn, depth)?.sign(storage.signer())?;
 
         let raw_git = storage.as_raw();
 
@@ -358,7 +442,7 @@ impl Refs {
         let commit_id = {
             let author = raw_git.signature()?;
             raw_git.commit(
-                Some(reference::RefLike::from(&branch).as_str()),
+                None,
                 &author,
                 &author,
                 &format!("Update rad/signed_refs for {}", urn),
@@ -366,6 +450,17 @@ impl Refs {
                 &parent.iter().collect::<Vec<&git2::Commit>>(),
             )?
         };
+
+        // Only move the branch if nobody else did in the meantime
+        let mut tx = storage.transaction()?;
+        tx.update(
+            &branch,
+            parent.as_ref().map(|parent| parent.id()),
+            commit_id,
+            "update signed refs",
+        )
+        .and_then(|()| tx.commit())
+        .map_err(storage::Error::from)?;
         tracing::trace!(
             "updated signed refs at {} to {}: {:?}",
             branch,
@@ -4,14 +4,13 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 use std::{
-    collections::{BTreeMap, BTreeSet},
+    collections::{btree_map, BTreeMap, BTreeSet},
     convert::{TryFrom, TryInto},
-    iter,
     net::SocketAddr,
 };
 
 use either::Either;
-use git_ext::{self as ext, is_exists_err};
+use git_ext::{self as ext, is_not_found_err};
 use nonempty::NonEmpty;
 use std_ext::result::ResultExt as _;
 use thiserror::Error;
@@ -25,18 +24,37 @@ use super::{
     types::{reference, Force, Namespace, Reference},
 };
 use crate::{
-    identities::git::{Person, Project, SomeIdentity, VerifiedPerson, VerifiedProject},
+    identities::git::{
+        Doc,
+        Identity,
+        IndirectDelegation,
+        Org,
+        Person,
+        Project,
+        SomeIdentity,
+        VerifiedPerson,
+        VerifiedProject,
+    },
     peer::PeerId,
 };
 
 pub use crate::identities::git::Urn;
 
+pub mod plan;
+pub use plan::{plan, Plan, RefUpdate};
+
+pub mod policy;
+pub use policy::{Decision, Policy, ReplicationPolicy};
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Error {
     #[error("cannot replicate from self")]
     SelfReplication,
 
+    #[error("no providers to replicate from")]
+    NoProviders,
+
     #[error("identity not found")]
     MissingIdentity,
 
@@ -75,11 +93,26 @@ pub enum Error {
 
     #[error(transparent)]
     Store(#[from] storage::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct Config {
     pub fetch_limit: fetch::Limit,
+    /// The [`ReplicationPolicy`] consulted for each ref updated when fetching
+    /// the heads of tracked peers.
+    pub policy: Policy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fetch_limit: fetch::Limit::default(),
+            policy: Policy::default(),
+        }
+    }
 }
 
 pub enum Replication {
@@ -95,13 +128,47 @@ pub enum Replication {
     },
 }
 
-/// The result of replicating an identity will tell us if we are at the latest
-/// tip or, in the case of our peer being a delegate, if we are behind and
-/// require updating the document.
+/// The result of replicating an identity.
 #[derive(Debug)]
-pub enum ReplicateResult {
+pub struct ReplicateResult {
+    /// Whether we are at the latest tip of the identity.
+    pub identity: IdentityStatus,
+    /// The refs in the namespace of the identity which were changed by the
+    /// replication, and how.
+    pub updated: BTreeMap<ext::RefLike, Updated>,
+}
+
+impl ReplicateResult {
+    /// The refs which were not fast-forwarded, ie. whose history was rewritten.
+    pub fn rewritten(&self) -> impl Iterator<Item = &ext::RefLike> {
+        self.updated
+            .iter()
+            .filter_map(|(name, updated)| matches!(updated, Updated::Forced { .. }).then_some(name))
+    }
+}
+
+/// Tells us if we are at the latest tip or, in the case of our peer being a
+/// delegate, if we are behind and require updating the document, or ahead of
+/// the other delegates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdentityStatus {
     Latest,
     Behind,
+    Ahead,
+}
+
+/// How a ref was changed by the replication.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Updated {
+    /// The ref did not exist before.
+    New { new: ext::Oid },
+    /// The new target is a descendant of the old one.
+    FastForward { old: ext::Oid, new: ext::Oid },
+    /// The new target is not a descendant of the old one, ie. the history was
+    /// rewritten.
+    Forced { old: ext::Oid, new: ext::Oid },
+    /// The ref was removed.
+    Deleted { old: ext::Oid },
 }
 
 /// Attempt to fetch `urn` from `remote_peer`, optionally supplying
@@ -147,16 +214,31 @@ where
     Addrs: IntoIterator<Item = SocketAddr>,
 {
     let urn = Urn::new(urn.id);
-    let local_peer_id = storage.peer_id();
-
-    if local_peer_id == &remote_peer {
+    if storage.peer_id() == &remote_peer {
         return Err(Error::SelfReplication);
     }
 
     let mut fetcher = storage.fetcher(urn.clone(), remote_peer, addr_hints)?;
-    let (result, mut remove) = match replication(
+    replicate_with(storage, &mut fetcher, config, whoami, urn, remote_peer)
+}
+
+/// [`replicate`], using the given [`fetch::DefaultFetcher`].
+///
+/// This allows to replicate from sources other than the network, as long as
+/// they present the refs like the monorepo of `remote_peer` would.
+pub(super) fn replicate_with(
+    storage: &Storage,
+    fetcher: &mut fetch::DefaultFetcher,
+    config: Config,
+    whoami: Option<LocalIdentity>,
+    urn: Urn,
+    remote_peer: PeerId,
+) -> Result<ReplicateResult, Error> {
+    let local_peer_id = storage.peer_id();
+    let before = snapshot(storage, &urn)?;
+    let (identity, mut remove) = match replication(
         storage,
-        &mut fetcher,
+        fetcher,
         config.fetch_limit,
         urn.clone(),
         remote_peer,
@@ -168,6 +250,7 @@ where
         } => {
             let allowed = match identity {
                 SomeIdentity::Project(proj) => {
+                    project::fetch_orgs(fetcher, &config, &proj)?;
                     let delegates = project::delegate_views(storage, proj, Some(remote_peer))?;
                     let allowed = delegates.keys().copied().collect();
                     let rad_id = unsafe_into_urn(
@@ -175,14 +258,7 @@ where
                     );
                     let proj = identities::project::verify(storage, &rad_id)?
                         .ok_or(Error::MissingIdentity)?;
-                    project::ensure_setup(
-                        storage,
-                        &mut fetcher,
-                        config.fetch_limit,
-                        delegates,
-                        &rad_id,
-                        proj,
-                    )?;
+                    project::ensure_setup(storage, fetcher, &config, delegates, &rad_id, proj)?;
                     allowed
                 },
                 SomeIdentity::Person(person) => {
@@ -197,6 +273,13 @@ where
                         .map(PeerId::from)
                         .collect()
                 },
+                SomeIdentity::Org(org) => {
+                    let rad_id = unsafe_into_urn(
+                        Reference::rad_id(Namespace::from(&org.urn())).with_remote(remote_peer),
+                    );
+                    org::ensure_setup(storage, &rad_id, Some(remote_peer), org.clone())?;
+                    project::all_delegates(&org)
+                },
             };
 
             // Symref `rad/self` if a `LocalIdentity` was given
@@ -205,7 +288,7 @@ where
             }
 
             Ok::<_, Error>((
-                ReplicateResult::Latest,
+                IdentityStatus::Latest,
                 fetched_peers.difference(&allowed).copied().collect(),
             ))
         },
@@ -223,8 +306,8 @@ where
                     let rad_id = unsafe_into_urn(Reference::rad_id(Namespace::from(&urn)));
                     let result = project::ensure_setup(
                         &storage,
-                        &mut fetcher,
-                        config.fetch_limit,
+                        fetcher,
+                        &config,
                         delegate_views,
                         &rad_id,
                         proj,
@@ -239,7 +322,15 @@ where
                     let rad_id = unsafe_into_urn(Reference::rad_id(Namespace::from(&person.urn())));
                     person::ensure_setup(storage, &rad_id, person)?;
                     (
-                        ReplicateResult::Latest,
+                        IdentityStatus::Latest,
+                        tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>(),
+                    )
+                },
+                SomeIdentity::Org(org) => {
+                    let rad_id = unsafe_into_urn(Reference::rad_id(Namespace::from(&org.urn())));
+                    org::ensure_setup(storage, &rad_id, None, org)?;
+                    (
+                        IdentityStatus::Latest,
                         tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>(),
                     )
                 },
@@ -256,6 +347,19 @@ where
     // Remove any remote tracking branches we don't need
     prune(storage, &urn, remove.iter())?;
 
+    let result = ReplicateResult {
+        identity,
+        updated: classify(storage, before, snapshot(storage, &urn)?)?,
+    };
+    for name in result.rewritten() {
+        tracing::warn!(name = %name, "history was rewritten");
+    }
+
+    // Keep the namespace index up-to-date, if there is one
+    if let Err(e) = identities::query::update_index(storage, &urn) {
+        tracing::warn!(err = %e, "failed to update namespace index");
+    }
+
     // TODO: At this point, the tracking graph may have changed, and/or we
     // created top-level person namespaces. We will eventually converge, but
     // perhaps we'd want to return some kind of continuation here, so the caller
@@ -263,6 +367,392 @@ where
     Ok(result)
 }
 
+/// The result of [`replicate_many`].
+#[derive(Debug)]
+pub struct ReplicateMany {
+    /// The first provider from which the identity was replicated
+    /// successfully.
+    pub primary: PeerId,
+    /// The result of replicating the identity from [`Self::primary`].
+    ///
+    /// [`ReplicateResult::updated`] accounts for all providers.
+    pub result: ReplicateResult,
+    /// The tracked peers whose heads were requested from each provider during
+    /// the fan-out phase.
+    pub fetched: BTreeMap<PeerId, BTreeSet<PeerId>>,
+    /// Providers which failed, either when attempting to replicate the
+    /// identity, or during the fan-out phase.
+    pub failed: BTreeMap<PeerId, Error>,
+    /// Tracked peers for which the remote tracking branches do not (yet) match
+    /// their `rad/signed_refs`.
+    pub unverified: BTreeSet<PeerId>,
+}
+
+/// Attempt to fetch `urn` from several `providers` at once.
+///
+/// The `providers` are tried in the order given until [`replicate`] succeeds
+/// for one of them. The providers are not ranked: the first successful one is
+/// the [`ReplicateMany::primary`] provider, and all identity verification and
+/// tracking decisions are based on what it advertised. Callers which know
+/// which providers are more up-to-date should order them accordingly.
+///
+/// Afterwards, the tracked peers (including the delegates) of `urn` whose
+/// remote tracking branches do not match their `rad/signed_refs` are
+/// distributed among the remaining providers, which are then fetched from
+/// concurrently. Each provider is only asked for the heads which match the
+/// signed refs we already have, so no unverified data is admitted. Signed refs
+/// which don't descend from the ones we already have are rejected, so a
+/// provider with a stale view of a peer can't move them backwards. Finally,
+/// the remote tracking branches of every tracked peer are checked against its
+/// `rad/signed_refs`, and our own `rad/signed_refs` are updated.
+///
+/// Failures of individual providers are not fatal, but reported in
+/// [`ReplicateMany::failed`]. If no provider succeeds in replicating the
+/// identity, the last error is returned.
+#[allow(clippy::unit_arg)]
+#[tracing::instrument(skip(storage, whoami, providers), err)]
+pub fn replicate_many<Providers>(
+    storage: &Storage,
+    config: Config,
+    whoami: Option<LocalIdentity>,
+    urn: Urn,
+    providers: Providers,
+) -> Result<ReplicateMany, Error>
+where
+    Providers: IntoIterator<Item = (PeerId, Vec<SocketAddr>)>,
+{
+    let urn = Urn::new(urn.id);
+    let local_peer_id = storage.peer_id();
+    let before = snapshot(storage, &urn)?;
+
+    let mut providers = providers
+        .into_iter()
+        .filter(|(peer, _)| peer != local_peer_id)
+        .collect::<Vec<_>>()
+        .into_iter();
+    let mut failed = BTreeMap::new();
+    let mut last_attempt = None;
+
+    let (primary, mut result) = loop {
+        match providers.next() {
+            None => {
+                return Err(last_attempt
+                    .and_then(|peer| failed.remove(&peer))
+                    .unwrap_or(Error::NoProviders))
+            },
+            Some((peer, addr_hints)) => {
+                match replicate(
+                    storage,
+                    config.clone(),
+                    whoami.clone(),
+                    urn.clone(),
+                    peer,
+                    addr_hints,
+                ) {
+                    Ok(result) => break (peer, result),
+                    Err(err) => {
+                        tracing::warn!(provider = %peer, err = %err, "replication failed");
+                        failed.insert(peer, err);
+                        last_attempt = Some(peer);
+                    },
+                }
+            },
+        }
+    };
+    let rest = providers.collect::<Vec<_>>();
+
+    let mut pending = BTreeSet::new();
+    for peer in tracking::tracked(storage, &urn)? {
+        if !verify_signed_refs(storage, &urn, peer)? {
+            pending.insert(peer);
+        }
+    }
+
+    let assignments = assign_providers(&rest, pending);
+    let fetched = assignments
+        .iter()
+        .map(|(provider, (_, peers))| (*provider, peers.clone()))
+        .collect();
+
+    let handles = assignments
+        .into_iter()
+        .map(|(provider, (addr_hints, peers))| {
+            let storage = storage.reopen();
+            let urn = urn.clone();
+            let config = config.clone();
+            let handle = std::thread::spawn(move || {
+                let storage = storage?;
+                fetch_signed_heads(&storage, &config, &urn, provider, addr_hints, peers)
+            });
+            (provider, handle)
+        })
+        .collect::<Vec<_>>();
+
+    for (provider, handle) in handles {
+        match handle.join() {
+            Ok(Ok(())) => {},
+            Ok(Err(err)) => {
+                tracing::warn!(provider = %provider, err = %err, "fetch failed");
+                failed.insert(provider, err);
+            },
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    let mut unverified = BTreeSet::new();
+    for peer in tracking::tracked(storage, &urn)? {
+        if !verify_signed_refs(storage, &urn, peer)? {
+            unverified.insert(peer);
+        }
+    }
+
+    Refs::update(storage, &urn)?;
+    result.updated = classify(storage, before, snapshot(storage, &urn)?)?;
+
+    Ok(ReplicateMany {
+        primary,
+        result,
+        fetched,
+        failed,
+        unverified,
+    })
+}
+
+/// Distribute `peers` among `providers`.
+///
+/// A peer which is itself a provider is fetched from directly, the rest are
+/// assigned round-robin.
+fn assign_providers(
+    providers: &[(PeerId, Vec<SocketAddr>)],
+    peers: BTreeSet<PeerId>,
+) -> BTreeMap<PeerId, (Vec<SocketAddr>, BTreeSet<PeerId>)> {
+    let mut assignments = BTreeMap::new();
+    if providers.is_empty() {
+        return assignments;
+    }
+
+    let mut round_robin = providers.iter().cycle();
+    for peer in peers {
+        let (provider, addr_hints) = providers
+            .iter()
+            .find(|(provider, _)| provider == &peer)
+            .unwrap_or_else(|| round_robin.next().expect("providers is not empty"));
+        assignments
+            .entry(*provider)
+            .or_insert_with(|| (addr_hints.clone(), BTreeSet::new()))
+            .1
+            .insert(peer);
+    }
+
+    assignments
+}
+
+/// Fetch the heads of `peers` from `provider`, limited to what the peers have
+/// published in their `rad/signed_refs`.
+///
+/// To not admit any unverified data, this happens in two steps: first, only
+/// the `rad/signed_refs` of the `peers` are fetched, and their signatures
+/// checked. Then, only the heads matching the signed refs are fetched. All
+/// updates for a peer whose remote tracking branches don't match its
+/// `rad/signed_refs` afterwards are rolled back.
+#[allow(clippy::unit_arg)]
+#[tracing::instrument(level = "debug", skip(storage, addr_hints), err)]
+fn fetch_signed_heads(
+    storage: &Storage,
+    config: &Config,
+    urn: &Urn,
+    provider: PeerId,
+    addr_hints: Vec<SocketAddr>,
+    peers: BTreeSet<PeerId>,
+) -> Result<(), Error> {
+    if peers.is_empty() {
+        return Ok(());
+    }
+
+    let mut fetcher = storage.fetcher(urn.clone(), provider, addr_hints)?;
+    let signed_refs = fetcher
+        .fetch(fetch::Fetchspecs::SignedRefs {
+            remotes: peers.clone(),
+            limit: config.fetch_limit,
+        })
+        .map_err(|e| Error::Fetch(e.into()))?;
+    let stale = reject_stale_signed_refs(storage, urn, &signed_refs)?;
+
+    let mut tracked_sigrefs = BTreeMap::new();
+    for peer in peers.into_iter().filter(|peer| !stale.contains(peer)) {
+        match Refs::load(storage, urn, peer) {
+            Ok(Some(refs)) => {
+                tracked_sigrefs.insert(peer, refs);
+            },
+            Ok(None) => {},
+            Err(err) => {
+                tracing::warn!(peer = %peer, err = %err, "invalid signed refs");
+                roll_back(storage, urn, &signed_refs, peer)?;
+            },
+        }
+    }
+
+    if tracked_sigrefs.is_empty() {
+        return Ok(());
+    }
+    let policies = tracking_policies(storage, urn, tracked_sigrefs.keys())?;
+
+    let heads = fetcher
+        .fetch(fetch::Fetchspecs::SignedHeads {
+            tracked_sigrefs: tracked_sigrefs.clone(),
+            policies,
+            limit: config.fetch_limit,
+        })
+        .map_err(|e| Error::Fetch(e.into()))?;
+    apply_policy(storage, &config.policy, urn, &heads)?;
+
+    for peer in tracked_sigrefs.keys() {
+        if !verify_signed_refs(storage, urn, *peer)? {
+            tracing::warn!(peer = %peer, "fetched refs do not match signed refs");
+            roll_back(storage, urn, &heads, *peer)?;
+            roll_back(storage, urn, &signed_refs, *peer)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Roll back the `rad/signed_refs` updated by a fetch which don't descend from
+/// the ones we had before, ie. which were fetched from a provider with a stale
+/// view of the peer they belong to.
+///
+/// Returns the peers whose `rad/signed_refs` were rolled back.
+fn reject_stale_signed_refs(
+    storage: &Storage,
+    urn: &Urn,
+    result: &fetch::FetchResult,
+) -> Result<BTreeSet<PeerId>, Error> {
+    let repo = storage.as_raw();
+    let mut stale = BTreeSet::new();
+    for (name, new) in result.updated_tips.iter() {
+        let peer = match remote_of(urn, name) {
+            Some((peer, rest)) if rest == "rad/signed_refs" => peer,
+            _ => continue,
+        };
+        if let Some(old) = result.previous_tips.get(name) {
+            if !repo.graph_descendant_of(**new, **old).unwrap_or(false) {
+                tracing::warn!(peer = %peer, old = %old, new = %new, "rejecting stale signed refs");
+                roll_back_ref(storage, name, Some(*old))?;
+                stale.insert(peer);
+            }
+        }
+    }
+
+    Ok(stale)
+}
+
+/// Restore the refs of `peer` in the namespace of `urn` updated by a fetch to
+/// their previous targets, deleting the ones which didn't exist before.
+fn roll_back(
+    storage: &Storage,
+    urn: &Urn,
+    result: &fetch::FetchResult,
+    peer: PeerId,
+) -> Result<(), Error> {
+    for name in result.updated_tips.keys() {
+        if matches!(remote_of(urn, name), Some((remote, _)) if remote == peer) {
+            roll_back_ref(storage, name, result.previous_tips.get(name).copied())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Restore `name` to `old`, or delete it if it didn't exist before.
+fn roll_back_ref(
+    storage: &Storage,
+    name: &ext::RefLike,
+    old: Option<ext::Oid>,
+) -> Result<(), Error> {
+    match old {
+        Some(old) => storage
+            .as_raw()
+            .reference(name.as_str(), *old, true, "rolled back")
+            .map(|_| ())
+            .map_err(|e| Error::Store(e.into())),
+        None => prune_reference(storage, name),
+    }
+}
+
+/// Split `name` into the peer and the name relative to the peer's remote, if it
+/// is a remote tracking branch in the namespace of `urn`.
+fn remote_of(urn: &Urn, name: &ext::RefLike) -> Option<(PeerId, String)> {
+    use std::str::FromStr;
+
+    let remotes = reflike!("refs/namespaces")
+        .join(urn)
+        .join(reflike!("refs/remotes"));
+    let suffix = name.strip_prefix(&remotes).ok()?;
+    let mut components = suffix.as_str().splitn(2, '/');
+    let peer = PeerId::from_str(components.next()?).ok()?;
+
+    Some((peer, components.next().unwrap_or_default().to_owned()))
+}
+
+/// Determine if the remote tracking branches of `peer` match what `peer` has
+/// published in its `rad/signed_refs`, as far as its tracking
+/// [`tracking::Policy`] allows.
+///
+/// If no `rad/signed_refs` are found for `peer`, `false` is returned.
+fn verify_signed_refs(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error> {
+    let refs = match Refs::load(storage, urn, peer)? {
+        None => return Ok(false),
+        Some(refs) => refs,
+    };
+    let policy = tracking::policy(storage, urn, peer)?.unwrap_or_default();
+
+    for ((name, target), category) in refs
+        .iter_categorised()
+        .filter(|((name, _), category)| policy.allows(*category, name))
+    {
+        let branch = Reference {
+            remote: Some(peer),
+            category,
+            name: ext::RefLike::from(name.clone()),
+            namespace: Some(Namespace::from(urn)),
+        };
+        match storage.reference(&branch)? {
+            Some(reference) if reference.target() == Some(**target) => continue,
+            _ => return Ok(false),
+        }
+    }
+
+    Ok(true)
+}
+
+/// Perform maintenance on the `storage`.
+///
+/// For every identity found in the `storage`, the remote tracking branches of
+/// peers which are not part of its tracking graph (anymore) are removed (see
+/// [`tracking::prune_dangling`]). Afterwards, the objects no longer reachable
+/// are garbage collected, and the rest repacked (see [`Storage::gc`]).
+///
+/// Returns the peers which were pruned, per identity.
+#[tracing::instrument(skip(storage), err)]
+pub fn maintain(storage: &Storage) -> Result<BTreeMap<Urn, BTreeSet<PeerId>>, Error> {
+    let urns = identities::any::list(storage)?
+        .map(|identity| identity.map(|identity| identity.urn()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut pruned = BTreeMap::new();
+    for urn in urns {
+        let peers = tracking::prune_dangling(storage, &urn)?;
+        if !peers.is_empty() {
+            tracing::info!(urn = %urn, peers = ?peers, "pruned dangling remotes");
+            pruned.insert(urn, peers);
+        }
+    }
+
+    storage.gc()?;
+
+    Ok(pruned)
+}
+
 /// Identify the type of replication case we're in -- whether it's a new
 /// identity which we're cloning onto our machine or an existing identity that
 /// we are updating.
@@ -289,7 +779,7 @@ fn replication(
         let fetched_peers = fetcher
             .fetch(fetch::Fetchspecs::PeekAll { limit })
             .map_err(|e| Error::Fetch(e.into()))
-            .and_then(project::fetched_peers)?;
+            .and_then(|result| project::fetched_peers(storage, result))?;
 
         let remote_ident =
             unsafe_into_urn(Reference::rad_id(Namespace::from(&urn)).with_remote(remote_peer));
@@ -309,7 +799,9 @@ fn replication(
 
                 remotes
             },
-            SomeIdentity::Person(_) => tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>(),
+            SomeIdentity::Person(_) | SomeIdentity::Org(_) => {
+                tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>()
+            },
         };
 
         let _ = fetcher
@@ -334,11 +826,47 @@ fn unsafe_into_urn(reference: Reference<git_ext::RefLike>) -> Urn {
 #[allow(clippy::unit_arg)]
 #[tracing::instrument(level = "trace", skip(storage), err)]
 fn ensure_rad_id(storage: &Storage, urn: &Urn, tip: ext::Oid) -> Result<(), Error> {
+    let mut tx = storage.transaction()?;
     identities::common::IdRef::from(urn)
-        .create(storage, tip)
+        .create_in(&mut tx, tip)
+        .and_then(|()| tx.commit())
         .map_err(|e| Error::Store(e.into()))
 }
 
+/// Adopt the `rad/id` of `delegate_urn` at `tip`, if we don't have it yet, and
+/// point the `rad/ids/<delegate_urn>` of `delegating_urn` to it.
+#[allow(clippy::unit_arg)]
+#[tracing::instrument(level = "trace", skip(storage), err)]
+fn adopt_delegate(
+    storage: &Storage,
+    delegate_urn: &Urn,
+    tip: ext::Oid,
+    delegating_urn: &Urn,
+) -> Result<(), Error> {
+    let mut tx = storage.transaction()?;
+    identities::common::IdRef::from(delegate_urn)
+        .create_in(&mut tx, tip)
+        .map_err(storage::Error::from)?;
+    Reference::try_from(delegate_urn)
+        .map_err(|e| Error::RefFromUrn {
+            urn: delegate_urn.clone(),
+            source: e,
+        })?
+        .symbolic_ref::<_, PeerId>(
+            Reference::rad_delegate(Namespace::from(delegating_urn), delegate_urn),
+            Force::False,
+        )
+        .create_in(&mut tx)
+        .or_matches(
+            |e| matches!(e, storage::transaction::Error::Exists(_)),
+            || Ok(()),
+        )
+        .map_err(storage::Error::from)?;
+    tx.commit().map_err(storage::Error::from)?;
+
+    Ok(())
+}
+
 /// Untrack the list of `PeerId`s, which also has the side-effect of removing
 /// that peer's remote references in the storage.
 ///
@@ -370,6 +898,277 @@ fn prune<'a>(
     Ok(())
 }
 
+/// Record the targets of all direct refs in the namespace of `urn`.
+fn snapshot(storage: &Storage, urn: &Urn) -> Result<BTreeMap<ext::RefLike, ext::Oid>, Error> {
+    let mut refs = BTreeMap::new();
+    for reference in storage.references_glob(storage::glob::RefspecMatcher::from(
+        reflike!("refs/namespaces")
+            .join(urn)
+            .with_pattern_suffix(refspec_pattern!("*")),
+    ))? {
+        let reference = reference?;
+        if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+            if let Ok(name) = ext::RefLike::try_from(name) {
+                refs.insert(name, target.into());
+            }
+        }
+    }
+
+    Ok(refs)
+}
+
+/// Compare two [`snapshot`]s, determining how each ref was changed.
+fn classify(
+    storage: &Storage,
+    mut before: BTreeMap<ext::RefLike, ext::Oid>,
+    after: BTreeMap<ext::RefLike, ext::Oid>,
+) -> Result<BTreeMap<ext::RefLike, Updated>, Error> {
+    let repo = storage.as_raw();
+    let mut updated = BTreeMap::new();
+    for (name, new) in after {
+        let update = match before.remove(&name) {
+            None => Updated::New { new },
+            Some(old) if old == new => continue,
+            Some(old) => {
+                // Non-commits (eg. annotated tags) are never fast-forwards
+                let is_ff = repo.graph_descendant_of(*new, *old).unwrap_or(false);
+                if is_ff {
+                    Updated::FastForward { old, new }
+                } else {
+                    Updated::Forced { old, new }
+                }
+            },
+        };
+        updated.insert(name, update);
+    }
+    updated.extend(
+        before
+            .into_iter()
+            .map(|(name, old)| (name, Updated::Deleted { old })),
+    );
+
+    Ok(updated)
+}
+
+/// Consult the `policy` about each remote tracking branch of `urn` updated by a
+/// fetch, and roll back or quarantine the updates it doesn't accept.
+///
+/// The `rad` refs are exempt from the policy.
+///
+/// **Note**: the policy operates on refs only. Its decisions depend on the
+/// objects introduced by an update (see [`update_size`]), so by the time it is
+/// consulted, the fetched pack is already in the object database. Rolling
+/// back an update merely leaves its objects unreachable, they are collected by
+/// the next [`maintain`] run.
+#[allow(clippy::unit_arg)]
+#[tracing::instrument(level = "trace", skip(storage, policy, result), err)]
+fn apply_policy(
+    storage: &Storage,
+    policy: &Policy,
+    urn: &Urn,
+    result: &fetch::FetchResult,
+) -> Result<(), Error> {
+    let repo = storage.as_raw();
+    let mut size_limits = BTreeMap::new();
+
+    // The history we had before the fetch: the previous targets of the updated
+    // refs, and the refs the fetch didn't touch
+    let known = snapshot(storage, urn)?
+        .into_iter()
+        .filter(|(name, _)| !result.updated_tips.contains_key(name))
+        .map(|(_, oid)| oid)
+        .chain(result.previous_tips.values().copied())
+        .collect::<BTreeSet<_>>();
+
+    for (name, new) in result.updated_tips.iter() {
+        let peer = match remote_of(urn, name) {
+            Some((_, rest)) if rest.starts_with("rad/") => continue,
+            Some((peer, _)) => peer,
+            None => continue,
+        };
+
+        let old = result.previous_tips.get(name).copied();
+        let remaining = match size_limits.entry(peer) {
+            btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(tracking::policy(storage, urn, peer)?.and_then(|p| p.size_limit))
+            },
+        };
+        let size = update_size(repo, &known, *new, *remaining)
+            .map_err(|e: git2::Error| Error::Store(e.into()))?;
+        let update = policy::Update {
+            urn,
+            peer,
+            name,
+            old,
+            new: *new,
+            size,
+        };
+
+        // The tracking policy takes precedence
+        let decision = match remaining {
+            Some(remaining) if size > *remaining => {
+                tracing::warn!(name = %name, size = size, "update exceeds tracking size limit");
+                Decision::Reject
+            },
+            Some(remaining) => {
+                *remaining -= size;
+                policy.decide(&update)
+            },
+            None => policy.decide(&update),
+        };
+
+        match decision {
+            Decision::Accept => continue,
+            Decision::Reject => {
+                tracing::warn!(name = %name, new = %new, "update rejected by policy");
+            },
+            Decision::Quarantine => {
+                let quarantined = policy::quarantine(urn, name);
+                tracing::warn!(
+                    name = %name,
+                    new = %new,
+                    quarantine = %quarantined,
+                    "update quarantined by policy"
+                );
+                repo.reference(quarantined.as_str(), **new, true, "quarantined by policy")
+                    .map_err(|e| Error::Store(e.into()))?;
+            },
+        }
+
+        roll_back_ref(storage, name, old)?;
+    }
+
+    Ok(())
+}
+
+/// Determine the size in bytes of the blobs reachable from `new`, but not from
+/// any of the `known` tips.
+///
+/// If `new` is not a commit (or a tag pointing to one), the size of the object
+/// itself is returned.
+///
+/// The history is walked only until the size exceeds `limit`, if given, in
+/// which case the size returned is merely known to be greater than `limit`.
+///
+/// As this inspects the fetched objects, a tracking size limit can only be
+/// enforced after the fact, see [`apply_policy`].
+fn update_size(
+    repo: &git2::Repository,
+    known: &BTreeSet<ext::Oid>,
+    new: ext::Oid,
+    limit: Option<u64>,
+) -> Result<u64, git2::Error> {
+    let mut walk = repo.revwalk()?;
+    if walk.push(*new).is_err() {
+        let (size, _) = repo.odb()?.read_header(*new)?;
+        return Ok(size as u64);
+    }
+    for oid in known {
+        // Known tips may point to something other than commits, which don't
+        // limit the walk anyways
+        if let Err(e) = walk.hide(**oid) {
+            tracing::trace!(oid = %oid, err = %e, "unable to hide known tip");
+        }
+    }
+
+    let mut seen = BTreeSet::new();
+    let mut size = 0;
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let parent_tree = commit.parents().next().map(|p| p.tree()).transpose()?;
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        for delta in diff.deltas() {
+            let file = delta.new_file();
+            if file.exists() && seen.insert(file.id()) {
+                size += file.size();
+            }
+        }
+        if matches!(limit, Some(limit) if size > limit) {
+            break;
+        }
+    }
+
+    Ok(size)
+}
+
+/// Read the tracking [`tracking::Policy`] of each of `peers`, defaulting to
+/// [`tracking::Policy::default`] for peers which are not tracked.
+fn tracking_policies<'a>(
+    storage: &Storage,
+    urn: &Urn,
+    peers: impl Iterator<Item = &'a PeerId>,
+) -> Result<BTreeMap<PeerId, tracking::Policy>, Error> {
+    peers
+        .map(|peer| {
+            let policy = tracking::policy(storage, urn, *peer)?.unwrap_or_default();
+            Ok((*peer, policy))
+        })
+        .collect()
+}
+
+/// Remove the remote tracking branches of `peer` in the context of `urn` which
+/// are not (anymore) part of its signed `refs`, or not allowed by its tracking
+/// `policy`.
+///
+/// Only the `heads`, `tags` and `notes` categories are considered, as the
+/// `rad` refs are required for identity verification, and are maintained as
+/// part of the replication process itself.
+#[allow(clippy::unit_arg)]
+#[tracing::instrument(level = "trace", skip(storage, refs, policy), err)]
+fn prune_unsigned(
+    storage: &Storage,
+    urn: &Urn,
+    peer: PeerId,
+    refs: &Refs,
+    policy: &tracking::Policy,
+) -> Result<(), Error> {
+    let namespace = Namespace::from(urn);
+    let signed = refs
+        .iter_categorised()
+        .filter(|((name, _), category)| policy.allows(*category, name))
+        .map(|((name, _), category)| {
+            ext::RefLike::from(Reference {
+                remote: Some(peer),
+                category,
+                name: ext::RefLike::from(name.clone()),
+                namespace: Some(namespace.clone()),
+            })
+        })
+        .collect::<BTreeSet<_>>();
+
+    let mut unsigned = Vec::new();
+    for remote in vec![
+        Reference::heads(namespace.clone(), peer),
+        Reference::tags(namespace.clone(), peer),
+        Reference::notes(namespace.clone(), peer),
+    ] {
+        for name in storage.reference_names(&remote)? {
+            let name = name?;
+            if !signed.contains(&name) {
+                unsigned.push(name)
+            }
+        }
+    }
+
+    for name in unsigned {
+        tracing::debug!(name = %name, "pruning unsigned ref");
+        prune_reference(storage, &name)?;
+    }
+
+    Ok(())
+}
+
+/// Delete the reference `name`, if it exists.
+fn prune_reference(storage: &Storage, name: &ext::RefLike) -> Result<(), Error> {
+    storage
+        .as_raw()
+        .find_reference(name.as_str())
+        .and_then(|mut r| r.delete())
+        .or_matches(is_not_found_err, || Ok(()))
+        .map_err(|e: git2::Error| Error::Store(e.into()))
+}
+
 // Return three sets where the first consists of elements in `ys` but not in
 // `xs` and the second vice-versa, and the final set contains the elements they
 // both share.
@@ -455,8 +1254,12 @@ mod person {
             let delegate =
                 unsafe_into_urn(Reference::rad_id(Namespace::from(rad_id)).with_remote(*delegate));
             tracing::debug!(mine = %rad_id, theirs = %delegate, "checking for a fork");
+            identities::fork::prune(storage, rad_id, &delegate)?;
             match identities::person::is_fork(&storage, &rad_id, &delegate) {
                 Ok(false) => { /* all good */ },
+                Ok(true) if identities::fork::is_ignored(&storage, &rad_id, &delegate)? => {
+                    tracing::debug!(mine = %rad_id, theirs = %delegate, "ignoring resolved fork");
+                },
                 Ok(true) => {
                     return Err(Error::Fork {
                         mine: rad_id.clone(),
@@ -500,6 +1303,113 @@ mod person {
     }
 }
 
+mod org {
+    use super::*;
+
+    /// Process the `Org` that was replicated by:
+    ///   * Adopting its members, as found in its `rad/ids/*`
+    ///   * Verifying the identity against those members
+    ///   * Tracking the keys of the members
+    ///   * Ensuring we have a top-level `rad/id` that points to the latest
+    ///     version
+    #[allow(clippy::unit_arg)]
+    #[tracing::instrument(level = "trace", skip(storage), err)]
+    pub fn ensure_setup(
+        storage: &Storage,
+        rad_id: &Urn,
+        remote_peer: Option<PeerId>,
+        org: Org,
+    ) -> Result<(), Error> {
+        let local_peer = storage.peer_id();
+        let urn = org.urn();
+
+        adopt_members(storage, rad_id, remote_peer)?;
+        let delegates = match identities::org::verify(storage, rad_id)? {
+            None => Err(Error::MissingIdentity),
+            Some(org) => Ok(project::all_delegates(&org)),
+        }?;
+        for peer_id in delegates.iter() {
+            if peer_id != local_peer {
+                tracking::track(storage, &urn, *peer_id)?;
+            }
+        }
+
+        // Create `rad/id` here, if not exists
+        adopt_latest(storage, &urn, delegates)
+    }
+
+    /// Adopt the members of the org at `rad_id`, as found in the `rad/ids/*`
+    /// of the org, and track their keys.
+    ///
+    /// Returns the verified members of the org.
+    ///
+    /// # Errors
+    ///   * If a member is missing from the `rad/ids/*` of the org
+    #[allow(clippy::unit_arg)]
+    #[tracing::instrument(level = "trace", skip(storage), err)]
+    pub fn adopt_members(
+        storage: &Storage,
+        rad_id: &Urn,
+        remote_peer: Option<PeerId>,
+    ) -> Result<Vec<VerifiedPerson>, Error> {
+        let local_peer = storage.peer_id();
+        let org_urn = Urn::new(rad_id.id.clone());
+        let org = identities::org::get(storage, rad_id)?
+            .ok_or_else(|| Error::Missing(rad_id.clone().into()))?;
+
+        let mut members = Vec::new();
+        for member in org.delegations().iter().indirect() {
+            let in_rad_ids = unsafe_into_urn(
+                Reference::rad_delegate(Namespace::from(&org_urn), &member.urn())
+                    .with_remote(remote_peer),
+            );
+            let person = identities::person::verify(storage, &in_rad_ids)?
+                .ok_or_else(|| Error::Missing(in_rad_ids.clone().into()))?;
+            for key in person.delegations().iter() {
+                let peer_id = PeerId::from(*key);
+                if &peer_id != local_peer {
+                    tracking::track(storage, &person.urn(), peer_id)?;
+                    tracking::track(storage, &org_urn, peer_id)?;
+                }
+            }
+            adopt_delegate(storage, &person.urn(), person.content_id, &org_urn)?;
+            members.push(person);
+        }
+
+        Ok(members)
+    }
+
+    /// Adopt the `rad/id` that has the most up-to-date commit from the set of
+    /// `Org` delegates.
+    #[allow(clippy::unit_arg)]
+    #[tracing::instrument(level = "trace", skip(storage), err)]
+    pub fn adopt_latest(
+        storage: &Storage,
+        urn: &Urn,
+        delegates: BTreeSet<PeerId>,
+    ) -> Result<(), Error> {
+        let local_peer = storage.peer_id();
+        let orgs = NonEmpty::from_vec(
+            delegates
+                .into_iter()
+                .flat_map(|peer| {
+                    let urn = if &peer == local_peer {
+                        urn.clone()
+                    } else {
+                        unsafe_into_urn(Reference::rad_id(Namespace::from(urn)).with_remote(peer))
+                    };
+                    identities::org::get(storage, &urn).ok().flatten()
+                })
+                .collect(),
+        );
+        let tip = match orgs {
+            Some(orgs) => identities::org::latest_tip(storage, orgs).map_err(Error::from),
+            None => Err(Error::MissingIdentities(urn.clone())),
+        }?;
+        ensure_rad_id(storage, urn, tip.into())
+    }
+}
+
 mod project {
     use super::*;
 
@@ -525,12 +1435,11 @@ mod project {
     pub fn ensure_setup(
         storage: &Storage,
         fetcher: &mut fetch::DefaultFetcher,
-        limit: fetch::Limit,
+        config: &Config,
         delegates: BTreeMap<PeerId, project::DelegateView>,
         rad_id: &Urn,
         proj: VerifiedProject,
-    ) -> Result<ReplicateResult, Error> {
-        let local_peer = storage.peer_id();
+    ) -> Result<IdentityStatus, Error> {
         project::ensure_no_forking(
             storage,
             rad_id,
@@ -539,36 +1448,41 @@ mod project {
 
         let urn = proj.urn();
         project::track_direct(storage, &proj)?;
-        let tracked = replicate_signed_refs(
+        replicate_signed_refs(
             storage,
             fetcher,
-            limit,
+            config,
             &urn,
             delegates
                 .values()
                 .map(|delegate| delegate.urn.clone())
                 .collect(),
         )?;
-        for peer in tracked {
-            if peer != *local_peer {
-                tracking::track(&storage, &urn, peer)?;
-            }
-        }
 
         project::adopt_latest(storage, &urn, delegates)
     }
 
     /// Fetch `rad/signed_refs` and `refs/heads` of the delegates and our
-    /// tracked graph, returning the set of tracked peers.
+    /// tracked graph, returning the set of tracked peers, including the ones
+    /// tracked transitively up to the configured depth (see
+    /// [`storage::config::Config::tracking_graph_depth`]).
+    ///
+    /// The updated heads are subject to the [`Config::policy`].
     #[allow(clippy::unit_arg)]
     #[tracing::instrument(level = "trace", skip(storage, fetcher), err)]
     pub fn replicate_signed_refs(
         storage: &Storage,
         fetcher: &mut fetch::DefaultFetcher,
-        limit: fetch::Limit,
+        config: &Config,
         urn: &Urn,
         delegates: BTreeSet<Urn>,
     ) -> Result<BTreeSet<PeerId>, Error> {
+        let local_peer = storage.peer_id();
+        let depth = storage
+            .config()?
+            .tracking_graph_depth()
+            .map_err(storage::Error::from)?;
+
         // Read `signed_refs` for all tracked
         let tracked = tracking::tracked(storage, &urn)?.collect::<BTreeSet<_>>();
         let tracked_sigrefs = tracked
@@ -580,22 +1494,65 @@ mod project {
                 Err(e) => Some(Err(e)),
             })
             .collect::<Result<BTreeMap<_, _>, _>>()?;
+        let policies = tracking_policies(storage, urn, tracked_sigrefs.keys())?;
 
         // Fetch all the rest
         tracing::debug!("fetching heads: {:?}, {:?}", tracked_sigrefs, delegates);
-        fetcher
+        let result = fetcher
             .fetch(fetch::Fetchspecs::Replicate {
                 tracked_sigrefs: tracked_sigrefs.clone(),
+                policies: policies.clone(),
                 delegates,
-                limit,
+                depth,
+                limit: config.fetch_limit,
             })
             .map_err(|e| Error::Fetch(e.into()))?;
+        apply_policy(storage, &config.policy, urn, &result)?;
+        reject_stale_signed_refs(storage, urn, &result)?;
 
-        Refs::update(storage, &urn)?;
-        Ok(tracked_sigrefs
+        // Remove what the tracked peers no longer advertise, or we no longer
+        // want
+        for (peer, refs) in tracked_sigrefs.iter() {
+            prune_unsigned(storage, urn, *peer, refs, &policies[peer])?;
+        }
+
+        let mut tracked = tracked_sigrefs.keys().copied().collect::<BTreeSet<_>>();
+        let transitive = tracked_sigrefs
             .iter()
-            .flat_map(|(peer, refs)| iter::once(*peer).chain(refs.remotes.flatten().copied()))
-            .collect())
+            .filter(|(peer, _)| policies[peer].follow_remotes)
+            .flat_map(|(_, refs)| {
+                refs.remotes
+                    .clone()
+                    .cutoff(depth)
+                    .flatten()
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .filter(|peer| !tracked_sigrefs.contains_key(peer) && peer != local_peer)
+            .collect::<BTreeSet<_>>();
+        for peer in transitive {
+            // The `rad/signed_refs` of the transitively tracked peers were
+            // fetched along with the heads, if the remote peer had them.
+            // Don't track peers whose `rad/signed_refs` are missing or don't
+            // verify.
+            match Refs::load(storage, urn, peer) {
+                Ok(Some(_)) => {
+                    tracking::track(storage, urn, peer)?;
+                    tracked.insert(peer);
+                },
+                Ok(None) => {
+                    tracing::debug!(peer = %peer, "no signed refs, not tracking")
+                },
+                Err(refs::stored::Error::Signed(e)) => {
+                    tracing::warn!(peer = %peer, err = %e, "invalid signed refs, not tracking")
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Refs::update(storage, &urn)?;
+
+        Ok(tracked)
     }
 
     /// Compare the given `rad_id` of the project against each delegate and
@@ -612,8 +1569,12 @@ mod project {
     ) -> Result<(), Error> {
         for delegate in delegates.iter() {
             tracing::debug!(mine = %rad_id, theirs = %delegate, "checking for a fork");
+            identities::fork::prune(storage, rad_id, &delegate)?;
             match identities::project::is_fork(&storage, &rad_id, &delegate) {
                 Ok(false) => { /* all good */ },
+                Ok(true) if identities::fork::is_ignored(&storage, &rad_id, &delegate)? => {
+                    tracing::debug!(mine = %rad_id, theirs = %delegate, "ignoring resolved fork");
+                },
                 Ok(true) => {
                     return Err(Error::Fork {
                         mine: rad_id.clone(),
@@ -630,8 +1591,43 @@ mod project {
         Ok(())
     }
 
+    /// The orgs the project delegates to.
+    pub fn org_delegates(proj: &Project) -> BTreeSet<Urn> {
+        proj.delegations()
+            .iter()
+            .indirect()
+            .filter(|delegate| delegate.doc.payload.org().is_some())
+            .map(|delegate| delegate.urn())
+            .collect()
+    }
+
+    /// Fetch the `rad/ids/*` of the orgs the project delegates to, which refer
+    /// to the members of the respective org.
+    #[allow(clippy::unit_arg)]
+    #[tracing::instrument(level = "trace", skip(fetcher), err)]
+    pub fn fetch_orgs(
+        fetcher: &mut fetch::DefaultFetcher,
+        config: &Config,
+        proj: &Project,
+    ) -> Result<(), Error> {
+        let delegates = org_delegates(proj);
+        if !delegates.is_empty() {
+            fetcher
+                .fetch(fetch::Fetchspecs::Delegates {
+                    delegates,
+                    limit: config.fetch_limit,
+                })
+                .map_err(|e| Error::Fetch(e.into()))?;
+        }
+
+        Ok(())
+    }
+
     /// For each delegate in `remotes/<remote_peer>/rad/ids/*` get the view for
     /// that delegate that _should_ be local the `storage` after a fetch.
+    ///
+    /// For delegate orgs, a view is created for each key of each member of the
+    /// org, as found in the `rad/ids/*` of the org.
     #[allow(clippy::unit_arg)]
     #[tracing::instrument(level = "trace", skip(storage), err)]
     pub fn delegate_views(
@@ -642,41 +1638,51 @@ mod project {
         let mut delegate_views = BTreeMap::new();
         let local_peer_id = storage.peer_id();
         for delegate in proj.delegations().iter().indirect() {
-            let in_rad_ids = unsafe_into_urn(
-                Reference::rad_delegate(Namespace::from(&proj.urn()), &delegate.urn())
-                    .with_remote(remote_peer),
-            );
-            match identities::person::verify(storage, &in_rad_ids)? {
-                None => return Err(Error::Missing(in_rad_ids.into())),
-                Some(delegate_person) => {
-                    let person = delegate_person.clone();
-                    for key in delegate_person.delegations().iter() {
-                        let peer_id = PeerId::from(*key);
-                        let (urn, project) = if &peer_id == local_peer_id {
-                            let urn = proj.urn();
-                            let verified = identities::project::verify(storage, &urn)?
-                                .ok_or(Error::MissingIdentity)?;
-                            (urn, verified)
+            let is_org = delegate.doc.payload.org().is_some();
+            let persons = if is_org {
+                adopt_delegate_org(storage, &delegate.urn(), &proj.urn(), remote_peer)?
+            } else {
+                let in_rad_ids = unsafe_into_urn(
+                    Reference::rad_delegate(Namespace::from(&proj.urn()), &delegate.urn())
+                        .with_remote(remote_peer),
+                );
+                match identities::person::verify(storage, &in_rad_ids)? {
+                    None => return Err(Error::Missing(in_rad_ids.into())),
+                    Some(person) => vec![person],
+                }
+            };
+
+            for person in persons {
+                for key in person.delegations().iter() {
+                    let peer_id = PeerId::from(*key);
+                    let (urn, project) = if &peer_id == local_peer_id {
+                        let urn = proj.urn();
+                        let verified = identities::project::verify(storage, &urn)?
+                            .ok_or(Error::MissingIdentity)?;
+                        (urn, verified)
+                    } else {
+                        let remote_urn = unsafe_into_urn(
+                            Reference::rad_id(Namespace::from(&proj.urn())).with_remote(peer_id),
+                        );
+                        // Members of an org are adopted in the org's namespace
+                        if is_org {
+                            tracking::track(storage, &proj.urn(), peer_id)?;
                         } else {
-                            let remote_urn = unsafe_into_urn(
-                                Reference::rad_id(Namespace::from(&proj.urn()))
-                                    .with_remote(peer_id),
-                            );
                             adopt_delegate_person(storage, peer_id, &person, &proj.urn())?;
-                            let verified = identities::project::verify(storage, &remote_urn)?
-                                .ok_or(Error::MissingIdentity)?;
-                            (remote_urn, verified)
-                        };
-                        delegate_views.insert(
-                            peer_id,
-                            DelegateView {
-                                urn,
-                                delegate: person.clone(),
-                                project,
-                            },
-                        );
-                    }
-                },
+                        }
+                        let verified = identities::project::verify(storage, &remote_urn)?
+                            .ok_or(Error::MissingIdentity)?;
+                        (remote_urn, verified)
+                    };
+                    delegate_views.insert(
+                        peer_id,
+                        DelegateView {
+                            urn,
+                            delegate: person.clone(),
+                            project,
+                        },
+                    );
+                }
             }
         }
 
@@ -693,24 +1699,37 @@ mod project {
         project_urn: &Urn,
     ) -> Result<(), Error> {
         let delegate_urn = person.urn();
-        ensure_rad_id(storage, &delegate_urn, person.content_id)?;
         tracking::track(storage, &delegate_urn, peer)?;
         tracking::track(storage, &project_urn, peer)?;
 
-        // Now point our view to the top-level
-        Reference::try_from(&delegate_urn)
-            .map_err(|e| Error::RefFromUrn {
-                urn: delegate_urn.clone(),
-                source: e,
-            })?
-            .symbolic_ref::<_, PeerId>(
-                Reference::rad_delegate(Namespace::from(project_urn), &delegate_urn),
-                Force::False,
-            )
-            .create(storage.as_raw())
-            .and(Ok(()))
-            .or_matches(is_exists_err, || Ok(()))
-            .map_err(|e: git2::Error| Error::Store(e.into()))
+        // Adopt the delegate's `rad/id`, and point our view to the top-level
+        adopt_delegate(storage, &delegate_urn, person.content_id, project_urn)
+    }
+
+    /// Persist a delegate org identity, and its members, in our storage.
+    ///
+    /// The org is read from the `rad/ids/*` of the project, and its members
+    /// from the `rad/ids/*` of the org, as advertised by `remote_peer` (if
+    /// given). The members are adopted before verifying the org against them.
+    ///
+    /// Returns the verified members of the org.
+    #[allow(clippy::unit_arg)]
+    #[tracing::instrument(level = "trace", skip(storage), err)]
+    pub fn adopt_delegate_org(
+        storage: &Storage,
+        org_urn: &Urn,
+        project_urn: &Urn,
+        remote_peer: Option<PeerId>,
+    ) -> Result<Vec<VerifiedPerson>, Error> {
+        let in_rad_ids = unsafe_into_urn(
+            Reference::rad_delegate(Namespace::from(project_urn), org_urn).with_remote(remote_peer),
+        );
+        let members = org::adopt_members(storage, &in_rad_ids, remote_peer)?;
+        let org = identities::org::verify(storage, &in_rad_ids)?
+            .ok_or_else(|| Error::Missing(in_rad_ids.clone().into()))?;
+        adopt_delegate(storage, org_urn, org.content_id, project_urn)?;
+
+        Ok(members)
     }
 
     /// Track all direct delegations of a `Project`.
@@ -733,14 +1752,18 @@ mod project {
 
     /// Adopt the `rad/id` that has the most up-to-date commit from the set of
     /// `Project` delegates.
+    ///
+    /// If we are a delegate ourselves, our `rad/id` is left alone, and instead
+    /// compared against the latest tip of the _other_ delegates.
     #[allow(clippy::unit_arg)]
     #[tracing::instrument(level = "trace", skip(storage), err)]
     pub fn adopt_latest(
         storage: &Storage,
         urn: &Urn,
-        delegates: BTreeMap<PeerId, DelegateView>,
-    ) -> Result<ReplicateResult, Error> {
+        mut delegates: BTreeMap<PeerId, DelegateView>,
+    ) -> Result<IdentityStatus, Error> {
         let local_peer = storage.peer_id();
+        let ours = delegates.remove(local_peer);
         let projects = NonEmpty::from_vec(
             delegates
                 .values()
@@ -748,36 +1771,48 @@ mod project {
                 .collect::<Vec<_>>(),
         );
 
-        let tip = match projects {
-            Some(projects) => {
-                identities::project::latest_tip(storage, projects).map_err(Error::from)
+        match (ours, projects) {
+            (None, None) => Err(Error::MissingIdentities(urn.clone())),
+            (Some(_), None) => {
+                tracing::debug!("we are the only delegate");
+                Ok(IdentityStatus::Latest)
             },
-            None => Err(Error::MissingIdentities(urn.clone())),
-        }?;
-
-        // Are we a delegate?
-        match delegates.get(local_peer) {
-            None => {
+            (None, Some(projects)) => {
+                let tip = identities::project::latest_tip(storage, projects)?;
                 tracing::debug!(tip = %tip, "adopting latest tip");
                 ensure_rad_id(storage, urn, tip.into())?;
-                Ok(ReplicateResult::Latest)
+                Ok(IdentityStatus::Latest)
             },
-            Some(view) => {
-                Ok(if view.project.content_id == tip.into() {
+            (Some(view), Some(projects)) => {
+                let tip = identities::project::latest_tip(storage, projects)?;
+                let ours = *view.project.content_id;
+                let repo = storage.as_raw();
+                if ours == tip {
                     tracing::debug!("we are at the latest tip");
-                    ReplicateResult::Latest
+                    Ok(IdentityStatus::Latest)
+                } else if repo
+                    .graph_descendant_of(ours, tip)
+                    .map_err(|e| Error::Store(e.into()))?
+                {
+                    tracing::debug!("we are ahead of the other delegates");
+                    Ok(IdentityStatus::Ahead)
                 } else {
-                    // FIXME: We could be ahead
                     tracing::debug!("we need to update the project");
-                    ReplicateResult::Behind
-                })
+                    Ok(IdentityStatus::Behind)
+                }
             },
         }
     }
 
     /// Using the fetched references we parse out the set of `PeerId`s that were
     /// fetched.
-    pub fn fetched_peers(result: fetch::FetchResult) -> Result<BTreeSet<PeerId>, Error> {
+    ///
+    /// References which were fetched, but don't have the expected shape, are
+    /// pruned.
+    pub fn fetched_peers(
+        storage: &Storage,
+        result: fetch::FetchResult,
+    ) -> Result<BTreeSet<PeerId>, Error> {
         use std::str::FromStr;
 
         let mut peers = BTreeSet::new();
@@ -785,7 +1820,7 @@ mod project {
             let path: ext::RefLike = match Urn::try_from(reference.clone()).map(|urn| urn.path) {
                 Ok(Some(path)) => path,
                 Ok(None) | Err(_) => {
-                    /* FIXME: prune reference */
+                    prune_reference(storage, reference)?;
                     continue;
                 },
             };
@@ -795,7 +1830,7 @@ mod project {
             };
             let peer = match suffix.as_str().split('/').next().map(PeerId::from_str) {
                 None | Some(Err(_)) => {
-                    /* FIXME: prune reference */
+                    prune_reference(storage, reference)?;
                     continue;
                 },
                 Some(Ok(remote)) => remote,
@@ -806,8 +1841,10 @@ mod project {
         Ok(peers)
     }
 
-    pub fn all_delegates(proj: &Project) -> BTreeSet<PeerId> {
-        proj.delegations()
+    /// The keys of all delegates of a project or org, including the keys of
+    /// its delegate persons.
+    pub fn all_delegates<T>(id: &Identity<Doc<T, IndirectDelegation>>) -> BTreeSet<PeerId> {
+        id.delegations()
             .iter()
             .flat_map(|delegate| match delegate {
                 Either::Left(pk) => vec![PeerId::from(*pk)],
@@ -820,3 +1857,326 @@ mod project {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+
+    use librad_test::tempdir::WithTmpDir;
+
+    use crate::{keys::SecretKey, paths::Paths};
+
+    fn storage() -> WithTmpDir<Storage> {
+        WithTmpDir::new(|path| {
+            let paths = Paths::from_root(path)?;
+            Storage::open_or_init(&paths, SecretKey::new())
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        })
+        .unwrap()
+    }
+
+    fn commit(repo: &git2::Repository, msg: &str, parents: &[ext::Oid]) -> ext::Oid {
+        let tree = repo
+            .find_tree(repo.treebuilder(None).unwrap().write().unwrap())
+            .unwrap();
+        let parents = parents
+            .iter()
+            .map(|oid| repo.find_commit(**oid).unwrap())
+            .collect::<Vec<_>>();
+        let author = git2::Signature::now("dylan", "dylan@example.com").unwrap();
+        repo.commit(
+            None,
+            &author,
+            &author,
+            msg,
+            &tree,
+            &parents.iter().collect::<Vec<_>>(),
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn remote(urn: &Urn, peer: PeerId, name: &str) -> ext::RefLike {
+        reflike!("refs/namespaces")
+            .join(urn)
+            .join(reflike!("refs/remotes"))
+            .join(peer)
+            .join(ext::RefLike::try_from(name).unwrap())
+    }
+
+    fn target(storage: &Storage, name: &ext::RefLike) -> Option<ext::Oid> {
+        storage
+            .as_raw()
+            .refname_to_id(name.as_str())
+            .ok()
+            .map(ext::Oid::from)
+    }
+
+    /// Simulate a fetch which fast-forwarded `heads/master`, and created
+    /// `heads/feature` and `rad/self` of `peer`.
+    ///
+    /// Returns the previous and the new tip.
+    fn fetched(
+        storage: &Storage,
+        urn: &Urn,
+        peer: PeerId,
+    ) -> (fetch::FetchResult, ext::Oid, ext::Oid) {
+        let repo = storage.as_raw();
+        let base = commit(repo, "base", &[]);
+        let tip = commit(repo, "tip", &[base]);
+
+        let master = remote(urn, peer, "heads/master");
+        let updated_tips = vec![
+            master.clone(),
+            remote(urn, peer, "heads/feature"),
+            remote(urn, peer, "rad/self"),
+        ]
+        .into_iter()
+        .map(|name| {
+            repo.reference(name.as_str(), *tip, true, "fetched")
+                .unwrap();
+            (name, tip)
+        })
+        .collect();
+
+        (
+            fetch::FetchResult {
+                updated_tips,
+                previous_tips: Some((master, base)).into_iter().collect(),
+            },
+            base,
+            tip,
+        )
+    }
+
+    #[test]
+    fn policy_rejects() {
+        let storage = storage();
+        let urn = Urn::new(commit(storage.as_raw(), "urn", &[]));
+        let peer = PeerId::from(SecretKey::new());
+        let (result, base, tip) = fetched(&storage, &urn, peer);
+
+        let policy = Policy::new(|_: &policy::Update<'_>| Decision::Reject);
+        apply_policy(&storage, &policy, &urn, &result).unwrap();
+
+        // Updates are rolled back, new refs are removed
+        assert_eq!(
+            target(&storage, &remote(&urn, peer, "heads/master")),
+            Some(base)
+        );
+        assert_eq!(target(&storage, &remote(&urn, peer, "heads/feature")), None);
+        // The `rad` refs are exempt
+        assert_eq!(target(&storage, &remote(&urn, peer, "rad/self")), Some(tip));
+        assert_eq!(
+            target(
+                &storage,
+                &policy::quarantine(&urn, &remote(&urn, peer, "heads/feature"))
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn policy_quarantines() {
+        let storage = storage();
+        let urn = Urn::new(commit(storage.as_raw(), "urn", &[]));
+        let peer = PeerId::from(SecretKey::new());
+        let (result, base, tip) = fetched(&storage, &urn, peer);
+
+        let policy = Policy::new(|_: &policy::Update<'_>| Decision::Quarantine);
+        apply_policy(&storage, &policy, &urn, &result).unwrap();
+
+        for (name, old) in &[("heads/master", Some(base)), ("heads/feature", None)] {
+            let name = remote(&urn, peer, name);
+            assert_eq!(target(&storage, &name), *old);
+            assert_eq!(
+                target(&storage, &policy::quarantine(&urn, &name)),
+                Some(tip)
+            );
+        }
+    }
+
+    #[test]
+    fn update_size_stops_at_known_history() {
+        let storage = storage();
+        let repo = storage.as_raw();
+        let blob = |parents: &[ext::Oid], content: &[u8]| -> ext::Oid {
+            let mut tree = repo.treebuilder(None).unwrap();
+            tree.insert("blob", repo.blob(content).unwrap(), 0o100_644)
+                .unwrap();
+            let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+            let parents = parents
+                .iter()
+                .map(|oid| repo.find_commit(**oid).unwrap())
+                .collect::<Vec<_>>();
+            let author = git2::Signature::now("dylan", "dylan@example.com").unwrap();
+            repo.commit(
+                None,
+                &author,
+                &author,
+                "blob",
+                &tree,
+                &parents.iter().collect::<Vec<_>>(),
+            )
+            .unwrap()
+            .into()
+        };
+        let base = blob(&[], &[0; 64]);
+        let tip = blob(&[base], &[1; 8]);
+
+        // A new ref branching off known history only counts what's new
+        assert_eq!(
+            update_size(repo, &Some(base).into_iter().collect(), tip, None).unwrap(),
+            8
+        );
+        assert_eq!(update_size(repo, &BTreeSet::new(), tip, None).unwrap(), 72);
+        // The walk stops once the limit is exceeded
+        assert_eq!(
+            update_size(repo, &BTreeSet::new(), tip, Some(4)).unwrap(),
+            8
+        );
+    }
+
+    #[test]
+    fn policy_decides_per_ref() {
+        let storage = storage();
+        let urn = Urn::new(commit(storage.as_raw(), "urn", &[]));
+        let peer = PeerId::from(SecretKey::new());
+        let (result, base, tip) = fetched(&storage, &urn, peer);
+
+        let policy = Policy::new(move |update: &policy::Update<'_>| {
+            assert_eq!(update.peer, peer);
+            if update.old == Some(base) {
+                Decision::Accept
+            } else {
+                Decision::Reject
+            }
+        });
+        apply_policy(&storage, &policy, &urn, &result).unwrap();
+
+        assert_eq!(
+            target(&storage, &remote(&urn, peer, "heads/master")),
+            Some(tip)
+        );
+        assert_eq!(target(&storage, &remote(&urn, peer, "heads/feature")), None);
+    }
+
+    #[test]
+    fn stale_signed_refs_are_rejected() {
+        let storage = storage();
+        let repo = storage.as_raw();
+        let urn = Urn::new(commit(repo, "urn", &[]));
+        let stale = PeerId::from(SecretKey::new());
+        let ahead = PeerId::from(SecretKey::new());
+
+        let old = commit(repo, "signed refs", &[]);
+        let new = commit(repo, "signed refs, again", &[old]);
+        let mut result = fetch::FetchResult {
+            updated_tips: BTreeMap::new(),
+            previous_tips: BTreeMap::new(),
+        };
+        for (peer, (before, after)) in &[(stale, (new, old)), (ahead, (old, new))] {
+            let name = remote(&urn, *peer, "rad/signed_refs");
+            repo.reference(name.as_str(), **after, true, "fetched")
+                .unwrap();
+            result.updated_tips.insert(name.clone(), *after);
+            result.previous_tips.insert(name, *before);
+        }
+
+        assert_eq!(
+            reject_stale_signed_refs(&storage, &urn, &result).unwrap(),
+            Some(stale).into_iter().collect()
+        );
+        assert_eq!(
+            target(&storage, &remote(&urn, stale, "rad/signed_refs")),
+            Some(new)
+        );
+        assert_eq!(
+            target(&storage, &remote(&urn, ahead, "rad/signed_refs")),
+            Some(new)
+        );
+    }
+
+    #[test]
+    fn roll_back_only_affects_peer() {
+        let storage = storage();
+        let urn = Urn::new(commit(storage.as_raw(), "urn", &[]));
+        let peer = PeerId::from(SecretKey::new());
+        let other = PeerId::from(SecretKey::new());
+        let (mut result, base, tip) = fetched(&storage, &urn, peer);
+        let (other_result, _, other_tip) = fetched(&storage, &urn, other);
+        result.updated_tips.extend(other_result.updated_tips);
+        result.previous_tips.extend(other_result.previous_tips);
+
+        roll_back(&storage, &urn, &result, peer).unwrap();
+
+        assert_eq!(
+            target(&storage, &remote(&urn, peer, "heads/master")),
+            Some(base)
+        );
+        assert_eq!(target(&storage, &remote(&urn, peer, "rad/self")), None);
+        assert_ne!(base, tip);
+        assert_eq!(
+            target(&storage, &remote(&urn, other, "heads/master")),
+            Some(other_tip)
+        );
+        assert_eq!(
+            target(&storage, &remote(&urn, other, "rad/self")),
+            Some(other_tip)
+        );
+    }
+
+    #[test]
+    fn classify_updates() {
+        let storage = storage();
+        let repo = storage.as_raw();
+        let base = commit(repo, "base", &[]);
+        let tip = commit(repo, "tip", &[base]);
+        let rewritten = commit(repo, "rewritten", &[]);
+
+        let name = |s: &str| ext::RefLike::try_from(s).unwrap();
+        let before = vec![
+            (name("ff"), base),
+            (name("forced"), tip),
+            (name("same"), base),
+            (name("deleted"), base),
+        ]
+        .into_iter()
+        .collect();
+        let after = vec![
+            (name("ff"), tip),
+            (name("forced"), rewritten),
+            (name("same"), base),
+            (name("new"), tip),
+        ]
+        .into_iter()
+        .collect();
+
+        let updated = classify(&storage, before, after).unwrap();
+        assert_eq!(
+            updated,
+            vec![
+                (
+                    name("ff"),
+                    Updated::FastForward {
+                        old: base,
+                        new: tip
+                    }
+                ),
+                (
+                    name("forced"),
+                    Updated::Forced {
+                        old: tip,
+                        new: rewritten
+                    }
+                ),
+                (name("new"), Updated::New { new: tip }),
+                (name("deleted"), Updated::Deleted { old: base }),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+}
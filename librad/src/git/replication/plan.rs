@@ -0,0 +1,348 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Dry-run replication.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    net::SocketAddr,
+    path::Path,
+};
+
+use git_ext::{self as ext, is_not_found_err};
+use std_ext::result::ResultExt as _;
+
+use super::{project, Config, Error, Urn};
+use crate::{
+    git::{
+        fetch,
+        identities,
+        refs::{self, Refs},
+        storage::{self, glob, Storage},
+        tracking,
+        types::{Namespace, Reference},
+    },
+    identities::git::{Identities, SomeIdentity},
+    peer::PeerId,
+};
+
+/// The outcome of [`plan`].
+#[derive(Debug)]
+pub struct Plan {
+    /// The latest verified identity, as advertised by the remote peer.
+    pub identity: SomeIdentity,
+    /// The refs which would be updated by [`super::replicate`].
+    pub updates: BTreeMap<ext::RefLike, RefUpdate>,
+    /// The peers whose refs would be updated.
+    pub peers: BTreeSet<PeerId>,
+}
+
+/// A ref which would be created or moved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RefUpdate {
+    /// The current target of the ref in the [`Storage`], if it exists.
+    pub old: Option<ext::Oid>,
+    /// The target the ref would be updated to.
+    pub new: ext::Oid,
+}
+
+/// Determine what [`super::replicate`] would do when fetching `urn` from
+/// `remote_peer`, without modifying the [`Storage`].
+///
+/// The identity branches (`rad/*`) are fetched into a temporary repository,
+/// which borrows the objects already present in the [`Storage`]. The identity
+/// as advertised by `remote_peer` is verified, and the `rad/signed_refs` of the
+/// tracked peers and delegates are compared against the refs advertised by
+/// `remote_peer`, and the refs we already have.
+///
+/// Note that the git protocol does not tell us the size of the data before it
+/// is fetched, so the only indication of how much would be transferred are the
+/// number of [`Plan::updates`]. The size of the identity branches is, however,
+/// subject to [`Config::fetch_limit`].
+#[tracing::instrument(skip(storage, config, addr_hints), err)]
+pub fn plan<Addrs>(
+    storage: &Storage,
+    config: &Config,
+    urn: Urn,
+    remote_peer: PeerId,
+    addr_hints: Addrs,
+) -> Result<Plan, Error>
+where
+    Addrs: IntoIterator<Item = SocketAddr>,
+{
+    let urn = Urn::new(urn.id);
+    let local_peer = *storage.peer_id();
+
+    if local_peer == remote_peer {
+        return Err(Error::SelfReplication);
+    }
+
+    let scratch = tempfile::tempdir()?;
+    let repo = git2::Repository::init_bare(scratch.path()).map_err(storage::Error::from)?;
+    repo.odb()
+        .and_then(|odb| odb.add_disk_alternate(&storage.path().join("objects").to_string_lossy()))
+        .map_err(storage::Error::from)?;
+
+    let mut existing = BTreeSet::new();
+    if storage.has_urn(&urn)? {
+        existing = tracking::tracked(storage, &urn)?.collect();
+        match identities::any::get(storage, &urn)? {
+            Some(SomeIdentity::Project(proj)) => {
+                existing.append(&mut project::all_delegates(&proj));
+            },
+            Some(SomeIdentity::Org(org)) => {
+                existing.append(&mut project::all_delegates(&org));
+            },
+            _ => {},
+        }
+    }
+
+    let mut fetcher =
+        fetch::DefaultFetcher::with_repo(&repo, local_peer, urn.clone(), remote_peer, addr_hints)
+            .map_err(storage::Error::from)?;
+    let peek = if existing.is_empty() {
+        fetch::Fetchspecs::PeekAll {
+            limit: config.fetch_limit,
+        }
+    } else {
+        existing.insert(remote_peer);
+        fetch::Fetchspecs::Peek {
+            remotes: existing.clone(),
+            limit: config.fetch_limit,
+        }
+    };
+    fetcher.fetch(peek).map_err(|e| Error::Fetch(e.into()))?;
+
+    let identity = verify(storage, &repo, &mut fetcher, config, &urn, remote_peer)?;
+    let delegates = match &identity {
+        SomeIdentity::Project(proj) => project::all_delegates(proj),
+        SomeIdentity::Person(person) => person
+            .delegations()
+            .iter()
+            .copied()
+            .map(PeerId::from)
+            .collect(),
+        SomeIdentity::Org(org) => project::all_delegates(org),
+    };
+
+    let namespace = Namespace::from(&urn);
+    let mut updates = BTreeMap::new();
+
+    // Identity branches
+    let scratch_refs = glob::RefspecMatcher::from(
+        reflike!("refs/namespaces")
+            .join(&urn)
+            .with_pattern_suffix(refspec_pattern!("refs/remotes/*")),
+    );
+    for reference in repo.references().map_err(storage::Error::from)? {
+        let reference = reference.map_err(storage::Error::from)?;
+        if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+            if storage::Pattern::matches(&scratch_refs, name) {
+                let name = ext::RefLike::try_from(name).map_err(refs::stored::Error::from)?;
+                plan_update(storage, &mut updates, name, target.into())?;
+            }
+        }
+    }
+
+    // Signed heads
+    let candidates = existing
+        .into_iter()
+        .chain(delegates)
+        .filter(|peer| peer != &local_peer)
+        .collect::<BTreeSet<_>>();
+    for peer in candidates {
+        let refs = match signed_refs(storage, &repo, &urn, peer)? {
+            None => continue,
+            Some(refs) => refs,
+        };
+        let policy = tracking::policy(storage, &urn, peer)?.unwrap_or_default();
+        for ((name, target), category) in refs
+            .iter_categorised()
+            .filter(|((name, _), category)| policy.allows(*category, name))
+        {
+            let name = ext::RefLike::from(name.clone());
+            let advertised = if peer == remote_peer {
+                reflike!("refs/namespaces")
+                    .join(&namespace)
+                    .join(reflike!("refs"))
+                    .join(category)
+                    .join(&name)
+            } else {
+                reflike!("refs/namespaces")
+                    .join(&namespace)
+                    .join(reflike!("refs/remotes"))
+                    .join(peer)
+                    .join(category)
+                    .join(&name)
+            };
+            if fetcher.remote_heads().get(&advertised) != Some(target) {
+                continue;
+            }
+
+            let dst = Reference {
+                remote: Some(peer),
+                category,
+                name,
+                namespace: Some(namespace.clone()),
+            };
+            plan_update(storage, &mut updates, ext::RefLike::from(dst), *target)?;
+        }
+    }
+
+    let peers = updates
+        .keys()
+        .filter_map(|name| remote_of(&urn, name))
+        .collect();
+
+    Ok(Plan {
+        identity,
+        updates,
+        peers,
+    })
+}
+
+/// Verify the identity `urn` as advertised by `remote_peer`, using the
+/// branches fetched into `repo`.
+///
+/// The `rad/ids/*` of the orgs a project delegates to are fetched using
+/// `fetcher`, so the members of the orgs can be resolved.
+fn verify(
+    storage: &Storage,
+    repo: &git2::Repository,
+    fetcher: &mut fetch::DefaultFetcher,
+    config: &Config,
+    urn: &Urn,
+    remote_peer: PeerId,
+) -> Result<SomeIdentity, Error> {
+    let namespace = Namespace::from(urn);
+    let rad_id = Reference::rad_id(namespace.clone()).with_remote(remote_peer);
+    let tip = rad_id
+        .find(repo)
+        .map(Some)
+        .or_matches(is_not_found_err, || Ok(None))
+        .map_err(storage::Error::from)?
+        .and_then(|r| r.target())
+        .ok_or(Error::MissingIdentity)?;
+
+    let ids: Identities<'_, !> = Identities::from(repo);
+    let identity = ids
+        .some_identity(tip)
+        .map_err(identities::error::Error::from)?;
+    match identity {
+        SomeIdentity::Person(_) => {
+            let person = ids
+                .as_person()
+                .verify(tip)
+                .map_err(|e| identities::error::Error::Verify(e.into()))?;
+            Ok(SomeIdentity::Person(person.into_inner()))
+        },
+        SomeIdentity::Project(proj) => {
+            let orgs = project::org_delegates(&proj);
+            project::fetch_orgs(fetcher, config, &proj)?;
+
+            // The members of orgs are found in the `rad/ids/*` of the org
+            let namespaces = Some(namespace)
+                .into_iter()
+                .chain(orgs.iter().map(Namespace::from))
+                .collect();
+            let project = ids
+                .as_project()
+                .verify(tip, lookup(storage, repo, namespaces, remote_peer))
+                .map_err(|e| identities::error::Error::Verify(e.into()))?;
+            Ok(SomeIdentity::Project(project.into_inner()))
+        },
+        SomeIdentity::Org(_) => {
+            let org = ids
+                .as_org()
+                .verify(tip, lookup(storage, repo, vec![namespace], remote_peer))
+                .map_err(|e| identities::error::Error::Verify(e.into()))?;
+            Ok(SomeIdentity::Org(org.into_inner()))
+        },
+    }
+}
+
+/// Find the latest head of a delegate in the `rad/ids/*` of `namespaces`.
+///
+/// Prefer the delegate as advertised by the remote peer, fall back to what we
+/// already have.
+fn lookup<'a>(
+    storage: &'a Storage,
+    repo: &'a git2::Repository,
+    namespaces: Vec<Namespace<ext::Oid>>,
+    remote_peer: PeerId,
+) -> impl Fn(Urn) -> Result<git2::Oid, git2::Error> + 'a {
+    move |delegate: Urn| {
+        namespaces
+            .iter()
+            .find_map(|namespace| {
+                let in_rad_ids = Reference::rad_delegate(namespace.clone(), &delegate)
+                    .with_remote(remote_peer)
+                    .to_string();
+                repo.refname_to_id(&in_rad_ids).ok()
+            })
+            .map(Ok)
+            .unwrap_or_else(|| {
+                storage
+                    .as_raw()
+                    .refname_to_id(&Reference::rad_id(Namespace::from(&delegate)).to_string())
+            })
+    }
+}
+
+/// Read the `rad/signed_refs` of `peer`, preferring the ones fetched into
+/// `repo` over the ones already in `storage`.
+fn signed_refs(
+    storage: &Storage,
+    repo: &git2::Repository,
+    urn: &Urn,
+    peer: PeerId,
+) -> Result<Option<Refs>, Error> {
+    let branch = Reference::rad_signed_refs(Namespace::from(urn), peer);
+    let blob = ext::Blob::Tip {
+        branch: (&branch).into(),
+        path: Path::new(refs::stored::BLOB_PATH),
+    }
+    .get(repo);
+
+    match blob {
+        Ok(blob) => Ok(Some(
+            refs::Signed::<refs::Verified>::from_json(blob.content(), &peer)
+                .map(Refs::from)
+                .map_err(refs::stored::Error::from)?,
+        )),
+        Err(ext::blob::Error::NotFound(_)) => Ok(Refs::load(storage, urn, peer)?),
+        Err(e) => Err(storage::Error::from(e).into()),
+    }
+}
+
+fn plan_update(
+    storage: &Storage,
+    updates: &mut BTreeMap<ext::RefLike, RefUpdate>,
+    name: ext::RefLike,
+    new: ext::Oid,
+) -> Result<(), Error> {
+    let old = storage
+        .as_raw()
+        .refname_to_id(name.as_str())
+        .map(|oid| Some(ext::Oid::from(oid)))
+        .or_matches(is_not_found_err, || Ok(None))
+        .map_err(storage::Error::from)?;
+
+    if old != Some(new) {
+        updates.insert(name, RefUpdate { old, new });
+    }
+
+    Ok(())
+}
+
+fn remote_of(urn: &Urn, name: &ext::RefLike) -> Option<PeerId> {
+    let remotes = reflike!("refs/namespaces")
+        .join(urn)
+        .join(reflike!("refs/remotes"));
+    name.strip_prefix(&remotes)
+        .ok()
+        .and_then(|suffix| suffix.as_str().split('/').next()?.parse().ok())
+}
@@ -0,0 +1,153 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Policies deciding which of the data fetched during replication is admitted
+//! to the storage.
+//!
+//! Policies are applied to refs: a rejected update is rolled back after the
+//! fetch, and the objects it introduced are left to be garbage collected.
+
+use std::{fmt, ops::Deref, sync::Arc};
+
+use git_ext as ext;
+
+use crate::{identities::git::Urn, peer::PeerId};
+
+/// A ref which was updated when fetching the heads of a peer.
+#[derive(Clone, Debug)]
+pub struct Update<'a> {
+    /// The identity being replicated.
+    pub urn: &'a Urn,
+    /// The peer the ref belongs to (not necessarily the peer we fetched from).
+    pub peer: PeerId,
+    /// The fully qualified name of the remote tracking branch, eg.
+    /// `refs/namespaces/<urn>/refs/remotes/<peer>/heads/master`.
+    pub name: &'a ext::RefLike,
+    /// The target of the ref before the fetch, or `None` if it didn't exist.
+    pub old: Option<ext::Oid>,
+    /// The target of the ref after the fetch.
+    pub new: ext::Oid,
+    /// The size in bytes of the blobs introduced by the update, ie. the ones
+    /// reachable from `new`, but not from `old`.
+    pub size: u64,
+}
+
+/// The verdict of a [`ReplicationPolicy`] about an [`Update`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// Keep the update.
+    Accept,
+    /// Roll back the update, ie. reset the ref to its previous target, or
+    /// delete it if it didn't exist before.
+    Reject,
+    /// Roll back the update, but keep the new target in the quarantine
+    /// namespace (see [`quarantine`]), so it can be inspected and adopted
+    /// manually.
+    Quarantine,
+}
+
+/// Decides on each ref updated while replicating the heads of tracked peers.
+///
+/// Note that the identity branches (`rad/*`) are not subject to the policy,
+/// as they are required for verification.
+pub trait ReplicationPolicy: Send + Sync {
+    fn decide(&self, update: &Update) -> Decision;
+}
+
+impl<F> ReplicationPolicy for F
+where
+    F: Fn(&Update) -> Decision + Send + Sync,
+{
+    fn decide(&self, update: &Update) -> Decision {
+        self(update)
+    }
+}
+
+/// The default [`ReplicationPolicy`], which accepts everything.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Permissive;
+
+impl ReplicationPolicy for Permissive {
+    fn decide(&self, _: &Update) -> Decision {
+        Decision::Accept
+    }
+}
+
+/// A shareable [`ReplicationPolicy`], as used in
+/// [`super::Config::policy`].
+#[derive(Clone)]
+pub struct Policy(Arc<dyn ReplicationPolicy>);
+
+impl Policy {
+    pub fn new<P>(policy: P) -> Self
+    where
+        P: ReplicationPolicy + 'static,
+    {
+        Self(Arc::new(policy))
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self::new(Permissive)
+    }
+}
+
+impl fmt::Debug for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Policy(..)")
+    }
+}
+
+impl Deref for Policy {
+    type Target = dyn ReplicationPolicy;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+/// The name of the ref a quarantined update of `name` is kept at.
+///
+/// `refs/namespaces/<urn>/refs/remotes/<peer>/heads/master` becomes
+/// `refs/quarantine/<urn>/remotes/<peer>/heads/master`. The quarantine
+/// namespace lies outside of `refs/namespaces`, so quarantined refs are
+/// neither advertised to other peers, nor considered by any identity or
+/// signed refs operations.
+pub fn quarantine(urn: &Urn, name: &ext::RefLike) -> ext::RefLike {
+    let namespace = reflike!("refs/namespaces").join(urn).join(reflike!("refs"));
+    let suffix = name
+        .strip_prefix(&namespace)
+        .unwrap_or_else(|_| name.clone());
+
+    reflike!("refs/quarantine").join(urn).join(suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::keys::SecretKey;
+
+    #[test]
+    fn quarantine_is_outside_of_namespaces() {
+        let urn = Urn::new(git2::Oid::zero().into());
+        let peer = PeerId::from(SecretKey::new());
+        let branch = reflike!("remotes")
+            .join(peer)
+            .join(reflike!("heads/master"));
+
+        assert_eq!(
+            reflike!("refs/quarantine").join(&urn).join(&branch),
+            quarantine(
+                &urn,
+                &reflike!("refs/namespaces")
+                    .join(&urn)
+                    .join(reflike!("refs"))
+                    .join(&branch)
+            )
+        )
+    }
+}
@@ -3,13 +3,17 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{convert::TryFrom, fmt::Debug, path::Path};
+use std::{
+    convert::TryFrom,
+    io,
+    ops::Deref,
+    process::{Command, ExitStatus},
+};
 
-use git_ext::{self as ext, blob, is_not_found_err, RefLike, RefspecPattern};
-use std_ext::result::ResultExt as _;
+use git_ext::{self as ext, is_not_found_err};
 use thiserror::Error;
 
-use super::types::{reference, Many, Namespace, One, Reference};
+use super::types::reference;
 use crate::{
     identities::git::Identities,
     paths::Paths,
@@ -17,13 +21,19 @@ use crate::{
     signer::{BoxedSigner, Signer, SomeSigner},
 };
 
+pub mod backend;
 pub mod config;
+pub mod fsck;
 pub mod glob;
 pub mod pool;
+pub mod read;
+pub mod transaction;
 
 pub use config::Config;
 pub use glob::Pattern;
-pub use pool::{Pool, Pooled};
+pub use pool::{Pool, Pooled, ReadOnlyPool, ReadOnlyPooled};
+pub use read::ReadOnly;
+pub use transaction::Transaction;
 
 // FIXME: should be at the crate root
 pub use crate::identities::git::Urn;
@@ -43,14 +53,28 @@ pub enum Error {
     #[error(transparent)]
     Blob(#[from] ext::blob::Error),
 
+    #[error(transparent)]
+    Transaction(#[from] transaction::Error),
+
+    #[error("`git gc` failed with {0}")]
+    Gc(ExitStatus),
+
+    #[error("the spawned blocking task was cancelled")]
+    Cancelled,
+
     #[error(transparent)]
     Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
 }
 
 /// Low-level operations on the link "monorepo".
+///
+/// The read-only operations are provided by [`ReadOnly`], which a `Storage`
+/// dereferences to.
 pub struct Storage {
-    backend: git2::Repository,
-    peer_id: PeerId,
+    inner: ReadOnly,
     signer: BoxedSigner,
 }
 
@@ -60,22 +84,25 @@ impl Storage {
         S: Signer + Clone,
         S::Error: std::error::Error + Send + Sync + 'static,
     {
-        crate::git::init();
-
-        let backend = git2::Repository::open_bare(paths.git_dir())?;
-        let peer_id = Config::try_from(&backend)?.peer_id()?;
-
-        if peer_id != PeerId::from_signer(&signer) {
+        let inner = ReadOnly::open(paths)?;
+        if inner.peer_id != PeerId::from_signer(&signer) {
             return Err(Error::SignerKeyMismatch);
         }
 
         Ok(Self {
-            backend,
-            peer_id,
+            inner,
             signer: BoxedSigner::from(SomeSigner { signer }),
         })
     }
 
+    /// Open the storage at `paths` for reading only.
+    ///
+    /// Unlike [`Storage::open`], this does not require a [`Signer`]. The
+    /// storage must have been initialised before.
+    pub fn open_readonly(paths: &Paths) -> Result<ReadOnly, Error> {
+        ReadOnly::open(paths)
+    }
+
     pub fn init<S>(paths: &Paths, signer: S) -> Result<Self, Error>
     where
         S: Signer + Clone,
@@ -94,8 +121,7 @@ impl Storage {
         let peer_id = PeerId::from_signer(&signer);
 
         Ok(Self {
-            backend,
-            peer_id,
+            inner: ReadOnly { backend, peer_id },
             signer: BoxedSigner::from(SomeSigner { signer }),
         })
     }
@@ -115,187 +141,70 @@ impl Storage {
         }
     }
 
-    pub fn peer_id(&self) -> &PeerId {
-        &self.peer_id
-    }
-
-    pub fn path(&self) -> &Path {
-        &self.backend.path()
-    }
-
-    #[tracing::instrument(level = "debug", skip(self), err)]
-    pub fn has_urn(&self, urn: &Urn) -> Result<bool, Error> {
-        self.has_ref(&Reference::try_from(urn)?)
+    /// Open another handle to the same underlying repository, using the same
+    /// signer as `self`.
+    ///
+    /// A [`Storage`] can not be shared between threads, so this is useful for
+    /// performing operations in parallel.
+    pub fn reopen(&self) -> Result<Self, Error> {
+        Ok(Self {
+            inner: self.inner.reopen()?,
+            signer: self.signer.clone(),
+        })
     }
 
-    #[tracing::instrument(level = "debug", skip(self), err)]
-    pub fn has_ref<'a>(&self, reference: &'a Reference<One>) -> Result<bool, Error> {
-        self.backend
-            .find_reference(RefLike::from(reference).as_str())
-            .and(Ok(true))
-            .or_matches(is_not_found_err, || Ok(false))
+    /// Start a [`Transaction`], so as to update multiple refs together.
+    pub fn transaction(&self) -> Result<Transaction, Error> {
+        Ok(Transaction::new(self.as_raw())?)
     }
 
-    #[tracing::instrument(level = "debug", skip(self), err)]
-    pub fn has_commit<Oid>(&self, urn: &Urn, oid: Oid) -> Result<bool, Error>
-    where
-        Oid: AsRef<git2::Oid> + Debug,
-    {
-        let oid = oid.as_ref();
-        if oid.is_zero() {
-            // XXX: should this be a panic or error?
-            tracing::warn!("zero oid");
-            return Ok(false);
-        }
-
-        self.backend
-            .find_commit(*oid)
-            .and_then(|commit| {
-                let namespace = Namespace::from(urn);
-                let branch = {
-                    let path = match &urn.path {
-                        Some(refl) => refl.as_str(),
-                        None => "rad/id",
-                    };
-                    path.strip_prefix("refs/").unwrap_or(path)
-                };
-                self.backend
-                    .refname_to_id(&format!("refs/namespaces/{}/refs/{}", namespace, branch))
-                    .and_then(|tip| {
-                        Ok(tip == commit.id()
-                            || self.backend.graph_descendant_of(tip, commit.id())?)
-                    })
-                    .or_matches(is_not_found_err, || Ok(false))
-            })
-            .or_matches(is_not_found_err, || Ok(false))
+    pub fn config(&self) -> Result<Config<BoxedSigner>, Error> {
+        Ok(Config::try_from(self)?)
     }
 
+    /// Repack the objects and pack the refs of the underlying repository,
+    /// removing unreachable objects which have expired.
+    ///
+    /// This is not supported by `libgit2`, so the `git` executable is invoked.
+    /// It is safe to call this while other handles to the storage are in use,
+    /// as `git gc` does not discard recently created objects.
     #[tracing::instrument(level = "debug", skip(self), err)]
-    pub fn has_object<Oid>(&self, oid: Oid) -> Result<bool, Error>
-    where
-        Oid: AsRef<git2::Oid> + Debug,
-    {
-        let oid = oid.as_ref();
-        if oid.is_zero() {
-            return Ok(false);
-        }
-
-        Ok(self.backend.odb()?.exists(*oid))
-    }
-
-    #[tracing::instrument(level = "trace", skip(self), err)]
-    pub fn tip(&self, urn: &Urn) -> Result<Option<ext::Oid>, Error> {
-        let reference = self
-            .backend
-            .find_reference(RefLike::from(&Reference::try_from(urn)?).as_str())
-            .map(Some)
-            .or_matches::<Error, _, _>(is_not_found_err, || Ok(None))?;
-
-        match reference {
-            None => Ok(None),
-            Some(r) => Ok(Some(r.peel_to_commit()?.id().into())),
+    pub fn gc(&self) -> Result<(), Error> {
+        let status = Command::new("git")
+            .envs(::std::env::vars().filter(|(key, _)| key.starts_with("GIT_TRACE")))
+            .current_dir(self.path())
+            .args(&["gc", "--quiet"])
+            .status()?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::Gc(status))
         }
     }
 
-    #[tracing::instrument(level = "trace", skip(self), err)]
-    pub fn reference<'a>(
-        &'a self,
-        reference: &Reference<One>,
-    ) -> Result<Option<git2::Reference<'a>>, Error> {
-        reference
-            .find(&self.backend)
-            .map(Some)
-            .or_matches(is_not_found_err, || Ok(None))
-    }
-
-    #[tracing::instrument(level = "trace", skip(self), err)]
-    pub fn references<'a>(
-        &'a self,
-        reference: &Reference<Many>,
-    ) -> Result<impl Iterator<Item = Result<git2::Reference<'a>, Error>> + 'a, Error> {
-        self.references_glob(glob::RefspecMatcher::from(RefspecPattern::from(reference)))
-    }
-
-    #[tracing::instrument(level = "trace", skip(self), err)]
-    pub fn reference_names<'a>(
-        &'a self,
-        reference: &Reference<Many>,
-    ) -> Result<impl Iterator<Item = Result<ext::RefLike, Error>> + 'a, Error> {
-        self.reference_names_glob(glob::RefspecMatcher::from(RefspecPattern::from(reference)))
-    }
-
-    #[tracing::instrument(level = "trace", skip(self), err)]
-    pub fn references_glob<'a, G: 'a>(
-        &'a self,
-        glob: G,
-    ) -> Result<impl Iterator<Item = Result<git2::Reference<'a>, Error>> + 'a, Error>
-    where
-        G: Pattern + Debug,
-    {
-        Ok(self
-            .backend
-            .references()?
-            .filter_map(move |reference| match reference {
-                Ok(reference) => match reference.name() {
-                    Some(name) if glob.matches(name) => Some(Ok(reference)),
-                    _ => None,
-                },
-
-                Err(e) => Some(Err(e.into())),
-            }))
-    }
-
-    #[tracing::instrument(level = "trace", skip(self), err)]
-    pub fn reference_names_glob<'a, G: 'a>(
-        &'a self,
-        glob: G,
-    ) -> Result<impl Iterator<Item = Result<ext::RefLike, Error>> + 'a, Error>
-    where
-        G: Pattern + Debug,
-    {
-        let iter = ReferenceNames {
-            iter: self.backend.references()?,
-        };
-        Ok(iter.filter_map(move |refname| match refname {
-            Ok(reflike) if glob.matches(&reflike) => Some(Ok(reflike)),
-            Ok(_) => None,
-
-            Err(e) => Some(Err(e)),
-        }))
-    }
-
-    #[tracing::instrument(level = "trace", skip(self), err)]
-    pub fn blob<'a>(
-        &'a self,
-        reference: &'a Reference<One>,
-        path: &'a Path,
-    ) -> Result<Option<git2::Blob<'a>>, Error> {
-        ext::Blob::Tip {
-            branch: reference.into(),
-            path,
-        }
-        .get(self.as_raw())
-        .map(Some)
-        .or_matches(|e| matches!(e, blob::Error::NotFound(_)), || Ok(None))
-    }
-
-    pub fn config(&self) -> Result<Config<BoxedSigner>, Error> {
-        Ok(Config::try_from(self)?)
-    }
-
     pub(super) fn signer(&self) -> &BoxedSigner {
         &self.signer
     }
 
+    /// Access the identities stored in `self`, for reading and writing.
+    ///
+    /// Shadows [`ReadOnly::identities`], which only allows reading.
     pub(super) fn identities<'a, T: 'a>(&'a self) -> Identities<'a, T> {
         Identities::from(self.as_raw())
     }
 
-    // TODO: we would need to wrap a few more low-level git operations (such as:
-    // create commit, manipulate refs, manipulate config) in order to be able to
-    // model "capabilities" in terms of traits.
+    // TODO: see [`ReadOnly::as_raw`]
     pub(super) fn as_raw(&self) -> &git2::Repository {
-        &self.backend
+        self.inner.as_raw()
+    }
+}
+
+impl Deref for Storage {
+    type Target = ReadOnly;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
     }
 }
 
@@ -305,25 +214,8 @@ impl AsRef<Storage> for Storage {
     }
 }
 
-struct ReferenceNames<'a> {
-    iter: git2::References<'a>,
-}
-
-impl<'a> Iterator for ReferenceNames<'a> {
-    type Item = Result<ext::RefLike, Error>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let names = self.iter.names();
-        for name in names {
-            match name {
-                Err(e) => return Some(Err(e.into())),
-                Ok(name) => match ext::RefLike::try_from(name).ok() {
-                    Some(refl) => return Some(Ok(refl)),
-                    None => continue,
-                },
-            }
-        }
-
-        None
+impl AsRef<ReadOnly> for Storage {
+    fn as_ref(&self) -> &ReadOnly {
+        &self.inner
     }
 }
@@ -0,0 +1,668 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Capabilities of the storage backend.
+//!
+//! The traits in this module model the low-level operations on refs, objects
+//! and the configuration which higher layers rely on. The link "monorepo"
+//! ([`git2::Repository`], and by extension [`super::Storage`]) is one
+//! implementation, [`memory::Memory`] is another one which is useful for unit
+//! tests which should not touch the disk. A [`super::ReadOnly`] storage only
+//! implements the reading capabilities.
+//!
+//! Code which only needs a subset of the capabilities should be generic over
+//! the respective traits, eg. `B: ReadConfig`, rather than depending on
+//! [`super::Storage`].
+
+use std::{convert::TryFrom, path::Path};
+
+use git_ext::{self as ext, is_not_found_err};
+use std_ext::result::ResultExt as _;
+
+use super::{
+    super::types::Force,
+    glob::{self, Pattern as _},
+    ReadOnly,
+    Storage,
+};
+
+pub mod memory;
+pub use memory::Memory;
+
+/// A storage backend.
+pub trait Backend {
+    type Error: std::error::Error + Send + Sync + 'static;
+}
+
+/// Reading refs.
+pub trait ReadRefs: Backend {
+    /// The target of the ref `name`, following symbolic refs.
+    ///
+    /// `None` is returned if the ref (or the target of a symbolic ref) does not
+    /// exist.
+    fn ref_target(&self, name: &ext::RefLike) -> Result<Option<ext::Oid>, Self::Error>;
+
+    /// The target of the symbolic ref `name`.
+    ///
+    /// `None` is returned if the ref does not exist, or is not symbolic.
+    fn symbolic_ref_target(&self, name: &ext::RefLike)
+        -> Result<Option<ext::RefLike>, Self::Error>;
+
+    /// The names of all refs matching `glob`.
+    fn ref_names(&self, glob: &glob::RefspecMatcher) -> Result<Vec<ext::RefLike>, Self::Error>;
+}
+
+/// Creating, updating and removing refs.
+pub trait WriteRefs: ReadRefs {
+    /// Point the ref `name` to `target`.
+    ///
+    /// If `force` is [`Force::False`] and the ref already exists, an error is
+    /// returned.
+    fn set_ref(
+        &self,
+        name: &ext::RefLike,
+        target: ext::Oid,
+        force: Force,
+        msg: &str,
+    ) -> Result<(), Self::Error>;
+
+    /// Make `name` a symbolic ref pointing to `target`.
+    ///
+    /// If `force` is [`Force::False`] and the ref already exists, an error is
+    /// returned.
+    fn set_symbolic_ref(
+        &self,
+        name: &ext::RefLike,
+        target: &ext::RefLike,
+        force: Force,
+        msg: &str,
+    ) -> Result<(), Self::Error>;
+
+    /// Remove the ref `name`, returning `true` if it existed.
+    fn remove_ref(&self, name: &ext::RefLike) -> Result<bool, Self::Error>;
+}
+
+/// A commit, as far as the higher layers are concerned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Commit {
+    pub tree: ext::Oid,
+    pub parents: Vec<ext::Oid>,
+    /// Seconds since the epoch.
+    pub time: i64,
+    /// `None` if the message is not valid UTF-8.
+    pub message: Option<String>,
+}
+
+/// An entry of a tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeEntry {
+    pub name: String,
+    pub oid: ext::Oid,
+    pub kind: Option<git2::ObjectType>,
+}
+
+/// Reading objects.
+pub trait ReadObjects: Backend {
+    fn has_object(&self, oid: ext::Oid) -> Result<bool, Self::Error>;
+
+    /// The content of the blob `oid`, or `None` if it doesn't exist.
+    fn read_blob(&self, oid: ext::Oid) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// The commit `oid`, or `None` if it doesn't exist.
+    fn read_commit(&self, oid: ext::Oid) -> Result<Option<Commit>, Self::Error>;
+
+    /// The entries of the tree `oid`, or `None` if it doesn't exist.
+    fn read_tree(&self, oid: ext::Oid) -> Result<Option<Vec<TreeEntry>>, Self::Error>;
+
+    /// The entry at `path` relative to the tree `tree`, or `None` if there is
+    /// no such entry.
+    fn tree_entry(&self, tree: ext::Oid, path: &Path) -> Result<Option<TreeEntry>, Self::Error> {
+        let mut entry = None;
+        let mut current = tree;
+        for name in path.iter() {
+            entry = self.read_tree(current)?.and_then(|entries| {
+                entries
+                    .into_iter()
+                    .find(|entry| name.to_str() == Some(entry.name.as_str()))
+            });
+            match &entry {
+                None => return Ok(None),
+                Some(entry) => current = entry.oid,
+            }
+        }
+
+        Ok(entry)
+    }
+
+    /// The content of the blob at `path` in the tree of the commit `commit`.
+    ///
+    /// `None` is returned if the commit doesn't exist, or there is no blob at
+    /// `path`.
+    fn read_blob_at(&self, commit: ext::Oid, path: &Path) -> Result<Option<Vec<u8>>, Self::Error> {
+        let tree = match self.read_commit(commit)? {
+            None => return Ok(None),
+            Some(commit) => commit.tree,
+        };
+        match self.tree_entry(tree, path)? {
+            Some(entry) if entry.kind == Some(git2::ObjectType::Blob) => self.read_blob(entry.oid),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Writing objects.
+pub trait WriteObjects: ReadObjects {
+    /// Write `data` as a blob, returning its [`ext::Oid`].
+    fn write_blob(&self, data: &[u8]) -> Result<ext::Oid, Self::Error>;
+
+    /// Write a tree consisting of `entries`, returning its [`ext::Oid`].
+    fn write_tree(&self, entries: &[TreeEntry]) -> Result<ext::Oid, Self::Error>;
+
+    /// Commit `tree` on top of `parents`, returning the [`ext::Oid`] of the
+    /// commit.
+    fn write_commit(
+        &self,
+        tree: ext::Oid,
+        parents: &[ext::Oid],
+        message: &str,
+    ) -> Result<ext::Oid, Self::Error>;
+}
+
+/// Reading configuration values.
+///
+/// Keys are in the format understood by `git config`, eg.
+/// `remote.origin.url`. Like in `git`, the section and variable names are
+/// case-insensitive, while subsection names are not. Multi-valued keys are not
+/// supported.
+pub trait ReadConfig: Backend {
+    fn config_str(&self, key: &str) -> Result<Option<String>, Self::Error>;
+    fn config_bool(&self, key: &str) -> Result<Option<bool>, Self::Error>;
+    fn config_i64(&self, key: &str) -> Result<Option<i64>, Self::Error>;
+
+    /// All keys starting with `prefix`.
+    ///
+    /// The keys are returned in canonical form, ie. with the section and
+    /// variable names in lowercase. The section name of `prefix` is matched
+    /// case-insensitively.
+    fn config_keys(&self, prefix: &str) -> Result<Vec<String>, Self::Error>;
+}
+
+/// Writing configuration values.
+pub trait WriteConfig: ReadConfig {
+    fn set_config_str(&self, key: &str, value: &str) -> Result<(), Self::Error>;
+    fn set_config_bool(&self, key: &str, value: bool) -> Result<(), Self::Error>;
+    fn set_config_i64(&self, key: &str, value: i64) -> Result<(), Self::Error>;
+
+    /// Remove `key`, returning `true` if it existed.
+    fn remove_config(&self, key: &str) -> Result<bool, Self::Error>;
+}
+
+/// The file mode of a tree entry of `kind`.
+fn file_mode(kind: Option<git2::ObjectType>) -> i32 {
+    match kind {
+        Some(git2::ObjectType::Tree) => 0o040_000,
+        Some(git2::ObjectType::Commit) => 0o160_000,
+        _ => 0o100_644,
+    }
+}
+
+/// Lowercase the section name of the config `key` (or key prefix).
+fn lowercase_section(key: &str) -> String {
+    match key.find('.') {
+        None => key.to_lowercase(),
+        Some(i) => format!("{}{}", key[..i].to_lowercase(), &key[i..]),
+    }
+}
+
+impl Backend for git2::Repository {
+    type Error = git2::Error;
+}
+
+impl ReadRefs for git2::Repository {
+    fn ref_target(&self, name: &ext::RefLike) -> Result<Option<ext::Oid>, Self::Error> {
+        self.refname_to_id(name.as_str())
+            .map(|oid| Some(oid.into()))
+            .or_matches(is_not_found_err, || Ok(None))
+    }
+
+    fn symbolic_ref_target(
+        &self,
+        name: &ext::RefLike,
+    ) -> Result<Option<ext::RefLike>, Self::Error> {
+        let reference = self
+            .find_reference(name.as_str())
+            .map(Some)
+            .or_matches::<git2::Error, _, _>(is_not_found_err, || Ok(None))?;
+        Ok(reference.and_then(|r| {
+            r.symbolic_target()
+                .and_then(|target| ext::RefLike::try_from(target).ok())
+        }))
+    }
+
+    fn ref_names(&self, glob: &glob::RefspecMatcher) -> Result<Vec<ext::RefLike>, Self::Error> {
+        let mut names = Vec::new();
+        for reference in self.references()? {
+            if let Some(name) = reference?.name() {
+                if glob.matches(name) {
+                    if let Ok(name) = ext::RefLike::try_from(name) {
+                        names.push(name)
+                    }
+                }
+            }
+        }
+        Ok(names)
+    }
+}
+
+impl WriteRefs for git2::Repository {
+    fn set_ref(
+        &self,
+        name: &ext::RefLike,
+        target: ext::Oid,
+        force: Force,
+        msg: &str,
+    ) -> Result<(), Self::Error> {
+        self.reference(
+            name.as_str(),
+            target.into(),
+            matches!(force, Force::True),
+            msg,
+        )
+        .map(|_| ())
+    }
+
+    fn set_symbolic_ref(
+        &self,
+        name: &ext::RefLike,
+        target: &ext::RefLike,
+        force: Force,
+        msg: &str,
+    ) -> Result<(), Self::Error> {
+        self.reference_symbolic(
+            name.as_str(),
+            target.as_str(),
+            matches!(force, Force::True),
+            msg,
+        )
+        .map(|_| ())
+    }
+
+    fn remove_ref(&self, name: &ext::RefLike) -> Result<bool, Self::Error> {
+        self.find_reference(name.as_str())
+            .and_then(|mut r| r.delete())
+            .map(|()| true)
+            .or_matches(is_not_found_err, || Ok(false))
+    }
+}
+
+impl ReadObjects for git2::Repository {
+    fn has_object(&self, oid: ext::Oid) -> Result<bool, Self::Error> {
+        Ok(self.odb()?.exists(oid.into()))
+    }
+
+    fn read_blob(&self, oid: ext::Oid) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.find_blob(oid.into())
+            .map(|blob| Some(blob.content().to_vec()))
+            .or_matches(is_not_found_err, || Ok(None))
+    }
+
+    fn read_commit(&self, oid: ext::Oid) -> Result<Option<Commit>, Self::Error> {
+        self.find_commit(oid.into())
+            .map(|commit| {
+                Some(Commit {
+                    tree: commit.tree_id().into(),
+                    parents: commit.parent_ids().map(ext::Oid::from).collect(),
+                    time: commit.time().seconds(),
+                    message: commit.message().map(str::to_owned),
+                })
+            })
+            .or_matches(is_not_found_err, || Ok(None))
+    }
+
+    fn read_tree(&self, oid: ext::Oid) -> Result<Option<Vec<TreeEntry>>, Self::Error> {
+        self.find_tree(oid.into())
+            .map(|tree| {
+                Some(
+                    tree.iter()
+                        .map(|entry| TreeEntry {
+                            name: String::from_utf8_lossy(entry.name_bytes()).into_owned(),
+                            oid: entry.id().into(),
+                            kind: entry.kind(),
+                        })
+                        .collect(),
+                )
+            })
+            .or_matches(is_not_found_err, || Ok(None))
+    }
+}
+
+impl WriteObjects for git2::Repository {
+    fn write_blob(&self, data: &[u8]) -> Result<ext::Oid, Self::Error> {
+        self.blob(data).map(ext::Oid::from)
+    }
+
+    fn write_tree(&self, entries: &[TreeEntry]) -> Result<ext::Oid, Self::Error> {
+        let mut builder = self.treebuilder(None)?;
+        for entry in entries {
+            builder.insert(&entry.name, entry.oid.into(), file_mode(entry.kind))?;
+        }
+        builder.write().map(ext::Oid::from)
+    }
+
+    fn write_commit(
+        &self,
+        tree: ext::Oid,
+        parents: &[ext::Oid],
+        message: &str,
+    ) -> Result<ext::Oid, Self::Error> {
+        let tree = self.find_tree(tree.into())?;
+        let parents = parents
+            .iter()
+            .map(|parent| self.find_commit((*parent).into()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let author = self.signature()?;
+        self.commit(
+            None,
+            &author,
+            &author,
+            message,
+            &tree,
+            &parents.iter().collect::<Vec<_>>(),
+        )
+        .map(ext::Oid::from)
+    }
+}
+
+impl ReadConfig for git2::Repository {
+    fn config_str(&self, key: &str) -> Result<Option<String>, Self::Error> {
+        self.config()?
+            .get_string(key)
+            .map(Some)
+            .or_matches(is_not_found_err, || Ok(None))
+    }
+
+    fn config_bool(&self, key: &str) -> Result<Option<bool>, Self::Error> {
+        self.config()?
+            .get_bool(key)
+            .map(Some)
+            .or_matches(is_not_found_err, || Ok(None))
+    }
+
+    fn config_i64(&self, key: &str) -> Result<Option<i64>, Self::Error> {
+        self.config()?
+            .get_i64(key)
+            .map(Some)
+            .or_matches(is_not_found_err, || Ok(None))
+    }
+
+    fn config_keys(&self, prefix: &str) -> Result<Vec<String>, Self::Error> {
+        let config = self.config()?;
+        let prefix = lowercase_section(prefix);
+        let mut keys = Vec::new();
+        for entry in &config.entries(None)? {
+            if let Some(name) = entry?.name() {
+                if name.starts_with(&prefix) {
+                    keys.push(name.to_owned())
+                }
+            }
+        }
+        Ok(keys)
+    }
+}
+
+impl WriteConfig for git2::Repository {
+    fn set_config_str(&self, key: &str, value: &str) -> Result<(), Self::Error> {
+        self.config()?.set_str(key, value)
+    }
+
+    fn set_config_bool(&self, key: &str, value: bool) -> Result<(), Self::Error> {
+        self.config()?.set_bool(key, value)
+    }
+
+    fn set_config_i64(&self, key: &str, value: i64) -> Result<(), Self::Error> {
+        self.config()?.set_i64(key, value)
+    }
+
+    fn remove_config(&self, key: &str) -> Result<bool, Self::Error> {
+        self.config()?
+            .remove(key)
+            .map(|()| true)
+            .or_matches(is_not_found_err, || Ok(false))
+    }
+}
+
+impl<B: Backend + ?Sized> Backend for &B {
+    type Error = B::Error;
+}
+
+impl<B: ReadRefs + ?Sized> ReadRefs for &B {
+    fn ref_target(&self, name: &ext::RefLike) -> Result<Option<ext::Oid>, Self::Error> {
+        (**self).ref_target(name)
+    }
+
+    fn symbolic_ref_target(
+        &self,
+        name: &ext::RefLike,
+    ) -> Result<Option<ext::RefLike>, Self::Error> {
+        (**self).symbolic_ref_target(name)
+    }
+
+    fn ref_names(&self, glob: &glob::RefspecMatcher) -> Result<Vec<ext::RefLike>, Self::Error> {
+        (**self).ref_names(glob)
+    }
+}
+
+impl<B: ReadObjects + ?Sized> ReadObjects for &B {
+    fn has_object(&self, oid: ext::Oid) -> Result<bool, Self::Error> {
+        (**self).has_object(oid)
+    }
+
+    fn read_blob(&self, oid: ext::Oid) -> Result<Option<Vec<u8>>, Self::Error> {
+        (**self).read_blob(oid)
+    }
+
+    fn read_commit(&self, oid: ext::Oid) -> Result<Option<Commit>, Self::Error> {
+        (**self).read_commit(oid)
+    }
+
+    fn read_tree(&self, oid: ext::Oid) -> Result<Option<Vec<TreeEntry>>, Self::Error> {
+        (**self).read_tree(oid)
+    }
+}
+
+impl<B: ReadConfig + ?Sized> ReadConfig for &B {
+    fn config_str(&self, key: &str) -> Result<Option<String>, Self::Error> {
+        (**self).config_str(key)
+    }
+
+    fn config_bool(&self, key: &str) -> Result<Option<bool>, Self::Error> {
+        (**self).config_bool(key)
+    }
+
+    fn config_i64(&self, key: &str) -> Result<Option<i64>, Self::Error> {
+        (**self).config_i64(key)
+    }
+
+    fn config_keys(&self, prefix: &str) -> Result<Vec<String>, Self::Error> {
+        (**self).config_keys(prefix)
+    }
+}
+
+impl Backend for ReadOnly {
+    type Error = git2::Error;
+}
+
+impl ReadRefs for ReadOnly {
+    fn ref_target(&self, name: &ext::RefLike) -> Result<Option<ext::Oid>, Self::Error> {
+        self.as_raw().ref_target(name)
+    }
+
+    fn symbolic_ref_target(
+        &self,
+        name: &ext::RefLike,
+    ) -> Result<Option<ext::RefLike>, Self::Error> {
+        self.as_raw().symbolic_ref_target(name)
+    }
+
+    fn ref_names(&self, glob: &glob::RefspecMatcher) -> Result<Vec<ext::RefLike>, Self::Error> {
+        self.as_raw().ref_names(glob)
+    }
+}
+
+impl ReadObjects for ReadOnly {
+    fn has_object(&self, oid: ext::Oid) -> Result<bool, Self::Error> {
+        ReadObjects::has_object(self.as_raw(), oid)
+    }
+
+    fn read_blob(&self, oid: ext::Oid) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.as_raw().read_blob(oid)
+    }
+
+    fn read_commit(&self, oid: ext::Oid) -> Result<Option<Commit>, Self::Error> {
+        self.as_raw().read_commit(oid)
+    }
+
+    fn read_tree(&self, oid: ext::Oid) -> Result<Option<Vec<TreeEntry>>, Self::Error> {
+        self.as_raw().read_tree(oid)
+    }
+}
+
+impl ReadConfig for ReadOnly {
+    fn config_str(&self, key: &str) -> Result<Option<String>, Self::Error> {
+        self.as_raw().config_str(key)
+    }
+
+    fn config_bool(&self, key: &str) -> Result<Option<bool>, Self::Error> {
+        self.as_raw().config_bool(key)
+    }
+
+    fn config_i64(&self, key: &str) -> Result<Option<i64>, Self::Error> {
+        self.as_raw().config_i64(key)
+    }
+
+    fn config_keys(&self, prefix: &str) -> Result<Vec<String>, Self::Error> {
+        self.as_raw().config_keys(prefix)
+    }
+}
+
+impl Backend for Storage {
+    type Error = git2::Error;
+}
+
+impl ReadRefs for Storage {
+    fn ref_target(&self, name: &ext::RefLike) -> Result<Option<ext::Oid>, Self::Error> {
+        self.as_raw().ref_target(name)
+    }
+
+    fn symbolic_ref_target(
+        &self,
+        name: &ext::RefLike,
+    ) -> Result<Option<ext::RefLike>, Self::Error> {
+        self.as_raw().symbolic_ref_target(name)
+    }
+
+    fn ref_names(&self, glob: &glob::RefspecMatcher) -> Result<Vec<ext::RefLike>, Self::Error> {
+        self.as_raw().ref_names(glob)
+    }
+}
+
+impl WriteRefs for Storage {
+    fn set_ref(
+        &self,
+        name: &ext::RefLike,
+        target: ext::Oid,
+        force: Force,
+        msg: &str,
+    ) -> Result<(), Self::Error> {
+        self.as_raw().set_ref(name, target, force, msg)
+    }
+
+    fn set_symbolic_ref(
+        &self,
+        name: &ext::RefLike,
+        target: &ext::RefLike,
+        force: Force,
+        msg: &str,
+    ) -> Result<(), Self::Error> {
+        self.as_raw().set_symbolic_ref(name, target, force, msg)
+    }
+
+    fn remove_ref(&self, name: &ext::RefLike) -> Result<bool, Self::Error> {
+        self.as_raw().remove_ref(name)
+    }
+}
+
+impl ReadObjects for Storage {
+    fn has_object(&self, oid: ext::Oid) -> Result<bool, Self::Error> {
+        ReadObjects::has_object(self.as_raw(), oid)
+    }
+
+    fn read_blob(&self, oid: ext::Oid) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.as_raw().read_blob(oid)
+    }
+
+    fn read_commit(&self, oid: ext::Oid) -> Result<Option<Commit>, Self::Error> {
+        self.as_raw().read_commit(oid)
+    }
+
+    fn read_tree(&self, oid: ext::Oid) -> Result<Option<Vec<TreeEntry>>, Self::Error> {
+        self.as_raw().read_tree(oid)
+    }
+}
+
+impl WriteObjects for Storage {
+    fn write_blob(&self, data: &[u8]) -> Result<ext::Oid, Self::Error> {
+        self.as_raw().write_blob(data)
+    }
+
+    fn write_tree(&self, entries: &[TreeEntry]) -> Result<ext::Oid, Self::Error> {
+        self.as_raw().write_tree(entries)
+    }
+
+    fn write_commit(
+        &self,
+        tree: ext::Oid,
+        parents: &[ext::Oid],
+        message: &str,
+    ) -> Result<ext::Oid, Self::Error> {
+        self.as_raw().write_commit(tree, parents, message)
+    }
+}
+
+impl ReadConfig for Storage {
+    fn config_str(&self, key: &str) -> Result<Option<String>, Self::Error> {
+        self.as_raw().config_str(key)
+    }
+
+    fn config_bool(&self, key: &str) -> Result<Option<bool>, Self::Error> {
+        self.as_raw().config_bool(key)
+    }
+
+    fn config_i64(&self, key: &str) -> Result<Option<i64>, Self::Error> {
+        self.as_raw().config_i64(key)
+    }
+
+    fn config_keys(&self, prefix: &str) -> Result<Vec<String>, Self::Error> {
+        self.as_raw().config_keys(prefix)
+    }
+}
+
+impl WriteConfig for Storage {
+    fn set_config_str(&self, key: &str, value: &str) -> Result<(), Self::Error> {
+        self.as_raw().set_config_str(key, value)
+    }
+
+    fn set_config_bool(&self, key: &str, value: bool) -> Result<(), Self::Error> {
+        self.as_raw().set_config_bool(key, value)
+    }
+
+    fn set_config_i64(&self, key: &str, value: i64) -> Result<(), Self::Error> {
+        self.as_raw().set_config_i64(key, value)
+    }
+
+    fn remove_config(&self, key: &str) -> Result<bool, Self::Error> {
+        self.as_raw().remove_config(key)
+    }
+}
@@ -0,0 +1,460 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! An in-memory [`super::Backend`].
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use git_ext as ext;
+use thiserror::Error;
+
+use super::{
+    super::{
+        super::types::Force,
+        glob::{self, Pattern as _},
+    },
+    file_mode,
+    lowercase_section,
+    Backend,
+    Commit,
+    ReadConfig,
+    ReadObjects,
+    ReadRefs,
+    TreeEntry,
+    WriteConfig,
+    WriteObjects,
+    WriteRefs,
+};
+
+/// The maximum number of symbolic refs followed when resolving a ref, same as
+/// `git`.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("ref `{0}` already exists")]
+    Exists(ext::RefLike),
+
+    #[error("too many levels of symbolic refs when resolving `{0}`")]
+    SymrefDepth(ext::RefLike),
+
+    #[error("config value `{value}` of `{key}` is not a valid {ty}")]
+    Malformed {
+        key: String,
+        value: String,
+        ty: &'static str,
+    },
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Target {
+    Direct(ext::Oid),
+    Symbolic(ext::RefLike),
+}
+
+#[derive(Clone, Debug)]
+enum Object {
+    Blob(Vec<u8>),
+    Tree(Vec<TreeEntry>),
+    Commit(Commit),
+}
+
+/// A [`super::Backend`] which keeps everything in memory.
+///
+/// Blobs, trees and commits are supported as objects. Their [`ext::Oid`]s are
+/// computed the same way `git` does, so blobs and trees are interchangeable
+/// with those of a [`git2::Repository`]. Commits are authored by a fixed
+/// signature, and will thus have different [`ext::Oid`]s.
+///
+/// Config keys are stored in canonical form, ie. with the section and variable
+/// names in lowercase, like `git` does.
+#[derive(Debug, Default)]
+pub struct Memory {
+    refs: RefCell<BTreeMap<ext::RefLike, Target>>,
+    objects: RefCell<BTreeMap<ext::Oid, Object>>,
+    config: RefCell<BTreeMap<String, String>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn set(&self, name: &ext::RefLike, target: Target, force: Force) -> Result<(), Error> {
+        let mut refs = self.refs.borrow_mut();
+        if matches!(force, Force::False) && refs.contains_key(name) {
+            return Err(Error::Exists(name.clone()));
+        }
+        refs.insert(name.clone(), target);
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        kind: git2::ObjectType,
+        data: &[u8],
+        object: Object,
+    ) -> Result<ext::Oid, Error> {
+        let oid = ext::Oid::from(git2::Oid::hash_object(kind, data)?);
+        self.objects.borrow_mut().insert(oid, object);
+        Ok(oid)
+    }
+}
+
+/// Lowercase the section and variable names of the config `key`.
+fn canonical_key(key: &str) -> String {
+    let key = lowercase_section(key);
+    match key.rfind('.') {
+        None => key,
+        Some(i) => format!("{}{}", &key[..i], key[i..].to_lowercase()),
+    }
+}
+
+impl Backend for Memory {
+    type Error = Error;
+}
+
+impl ReadRefs for Memory {
+    fn ref_target(&self, name: &ext::RefLike) -> Result<Option<ext::Oid>, Self::Error> {
+        let refs = self.refs.borrow();
+        let mut current = name;
+        for _ in 0..=MAX_SYMREF_DEPTH {
+            match refs.get(current) {
+                None => return Ok(None),
+                Some(Target::Direct(oid)) => return Ok(Some(*oid)),
+                Some(Target::Symbolic(target)) => current = target,
+            }
+        }
+
+        Err(Error::SymrefDepth(name.clone()))
+    }
+
+    fn symbolic_ref_target(
+        &self,
+        name: &ext::RefLike,
+    ) -> Result<Option<ext::RefLike>, Self::Error> {
+        Ok(match self.refs.borrow().get(name) {
+            Some(Target::Symbolic(target)) => Some(target.clone()),
+            _ => None,
+        })
+    }
+
+    fn ref_names(&self, glob: &glob::RefspecMatcher) -> Result<Vec<ext::RefLike>, Self::Error> {
+        Ok(self
+            .refs
+            .borrow()
+            .keys()
+            .filter(|name| glob.matches(name.as_str()))
+            .cloned()
+            .collect())
+    }
+}
+
+impl WriteRefs for Memory {
+    fn set_ref(
+        &self,
+        name: &ext::RefLike,
+        target: ext::Oid,
+        force: Force,
+        _msg: &str,
+    ) -> Result<(), Self::Error> {
+        self.set(name, Target::Direct(target), force)
+    }
+
+    fn set_symbolic_ref(
+        &self,
+        name: &ext::RefLike,
+        target: &ext::RefLike,
+        force: Force,
+        _msg: &str,
+    ) -> Result<(), Self::Error> {
+        self.set(name, Target::Symbolic(target.clone()), force)
+    }
+
+    fn remove_ref(&self, name: &ext::RefLike) -> Result<bool, Self::Error> {
+        Ok(self.refs.borrow_mut().remove(name).is_some())
+    }
+}
+
+impl ReadObjects for Memory {
+    fn has_object(&self, oid: ext::Oid) -> Result<bool, Self::Error> {
+        Ok(self.objects.borrow().contains_key(&oid))
+    }
+
+    fn read_blob(&self, oid: ext::Oid) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(match self.objects.borrow().get(&oid) {
+            Some(Object::Blob(data)) => Some(data.clone()),
+            _ => None,
+        })
+    }
+
+    fn read_commit(&self, oid: ext::Oid) -> Result<Option<Commit>, Self::Error> {
+        Ok(match self.objects.borrow().get(&oid) {
+            Some(Object::Commit(commit)) => Some(commit.clone()),
+            _ => None,
+        })
+    }
+
+    fn read_tree(&self, oid: ext::Oid) -> Result<Option<Vec<TreeEntry>>, Self::Error> {
+        Ok(match self.objects.borrow().get(&oid) {
+            Some(Object::Tree(entries)) => Some(entries.clone()),
+            _ => None,
+        })
+    }
+}
+
+impl WriteObjects for Memory {
+    fn write_blob(&self, data: &[u8]) -> Result<ext::Oid, Self::Error> {
+        self.write(git2::ObjectType::Blob, data, Object::Blob(data.to_vec()))
+    }
+
+    fn write_tree(&self, entries: &[TreeEntry]) -> Result<ext::Oid, Self::Error> {
+        // Trees are sorted by name, where the names of subtrees sort as if they
+        // had a trailing slash
+        let mut entries = entries.to_vec();
+        entries.sort_by_key(|entry| {
+            let mut key = entry.name.clone().into_bytes();
+            if entry.kind == Some(git2::ObjectType::Tree) {
+                key.push(b'/')
+            }
+            key
+        });
+
+        let mut data = Vec::new();
+        for entry in &entries {
+            data.extend_from_slice(
+                format!("{:o} {}\0", file_mode(entry.kind), entry.name).as_bytes(),
+            );
+            data.extend_from_slice(git2::Oid::from(entry.oid).as_bytes());
+        }
+        self.write(git2::ObjectType::Tree, &data, Object::Tree(entries))
+    }
+
+    fn write_commit(
+        &self,
+        tree: ext::Oid,
+        parents: &[ext::Oid],
+        message: &str,
+    ) -> Result<ext::Oid, Self::Error> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs() as i64)
+            .unwrap_or_default();
+
+        let mut data = format!("tree {}\n", tree);
+        for parent in parents {
+            data.push_str(&format!("parent {}\n", parent));
+        }
+        for role in &["author", "committer"] {
+            data.push_str(&format!(
+                "{} radicle <radicle@localhost> {} +0000\n",
+                role, time
+            ));
+        }
+        data.push_str(&format!("\n{}", message));
+
+        self.write(
+            git2::ObjectType::Commit,
+            data.as_bytes(),
+            Object::Commit(Commit {
+                tree,
+                parents: parents.to_vec(),
+                time,
+                message: Some(message.to_owned()),
+            }),
+        )
+    }
+}
+
+impl ReadConfig for Memory {
+    fn config_str(&self, key: &str) -> Result<Option<String>, Self::Error> {
+        Ok(self.config.borrow().get(&canonical_key(key)).cloned())
+    }
+
+    fn config_bool(&self, key: &str) -> Result<Option<bool>, Self::Error> {
+        self.config_str(key)?
+            .map(|value| match value.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok(true),
+                "false" | "no" | "off" | "0" | "" => Ok(false),
+                _ => Err(Error::Malformed {
+                    key: key.to_owned(),
+                    value,
+                    ty: "bool",
+                }),
+            })
+            .transpose()
+    }
+
+    fn config_i64(&self, key: &str) -> Result<Option<i64>, Self::Error> {
+        self.config_str(key)?
+            .map(|value| {
+                value.parse().map_err(|_| Error::Malformed {
+                    key: key.to_owned(),
+                    value,
+                    ty: "integer",
+                })
+            })
+            .transpose()
+    }
+
+    fn config_keys(&self, prefix: &str) -> Result<Vec<String>, Self::Error> {
+        let prefix = lowercase_section(prefix);
+        Ok(self
+            .config
+            .borrow()
+            .keys()
+            .filter(|key| key.starts_with(&prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+impl WriteConfig for Memory {
+    fn set_config_str(&self, key: &str, value: &str) -> Result<(), Self::Error> {
+        self.config
+            .borrow_mut()
+            .insert(canonical_key(key), value.to_owned());
+        Ok(())
+    }
+
+    fn set_config_bool(&self, key: &str, value: bool) -> Result<(), Self::Error> {
+        self.set_config_str(key, if value { "true" } else { "false" })
+    }
+
+    fn set_config_i64(&self, key: &str, value: i64) -> Result<(), Self::Error> {
+        self.set_config_str(key, &value.to_string())
+    }
+
+    fn remove_config(&self, key: &str) -> Result<bool, Self::Error> {
+        Ok(self
+            .config
+            .borrow_mut()
+            .remove(&canonical_key(key))
+            .is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use crate::{git::storage::Storage, keys::SecretKey, paths::Paths};
+
+    #[test]
+    fn oids_match_git() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp.path()).unwrap();
+            let storage = Storage::open_or_init(&paths, SecretKey::new()).unwrap();
+            let memory = Memory::new();
+
+            let data = b"sneakernet";
+            let blob = memory.write_blob(data).unwrap();
+            assert_eq!(blob, storage.as_raw().write_blob(data).unwrap());
+            assert_eq!(memory.read_blob(blob).unwrap(), Some(data.to_vec()));
+
+            let leaf = vec![TreeEntry {
+                name: "refs".to_owned(),
+                oid: blob,
+                kind: Some(git2::ObjectType::Blob),
+            }];
+            let subtree = memory.write_tree(&leaf).unwrap();
+            assert_eq!(subtree, storage.as_raw().write_tree(&leaf).unwrap());
+
+            let entries = vec![
+                TreeEntry {
+                    name: "signed".to_owned(),
+                    oid: subtree,
+                    kind: Some(git2::ObjectType::Tree),
+                },
+                TreeEntry {
+                    name: "signed.json".to_owned(),
+                    oid: blob,
+                    kind: Some(git2::ObjectType::Blob),
+                },
+            ];
+            let tree = memory.write_tree(&entries).unwrap();
+            assert_eq!(tree, storage.as_raw().write_tree(&entries).unwrap());
+
+            let commit = memory.write_commit(tree, &[], "sneakernet").unwrap();
+            assert_eq!(
+                memory
+                    .read_blob_at(commit, Path::new("signed/refs"))
+                    .unwrap(),
+                Some(data.to_vec())
+            );
+            assert_eq!(
+                memory.read_blob_at(commit, Path::new("signed")).unwrap(),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn config_names_are_case_insensitive() {
+        let memory = Memory::new();
+        memory
+            .set_config_bool("Remote.Alice/Bob.radFollowRemotes", false)
+            .unwrap();
+
+        assert_eq!(
+            memory
+                .config_bool("remote.Alice/Bob.radfollowremotes")
+                .unwrap(),
+            Some(false)
+        );
+        assert_eq!(
+            memory.config_keys("REMOTE.Alice/").unwrap(),
+            vec!["remote.Alice/Bob.radfollowremotes".to_owned()]
+        );
+        assert!(memory.config_keys("remote.alice/").unwrap().is_empty());
+        assert!(memory
+            .remove_config("remote.Alice/Bob.RADFOLLOWREMOTES")
+            .unwrap());
+    }
+
+    #[test]
+    fn symbolic_refs() {
+        let memory = Memory::new();
+        let oid = memory.write_blob(b"sneakernet").unwrap();
+        let master = reflike!("refs/heads/master");
+        let head = reflike!("refs/heads/sym");
+
+        memory.set_ref(&master, oid, Force::False, "").unwrap();
+        memory
+            .set_symbolic_ref(&head, &master, Force::False, "")
+            .unwrap();
+        assert_eq!(memory.ref_target(&head).unwrap(), Some(oid));
+        assert_eq!(
+            memory.symbolic_ref_target(&head).unwrap(),
+            Some(master.clone())
+        );
+        assert!(matches!(
+            memory.set_ref(&master, oid, Force::False, ""),
+            Err(Error::Exists(_))
+        ));
+
+        assert!(memory.remove_ref(&master).unwrap());
+        assert_eq!(memory.ref_target(&head).unwrap(), None);
+        assert_eq!(
+            memory
+                .ref_names(&glob::RefspecMatcher::from(refspec_pattern!(
+                    "refs/heads/*"
+                )))
+                .unwrap(),
+            vec![]
+        );
+    }
+}
@@ -5,13 +5,16 @@
 
 #![allow(unused)]
 
-use std::{convert::TryFrom, io, marker::PhantomData};
+use std::{collections::BTreeSet, convert::TryFrom, io, marker::PhantomData};
 
 use git_ext::{self as ext, is_not_found_err};
 use std_ext::result::ResultExt as _;
 use thiserror::Error;
 
-use super::{super::identities::local::LocalIdentity, Storage};
+use super::{
+    super::{identities::local::LocalIdentity, refs},
+    Storage,
+};
 use crate::{
     identities::{
         git::{Identities, Urn, VerifiedPerson},
@@ -26,6 +29,8 @@ const CONFIG_USER_NAME: &str = "user.name";
 const CONFIG_USER_EMAIL: &str = "user.email";
 const CONFIG_RAD_SELF: &str = "rad.self";
 const CONFIG_RAD_PEER_ID: &str = "rad.peerid";
+const CONFIG_RAD_TRUSTEES: &str = "rad.trustees";
+const CONFIG_RAD_TRACKING_GRAPH_DEPTH: &str = "rad.trackingGraphDepth";
 
 #[derive(Debug, Error)]
 #[non_exhaustive]
@@ -39,6 +44,9 @@ pub enum Error {
     #[error(transparent)]
     Urn(#[from] urn::ParseError<ext::oid::FromMultihashError>),
 
+    #[error("invalid tracking graph depth {0}")]
+    TrackingGraphDepth(i64),
+
     #[error(transparent)]
     Git(#[from] git2::Error),
 
@@ -152,6 +160,66 @@ where
             },
         }
     }
+
+    /// Add `peer` to the set of default trustees, ie. peers which are tracked
+    /// in the context of any identity they announce.
+    ///
+    /// `true` is returned if `peer` was not a trustee before.
+    pub fn add_trustee(&mut self, peer: PeerId) -> Result<bool, Error> {
+        let mut trustees = self.trustees()?;
+        let was_added = trustees.insert(peer);
+        if was_added {
+            self.set_trustees(&trustees)?;
+        }
+
+        Ok(was_added)
+    }
+
+    /// Remove `peer` from the set of default trustees.
+    ///
+    /// `true` is returned if `peer` was a trustee before.
+    pub fn remove_trustee(&mut self, peer: &PeerId) -> Result<bool, Error> {
+        let mut trustees = self.trustees()?;
+        let was_removed = trustees.remove(peer);
+        if was_removed {
+            self.set_trustees(&trustees)?;
+        }
+
+        Ok(was_removed)
+    }
+
+    /// Set the depth of the tracking graph to retain per peer when publishing
+    /// our `rad/signed_refs`, and to follow when tracking transitively.
+    ///
+    /// See [`Config::tracking_graph_depth`].
+    pub fn set_tracking_graph_depth(&mut self, depth: usize) -> Result<(), Error> {
+        let depth = i64::try_from(depth).map_err(|_| Error::TrackingGraphDepth(i64::MAX))?;
+        self.inner
+            .set_i64(CONFIG_RAD_TRACKING_GRAPH_DEPTH, depth)
+            .map_err(Error::from)
+    }
+
+    fn set_trustees(&mut self, trustees: &BTreeSet<PeerId>) -> Result<(), Error> {
+        if trustees.is_empty() {
+            return self
+                .inner
+                .remove(CONFIG_RAD_TRUSTEES)
+                .or_matches::<Error, _, _>(is_not_found_err, || Ok(()));
+        }
+
+        // **NB**: stored as a single space-separated value, as `libgit2`'s
+        // multivar support is unreliable
+        self.inner
+            .set_str(
+                CONFIG_RAD_TRUSTEES,
+                &trustees
+                    .iter()
+                    .map(|peer| peer.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            )
+            .map_err(Error::from)
+    }
 }
 
 impl<S> Config<'_, S> {
@@ -179,6 +247,44 @@ impl<S> Config<'_, S> {
             .map(|urn| urn.parse().map_err(Error::from))
             .transpose()
     }
+
+    /// The set of default trustees.
+    ///
+    /// See [`Config::add_trustee`].
+    pub fn trustees(&self) -> Result<BTreeSet<PeerId>, Error> {
+        self.inner
+            .snapshot()?
+            .get_string(CONFIG_RAD_TRUSTEES)
+            .map(Some)
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(None))?
+            .map(|trustees| {
+                trustees
+                    .split_whitespace()
+                    .map(|peer| peer.parse().map_err(Error::from))
+                    .collect()
+            })
+            .unwrap_or_else(|| Ok(BTreeSet::new()))
+    }
+
+    /// Determine if `peer` is a default trustee.
+    pub fn is_trustee(&self, peer: &PeerId) -> Result<bool, Error> {
+        Ok(self.trustees()?.contains(peer))
+    }
+
+    /// The depth of the tracking graph to retain per peer.
+    ///
+    /// Defaults to [`refs::DEFAULT_TRACKING_GRAPH_DEPTH`] if not set. A depth
+    /// of `0` disables transitive tracking.
+    pub fn tracking_graph_depth(&self) -> Result<usize, Error> {
+        self.inner
+            .snapshot()?
+            .get_i64(CONFIG_RAD_TRACKING_GRAPH_DEPTH)
+            .map(Some)
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(None))?
+            .map_or(Ok(refs::DEFAULT_TRACKING_GRAPH_DEPTH), |depth| {
+                usize::try_from(depth).map_err(|_| Error::TrackingGraphDepth(depth))
+            })
+    }
 }
 
 impl Config<'_, PhantomData<!>> {
@@ -259,4 +365,30 @@ mod tests {
             Err(Error::AlreadyInitialised(pid)) if pid == *ALICE_PEER_ID
         )
     }
+
+    #[test]
+    fn add_remove_trustee() {
+        let mut config = setup(&*ALICE_KEY);
+        let bob = PeerId::from(&*BOB_KEY);
+
+        assert!(config.trustees().unwrap().is_empty());
+        assert!(config.add_trustee(bob).unwrap());
+        assert!(!config.add_trustee(bob).unwrap());
+        assert!(config.is_trustee(&bob).unwrap());
+        assert!(config.remove_trustee(&bob).unwrap());
+        assert!(!config.remove_trustee(&bob).unwrap());
+        assert!(!config.is_trustee(&bob).unwrap())
+    }
+
+    #[test]
+    fn tracking_graph_depth() {
+        let mut config = setup(&*ALICE_KEY);
+
+        assert_eq!(
+            config.tracking_graph_depth().unwrap(),
+            refs::DEFAULT_TRACKING_GRAPH_DEPTH
+        );
+        config.set_tracking_graph_depth(0).unwrap();
+        assert_eq!(config.tracking_graph_depth().unwrap(), 0)
+    }
 }
@@ -0,0 +1,592 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Integrity checks of the [`Storage`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    str::FromStr,
+};
+
+use git_ext::{self as ext, OneLevel};
+use thiserror::Error;
+
+use super::{
+    super::{
+        identities,
+        refs::{self, Refs},
+        tracking,
+        types::{Namespace, Reference, RefsCategory},
+    },
+    glob,
+    Storage,
+};
+use crate::{
+    identities::git::{delegation_keys, SomeIdentity},
+    peer::PeerId,
+};
+
+pub use crate::identities::git::Urn;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Identities(#[from] identities::error::Error),
+
+    #[error(transparent)]
+    Refs(#[from] refs::stored::Error),
+
+    #[error(transparent)]
+    Track(#[from] tracking::Error),
+
+    #[error(transparent)]
+    Store(#[from] super::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// Whether [`fsck`] should only report, or also repair what it can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Check,
+    Repair,
+}
+
+/// An inconsistency found by [`fsck`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Finding {
+    /// An identity could not be read at all.
+    Unreadable { reason: String },
+
+    /// The `rad/id` of `urn` does not verify.
+    UnverifiedIdentity { urn: Urn, reason: String },
+
+    /// The `rad/signed_refs` of `peer` in the context of `urn` can not be
+    /// loaded, or their signature doesn't check out.
+    InvalidSignedRefs {
+        urn: Urn,
+        peer: PeerId,
+        reason: String,
+    },
+
+    /// A ref of `peer` differs from what `peer` published in its
+    /// `rad/signed_refs`.
+    SignedRefMismatch {
+        urn: Urn,
+        peer: PeerId,
+        name: ext::RefLike,
+        expected: ext::Oid,
+        actual: Option<ext::Oid>,
+    },
+
+    /// Our own `rad/signed_refs` don't reflect the refs we have.
+    ///
+    /// Repaired by [`Refs::update`].
+    OutdatedSignedRefs { urn: Urn },
+
+    /// A symbolic ref, such as `rad/self` or `rad/ids/*`, does not resolve.
+    ///
+    /// Repaired by removing the symbolic ref.
+    DanglingSymref { urn: Urn, name: ext::RefLike },
+
+    /// `peer` is a tracked delegate of `urn`, but we don't have any of its
+    /// refs.
+    ///
+    /// Other tracked peers are not considered, as they may just not have been
+    /// replicated yet.
+    MissingRemote { urn: Urn, peer: PeerId },
+
+    /// We have refs of `peer` in the context of `urn`, but `peer` is not part
+    /// of the tracking graph of `urn` (see [`refs::tracking_graph`]).
+    ///
+    /// Repaired by [`tracking::prune_dangling`].
+    UntrackedRemote { urn: Urn, peer: PeerId },
+
+    /// `peer` is tracked in the context of `urn`, but there is no identity
+    /// `urn` in the storage.
+    ///
+    /// Repaired by [`tracking::untrack`].
+    OrphanedTracking { urn: Urn, peer: PeerId },
+}
+
+impl Finding {
+    /// Whether [`fsck`] can repair this finding in [`Mode::Repair`].
+    pub fn is_repairable(&self) -> bool {
+        matches!(
+            self,
+            Self::OutdatedSignedRefs { .. }
+                | Self::DanglingSymref { .. }
+                | Self::UntrackedRemote { .. }
+                | Self::OrphanedTracking { .. }
+        )
+    }
+}
+
+/// The outcome of [`fsck`].
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Everything which was found to be inconsistent.
+    pub findings: Vec<Finding>,
+    /// The subset of [`Report::findings`] which was repaired.
+    pub repaired: Vec<Finding>,
+}
+
+impl Report {
+    /// `true` if no inconsistencies were found.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// Check the integrity of the [`Storage`].
+///
+/// For every identity found by [`identities::any::list`], it is checked that:
+///
+/// * the `rad/id` verifies
+/// * the `rad/signed_refs` of every remote peer verify, and match the refs we
+///   have of that peer (as far as its [`tracking::Policy`] allows)
+/// * our own `rad/signed_refs` are up-to-date
+/// * all symbolic refs (`rad/self`, `rad/ids/*`) resolve
+/// * the peers in the tracking graph correspond to the remote tracking branches
+///   we have
+///
+/// Additionally, tracking relationships for identities which don't exist are
+/// reported.
+///
+/// In [`Mode::Repair`], the [repairable](Finding::is_repairable) findings are
+/// repaired after all checks have been run.
+#[tracing::instrument(skip(storage), err)]
+pub fn fsck(storage: &Storage, mode: Mode) -> Result<Report, Error> {
+    let mut report = Report::default();
+
+    let mut urns = BTreeSet::new();
+    for identity in identities::any::list(storage)? {
+        match identity {
+            Err(e) => report.findings.push(Finding::Unreadable {
+                reason: e.to_string(),
+            }),
+            Ok(identity) => {
+                let urn = identity.urn();
+                check_identity(storage, &urn, &identity, &mut report.findings)?;
+                urns.insert(urn);
+            },
+        }
+    }
+    check_orphans(storage, &urns, &mut report.findings)?;
+
+    if mode == Mode::Repair {
+        for finding in report.findings.iter().filter(|f| f.is_repairable()) {
+            repair(storage, finding)?;
+            report.repaired.push(finding.clone());
+        }
+    }
+
+    Ok(report)
+}
+
+fn check_identity(
+    storage: &Storage,
+    urn: &Urn,
+    identity: &SomeIdentity,
+    findings: &mut Vec<Finding>,
+) -> Result<(), Error> {
+    let verified = match identity {
+        SomeIdentity::Person(_) => identities::person::verify(storage, urn).map(|_| ()),
+        SomeIdentity::Project(_) => identities::project::verify(storage, urn).map(|_| ()),
+        SomeIdentity::Org(_) => identities::org::verify(storage, urn).map(|_| ()),
+    };
+    if let Err(e) = verified {
+        findings.push(Finding::UnverifiedIdentity {
+            urn: urn.clone(),
+            reason: e.to_string(),
+        });
+    }
+
+    // Our own signed refs
+    let published = Refs::load(storage, urn, None::<PeerId>)?;
+    let depth = storage
+        .config()?
+        .tracking_graph_depth()
+        .map_err(super::Error::from)?;
+    let current = Refs::compute(storage, urn, depth)?;
+    if published.map(|refs| categorised(&refs)) != Some(categorised(&current)) {
+        findings.push(Finding::OutdatedSignedRefs { urn: urn.clone() });
+    }
+
+    // Remotes
+    let graph = current.remotes.flatten().copied().collect::<BTreeSet<_>>();
+    let remotes = remotes(storage, urn)?;
+    for peer in delegates(identity).intersection(&graph) {
+        if !remotes.contains(peer) {
+            findings.push(Finding::MissingRemote {
+                urn: urn.clone(),
+                peer: *peer,
+            });
+        }
+    }
+    for peer in &remotes {
+        if !graph.contains(peer) {
+            findings.push(Finding::UntrackedRemote {
+                urn: urn.clone(),
+                peer: *peer,
+            });
+            continue;
+        }
+        check_signed_refs(storage, urn, *peer, findings)?;
+    }
+
+    // Symbolic refs
+    let namespace = glob::RefspecMatcher::from(
+        reflike!("refs/namespaces")
+            .join(urn)
+            .with_pattern_suffix(refspec_pattern!("*")),
+    );
+    for reference in storage.references_glob(namespace)? {
+        let reference = reference?;
+        if reference.symbolic_target().is_none() {
+            continue;
+        }
+        if reference.resolve().is_err() {
+            if let Some(name) = reference.name() {
+                findings.push(Finding::DanglingSymref {
+                    urn: urn.clone(),
+                    name: ext::RefLike::try_from(name).map_err(refs::stored::Error::from)?,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_signed_refs(
+    storage: &Storage,
+    urn: &Urn,
+    peer: PeerId,
+    findings: &mut Vec<Finding>,
+) -> Result<(), Error> {
+    let refs = match Refs::load(storage, urn, peer) {
+        Ok(Some(refs)) => refs,
+        // Not replicated yet
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            findings.push(Finding::InvalidSignedRefs {
+                urn: urn.clone(),
+                peer,
+                reason: e.to_string(),
+            });
+            return Ok(());
+        },
+    };
+    let policy = tracking::policy(storage, urn, peer)?.unwrap_or_default();
+
+    for ((name, target), category) in refs
+        .iter_categorised()
+        .filter(|((name, _), category)| policy.allows(*category, name))
+    {
+        let branch = Reference {
+            remote: Some(peer),
+            category,
+            name: ext::RefLike::from(name.clone()),
+            namespace: Some(Namespace::from(urn)),
+        };
+        let actual = storage
+            .reference(&branch)?
+            .and_then(|r| r.target())
+            .map(ext::Oid::from);
+        if actual != Some(*target) {
+            findings.push(Finding::SignedRefMismatch {
+                urn: urn.clone(),
+                peer,
+                name: ext::RefLike::from(&branch),
+                expected: *target,
+                actual,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn check_orphans(
+    storage: &Storage,
+    urns: &BTreeSet<Urn>,
+    findings: &mut Vec<Finding>,
+) -> Result<(), Error> {
+    let remotes = storage.as_raw().remotes()?;
+    for name in remotes.iter().flatten() {
+        let mut parts = name.splitn(2, '/');
+        let (id, peer) = match (parts.next(), parts.next()) {
+            (Some(id), Some(peer)) => (id, peer),
+            _ => continue,
+        };
+        let urn = ext::RefLike::try_from(id)
+            .ok()
+            .and_then(|id| Urn::try_from(reflike!("refs/namespaces").join(id)).ok());
+        let peer = PeerId::from_str(peer).ok();
+        if let (Some(urn), Some(peer)) = (urn, peer) {
+            if !urns.contains(&urn) && !storage.has_urn(&urn)? {
+                findings.push(Finding::OrphanedTracking { urn, peer });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn repair(storage: &Storage, finding: &Finding) -> Result<(), Error> {
+    tracing::info!(finding = ?finding, "repairing");
+    match finding {
+        Finding::OutdatedSignedRefs { urn } => {
+            Refs::update(storage, urn)?;
+        },
+        Finding::DanglingSymref { name, .. } => {
+            let mut tx = storage.transaction()?;
+            tx.remove(name.clone())
+                .and_then(|_| tx.commit())
+                .map_err(super::Error::from)?;
+        },
+        Finding::UntrackedRemote { urn, .. } => {
+            tracking::prune_dangling(storage, urn)?;
+        },
+        Finding::OrphanedTracking { urn, peer } => {
+            tracking::untrack(storage, urn, *peer)?;
+        },
+        _ => {},
+    }
+
+    Ok(())
+}
+
+/// The peers holding the keys `identity` delegates to.
+fn delegates(identity: &SomeIdentity) -> BTreeSet<PeerId> {
+    let keys = match identity {
+        SomeIdentity::Person(person) => person.delegations().iter().collect(),
+        SomeIdentity::Project(project) => delegation_keys(project.delegations()),
+        SomeIdentity::Org(org) => delegation_keys(org.delegations()),
+    };
+    keys.into_iter().copied().map(PeerId::from).collect()
+}
+
+/// The peers we have remote tracking branches for in the context of `urn`.
+fn remotes(storage: &Storage, urn: &Urn) -> Result<BTreeSet<PeerId>, Error> {
+    let prefix = reflike!("refs/namespaces")
+        .join(urn)
+        .join(reflike!("refs/remotes"));
+    let mut peers = BTreeSet::new();
+    for name in storage.reference_names_glob(glob::RefspecMatcher::from(
+        prefix.with_pattern_suffix(refspec_pattern!("*")),
+    ))? {
+        let name = name?;
+        if let Some(Ok(peer)) = name
+            .strip_prefix(&prefix)
+            .ok()
+            .and_then(|suffix| suffix.as_str().split('/').next().map(PeerId::from_str))
+        {
+            peers.insert(peer);
+        }
+    }
+
+    Ok(peers)
+}
+
+fn categorised(refs: &Refs) -> BTreeMap<(RefsCategory, OneLevel), ext::Oid> {
+    refs.iter_categorised()
+        .map(|((name, target), category)| ((category, name.clone()), *target))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{identities::payload, keys::SecretKey, paths::Paths};
+
+    fn alice(storage: &Storage, key: &SecretKey) -> Urn {
+        identities::person::create(
+            storage,
+            payload::Person {
+                name: "alice".into(),
+            },
+            Some(key.public()).into_iter().collect(),
+        )
+        .unwrap()
+        .urn()
+    }
+
+    fn rad_id(storage: &Storage, urn: &Urn) -> git2::Oid {
+        storage
+            .reference(&Reference::rad_id(Namespace::from(urn)))
+            .unwrap()
+            .and_then(|r| r.target())
+            .unwrap()
+    }
+
+    #[test]
+    fn repairs_orphaned_tracking() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp).unwrap();
+            let storage = Storage::open_or_init(&paths, SecretKey::new()).unwrap();
+            let remote_peer = PeerId::from(SecretKey::new());
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            assert!(fsck(&storage, Mode::Check).unwrap().is_clean());
+
+            tracking::track(&storage, &urn, remote_peer).unwrap();
+            let orphan = Finding::OrphanedTracking {
+                urn: urn.clone(),
+                peer: remote_peer,
+            };
+            let report = fsck(&storage, Mode::Check).unwrap();
+            assert_eq!(report.findings, vec![orphan.clone()]);
+            assert!(report.repaired.is_empty());
+
+            let report = fsck(&storage, Mode::Repair).unwrap();
+            assert_eq!(report.repaired, vec![orphan]);
+            assert!(!tracking::is_tracked(&storage, &urn, remote_peer).unwrap());
+            assert!(fsck(&storage, Mode::Check).unwrap().is_clean());
+        }
+    }
+
+    #[test]
+    fn repairs_untracked_remote() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp.path()).unwrap();
+            let key = SecretKey::new();
+            let storage = Storage::open_or_init(&paths, key.clone()).unwrap();
+            let urn = alice(&storage, &key);
+            let untracked = PeerId::from(SecretKey::new());
+
+            assert!(fsck(&storage, Mode::Check).unwrap().is_clean());
+
+            let branch = Reference::head(Namespace::from(&urn), untracked, reflike!("master"));
+            storage
+                .as_raw()
+                .reference(
+                    ext::RefLike::from(&branch).as_str(),
+                    rad_id(&storage, &urn),
+                    false,
+                    "",
+                )
+                .unwrap();
+            let finding = Finding::UntrackedRemote {
+                urn: urn.clone(),
+                peer: untracked,
+            };
+            assert!(finding.is_repairable());
+            assert_eq!(
+                fsck(&storage, Mode::Check).unwrap().findings,
+                vec![finding.clone()]
+            );
+
+            let report = fsck(&storage, Mode::Repair).unwrap();
+            assert_eq!(report.repaired, vec![finding]);
+            assert!(!storage.has_ref(&branch).unwrap());
+            assert!(fsck(&storage, Mode::Check).unwrap().is_clean());
+        }
+    }
+
+    #[test]
+    fn repairs_dangling_symref() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp.path()).unwrap();
+            let key = SecretKey::new();
+            let storage = Storage::open_or_init(&paths, key.clone()).unwrap();
+            let urn = alice(&storage, &key);
+            let gone = Urn::new(git2::Oid::zero().into());
+
+            let name = ext::RefLike::from(Reference::rad_delegate(Namespace::from(&urn), &gone));
+            storage
+                .as_raw()
+                .reference_symbolic(
+                    name.as_str(),
+                    ext::RefLike::from(Reference::rad_id(Namespace::from(&gone))).as_str(),
+                    false,
+                    "",
+                )
+                .unwrap();
+            let finding = Finding::DanglingSymref {
+                urn: urn.clone(),
+                name: name.clone(),
+            };
+            assert!(fsck(&storage, Mode::Check)
+                .unwrap()
+                .findings
+                .contains(&finding));
+
+            let report = fsck(&storage, Mode::Repair).unwrap();
+            assert!(report.repaired.contains(&finding));
+            assert!(storage.as_raw().find_reference(name.as_str()).is_err());
+            assert!(fsck(&storage, Mode::Check).unwrap().is_clean());
+        }
+    }
+
+    #[test]
+    fn reports_missing_delegate_remote() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let key = SecretKey::new();
+            let storage =
+                Storage::open_or_init(&Paths::from_root(tmp.path().join("local")).unwrap(), key)
+                    .unwrap();
+
+            // An identity delegating to someone else
+            let other_key = SecretKey::new();
+            let other = Storage::open_or_init(
+                &Paths::from_root(tmp.path().join("other")).unwrap(),
+                other_key.clone(),
+            )
+            .unwrap();
+            let urn = alice(&other, &other_key);
+            let id_ref = ext::RefLike::from(Reference::rad_id(Namespace::from(&urn)));
+            storage
+                .as_raw()
+                .remote_anonymous(other.path().to_str().unwrap())
+                .unwrap()
+                .fetch(&[&format!("{}:{}", id_ref, id_ref)], None, None)
+                .unwrap();
+
+            let delegate = PeerId::from(other_key);
+            let tracked = PeerId::from(SecretKey::new());
+            tracking::track(&storage, &urn, delegate).unwrap();
+            tracking::track(&storage, &urn, tracked).unwrap();
+
+            // Only the delegate is expected to have been replicated
+            let missing = Finding::MissingRemote {
+                urn: urn.clone(),
+                peer: delegate,
+            };
+            assert!(!missing.is_repairable());
+            let findings = fsck(&storage, Mode::Check).unwrap().findings;
+            assert!(findings.contains(&missing));
+            assert!(!findings.contains(&Finding::MissingRemote {
+                urn: urn.clone(),
+                peer: tracked,
+            }));
+
+            let branch = Reference::head(Namespace::from(&urn), delegate, reflike!("master"));
+            storage
+                .as_raw()
+                .reference(
+                    ext::RefLike::from(&branch).as_str(),
+                    rad_id(&storage, &urn),
+                    false,
+                    "",
+                )
+                .unwrap();
+            let findings = fsck(&storage, Mode::Check).unwrap().findings;
+            assert!(!findings.contains(&missing));
+            assert!(!findings
+                .iter()
+                .any(|f| matches!(f, Finding::UntrackedRemote { .. })));
+        }
+    }
+}
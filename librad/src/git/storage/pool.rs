@@ -3,16 +3,27 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::sync::{Arc, Mutex};
+use std::{
+    panic,
+    sync::{Arc, Mutex},
+};
 
-use deadpool::managed::{Manager, RecycleResult};
+use deadpool::managed::{Manager, PoolError, RecycleResult};
+use tokio::task;
 
-use super::{Error, Storage};
+use super::{Error, ReadOnly, Storage};
 use crate::{paths::Paths, signer::Signer};
 
 pub type Pool = deadpool::managed::Pool<Storage, Error>;
 pub type Pooled = deadpool::managed::Object<Storage, Error>;
 
+/// A pool of [`ReadOnly`] handles, managed by a [`ReadOnlyConfig`].
+///
+/// Read-only handles are pooled separately from [`Storage`] handles, so that
+/// readers don't compete with writers for the same pool slots.
+pub type ReadOnlyPool = deadpool::managed::Pool<ReadOnly, Error>;
+pub type ReadOnlyPooled = deadpool::managed::Object<ReadOnly, Error>;
+
 /// Wrapper so we can use [`Pooled`] as `AsRef<Storage>`.
 // TODO: may go away once https://github.com/bikeshedder/deadpool/pull/69
 // appears in a released version.
@@ -54,15 +65,172 @@ where
     S::Error: std::error::Error + Send + Sync + 'static,
 {
     async fn create(&self) -> Result<Storage, Error> {
-        // FIXME(kim): we should `block_in_place` here, but that forces the
-        // threaded runtime onto users
-        let _lock = self.lock.lock().unwrap();
-        {
-            Storage::open_or_init(&self.paths, self.signer.clone())
-        }
+        let paths = self.paths.clone();
+        let signer = self.signer.clone();
+        let lock = self.lock.clone();
+        spawn_blocking(move || {
+            let _lock = lock.lock().unwrap();
+            Storage::open_or_init(&paths, signer)
+        })
+        .await
+        .unwrap_or(Err(Error::Cancelled))
     }
 
     async fn recycle(&self, _: &mut Storage) -> RecycleResult<Error> {
         Ok(())
     }
 }
+
+/// [`Manager`] of a [`ReadOnlyPool`].
+///
+/// Unlike [`Config`], this does not require a [`Signer`], and the storage must
+/// have been initialised before.
+#[derive(Clone)]
+pub struct ReadOnlyConfig {
+    paths: Paths,
+}
+
+impl ReadOnlyConfig {
+    pub fn new(paths: Paths) -> Self {
+        Self { paths }
+    }
+}
+
+#[async_trait]
+impl Manager<ReadOnly, Error> for ReadOnlyConfig {
+    async fn create(&self) -> Result<ReadOnly, Error> {
+        let paths = self.paths.clone();
+        spawn_blocking(move || ReadOnly::open(&paths))
+            .await
+            .unwrap_or(Err(Error::Cancelled))
+    }
+
+    async fn recycle(&self, _: &mut ReadOnly) -> RecycleResult<Error> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum WithStorageError {
+    #[error("the spawned blocking task was cancelled")]
+    Cancelled,
+
+    #[error(transparent)]
+    Pool(PoolError<Error>),
+}
+
+impl From<PoolError<Error>> for WithStorageError {
+    fn from(e: PoolError<Error>) -> Self {
+        Self::Pool(e)
+    }
+}
+
+/// Run `blocking` with a handle from `pool`.
+///
+/// The closure is always run on the blocking threadpool (see
+/// [`tokio::task::spawn_blocking`]), so it is safe to perform git operations
+/// in it without stalling the executor. Panics are propagated to the caller.
+pub async fn with_storage<T, F, A>(
+    pool: &deadpool::managed::Pool<T, Error>,
+    blocking: F,
+) -> Result<A, WithStorageError>
+where
+    T: Send + 'static,
+    F: FnOnce(&T) -> A + Send + 'static,
+    A: Send + 'static,
+{
+    let saturation = Saturation::from(pool);
+    if saturation.waiting > 0 {
+        tracing::debug!(
+            waiting = saturation.waiting,
+            max_size = saturation.max_size,
+            "storage pool saturated"
+        );
+    }
+
+    let storage = pool.get().await?;
+    spawn_blocking(move || blocking(&storage))
+        .await
+        .ok_or(WithStorageError::Cancelled)
+}
+
+/// Saturation metrics of a storage pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Saturation {
+    /// The maximum number of handles the pool will create.
+    pub max_size: usize,
+    /// The number of handles created so far.
+    pub size: usize,
+    /// The number of handles currently in use.
+    pub in_use: usize,
+    /// The number of tasks waiting for a handle to become available.
+    pub waiting: usize,
+}
+
+impl Saturation {
+    /// The fraction of [`Saturation::max_size`] currently in use, between `0.0`
+    /// and `1.0`.
+    pub fn ratio(&self) -> f32 {
+        if self.max_size == 0 {
+            return 1.0;
+        }
+        self.in_use as f32 / self.max_size as f32
+    }
+}
+
+impl<T> From<&deadpool::managed::Pool<T, Error>> for Saturation {
+    fn from(pool: &deadpool::managed::Pool<T, Error>) -> Self {
+        let status = pool.status();
+        let available = status.available.max(0) as usize;
+        Self {
+            max_size: status.max_size,
+            size: status.size,
+            in_use: status.size.saturating_sub(available),
+            waiting: (-status.available).max(0) as usize,
+        }
+    }
+}
+
+/// Run `f` on the blocking threadpool, propagating panics.
+///
+/// `None` is returned if the task was cancelled, which happens if the runtime
+/// is shutting down.
+async fn spawn_blocking<F, A>(f: F) -> Option<A>
+where
+    F: FnOnce() -> A + Send + 'static,
+    A: Send + 'static,
+{
+    match task::spawn_blocking(f).await {
+        Ok(a) => Some(a),
+        Err(e) if e.is_cancelled() => None,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(e) => panic!("unknown error awaiting spawned blocking task: {:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::keys::SecretKey;
+
+    #[tokio::test]
+    async fn with_storage_reports_saturation() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = Paths::from_root(tmp.path()).unwrap();
+        let pool = Pool::new(Config::new(paths, SecretKey::new()), 2);
+
+        let peer_id = with_storage(&pool, |storage| *storage.peer_id())
+            .await
+            .unwrap();
+        let held = pool.get().await.unwrap();
+        assert_eq!(held.peer_id(), &peer_id);
+
+        let saturation = Saturation::from(&pool);
+        assert_eq!(saturation.max_size, 2);
+        assert_eq!(saturation.in_use, 1);
+        assert_eq!(saturation.waiting, 0);
+        assert!((saturation.ratio() - 0.5).abs() < f32::EPSILON);
+    }
+}
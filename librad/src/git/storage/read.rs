@@ -0,0 +1,431 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Read-only access to the link "monorepo".
+
+use std::{convert::TryFrom, fmt::Debug, marker::PhantomData, path::Path};
+
+use git_ext::{self as ext, blob, is_not_found_err, RefLike, RefspecPattern};
+use nonempty::NonEmpty;
+use std_ext::result::ResultExt as _;
+
+use super::{
+    super::types::{Many, Namespace, One, Reference},
+    glob,
+    Config,
+    Error,
+    Pattern,
+    Urn,
+};
+use crate::{
+    identities::git::{
+        error as identities_error,
+        fork::Fork,
+        history::History,
+        Identities,
+        Org,
+        Person,
+        Project,
+        SomeIdentity,
+        VerifiedIdentity,
+        VerifiedOrg,
+        VerifiedPerson,
+        VerifiedProject,
+    },
+    paths::Paths,
+    peer::PeerId,
+};
+
+/// A handle to the link "monorepo" which can only be used for reading.
+///
+/// Opening a `ReadOnly` handle does not require a [`crate::signer::Signer`],
+/// and never modifies the storage. See [`super::Storage::open_readonly`].
+pub struct ReadOnly {
+    pub(super) backend: git2::Repository,
+    pub(super) peer_id: PeerId,
+}
+
+impl ReadOnly {
+    /// Open the storage at `paths`, which must have been initialised before.
+    pub fn open(paths: &Paths) -> Result<Self, Error> {
+        crate::git::init();
+
+        let backend = git2::Repository::open_bare(paths.git_dir())?;
+        let peer_id = Config::try_from(&backend)?.peer_id()?;
+
+        Ok(Self { backend, peer_id })
+    }
+
+    /// Open another handle to the same underlying repository.
+    pub fn reopen(&self) -> Result<Self, Error> {
+        let backend = git2::Repository::open_bare(self.path())?;
+        Ok(Self {
+            backend,
+            peer_id: self.peer_id,
+        })
+    }
+
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.backend.path()
+    }
+
+    #[tracing::instrument(level = "debug", skip(self), err)]
+    pub fn has_urn(&self, urn: &Urn) -> Result<bool, Error> {
+        self.has_ref(&Reference::try_from(urn)?)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self), err)]
+    pub fn has_ref<'a>(&self, reference: &'a Reference<One>) -> Result<bool, Error> {
+        self.backend
+            .find_reference(RefLike::from(reference).as_str())
+            .and(Ok(true))
+            .or_matches(is_not_found_err, || Ok(false))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self), err)]
+    pub fn has_commit<Oid>(&self, urn: &Urn, oid: Oid) -> Result<bool, Error>
+    where
+        Oid: AsRef<git2::Oid> + Debug,
+    {
+        let oid = oid.as_ref();
+        if oid.is_zero() {
+            // XXX: should this be a panic or error?
+            tracing::warn!("zero oid");
+            return Ok(false);
+        }
+
+        self.backend
+            .find_commit(*oid)
+            .and_then(|commit| {
+                let namespace = Namespace::from(urn);
+                let branch = {
+                    let path = match &urn.path {
+                        Some(refl) => refl.as_str(),
+                        None => "rad/id",
+                    };
+                    path.strip_prefix("refs/").unwrap_or(path)
+                };
+                self.backend
+                    .refname_to_id(&format!("refs/namespaces/{}/refs/{}", namespace, branch))
+                    .and_then(|tip| {
+                        Ok(tip == commit.id()
+                            || self.backend.graph_descendant_of(tip, commit.id())?)
+                    })
+                    .or_matches(is_not_found_err, || Ok(false))
+            })
+            .or_matches(is_not_found_err, || Ok(false))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self), err)]
+    pub fn has_object<Oid>(&self, oid: Oid) -> Result<bool, Error>
+    where
+        Oid: AsRef<git2::Oid> + Debug,
+    {
+        let oid = oid.as_ref();
+        if oid.is_zero() {
+            return Ok(false);
+        }
+
+        Ok(self.backend.odb()?.exists(*oid))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn tip(&self, urn: &Urn) -> Result<Option<ext::Oid>, Error> {
+        let reference = self
+            .backend
+            .find_reference(RefLike::from(&Reference::try_from(urn)?).as_str())
+            .map(Some)
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(None))?;
+
+        match reference {
+            None => Ok(None),
+            Some(r) => Ok(Some(r.peel_to_commit()?.id().into())),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn reference<'a>(
+        &'a self,
+        reference: &Reference<One>,
+    ) -> Result<Option<git2::Reference<'a>>, Error> {
+        reference
+            .find(&self.backend)
+            .map(Some)
+            .or_matches(is_not_found_err, || Ok(None))
+    }
+
+    /// Resolve `reference` to the object it points to, following symbolic
+    /// refs.
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn reference_oid(&self, reference: &Reference<One>) -> Result<ext::Oid, Error> {
+        Ok(self
+            .backend
+            .refname_to_id(RefLike::from(reference).as_str())?
+            .into())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn references<'a>(
+        &'a self,
+        reference: &Reference<Many>,
+    ) -> Result<impl Iterator<Item = Result<git2::Reference<'a>, Error>> + 'a, Error> {
+        self.references_glob(glob::RefspecMatcher::from(RefspecPattern::from(reference)))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn reference_names<'a>(
+        &'a self,
+        reference: &Reference<Many>,
+    ) -> Result<impl Iterator<Item = Result<ext::RefLike, Error>> + 'a, Error> {
+        self.reference_names_glob(glob::RefspecMatcher::from(RefspecPattern::from(reference)))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn references_glob<'a, G: 'a>(
+        &'a self,
+        glob: G,
+    ) -> Result<impl Iterator<Item = Result<git2::Reference<'a>, Error>> + 'a, Error>
+    where
+        G: Pattern + Debug,
+    {
+        Ok(self
+            .backend
+            .references()?
+            .filter_map(move |reference| match reference {
+                Ok(reference) => match reference.name() {
+                    Some(name) if glob.matches(name) => Some(Ok(reference)),
+                    _ => None,
+                },
+
+                Err(e) => Some(Err(e.into())),
+            }))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn reference_names_glob<'a, G: 'a>(
+        &'a self,
+        glob: G,
+    ) -> Result<impl Iterator<Item = Result<ext::RefLike, Error>> + 'a, Error>
+    where
+        G: Pattern + Debug,
+    {
+        let iter = ReferenceNames {
+            iter: self.backend.references()?,
+        };
+        Ok(iter.filter_map(move |refname| match refname {
+            Ok(reflike) if glob.matches(&reflike) => Some(Ok(reflike)),
+            Ok(_) => None,
+
+            Err(e) => Some(Err(e)),
+        }))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self), err)]
+    pub fn blob<'a>(
+        &'a self,
+        reference: &'a Reference<One>,
+        path: &'a Path,
+    ) -> Result<Option<git2::Blob<'a>>, Error> {
+        ext::Blob::Tip {
+            branch: reference.into(),
+            path,
+        }
+        .get(self.as_raw())
+        .map(Some)
+        .or_matches(|e| matches!(e, blob::Error::NotFound(_)), || Ok(None))
+    }
+
+    /// Read the configuration of the storage.
+    ///
+    /// Note that the returned [`Config`] can not be used to modify the
+    /// configuration.
+    pub fn config(&self) -> Result<Config<PhantomData<!>>, Error> {
+        Ok(Config::try_from(self.as_raw())?)
+    }
+
+    /// Access the identities stored in `self`, for reading only.
+    pub(in crate::git) fn identities<'a, T: 'a>(&'a self) -> ReadIdentities<'a, T> {
+        ReadIdentities {
+            inner: Identities::from(self.as_raw()),
+        }
+    }
+
+    // TODO: the write paths of the higher layers are not yet ported to the
+    // capabilities in `backend`, and still need the raw repository. Only
+    // [`super::Storage`] hands it out.
+    pub(super) fn as_raw(&self) -> &git2::Repository {
+        &self.backend
+    }
+}
+
+impl AsRef<ReadOnly> for ReadOnly {
+    fn as_ref(&self) -> &Self {
+        self
+    }
+}
+
+/// The read-only subset of [`Identities`], as obtained from a [`ReadOnly`]
+/// storage.
+pub struct ReadIdentities<'a, T> {
+    inner: Identities<'a, T>,
+}
+
+impl<'a, T: 'a> ReadIdentities<'a, T> {
+    /// See [`Identities::some_identity`].
+    pub fn some_identity(&self, oid: git2::Oid) -> Result<SomeIdentity, identities_error::Load> {
+        self.inner.some_identity(oid)
+    }
+
+    /// See [`Identities::fork`].
+    pub fn fork(&self, mine: git2::Oid, theirs: git2::Oid) -> Result<Fork, identities_error::Load> {
+        self.inner.fork(mine, theirs)
+    }
+}
+
+impl<'a, T: 'a> ReadIdentities<'a, VerifiedIdentity<T>> {
+    /// See [`Identities::is_fork`].
+    pub fn is_fork(
+        &self,
+        mine: &VerifiedIdentity<T>,
+        theirs: &VerifiedIdentity<T>,
+    ) -> Result<bool, identities_error::Store> {
+        self.inner.is_fork(mine, theirs)
+    }
+}
+
+impl<'a> ReadIdentities<'a, Person> {
+    pub fn get(&self, oid: git2::Oid) -> Result<Person, identities_error::Load> {
+        self.inner.get(oid)
+    }
+
+    pub fn verify(
+        &self,
+        head: git2::Oid,
+    ) -> Result<VerifiedPerson, identities_error::VerifyPerson> {
+        self.inner.verify(head)
+    }
+
+    pub fn history(&self, head: git2::Oid) -> Result<History, identities_error::Load> {
+        self.inner.history(head)
+    }
+
+    pub fn latest_tip(
+        &self,
+        persons: NonEmpty<Person>,
+    ) -> Result<git2::Oid, identities_error::Store> {
+        self.inner.latest_tip(persons)
+    }
+}
+
+impl<'a> ReadIdentities<'a, Project> {
+    pub fn get(&self, oid: git2::Oid) -> Result<Project, identities_error::Load> {
+        self.inner.get(oid)
+    }
+
+    pub fn verify<F, E>(
+        &self,
+        head: git2::Oid,
+        find_latest_head: F,
+    ) -> Result<VerifiedProject, identities_error::VerifyProject>
+    where
+        F: Fn(Urn) -> Result<git2::Oid, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.inner.verify(head, find_latest_head)
+    }
+
+    pub fn history<F, E>(
+        &self,
+        head: git2::Oid,
+        find_latest_head: F,
+    ) -> Result<History, identities_error::Load>
+    where
+        F: Fn(Urn) -> Result<git2::Oid, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.inner.history(head, find_latest_head)
+    }
+
+    pub fn latest_tip(
+        &self,
+        projects: NonEmpty<Project>,
+    ) -> Result<git2::Oid, identities_error::Store> {
+        self.inner.latest_tip(projects)
+    }
+}
+
+impl<'a> ReadIdentities<'a, Org> {
+    pub fn get(&self, oid: git2::Oid) -> Result<Org, identities_error::Load> {
+        self.inner.get(oid)
+    }
+
+    pub fn verify<F, E>(
+        &self,
+        head: git2::Oid,
+        find_latest_head: F,
+    ) -> Result<VerifiedOrg, identities_error::VerifyOrg>
+    where
+        F: Fn(Urn) -> Result<git2::Oid, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.inner.verify(head, find_latest_head)
+    }
+
+    pub fn latest_tip(&self, orgs: NonEmpty<Org>) -> Result<git2::Oid, identities_error::Store> {
+        self.inner.latest_tip(orgs)
+    }
+}
+
+struct ReferenceNames<'a> {
+    iter: git2::References<'a>,
+}
+
+impl<'a> Iterator for ReferenceNames<'a> {
+    type Item = Result<ext::RefLike, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let names = self.iter.names();
+        for name in names {
+            match name {
+                Err(e) => return Some(Err(e.into())),
+                Ok(name) => match ext::RefLike::try_from(name).ok() {
+                    Some(refl) => return Some(Ok(refl)),
+                    None => continue,
+                },
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{git::storage::Storage, keys::SecretKey};
+
+    #[test]
+    fn open_readonly() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp).unwrap();
+            assert!(Storage::open_readonly(&paths).is_err());
+
+            let key = SecretKey::new();
+            let storage = Storage::open_or_init(&paths, key.clone()).unwrap();
+            let readonly = Storage::open_readonly(&paths).unwrap();
+            assert_eq!(readonly.peer_id(), &PeerId::from(key));
+            assert_eq!(readonly.peer_id(), storage.peer_id());
+            assert!(!readonly
+                .has_urn(&Urn::new(git2::Oid::zero().into()))
+                .unwrap());
+        }
+    }
+}
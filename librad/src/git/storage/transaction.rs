@@ -0,0 +1,408 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Updating multiple refs at once.
+
+use std::collections::BTreeMap;
+
+use git_ext::{self as ext, is_not_found_err};
+use std_ext::result::ResultExt as _;
+use thiserror::Error;
+
+use super::super::types::Force;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("ref `{0}` already exists")]
+    Exists(ext::RefLike),
+
+    #[error("ref `{name}` was expected to point to {expected:?}, but points to {actual:?}")]
+    Mismatch {
+        name: ext::RefLike,
+        expected: Option<ext::Oid>,
+        actual: Option<ext::Oid>,
+    },
+
+    #[error("target `{target}` of symbolic ref `{name}` does not exist")]
+    MissingTarget {
+        name: ext::RefLike,
+        target: ext::RefLike,
+    },
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// A set of ref updates which are applied together.
+///
+/// Each ref is locked (using git's lock files) when it is first touched by the
+/// transaction, and stays locked until the transaction is committed or
+/// dropped. Nothing is written to the refdb before [`Transaction::commit`],
+/// which means that a failure while building the transaction leaves the refdb
+/// untouched, and that concurrent writers to the same refs fail instead of
+/// interleaving.
+///
+/// The updates are recorded by the transaction, so later updates of the same
+/// ref within the transaction see the earlier ones: eg. a ref can be re-created
+/// after removing it, and removing a ref created by the transaction cancels its
+/// creation. Reading refs from the [`super::Storage`], however, does not
+/// reflect the pending updates of a transaction.
+///
+/// # Caveats
+///
+/// `libgit2` writes the locked refs one after the other when committing. A
+/// crash _during_ the commit may thus still leave a subset of the updates
+/// applied, although the window is much smaller than when updating the refs
+/// independently.
+pub struct Transaction<'a> {
+    repo: &'a git2::Repository,
+    inner: git2::Transaction<'a>,
+    locked: BTreeMap<ext::RefLike, Option<ext::Oid>>,
+    pending: BTreeMap<ext::RefLike, Update>,
+}
+
+/// An update of a ref, applied to the refdb on [`Transaction::commit`].
+enum Update {
+    Target { target: git2::Oid, msg: String },
+    Symbolic { target: ext::RefLike, msg: String },
+    Remove,
+}
+
+impl<'a> Transaction<'a> {
+    pub(super) fn new(repo: &'a git2::Repository) -> Result<Self, Error> {
+        Ok(Self {
+            repo,
+            inner: repo.transaction()?,
+            locked: BTreeMap::new(),
+            pending: BTreeMap::new(),
+        })
+    }
+
+    /// Lock the ref `name`, returning its target prior to the transaction.
+    ///
+    /// The target of a symbolic ref is `None`.
+    fn lock(&mut self, name: &ext::RefLike) -> Result<Option<ext::Oid>, Error> {
+        if let Some(target) = self.locked.get(name) {
+            return Ok(*target);
+        }
+
+        self.inner.lock_ref(name.as_str())?;
+        let target = self
+            .repo
+            .find_reference(name.as_str())
+            .map(|r| r.target().map(ext::Oid::from))
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(None))?;
+        self.locked.insert(name.clone(), target);
+
+        Ok(target)
+    }
+
+    fn exists(&self, name: &ext::RefLike) -> Result<bool, Error> {
+        self.repo
+            .find_reference(name.as_str())
+            .and(Ok(true))
+            .or_matches(is_not_found_err, || Ok(false))
+    }
+
+    /// Whether `name` exists and is not removed by this transaction, or is
+    /// created by this transaction.
+    fn is_taken(&self, name: &ext::RefLike) -> Result<bool, Error> {
+        match self.pending.get(name) {
+            Some(Update::Remove) => Ok(false),
+            Some(_) => Ok(true),
+            None => self.exists(name),
+        }
+    }
+
+    /// The target of the ref `name`, taking the updates of this transaction
+    /// into account.
+    ///
+    /// The target of a symbolic ref is `None`.
+    fn target(&mut self, name: &ext::RefLike) -> Result<Option<ext::Oid>, Error> {
+        let locked = self.lock(name)?;
+        Ok(match self.pending.get(name) {
+            Some(Update::Target { target, .. }) => Some(ext::Oid::from(*target)),
+            Some(_) => None,
+            None => locked,
+        })
+    }
+
+    /// Point the ref `name` to `target`.
+    ///
+    /// If `force` is [`Force::False`] and the ref already exists, an error is
+    /// returned.
+    pub fn set_target<N>(
+        &mut self,
+        name: N,
+        target: git2::Oid,
+        force: Force,
+        msg: &str,
+    ) -> Result<(), Error>
+    where
+        N: Into<ext::RefLike>,
+    {
+        let name = name.into();
+        self.lock(&name)?;
+        if matches!(force, Force::False) && self.is_taken(&name)? {
+            return Err(Error::Exists(name));
+        }
+
+        self.pending.insert(
+            name,
+            Update::Target {
+                target,
+                msg: msg.to_owned(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Point the ref `name` to `target`, provided it currently points to
+    /// `expected`.
+    ///
+    /// `expected` being `None` means that the ref must not exist.
+    pub fn update<N>(
+        &mut self,
+        name: N,
+        expected: Option<git2::Oid>,
+        target: git2::Oid,
+        msg: &str,
+    ) -> Result<(), Error>
+    where
+        N: Into<ext::RefLike>,
+    {
+        let name = name.into();
+        let actual = self.target(&name)?;
+        let expected = expected.map(ext::Oid::from);
+        if actual != expected || (expected.is_none() && self.is_taken(&name)?) {
+            return Err(Error::Mismatch {
+                name,
+                expected,
+                actual,
+            });
+        }
+
+        self.pending.insert(
+            name,
+            Update::Target {
+                target,
+                msg: msg.to_owned(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Make `name` a symbolic ref pointing to `target`.
+    ///
+    /// `target` must either exist, or be created by this transaction. If
+    /// `force` is [`Force::False`] and `name` already exists, an error is
+    /// returned.
+    pub fn set_symbolic_target<N, T>(
+        &mut self,
+        name: N,
+        target: T,
+        force: Force,
+        msg: &str,
+    ) -> Result<(), Error>
+    where
+        N: Into<ext::RefLike>,
+        T: Into<ext::RefLike>,
+    {
+        let name = name.into();
+        let target = target.into();
+        if !self.is_taken(&target)? {
+            return Err(Error::MissingTarget { name, target });
+        }
+
+        self.lock(&name)?;
+        if matches!(force, Force::False) && self.is_taken(&name)? {
+            return Err(Error::Exists(name));
+        }
+
+        self.pending.insert(
+            name,
+            Update::Symbolic {
+                target,
+                msg: msg.to_owned(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Remove the ref `name`.
+    ///
+    /// `true` is returned if the ref exists, and will be removed when the
+    /// transaction is committed, or if it was created by this transaction, in
+    /// which case the creation is cancelled. Otherwise, `false` is returned.
+    pub fn remove<N>(&mut self, name: N) -> Result<bool, Error>
+    where
+        N: Into<ext::RefLike>,
+    {
+        let name = name.into();
+        self.lock(&name)?;
+        if !self.is_taken(&name)? {
+            return Ok(false);
+        }
+
+        if self.exists(&name)? {
+            self.pending.insert(name, Update::Remove);
+        } else {
+            self.pending.remove(&name);
+        }
+
+        Ok(true)
+    }
+
+    /// Apply all updates.
+    pub fn commit(mut self) -> Result<(), Error> {
+        for (name, update) in &self.pending {
+            match update {
+                Update::Target { target, msg } => {
+                    self.inner.set_target(name.as_str(), *target, None, msg)?
+                },
+                Update::Symbolic { target, msg } => {
+                    self.inner
+                        .set_symbolic_target(name.as_str(), target.as_str(), None, msg)?
+                },
+                Update::Remove => self.inner.remove(name.as_str())?,
+            }
+        }
+        Ok(self.inner.commit()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{git::storage::Storage, keys::SecretKey, paths::Paths};
+
+    fn commit(repo: &git2::Repository) -> git2::Oid {
+        let tree = repo.treebuilder(None).unwrap().write().unwrap();
+        let tree = repo.find_tree(tree).unwrap();
+        let author = git2::Signature::now("leboeuf", "leboeuf@acme.com").unwrap();
+        repo.commit(None, &author, &author, "initial", &tree, &[])
+            .unwrap()
+    }
+
+    #[test]
+    fn all_or_nothing() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp).unwrap();
+            let storage = Storage::open_or_init(&paths, SecretKey::new()).unwrap();
+            let repo = storage.as_raw();
+            let oid = commit(repo);
+            repo.reference("refs/heads/existing", oid, false, "")
+                .unwrap();
+
+            // Fails midway, nothing is written
+            {
+                let mut tx = storage.transaction().unwrap();
+                tx.set_target(reflike!("refs/heads/new"), oid, Force::False, "")
+                    .unwrap();
+                assert!(matches!(
+                    tx.set_target(reflike!("refs/heads/existing"), oid, Force::False, ""),
+                    Err(Error::Exists(_))
+                ));
+            }
+            assert!(repo.find_reference("refs/heads/new").is_err());
+
+            // Succeeds, everything is written
+            let mut tx = storage.transaction().unwrap();
+            tx.set_target(reflike!("refs/heads/new"), oid, Force::False, "")
+                .unwrap();
+            tx.set_symbolic_target(
+                reflike!("refs/heads/sym"),
+                reflike!("refs/heads/new"),
+                Force::False,
+                "",
+            )
+            .unwrap();
+            assert!(tx.remove(reflike!("refs/heads/existing")).unwrap());
+            assert!(!tx.remove(reflike!("refs/heads/nonexistent")).unwrap());
+            tx.commit().unwrap();
+
+            assert_eq!(repo.refname_to_id("refs/heads/sym").unwrap(), oid);
+            assert!(repo.find_reference("refs/heads/existing").is_err());
+        }
+    }
+
+    #[test]
+    fn update_checks_expected() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp).unwrap();
+            let storage = Storage::open_or_init(&paths, SecretKey::new()).unwrap();
+            let repo = storage.as_raw();
+            let oid = commit(repo);
+
+            let mut tx = storage.transaction().unwrap();
+            assert!(matches!(
+                tx.update(reflike!("refs/heads/master"), Some(oid), oid, ""),
+                Err(Error::Mismatch { actual: None, .. })
+            ));
+            tx.update(reflike!("refs/heads/master"), None, oid, "")
+                .unwrap();
+            tx.commit().unwrap();
+
+            assert_eq!(repo.refname_to_id("refs/heads/master").unwrap(), oid);
+        }
+    }
+
+    #[test]
+    fn remove_then_create() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp).unwrap();
+            let storage = Storage::open_or_init(&paths, SecretKey::new()).unwrap();
+            let repo = storage.as_raw();
+            let oid = commit(repo);
+            repo.reference("refs/heads/existing", oid, false, "")
+                .unwrap();
+
+            let mut tx = storage.transaction().unwrap();
+            assert!(tx.remove(reflike!("refs/heads/existing")).unwrap());
+            assert!(!tx.remove(reflike!("refs/heads/existing")).unwrap());
+            tx.set_target(reflike!("refs/heads/existing"), oid, Force::False, "")
+                .unwrap();
+            tx.commit().unwrap();
+
+            assert_eq!(repo.refname_to_id("refs/heads/existing").unwrap(), oid);
+        }
+    }
+
+    #[test]
+    fn create_then_remove() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp).unwrap();
+            let storage = Storage::open_or_init(&paths, SecretKey::new()).unwrap();
+            let repo = storage.as_raw();
+            let oid = commit(repo);
+
+            let mut tx = storage.transaction().unwrap();
+            tx.set_target(reflike!("refs/heads/new"), oid, Force::False, "")
+                .unwrap();
+            assert!(tx.remove(reflike!("refs/heads/new")).unwrap());
+            assert!(matches!(
+                tx.set_symbolic_target(
+                    reflike!("refs/heads/sym"),
+                    reflike!("refs/heads/new"),
+                    Force::False,
+                    ""
+                ),
+                Err(Error::MissingTarget { .. })
+            ));
+            tx.commit().unwrap();
+
+            assert!(repo.find_reference("refs/heads/new").is_err());
+        }
+    }
+}
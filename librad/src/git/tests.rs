@@ -3,5 +3,9 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+mod bundle;
 mod common;
+mod fork;
 mod project;
+mod proposal;
+mod query;
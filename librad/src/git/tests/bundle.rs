@@ -0,0 +1,58 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use either::Either::Left;
+
+use super::*;
+use crate::{
+    git::{bundle, identities, replication},
+    identities::{delegation, payload},
+    keys::SecretKey,
+};
+
+#[test]
+fn export_import() -> anyhow::Result<()> {
+    let dylan = SecretKey::new();
+    let exporter = common::storage(dylan.clone())?;
+    let importer = common::storage(SecretKey::new())?;
+
+    let whoami = common::dylan(&exporter, &dylan)?;
+    let proj = identities::project::create(
+        &exporter,
+        whoami,
+        payload::Project {
+            name: "sneakernet".into(),
+            description: None,
+            default_branch: None,
+        },
+        delegation::Indirect::try_from_iter(Some(Left(dylan.public()))).unwrap(),
+    )?;
+
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("sneakernet.bundle");
+    let manifest = bundle::export(&exporter, &proj.urn(), None, &path)?;
+    assert_eq!(&manifest.origin, exporter.peer_id());
+    assert!(manifest.verify().is_ok());
+
+    bundle::import(&importer, replication::Config::default(), None, &path)?;
+    assert_eq!(
+        Some(proj.clone()),
+        identities::project::get(&importer, &proj.urn())?
+    );
+
+    // Tampering with the manifest is detected
+    let mut tampered = manifest;
+    tampered.refs.clear();
+    std::fs::write(
+        bundle::Manifest::path_for(&path),
+        serde_json::to_vec(&tampered)?,
+    )?;
+    assert!(matches!(
+        bundle::import(&importer, replication::Config::default(), None, &path),
+        Err(bundle::Error::InvalidSignature)
+    ));
+
+    Ok(())
+}
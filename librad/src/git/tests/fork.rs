@@ -0,0 +1,413 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{convert::TryFrom, path::PathBuf};
+
+use either::Either::Left;
+
+use super::*;
+use crate::{
+    git::{
+        bundle,
+        identities::{self, fork, proposal, Error},
+        replication,
+        storage::Storage,
+        types::{Force, Namespace, Reference},
+        Urn,
+    },
+    identities::{
+        delegation,
+        git::{Identities, IndirectDelegation, Project, Verifying},
+        payload,
+        quorum,
+    },
+    keys::SecretKey,
+    peer::PeerId,
+};
+
+lazy_static! {
+    static ref DESKTOP: SecretKey = SecretKey::from_seed([
+        52, 5, 211, 193, 252, 179, 147, 197, 221, 38, 181, 200, 74, 100, 104, 208, 241, 143, 156,
+        130, 118, 94, 82, 173, 18, 164, 96, 77, 81, 82, 182, 149
+    ]);
+    static ref LAPTOP: SecretKey = SecretKey::from_seed([
+        197, 91, 169, 54, 48, 99, 79, 3, 69, 255, 168, 206, 253, 179, 132, 174, 11, 44, 130, 185,
+        181, 169, 203, 221, 41, 75, 222, 216, 113, 131, 19, 240
+    ]);
+}
+
+/// A person with delegates `DESKTOP` and `LAPTOP`, either of which can update
+/// it unilaterally. Both have diverged, and replicating from the desktop to
+/// the laptop fails.
+struct Forked {
+    desktop: common::TmpStorage,
+    laptop: common::TmpStorage,
+    urn: Urn,
+    theirs: Urn,
+    bundle: PathBuf,
+    _tmp: tempfile::TempDir,
+}
+
+impl Forked {
+    fn new() -> anyhow::Result<Self> {
+        let desktop = common::storage(DESKTOP.clone())?;
+        let laptop = common::storage(LAPTOP.clone())?;
+        let tmp = tempfile::tempdir()?;
+        let bundle = tmp.path().join("person.bundle");
+
+        let person = identities::person::create(
+            &desktop,
+            payload::Person {
+                name: "cheyenne".into(),
+            },
+            Some(DESKTOP.public()).into_iter().collect(),
+        )?;
+        let urn = person.urn();
+        identities::person::update_quorum(
+            &desktop,
+            &urn,
+            None,
+            Some(
+                vec![DESKTOP.public(), LAPTOP.public()]
+                    .into_iter()
+                    .collect(),
+            ),
+            Some(quorum::Policy::MinVotes(1)),
+        )?;
+        bundle::export(&desktop, &urn, None, &bundle)?;
+        bundle::import(&laptop, replication::Config::default(), None, &bundle)?;
+
+        rename(&desktop, &urn, "cheyenne (desktop)")?;
+        rename(&laptop, &urn, "cheyenne (laptop)")?;
+        bundle::export(&desktop, &urn, None, &bundle)?;
+
+        let theirs =
+            Urn::try_from(Reference::rad_id(Namespace::from(&urn)).with_remote(*desktop.peer_id()))
+                .map_err(anyhow::Error::msg)?;
+        let forked = Self {
+            desktop,
+            laptop,
+            urn,
+            theirs,
+            bundle,
+            _tmp: tmp,
+        };
+        assert_matches!(
+            forked.replicate(),
+            Err(bundle::Error::Replication(replication::Error::Fork { .. }))
+        );
+        assert!(identities::person::is_fork(
+            &forked.laptop,
+            &forked.urn,
+            &forked.theirs
+        )?);
+        assert!(!fork::is_ignored(
+            &forked.laptop,
+            &forked.urn,
+            &forked.theirs
+        )?);
+
+        Ok(forked)
+    }
+
+    fn replicate(&self) -> Result<replication::ReplicateResult, bundle::Error> {
+        bundle::import(
+            &self.laptop,
+            replication::Config::default(),
+            None,
+            &self.bundle,
+        )
+    }
+}
+
+/// The number of resolved forks of `urn` recorded by [`fork::keep_mine`] or
+/// a merge.
+fn fork_records(storage: &Storage, urn: &Urn) -> anyhow::Result<usize> {
+    Ok(storage
+        .as_raw()
+        .references_glob(&format!("refs/namespaces/{}/refs/forks/*", urn.encode_id()))?
+        .count())
+}
+
+fn rename(storage: &Storage, urn: &Urn, name: &str) -> anyhow::Result<()> {
+    identities::person::update(
+        storage,
+        urn,
+        None,
+        payload::PersonPayload::new(payload::Person { name: name.into() }),
+        None,
+    )?;
+    Ok(())
+}
+
+#[test]
+fn keep_mine() -> anyhow::Result<()> {
+    let forked = Forked::new()?;
+    let mine = identities::person::get(&forked.laptop, &forked.urn)?;
+
+    fork::keep_mine(&forked.laptop, &forked.urn, &forked.theirs)?;
+    assert!(fork::is_ignored(
+        &forked.laptop,
+        &forked.urn,
+        &forked.theirs
+    )?);
+    assert_eq!(fork_records(&forked.laptop, &forked.urn)?, 1);
+    forked.replicate()?;
+    assert_eq!(identities::person::get(&forked.laptop, &forked.urn)?, mine);
+
+    // A new tip of theirs is a fork again
+    rename(&forked.desktop, &forked.urn, "cheyenne (desktop, again)")?;
+    bundle::export(&forked.desktop, &forked.urn, None, &forked.bundle)?;
+    assert_matches!(
+        forked.replicate(),
+        Err(bundle::Error::Replication(replication::Error::Fork { .. }))
+    );
+    assert!(!fork::is_ignored(&forked.laptop, &forked.urn, &forked.theirs)?);
+    // The record of the previous tip is gone
+    assert_eq!(fork_records(&forked.laptop, &forked.urn)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn adopt_fork() -> anyhow::Result<()> {
+    let forked = Forked::new()?;
+
+    let adopted =
+        identities::person::adopt_fork(&forked.laptop, &forked.urn, &forked.theirs, None)?;
+    assert!(!identities::person::is_fork(
+        &forked.laptop,
+        &forked.urn,
+        &forked.theirs
+    )?);
+    forked.replicate()?;
+    assert_eq!(
+        identities::person::get(&forked.laptop, &forked.urn)?,
+        Some(adopted)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn merge_fork() -> anyhow::Result<()> {
+    let forked = Forked::new()?;
+
+    let merged = identities::person::merge_fork(&forked.laptop, &forked.urn, &forked.theirs, None)?;
+    assert!(fork::is_ignored(
+        &forked.laptop,
+        &forked.urn,
+        &forked.theirs
+    )?);
+    forked.replicate()?;
+    assert_eq!(
+        identities::person::verify(&forked.laptop, &forked.urn)?
+            .map(|verified| verified.into_inner()),
+        Some(merged)
+    );
+
+    Ok(())
+}
+
+/// A project with delegate `DESKTOP`, whose `rad/id` has diverged from the one
+/// replicated from `LAPTOP`.
+struct ForkedProject {
+    storage: common::TmpStorage,
+    urn: Urn,
+    theirs: Urn,
+    base: Project,
+    mine: Project,
+    their_id: Project,
+}
+
+impl ForkedProject {
+    /// If `delegations` are given, the fork on the laptop's side updates the
+    /// delegations to those, and is signed by both `DESKTOP` and `LAPTOP`.
+    fn new(delegations: Option<IndirectDelegation>) -> anyhow::Result<Self> {
+        let storage = common::storage(DESKTOP.clone())?;
+        let whoami = common::dylan(&storage, &DESKTOP)?;
+        let base = identities::project::create(
+            &storage,
+            whoami,
+            project_payload("tiny-skia"),
+            delegation::Indirect::try_from_iter(Some(Left(DESKTOP.public()))).unwrap(),
+        )?;
+        let urn = base.urn();
+
+        let their_id = {
+            let git = Identities::<Project>::from(storage.as_raw());
+            let signed_by_both = delegations.is_some();
+            let theirs = git.update(
+                Verifying::from(base.clone()).signed()?,
+                project_payload("tiny-skia (laptop)"),
+                delegations,
+                &*DESKTOP,
+            )?;
+            if signed_by_both {
+                git.create_from(Verifying::from(theirs).signed()?, &*LAPTOP)?
+            } else {
+                theirs
+            }
+        };
+        let their_ref =
+            Reference::rad_id(Namespace::from(&urn)).with_remote(PeerId::from(LAPTOP.public()));
+        their_ref.create(
+            storage.as_raw(),
+            *their_id.content_id,
+            Force::False,
+            "replicated",
+        )?;
+        let theirs = Urn::try_from(their_ref).map_err(anyhow::Error::msg)?;
+
+        let mine = identities::project::update(
+            &storage,
+            &urn,
+            None,
+            project_payload("tiny-skia (desktop)"),
+            None,
+        )?;
+        assert!(identities::project::is_fork(&storage, &urn, &theirs)?);
+
+        Ok(Self {
+            storage,
+            urn,
+            theirs,
+            base,
+            mine,
+            their_id,
+        })
+    }
+}
+
+fn project_payload(name: &str) -> payload::ProjectPayload {
+    payload::ProjectPayload::new(payload::Project {
+        name: name.into(),
+        description: None,
+        default_branch: None,
+    })
+}
+
+#[test]
+fn explain_project_fork() -> anyhow::Result<()> {
+    let forked = ForkedProject::new(None)?;
+
+    let explained =
+        identities::project::explain_fork(&forked.storage, &forked.urn, &forked.theirs)?;
+    assert_eq!(explained.base, Some(forked.base.content_id));
+    assert_eq!(explained.mine.head, forked.mine.content_id);
+    assert_eq!(explained.theirs.head, forked.their_id.content_id);
+    assert_eq!(
+        explained
+            .theirs
+            .commits
+            .iter()
+            .map(|commit| commit.revision)
+            .collect::<Vec<_>>(),
+        vec![forked.their_id.revision]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn adopt_project_fork() -> anyhow::Result<()> {
+    let forked = ForkedProject::new(None)?;
+
+    let adopted =
+        identities::project::adopt_fork(&forked.storage, &forked.urn, &forked.theirs, None)?;
+    assert_eq!(adopted, forked.their_id);
+    assert!(!identities::project::is_fork(
+        &forked.storage,
+        &forked.urn,
+        &forked.theirs
+    )?);
+    assert_eq!(
+        identities::project::verify(&forked.storage, &forked.urn)?
+            .map(|verified| verified.into_inner()),
+        Some(adopted)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn merge_project_fork() -> anyhow::Result<()> {
+    let forked = ForkedProject::new(None)?;
+
+    let merged =
+        identities::project::merge_fork(&forked.storage, &forked.urn, &forked.theirs, None)?;
+    assert_eq!(merged.revision, forked.mine.revision);
+    assert!(fork::is_ignored(
+        &forked.storage,
+        &forked.urn,
+        &forked.theirs
+    )?);
+    assert_eq!(
+        identities::project::verify(&forked.storage, &forked.urn)?
+            .map(|verified| verified.into_inner()),
+        Some(merged)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn merge_project_fork_requires_quorum() -> anyhow::Result<()> {
+    let forked = ForkedProject::new(Some(
+        delegation::Indirect::try_from_iter(vec![Left(DESKTOP.public()), Left(LAPTOP.public())])
+            .unwrap(),
+    ))?;
+
+    // Adding the laptop as a delegate needs to be signed by it
+    let revision =
+        match identities::project::merge_fork(&forked.storage, &forked.urn, &forked.theirs, None) {
+            Err(Error::Proposed(revision)) => revision,
+            res => panic!("expected a proposal, got {:?}", res),
+        };
+    assert_eq!(
+        identities::project::get(&forked.storage, &forked.urn)?,
+        Some(forked.mine.clone())
+    );
+    assert!(!fork::is_ignored(
+        &forked.storage,
+        &forked.urn,
+        &forked.theirs
+    )?);
+
+    // The laptop signs, and the proposal is finalised
+    let laptop = PeerId::from(LAPTOP.public());
+    {
+        let proposed = proposal::get(&forked.storage, &forked.urn, &revision, None)?
+            .expect("proposal exists")
+            .project;
+        let signed = Identities::<Project>::from(forked.storage.as_raw())
+            .create_from(Verifying::from(proposed).signed()?, &*LAPTOP)?;
+        Reference::rad_proposal(Namespace::from(&forked.urn), laptop, &revision).create(
+            forked.storage.as_raw(),
+            *signed.content_id,
+            Force::False,
+            "replicated",
+        )?;
+    }
+    proposal::sign(&forked.storage, &forked.urn, &revision, laptop)?;
+    proposal::finalise(&forked.storage, &forked.urn, None, &revision, None)?;
+
+    // Now the merge goes through
+    let merged =
+        identities::project::merge_fork(&forked.storage, &forked.urn, &forked.theirs, None)?;
+    assert_eq!(merged.revision, revision);
+    assert!(fork::is_ignored(
+        &forked.storage,
+        &forked.urn,
+        &forked.theirs
+    )?);
+    assert_eq!(
+        identities::project::verify(&forked.storage, &forked.urn)?
+            .map(|verified| verified.into_inner()),
+        Some(merged)
+    );
+
+    Ok(())
+}
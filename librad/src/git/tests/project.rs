@@ -3,13 +3,18 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use either::Either::Left;
+use either::Either::{Left, Right};
 
 use super::*;
 use crate::{
-    git::identities,
-    identities::{delegation, payload, SomeIdentity},
+    git::{
+        identities,
+        storage::ReadOnly,
+        types::{Namespace, Reference},
+    },
+    identities::{delegation, payload, Delegate, SomeIdentity},
     keys::SecretKey,
+    peer::PeerId,
 };
 
 lazy_static! {
@@ -43,3 +48,103 @@ fn create_anonymous() -> anyhow::Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn view_resolves_maintainers() -> anyhow::Result<()> {
+    let storage = common::storage(DYLAN.clone())?;
+    let whoami = common::dylan(&storage, &DYLAN)?;
+    let dylan = whoami.clone().into_inner().into_inner();
+    let proj = identities::project::create(
+        &storage,
+        whoami,
+        payload::Project {
+            name: "reMarkable 3".into(),
+            description: None,
+            default_branch: None,
+        },
+        delegation::Indirect::try_from_iter(Some(Right(Delegate::from(dylan.clone())))).unwrap(),
+    )?;
+
+    let view = identities::project::view(&storage, &proj.urn())?.expect("project not found");
+    assert_eq!(view.project.content_id, proj.content_id);
+    assert_eq!(view.maintainers.len(), 1);
+
+    let maintainer = &view.maintainers[0];
+    assert_eq!(maintainer.urn(), dylan.urn());
+    assert_eq!(maintainer.name.as_str(), "dylan");
+    assert_eq!(maintainer.delegate.revision, dylan.revision);
+    assert_eq!(maintainer.keys().collect::<Vec<_>>(), vec![&DYLAN.public()]);
+    assert!(maintainer.replicated);
+    assert!(maintainer.is_self);
+
+    Ok(())
+}
+
+#[test]
+fn view_resolves_replicated_maintainers() -> anyhow::Result<()> {
+    let storage = common::storage(DYLAN.clone())?;
+    let whoami = common::dylan(&storage, &DYLAN)?;
+    let dylan = whoami.clone().into_inner().into_inner();
+    let proj = identities::project::create(
+        &storage,
+        whoami,
+        payload::Project {
+            name: "reMarkable 3".into(),
+            description: None,
+            default_branch: None,
+        },
+        delegation::Indirect::try_from_iter(Some(Right(Delegate::from(dylan.clone())))).unwrap(),
+    )?;
+
+    // Pretend dylan is only known as replicated from a remote of the project
+    let repo = storage.as_raw();
+    let rad_id = Reference::rad_id(Namespace::from(&dylan.urn())).to_string();
+    let tip = repo.refname_to_id(&rad_id)?;
+    repo.reference(
+        &Reference::rad_delegate(Namespace::from(&proj.urn()), &dylan.urn())
+            .with_remote(PeerId::from(SecretKey::new().public()))
+            .to_string(),
+        tip,
+        false,
+        "replicated dylan",
+    )?;
+    repo.find_reference(&rad_id)?.delete()?;
+    assert!(identities::project::verify(&storage, &proj.urn()).is_err());
+
+    let view = identities::project::view(&storage, &proj.urn())?.expect("project not found");
+    assert_eq!(view.maintainers.len(), 1);
+    let maintainer = &view.maintainers[0];
+    assert_eq!(maintainer.urn(), dylan.urn());
+    assert_eq!(maintainer.delegate.revision, dylan.revision);
+    assert!(!maintainer.replicated);
+
+    Ok(())
+}
+
+#[test]
+fn read_only() -> anyhow::Result<()> {
+    let storage = common::storage(DYLAN.clone())?;
+    let whoami = common::dylan(&storage, &DYLAN)?;
+    let dylan = whoami.clone().into_inner().into_inner();
+    let proj = identities::project::create(
+        &storage,
+        whoami,
+        payload::Project {
+            name: "reMarkable 3".into(),
+            description: None,
+            default_branch: None,
+        },
+        delegation::Indirect::try_from_iter(Some(Right(Delegate::from(dylan)))).unwrap(),
+    )?;
+
+    let read_only = AsRef::<ReadOnly>::as_ref(&storage).reopen()?;
+    assert_eq!(
+        Some(proj.content_id),
+        identities::project::verify(&read_only, &proj.urn())?.map(|proj| proj.content_id)
+    );
+    let view = identities::project::view(&read_only, &proj.urn())?.expect("project not found");
+    assert_eq!(view.maintainers.len(), 1);
+    assert_eq!(identities::any::list(&read_only)?.count(), 2);
+
+    Ok(())
+}
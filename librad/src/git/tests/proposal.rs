@@ -0,0 +1,104 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use either::Either::Left;
+
+use super::*;
+use crate::{
+    git::{
+        identities::{self, proposal, Error},
+        types::{Force, Namespace, Reference},
+    },
+    identities::{
+        delegation,
+        git::{Identities, Project, VerificationError, Verifying},
+        payload,
+    },
+    keys::SecretKey,
+    peer::PeerId,
+};
+
+lazy_static! {
+    static ref DYLAN: SecretKey = SecretKey::from_seed([
+        188, 166, 161, 203, 144, 68, 64, 48, 105, 98, 55, 215, 50, 154, 43, 236, 168, 133, 230, 36,
+        134, 79, 175, 109, 234, 123, 23, 114, 61, 82, 96, 52
+    ]);
+    static ref JOAN: SecretKey = SecretKey::from_seed([
+        99, 122, 22, 183, 197, 76, 106, 18, 30, 37, 6, 234, 42, 123, 154, 71, 176, 65, 2, 27, 212,
+        104, 214, 188, 235, 168, 54, 117, 203, 211, 240, 83
+    ]);
+}
+
+#[test]
+fn propose_sign_finalise() -> anyhow::Result<()> {
+    let storage = common::storage(DYLAN.clone())?;
+    let whoami = common::dylan(&storage, &DYLAN)?;
+    let proj = identities::project::create(
+        &storage,
+        whoami,
+        payload::Project {
+            name: "tiny-skia".into(),
+            description: None,
+            default_branch: None,
+        },
+        delegation::Indirect::try_from_iter(Some(Left(DYLAN.public()))).unwrap(),
+    )?;
+    let urn = proj.urn();
+
+    // Dylan proposes to add Joan as a delegate
+    let proposed = proposal::propose(
+        &storage,
+        &urn,
+        None,
+        delegation::Indirect::try_from_iter(vec![Left(DYLAN.public()), Left(JOAN.public())])
+            .unwrap(),
+    )?;
+    let revision = proposed.project.revision;
+    assert!(!proposed.is_quorum());
+    assert_eq!(
+        identities::project::get(&storage, &urn)?.map(|proj| proj.content_id),
+        Some(proj.content_id)
+    );
+    assert_eq!(proposal::list(&storage, &urn)?, vec![proposed.clone()]);
+
+    // Which Joan has to agree to
+    assert_matches!(
+        proposal::finalise(&storage, &urn, None, &revision, None),
+        Err(Error::Verification(VerificationError::Quorum))
+    );
+
+    // Joan signs, and we get her copy of the proposal
+    let joan = PeerId::from(JOAN.public());
+    {
+        let theirs = Identities::<Project>::from(storage.as_raw())
+            .create_from(Verifying::from(proposed.project).signed()?, &*JOAN)?;
+        Reference::rad_proposal(Namespace::from(&urn), joan, &revision).create(
+            storage.as_raw(),
+            *theirs.content_id,
+            Force::False,
+            "replicated",
+        )?;
+    }
+
+    let signed = proposal::sign(&storage, &urn, &revision, joan)?;
+    assert!(signed.is_quorum());
+    assert_eq!(proposal::list(&storage, &urn)?.len(), 2);
+
+    let finalised = proposal::finalise(&storage, &urn, None, &revision, None)?;
+    assert_eq!(finalised, signed.project);
+    assert_eq!(
+        identities::project::verify(&storage, &urn)?.map(|proj| proj.content_id),
+        Some(finalised.content_id)
+    );
+    assert_eq!(proposal::get(&storage, &urn, &revision, None)?, None);
+
+    // Joan's copy is now stale
+    assert_matches!(
+        proposal::finalise(&storage, &urn, None, &revision, Some(joan)),
+        Err(Error::StaleProposal(rev)) if rev == revision
+    );
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::thread;
+
+use either::Either::Left;
+
+use super::*;
+use crate::{
+    git::{
+        identities::{
+            self,
+            query::{self, Filter, Index, Kind, Page},
+        },
+        storage::ReadOnly,
+    },
+    identities::{delegation, payload},
+    keys::SecretKey,
+};
+
+#[test]
+fn list_paginated() -> anyhow::Result<()> {
+    let key = SecretKey::new();
+    let storage = common::storage(key.clone())?;
+    let whoami = common::dylan(&storage, &key)?;
+    for name in &["rad", "radicle", "link"] {
+        identities::project::create(
+            &storage,
+            whoami.clone(),
+            payload::Project {
+                name: (*name).into(),
+                description: None,
+                default_branch: None,
+            },
+            delegation::Indirect::try_from_iter(Some(Left(key.public()))).unwrap(),
+        )?;
+    }
+
+    let projects = Filter {
+        kind: Some(Kind::Project),
+        ..Filter::default()
+    };
+    let all = query::list(&storage, &Filter::default(), &Page::default())?;
+    assert_eq!(all.entries.len(), 4);
+    assert_eq!(all.next, None);
+
+    let first = query::list(
+        &storage,
+        &projects,
+        &Page {
+            after: None,
+            limit: 2,
+        },
+    )?;
+    assert_eq!(first.entries.len(), 2);
+    let second = query::list(
+        &storage,
+        &projects,
+        &Page {
+            after: first.next.clone(),
+            limit: 2,
+        },
+    )?;
+    assert_eq!(second.entries.len(), 1);
+    assert_eq!(second.next, None);
+
+    let rad = Filter {
+        name_prefix: Some("rad".to_owned()),
+        delegate: Some(key.public()),
+        ..projects.clone()
+    };
+    assert_eq!(
+        query::list(&storage, &rad, &Page::default())?.entries.len(),
+        2
+    );
+
+    let by_urn = Filter {
+        urn_prefix: Some(whoami.urn().to_string()),
+        ..Filter::default()
+    };
+    let dylan = query::list(&storage, &by_urn, &Page::default())?;
+    assert_eq!(dylan.entries.len(), 1);
+    assert_eq!(dylan.entries[0].kind, Kind::Person);
+
+    let index = Index::create(&storage)?;
+    assert_eq!(Some(index.clone()), Index::open(&storage)?);
+    assert_eq!(index.list(&Filter::default(), &Page::default()), all);
+    assert_eq!(
+        index.list(
+            &projects,
+            &Page {
+                after: None,
+                limit: 2
+            }
+        ),
+        first
+    );
+
+    Ok(())
+}
+
+#[test]
+fn index_follows_local_writes() -> anyhow::Result<()> {
+    let key = SecretKey::new();
+    let storage = common::storage(key.clone())?;
+    let whoami = common::dylan(&storage, &key)?;
+    Index::create(&storage)?;
+
+    let proj = identities::project::create(
+        &storage,
+        whoami,
+        payload::Project {
+            name: "rad".into(),
+            description: None,
+            default_branch: None,
+        },
+        delegation::Indirect::try_from_iter(Some(Left(key.public()))).unwrap(),
+    )?;
+    let index = Index::open(&storage)?.expect("index was removed");
+    let listed = index.list(&Filter::default(), &Page::default());
+    assert_eq!(listed.entries.len(), 2);
+    assert!(listed.entries.iter().any(|entry| entry.urn == proj.urn()));
+
+    let proj = identities::project::update(
+        &storage,
+        &proj.urn(),
+        None,
+        payload::ProjectPayload::new(payload::Project {
+            name: "radicle".into(),
+            description: None,
+            default_branch: None,
+        }),
+        None,
+    )?;
+    let index = Index::open(&storage)?.expect("index was removed");
+    let entry = index
+        .list(&Filter::default(), &Page::default())
+        .entries
+        .into_iter()
+        .find(|entry| entry.urn == proj.urn())
+        .expect("project not indexed");
+    assert_eq!(entry.name, "radicle");
+    assert_eq!(entry.tip, proj.content_id);
+
+    Ok(())
+}
+
+#[test]
+fn index_concurrent_updates() -> anyhow::Result<()> {
+    let key = SecretKey::new();
+    let storage = common::storage(key.clone())?;
+    let whoami = common::dylan(&storage, &key)?;
+    let mut urns = Vec::new();
+    for name in &["rad", "radicle", "link"] {
+        let proj = identities::project::create(
+            &storage,
+            whoami.clone(),
+            payload::Project {
+                name: (*name).into(),
+                description: None,
+                default_branch: None,
+            },
+            delegation::Indirect::try_from_iter(Some(Left(key.public()))).unwrap(),
+        )?;
+        urns.push(proj.urn());
+    }
+    Index::create(&storage)?;
+
+    let writers = urns
+        .into_iter()
+        .map(|urn| {
+            let read_only = AsRef::<ReadOnly>::as_ref(&storage).reopen()?;
+            Ok(thread::spawn(move || {
+                for _ in 0..20 {
+                    query::update_index(&read_only, &urn).unwrap();
+                }
+            }))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let mut index = Index::open(&storage)?.expect("index was removed");
+    index.refresh(&storage)?;
+    assert_eq!(
+        index
+            .list(&Filter::default(), &Page::default())
+            .entries
+            .len(),
+        4
+    );
+
+    Ok(())
+}
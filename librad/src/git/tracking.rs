@@ -3,15 +3,17 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{ops::Range, str::FromStr};
+use std::{collections::BTreeSet, convert::TryFrom, str::FromStr};
 
-use git_ext::{is_exists_err, is_not_found_err, RefLike};
+use git_ext::{is_exists_err, is_not_found_err, OneLevel, RefLike, RefspecPattern};
 use std_ext::result::ResultExt as _;
 use thiserror::Error;
 
 use super::{
     p2p::url::GitUrlRef,
-    storage::{self, glob, Storage},
+    refs,
+    storage::{self, backend, glob, Pattern as _, Storage},
+    types::RefsCategory,
 };
 use crate::peer::PeerId;
 
@@ -23,9 +25,21 @@ pub enum Error {
     #[error("can't track oneself")]
     SelfReferential,
 
+    #[error("malformed tracking policy `{key}`: `{value}`")]
+    MalformedPolicy { key: String, value: String },
+
     #[error(transparent)]
     Store(#[from] storage::Error),
 
+    #[error(transparent)]
+    Config(#[from] storage::config::Error),
+
+    #[error("storage backend error")]
+    Backend(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("failed to determine the tracking graph")]
+    Graph(#[source] Box<refs::stored::Error>),
+
     #[error(transparent)]
     Git(#[from] git2::Error),
 }
@@ -38,7 +52,8 @@ pub enum Error {
 ///
 /// # Errors
 ///
-/// Attempting to track oneself (ie. [`Storage::peer_id`]) is an error.
+/// Attempting to track oneself (ie. [`storage::ReadOnly::peer_id`]) is an
+/// error.
 #[tracing::instrument(skip(storage), err)]
 pub fn track(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error> {
     let local_peer = storage.peer_id();
@@ -70,6 +85,58 @@ pub fn track(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error>
     Ok(was_created)
 }
 
+/// Track the given `peer` in the context of `urn`, replicating only what the
+/// [`Policy`] allows.
+///
+/// If `peer` is already tracked, its policy is replaced. The return value is
+/// the same as for [`track`].
+#[tracing::instrument(skip(storage), err)]
+pub fn track_with(
+    storage: &Storage,
+    urn: &Urn,
+    peer: PeerId,
+    policy: &Policy,
+) -> Result<bool, Error> {
+    let was_created = track(storage, urn, peer)?;
+    set_policy(storage, urn, peer, policy)?;
+    Ok(was_created)
+}
+
+/// Read the [`Policy`] for `peer` in the context of `urn`.
+///
+/// `None` is returned if `peer` is not tracked. If no policy was set
+/// explicitly, the [`Policy::default`] is returned.
+#[tracing::instrument(level = "trace", skip(storage), err)]
+pub fn policy<B>(storage: &B, urn: &Urn, peer: PeerId) -> Result<Option<Policy>, Error>
+where
+    B: backend::ReadConfig,
+{
+    if !is_tracked(storage, urn, peer)? {
+        return Ok(None);
+    }
+
+    read_policy(storage, &tracking_remote_name(urn, &peer)).map(Some)
+}
+
+/// Replace the [`Policy`] for `peer` in the context of `urn`.
+///
+/// `false` is returned if `peer` is not tracked, in which case nothing is
+/// changed.
+#[tracing::instrument(skip(storage), err)]
+pub fn set_policy(
+    storage: &Storage,
+    urn: &Urn,
+    peer: PeerId,
+    policy: &Policy,
+) -> Result<bool, Error> {
+    if !is_tracked(storage, urn, peer)? {
+        return Ok(false);
+    }
+
+    write_policy(storage, &tracking_remote_name(urn, &peer), policy)?;
+    Ok(true)
+}
+
 /// Remove the tracking of `peer` in the context of `urn`.
 ///
 /// `true` is returned if the tracking relationship existed and was removed as a
@@ -77,11 +144,13 @@ pub fn track(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error>
 ///
 /// # Caveats
 ///
-/// Untracking will also attempt to prune any remote branches associated with
-/// `peer` (this mirrors the behaviour of `git`). Since refdb operations are not
-/// (yet) atomic, this may fail, leaving "dangling" refs in the storage. It is
-/// safe to call this function repeatedly, so as to ensure all remote tracking
-/// branches have been pruned.
+/// Untracking will also prune any remote branches associated with `peer` (this
+/// mirrors the behaviour of `git`). The branches are removed in a single
+/// [`storage::Transaction`], but the tracking relationship itself is stored in
+/// the config, and is removed beforehand. If pruning fails, "dangling" refs may
+/// thus be left in the storage. It is safe to call this function repeatedly, so
+/// as to ensure all remote tracking branches have been pruned (see also
+/// [`prune_dangling`]).
 #[tracing::instrument(skip(storage), err)]
 pub fn untrack(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error> {
     let remote_name = tracking_remote_name(urn, &peer);
@@ -92,54 +161,174 @@ pub fn untrack(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error
         .or_matches::<Error, _, _>(is_not_found_err, || Ok(false))?;
 
     // Prune all remote branches
-    let prune = storage.references_glob(glob::RefspecMatcher::from(
-        reflike!("refs/namespaces")
-            .join(urn)
-            .join(reflike!("refs/remotes"))
-            .join(peer)
-            .with_pattern_suffix(refspec_pattern!("*")),
-    ))?;
-
+    let prune = storage
+        .reference_names_glob(glob::RefspecMatcher::from(
+            reflike!("refs/namespaces")
+                .join(urn)
+                .join(reflike!("refs/remotes"))
+                .join(peer)
+                .with_pattern_suffix(refspec_pattern!("*")),
+        ))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut tx = storage.transaction()?;
     for branch in prune {
-        branch?.delete()?;
+        tx.remove(branch).map_err(storage::Error::from)?;
     }
+    tx.commit().map_err(storage::Error::from)?;
 
     Ok(was_removed)
 }
 
+/// Remove the remote tracking branches of all peers which are not part of the
+/// tracking graph of `urn` (see [`refs::tracking_graph`]).
+///
+/// This is a consistency sweep for the caveat described in [`untrack`]: if
+/// pruning the remote branches failed midway, or a remote was removed by other
+/// means, `refs/remotes/<peer>/**` may be left dangling. The remotes of peers
+/// tracked transitively via a tracked peer are kept. The set of peers for
+/// which refs were removed is returned.
+#[tracing::instrument(skip(storage), err)]
+pub fn prune_dangling(storage: &Storage, urn: &Urn) -> Result<BTreeSet<PeerId>, Error> {
+    let tracked = refs::tracking_graph(storage, urn).map_err(|e| Error::Graph(Box::new(e)))?;
+    let remotes = reflike!("refs/namespaces")
+        .join(urn)
+        .join(reflike!("refs/remotes"));
+
+    let mut dangling = Vec::new();
+    let mut pruned = BTreeSet::new();
+    for name in storage.reference_names_glob(glob::RefspecMatcher::from(
+        remotes.with_pattern_suffix(refspec_pattern!("*")),
+    ))? {
+        let name = name?;
+        let peer = name
+            .strip_prefix(&remotes)
+            .ok()
+            .and_then(|suffix| suffix.as_str().split('/').next().map(PeerId::from_str));
+        match peer {
+            Some(Ok(peer)) if tracked.contains(&peer) => continue,
+            Some(Ok(peer)) => {
+                pruned.insert(peer);
+            },
+            None | Some(Err(_)) => {
+                tracing::warn!(name = %name, "pruning malformed remote tracking branch")
+            },
+        }
+        dangling.push(name);
+    }
+
+    let mut tx = storage.transaction()?;
+    for name in dangling {
+        tx.remove(name).map_err(storage::Error::from)?;
+    }
+    tx.commit().map_err(storage::Error::from)?;
+
+    Ok(pruned)
+}
+
 /// Determine if `peer` is tracked in the context of `urn`.
 #[tracing::instrument(level = "trace", skip(storage), err)]
-pub fn is_tracked(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error> {
+pub fn is_tracked<B>(storage: &B, urn: &Urn, peer: PeerId) -> Result<bool, Error>
+where
+    B: backend::ReadConfig,
+{
     storage
-        .as_raw()
-        .find_remote(&tracking_remote_name(urn, &peer))
-        .and(Ok(true))
-        .or_matches(is_not_found_err, || Ok(false))
+        .config_str(&format!("remote.{}.url", tracking_remote_name(urn, &peer)))
+        .map(|url| url.is_some())
+        .map_err(backend_error)
+}
+
+/// Track the given `peer` in the context of any identity it announces.
+///
+/// Unlike [`track`], this does not establish a tracking relationship by
+/// itself: when `peer` announces an identity we don't track it for yet, it
+/// will be tracked for that identity before replicating (see
+/// [`track_if_trusted`]).
+///
+/// `true` is returned if `peer` was not trusted before.
+///
+/// # Errors
+///
+/// Attempting to trust oneself (ie. [`storage::ReadOnly::peer_id`]) is an
+/// error.
+#[tracing::instrument(skip(storage), err)]
+pub fn trust(storage: &Storage, peer: PeerId) -> Result<bool, Error> {
+    if &peer == storage.peer_id() {
+        return Err(Error::SelfReferential);
+    }
+
+    Ok(storage.config()?.add_trustee(peer)?)
+}
+
+/// Stop tracking `peer` in the context of identities it announces in the
+/// future.
+///
+/// The tracking relationships established while `peer` was trusted are left
+/// untouched, use [`untrack`] to remove them.
+///
+/// `true` is returned if `peer` was trusted before.
+#[tracing::instrument(skip(storage), err)]
+pub fn untrust(storage: &Storage, peer: PeerId) -> Result<bool, Error> {
+    Ok(storage.config()?.remove_trustee(&peer)?)
+}
+
+/// The set of peers tracked in the context of any identity they announce.
+pub fn trusted(storage: &Storage) -> Result<BTreeSet<PeerId>, Error> {
+    Ok(storage.config()?.trustees()?)
+}
+
+/// Determine if `peer` is tracked in the context of `urn`, either explicitly
+/// or because it is [`trusted`].
+///
+/// If `peer` is trusted but not yet tracked in the context of `urn`, the
+/// tracking relationship is created as a side-effect.
+#[tracing::instrument(level = "trace", skip(storage), err)]
+pub fn track_if_trusted(storage: &Storage, urn: &Urn, peer: PeerId) -> Result<bool, Error> {
+    if is_tracked(storage, urn, peer)? {
+        return Ok(true);
+    }
+
+    if &peer != storage.peer_id() && storage.config()?.is_trustee(&peer)? {
+        track(storage, urn, peer)?;
+        return Ok(true);
+    }
+
+    Ok(false)
 }
 
 /// Obtain an iterator over the 1st degree tracked peers in the context of
 /// `urn`.
-pub fn tracked(storage: &Storage, urn: &Urn) -> Result<Tracked, Error> {
-    Ok(Tracked::collect(storage.as_raw(), urn)?)
+pub fn tracked<B>(storage: &B, urn: &Urn) -> Result<Tracked, Error>
+where
+    B: backend::ReadConfig,
+{
+    Tracked::collect(storage, urn)
 }
 
 /// Iterator over the 1st degree tracked peers.
 #[must_use = "iterators are lazy and do nothing unless consumed"]
 pub struct Tracked {
-    remotes: git2::string_array::StringArray,
-    range: Range<usize>,
-    prefix: String,
+    peers: std::collections::btree_set::IntoIter<PeerId>,
 }
 
 impl Tracked {
-    fn collect(repo: &git2::Repository, context: &Urn) -> Result<Self, git2::Error> {
-        let remotes = repo.remotes()?;
-        let range = 0..remotes.len();
-        let prefix = format!("{}/", RefLike::from(context));
+    fn collect<B>(backend: &B, context: &Urn) -> Result<Self, Error>
+    where
+        B: backend::ReadConfig,
+    {
+        let prefix = format!("remote.{}/", context.encode_id());
+        let peers = backend
+            .config_keys(&prefix)
+            .map_err(backend_error)?
+            .iter()
+            .filter_map(|key| {
+                key.strip_prefix(&prefix)
+                    .and_then(|rest| rest.strip_suffix(".url"))
+                    .and_then(|peer| PeerId::from_str(peer).ok())
+            })
+            .collect::<BTreeSet<_>>();
         Ok(Self {
-            remotes,
-            range,
-            prefix,
+            peers: peers.into_iter(),
         })
     }
 }
@@ -148,21 +337,197 @@ impl Iterator for Tracked {
     type Item = PeerId;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some(name) = self.range.next().and_then(|i| self.remotes.get(i)) {
-            if let Some(peer) = name
-                .strip_prefix(&self.prefix)
-                .and_then(|peer| PeerId::from_str(peer).ok())
-            {
-                return Some(peer);
-            }
+        self.peers.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.peers.size_hint()
+    }
+}
+
+/// What to replicate from a tracked peer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    /// The categories of refs to replicate.
+    ///
+    /// Note that the `rad` refs required for identity verification are always
+    /// replicated.
+    pub categories: BTreeSet<RefsCategory>,
+    /// If not empty, only the refs matching any of the patterns are
+    /// replicated. The patterns are matched against the name of the ref
+    /// relative to the peer, eg. `refs/heads/release/*`.
+    pub patterns: Vec<RefspecPattern>,
+    /// Whether to also track the peers tracked by this peer.
+    pub follow_remotes: bool,
+    /// The maximum size in bytes of the data replicated from this peer in one
+    /// go. Updates exceeding it are rejected.
+    ///
+    /// The limit is checked after the data was fetched, so it bounds what is
+    /// kept, not what is transferred.
+    pub size_limit: Option<u64>,
+}
+
+impl Default for Policy {
+    /// Replicate everything.
+    fn default() -> Self {
+        Self {
+            categories: vec![
+                RefsCategory::Heads,
+                RefsCategory::Rad,
+                RefsCategory::Tags,
+                RefsCategory::Notes,
+            ]
+            .into_iter()
+            .collect(),
+            patterns: vec![],
+            follow_remotes: true,
+            size_limit: None,
+        }
+    }
+}
+
+impl Policy {
+    /// Determine if the ref `name` of `category` should be replicated.
+    ///
+    /// [`RefsCategory::Rad`] refs are always allowed, regardless of
+    /// [`Policy::categories`] and [`Policy::patterns`].
+    pub fn allows(&self, category: RefsCategory, name: &OneLevel) -> bool {
+        if category == RefsCategory::Rad {
+            return true;
+        }
+        if !self.categories.contains(&category) {
+            return false;
+        }
+        if self.patterns.is_empty() {
+            return true;
         }
 
-        None
+        let qualified = reflike!("refs")
+            .join(RefLike::from(category))
+            .join(name.clone());
+        self.patterns
+            .iter()
+            .any(|pattern| glob::RefspecMatcher::from(pattern.clone()).matches(qualified.as_str()))
     }
+}
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.range.size_hint()
+const POLICY_CATEGORIES: &str = "radCategories";
+const POLICY_REFS: &str = "radRefs";
+const POLICY_FOLLOW_REMOTES: &str = "radFollowRemotes";
+const POLICY_SIZE_LIMIT: &str = "radSizeLimit";
+
+fn read_policy<B>(backend: &B, remote_name: &str) -> Result<Policy, Error>
+where
+    B: backend::ReadConfig,
+{
+    let key = |name: &str| format!("remote.{}.{}", remote_name, name);
+    let malformed = |key: String, value: &str| Error::MalformedPolicy {
+        key,
+        value: value.to_owned(),
+    };
+
+    let mut policy = Policy::default();
+
+    if let Some(categories) = backend
+        .config_str(&key(POLICY_CATEGORIES))
+        .map_err(backend_error)?
+    {
+        policy.categories = categories
+            .split_whitespace()
+            .map(|cat| {
+                RefsCategory::parse(cat).ok_or_else(|| malformed(key(POLICY_CATEGORIES), cat))
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    if let Some(patterns) = backend
+        .config_str(&key(POLICY_REFS))
+        .map_err(backend_error)?
+    {
+        policy.patterns = patterns
+            .split_whitespace()
+            .map(|pat| RefspecPattern::try_from(pat).map_err(|_| malformed(key(POLICY_REFS), pat)))
+            .collect::<Result<_, _>>()?;
+    }
+
+    if let Some(follow) = backend
+        .config_bool(&key(POLICY_FOLLOW_REMOTES))
+        .map_err(backend_error)?
+    {
+        policy.follow_remotes = follow;
+    }
+
+    if let Some(limit) = backend
+        .config_i64(&key(POLICY_SIZE_LIMIT))
+        .map_err(backend_error)?
+    {
+        policy.size_limit = Some(
+            u64::try_from(limit)
+                .map_err(|_| malformed(key(POLICY_SIZE_LIMIT), &limit.to_string()))?,
+        );
     }
+
+    Ok(policy)
+}
+
+fn write_policy<B>(backend: &B, remote_name: &str, policy: &Policy) -> Result<(), Error>
+where
+    B: backend::WriteConfig,
+{
+    let key = |name: &str| format!("remote.{}.{}", remote_name, name);
+
+    for name in &[
+        POLICY_CATEGORIES,
+        POLICY_REFS,
+        POLICY_FOLLOW_REMOTES,
+        POLICY_SIZE_LIMIT,
+    ] {
+        backend.remove_config(&key(name)).map_err(backend_error)?;
+    }
+
+    if policy == &Policy::default() {
+        return Ok(());
+    }
+
+    backend
+        .set_config_str(
+            &key(POLICY_CATEGORIES),
+            &policy
+                .categories
+                .iter()
+                .map(|cat| cat.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+        .map_err(backend_error)?;
+    backend
+        .set_config_str(
+            &key(POLICY_REFS),
+            &policy
+                .patterns
+                .iter()
+                .map(|pat| pat.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+        .map_err(backend_error)?;
+    backend
+        .set_config_bool(&key(POLICY_FOLLOW_REMOTES), policy.follow_remotes)
+        .map_err(backend_error)?;
+    if let Some(limit) = policy.size_limit {
+        backend
+            .set_config_i64(&key(POLICY_SIZE_LIMIT), limit as i64)
+            .map_err(backend_error)?;
+    }
+
+    Ok(())
+}
+
+fn backend_error<E>(e: E) -> Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    Error::Backend(Box::new(e))
 }
 
 fn tracking_remote_name(urn: &Urn, peer: &PeerId) -> String {
@@ -173,8 +538,6 @@ fn tracking_remote_name(urn: &Urn, peer: &PeerId) -> String {
 mod tests {
     use super::*;
 
-    use std::collections::BTreeSet;
-
     use crate::{keys::SecretKey, paths::Paths};
 
     #[test]
@@ -255,4 +618,254 @@ mod tests {
             )
         }
     }
+
+    #[test]
+    fn prune_dangling_removes_untracked_remotes() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp).unwrap();
+            let storage = Storage::open_or_init(&paths, SecretKey::new()).unwrap();
+            let tracked_peer = PeerId::from(SecretKey::new());
+            let dangling_peer = PeerId::from(SecretKey::new());
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            let repo = storage.as_raw();
+            let tree = repo.treebuilder(None).unwrap().write().unwrap();
+            let tree = repo.find_tree(tree).unwrap();
+            let author = git2::Signature::now("leboeuf", "leboeuf@acme.com").unwrap();
+            let commit = repo
+                .commit(None, &author, &author, "initial", &tree, &[])
+                .unwrap();
+
+            let branch = |peer: &PeerId| {
+                format!(
+                    "refs/namespaces/{}/refs/remotes/{}/heads/master",
+                    urn.encode_id(),
+                    peer
+                )
+            };
+            repo.reference(&branch(&tracked_peer), commit, false, "")
+                .unwrap();
+            repo.reference(&branch(&dangling_peer), commit, false, "")
+                .unwrap();
+
+            track(&storage, &urn, tracked_peer).unwrap();
+            assert_eq!(
+                Some(dangling_peer).into_iter().collect::<BTreeSet<_>>(),
+                prune_dangling(&storage, &urn).unwrap()
+            );
+            assert!(repo.find_reference(&branch(&tracked_peer)).is_ok());
+            assert!(repo.find_reference(&branch(&dangling_peer)).is_err());
+        }
+    }
+
+    #[test]
+    fn prune_dangling_keeps_transitive_remotes() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp).unwrap();
+            let storage = Storage::open_or_init(&paths, SecretKey::new()).unwrap();
+            let tracked_key = SecretKey::new();
+            let tracked_peer = PeerId::from(tracked_key.clone());
+            let transitive_peer = PeerId::from(SecretKey::new());
+            let dangling_peer = PeerId::from(SecretKey::new());
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            let repo = storage.as_raw();
+            let author = git2::Signature::now("leboeuf", "leboeuf@acme.com").unwrap();
+            let remote = |peer: &PeerId, name: &str| {
+                format!(
+                    "refs/namespaces/{}/refs/remotes/{}/{}",
+                    urn.encode_id(),
+                    peer,
+                    name
+                )
+            };
+
+            // The tracked peer tracks `transitive_peer`
+            let signed_refs = refs::Refs {
+                heads: Default::default(),
+                rad: Default::default(),
+                tags: Default::default(),
+                notes: Default::default(),
+                remotes: Some(transitive_peer).into_iter().collect(),
+            }
+            .sign(&tracked_key)
+            .unwrap();
+            let blob = repo
+                .blob(&serde_json::to_vec(&signed_refs).unwrap())
+                .unwrap();
+            let mut tree = repo.treebuilder(None).unwrap();
+            tree.insert(refs::stored::BLOB_PATH, blob, 0o100_644)
+                .unwrap();
+            let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+            let commit = repo
+                .commit(None, &author, &author, "signed refs", &tree, &[])
+                .unwrap();
+            repo.reference(&remote(&tracked_peer, "rad/signed_refs"), commit, false, "")
+                .unwrap();
+            for peer in &[transitive_peer, dangling_peer] {
+                repo.reference(&remote(peer, "heads/master"), commit, false, "")
+                    .unwrap();
+            }
+
+            track(&storage, &urn, tracked_peer).unwrap();
+            assert_eq!(
+                Some(dangling_peer).into_iter().collect::<BTreeSet<_>>(),
+                prune_dangling(&storage, &urn).unwrap()
+            );
+            assert!(repo
+                .find_reference(&remote(&tracked_peer, "rad/signed_refs"))
+                .is_ok());
+            assert!(repo
+                .find_reference(&remote(&transitive_peer, "heads/master"))
+                .is_ok());
+            assert!(repo
+                .find_reference(&remote(&dangling_peer, "heads/master"))
+                .is_err());
+
+            // Not following the remotes of the tracked peer drops them
+            set_policy(
+                &storage,
+                &urn,
+                tracked_peer,
+                &Policy {
+                    follow_remotes: false,
+                    ..Policy::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(
+                Some(transitive_peer).into_iter().collect::<BTreeSet<_>>(),
+                prune_dangling(&storage, &urn).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn trusted_is_tracked_everywhere() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp).unwrap();
+            let storage = Storage::open_or_init(&paths, SecretKey::new()).unwrap();
+            let remote_peer = PeerId::from(SecretKey::new());
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            assert!(!track_if_trusted(&storage, &urn, remote_peer).unwrap());
+            assert!(trust(&storage, remote_peer).unwrap());
+            assert_eq!(
+                Some(remote_peer).into_iter().collect::<BTreeSet<_>>(),
+                trusted(&storage).unwrap()
+            );
+            assert!(track_if_trusted(&storage, &urn, remote_peer).unwrap());
+            assert!(is_tracked(&storage, &urn, remote_peer).unwrap());
+
+            assert!(untrust(&storage, remote_peer).unwrap());
+            assert!(trusted(&storage).unwrap().is_empty());
+            assert!(is_tracked(&storage, &urn, remote_peer).unwrap());
+            assert_matches!(
+                trust(&storage, *storage.peer_id()),
+                Err(Error::SelfReferential)
+            )
+        }
+    }
+
+    #[test]
+    fn set_policy_policy() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let paths = Paths::from_root(tmp).unwrap();
+            let storage = Storage::open_or_init(&paths, SecretKey::new()).unwrap();
+            let remote_peer = PeerId::from(SecretKey::new());
+            let urn = Urn::new(git2::Oid::zero().into());
+
+            let custom = Policy {
+                categories: Some(RefsCategory::Heads).into_iter().collect(),
+                patterns: vec![refspec_pattern!("refs/heads/release/*")],
+                follow_remotes: false,
+                size_limit: Some(1024),
+            };
+
+            assert_eq!(None, policy(&storage, &urn, remote_peer).unwrap());
+            assert!(!set_policy(&storage, &urn, remote_peer, &custom).unwrap());
+
+            track_with(&storage, &urn, remote_peer, &custom).unwrap();
+            assert_eq!(Some(custom), policy(&storage, &urn, remote_peer).unwrap());
+
+            set_policy(&storage, &urn, remote_peer, &Policy::default()).unwrap();
+            assert_eq!(
+                Some(Policy::default()),
+                policy(&storage, &urn, remote_peer).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn policy_roundtrip_in_memory() {
+        let backend = backend::Memory::new();
+        let custom = Policy {
+            categories: Some(RefsCategory::Tags).into_iter().collect(),
+            patterns: vec![refspec_pattern!("refs/tags/v*")],
+            follow_remotes: false,
+            size_limit: None,
+        };
+
+        assert_eq!(Policy::default(), read_policy(&backend, "x/y").unwrap());
+        write_policy(&backend, "x/y", &custom).unwrap();
+        assert_eq!(custom, read_policy(&backend, "x/y").unwrap());
+        write_policy(&backend, "x/y", &Policy::default()).unwrap();
+        assert!(backend::ReadConfig::config_keys(&backend, "remote.x/y.")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn tracked_in_memory() {
+        let backend = backend::Memory::new();
+        let urn = Urn::new(git2::Oid::zero().into());
+        let remote_peer = PeerId::from(SecretKey::new());
+
+        assert!(!is_tracked(&backend, &urn, remote_peer).unwrap());
+        backend::WriteConfig::set_config_str(
+            &backend,
+            &format!("remote.{}.url", tracking_remote_name(&urn, &remote_peer)),
+            "rad://unused",
+        )
+        .unwrap();
+        assert!(is_tracked(&backend, &urn, remote_peer).unwrap());
+        assert_eq!(
+            vec![remote_peer],
+            tracked(&backend, &urn).unwrap().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(Policy::default()),
+            policy(&backend, &urn, remote_peer).unwrap()
+        );
+    }
+
+    #[test]
+    fn policy_allows() {
+        let policy = Policy {
+            categories: Some(RefsCategory::Heads).into_iter().collect(),
+            patterns: vec![refspec_pattern!("refs/heads/release/*")],
+            ..Policy::default()
+        };
+
+        assert!(policy.allows(RefsCategory::Heads, &OneLevel::from(reflike!("release/v1"))));
+        assert!(!policy.allows(RefsCategory::Heads, &OneLevel::from(reflike!("master"))));
+        assert!(!policy.allows(RefsCategory::Tags, &OneLevel::from(reflike!("release/v1"))));
+    }
+
+    #[test]
+    fn policy_allows_rad() {
+        let policy = Policy {
+            categories: Some(RefsCategory::Heads).into_iter().collect(),
+            patterns: vec![refspec_pattern!("refs/heads/release/*")],
+            ..Policy::default()
+        };
+
+        assert!(policy.allows(RefsCategory::Rad, &OneLevel::from(reflike!("id"))));
+        assert!(policy.allows(RefsCategory::Rad, &OneLevel::from(reflike!("signed_refs"))));
+        assert!(policy.allows(RefsCategory::Rad, &OneLevel::from(reflike!("ids/foo"))));
+    }
 }
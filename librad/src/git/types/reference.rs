@@ -16,7 +16,7 @@ use crate::{
     peer::{self, PeerId},
 };
 
-use super::{sealed, AsNamespace, Force, Namespace};
+use super::{super::storage, sealed, AsNamespace, Force, Namespace};
 
 use identities::git::Urn;
 
@@ -32,7 +32,7 @@ pub type Many = ext::RefspecPattern;
 /// Alias for [`Many`].
 pub type Multiple = Many;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum RefsCategory {
     Heads,
     Rad,
@@ -41,7 +41,7 @@ pub enum RefsCategory {
 }
 
 impl RefsCategory {
-    fn parse(s: &str) -> Option<Self> {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
         match s {
             "heads" => Some(Self::Heads),
             "rad" => Some(Self::Rad),
@@ -225,6 +225,24 @@ impl<N, R> Reference<N, R, One> {
         }
     }
 
+    /// Build a reference that points to:
+    ///     * `refs/namespaces/<namespace>/refs/rad/ids/proposals/<revision>`
+    ///     * `refs/namespaces/<namespace>/refs/remote/<peer_id>/rad/ids/
+    ///       proposals/<revision>`
+    pub fn rad_proposal(
+        namespace: impl Into<Option<N>>,
+        remote: impl Into<Option<R>>,
+        revision: &ext::Oid,
+    ) -> Self {
+        Self {
+            remote: remote.into(),
+            category: RefsCategory::Rad,
+            name: reflike!("ids/proposals")
+                .join(ext::RefLike::try_from(revision.to_string()).unwrap()),
+            namespace: namespace.into(),
+        }
+    }
+
     /// Build a reference that points to:
     ///     * `refs/namespaces/<namespace>/refs/rad/signed_refs`
     ///     * `refs/namespaces/<namespace>/refs/remote/<peer_id>/rad/
@@ -360,6 +378,18 @@ impl<N, R> Reference<N, R, Many> {
         }
     }
 
+    /// Build a reference that points to:
+    ///     * `refs[/namespaces/<namespace>/refs][/remotes/<remote>]/rad/ids/
+    ///       proposals/*`
+    pub fn rad_proposals(namespace: impl Into<Option<N>>, remote: impl Into<Option<R>>) -> Self {
+        Self {
+            remote: remote.into(),
+            category: RefsCategory::Rad,
+            name: refspec_pattern!("ids/proposals/*"),
+            namespace: namespace.into(),
+        }
+    }
+
     /// Build a reference that points to:
     ///     * `refs[/namespaces/<namespace>/refs][/remotes/<remote>]/heads/*`
     pub fn heads(namespace: impl Into<Option<N>>, remote: impl Into<Option<R>>) -> Self {
@@ -583,6 +613,25 @@ impl<S, T> SymbolicRef<S, T> {
             )
         })
     }
+
+    /// Like [`SymbolicRef::create`], but as part of the given
+    /// [`storage::Transaction`].
+    pub fn create_in(
+        &self,
+        tx: &mut storage::Transaction,
+    ) -> Result<(), storage::transaction::Error>
+    where
+        for<'b> &'b S: Into<ext::RefLike>,
+        for<'b> &'b T: Into<ext::RefLike>,
+    {
+        let source = Into::<ext::RefLike>::into(&self.source);
+        let target = Into::<ext::RefLike>::into(&self.target);
+
+        let reflog_msg = &format!("creating symbolic ref {} -> {}", source, target);
+        tracing::debug!("{}", reflog_msg);
+
+        tx.set_symbolic_target(source, target, self.force, reflog_msg)
+    }
 }
 
 #[cfg(test)]
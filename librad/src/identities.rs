@@ -7,7 +7,9 @@ pub mod delegation;
 pub mod generic;
 pub mod git;
 pub mod payload;
+pub mod quorum;
 pub mod relations;
+pub mod revocation;
 pub mod sign;
 pub mod urn;
 
@@ -29,6 +29,28 @@ pub trait Delegations: sealed::Sealed {
     /// Nb.: "threshold" means that there must be `quorum_threshold() + 1` votes
     /// to form a quorum.
     fn quorum_threshold(&self) -> usize;
+
+    /// Whether the [`Delegations::eligible`] `votes` form a quorum.
+    ///
+    /// By default, this is the case if there are more than
+    /// [`Delegations::quorum_threshold`] votes.
+    fn is_quorum(&self, votes: &BTreeSet<&PublicKey>) -> bool {
+        votes.len() > self.quorum_threshold()
+    }
+
+    /// The keys which have been revoked.
+    ///
+    /// Revoked keys are never eligible, and signatures by them are rejected on
+    /// subsequent revisions. By default, no keys are revoked.
+    fn revoked(&self) -> BTreeSet<&PublicKey> {
+        BTreeSet::new()
+    }
+
+    /// The time (in seconds since the epoch) after which `key` is no longer
+    /// eligible, if any. By default, keys don't expire.
+    fn expiry(&self, _key: &PublicKey) -> Option<i64> {
+        None
+    }
 }
 
 //// Forwarding impls for `Doc` and `Identity`
@@ -39,13 +61,38 @@ where
 {
     type Error = D::Error;
 
+    /// Excludes the [`generic::Doc::revocations`].
     fn eligible(&self, votes: BTreeSet<&PublicKey>) -> Result<BTreeSet<&PublicKey>, Self::Error> {
-        self.delegations.eligible(votes)
+        let mut eligible = self.delegations.eligible(votes)?;
+        eligible.retain(|key| !self.revocations.contains_key(key));
+        Ok(eligible)
     }
 
     fn quorum_threshold(&self) -> usize {
         self.delegations.quorum_threshold()
     }
+
+    /// Evaluates the [`generic::Doc::quorum`] policy, if any.
+    fn is_quorum(&self, votes: &BTreeSet<&PublicKey>) -> bool {
+        match &self.quorum {
+            Some(policy) => policy.is_quorum(votes),
+            None => self.delegations.is_quorum(votes),
+        }
+    }
+
+    fn revoked(&self) -> BTreeSet<&PublicKey> {
+        let mut revoked = self.delegations.revoked();
+        revoked.extend(self.revocations.keys());
+        revoked
+    }
+
+    /// The [`generic::Doc::not_after`] of `key`, if any.
+    fn expiry(&self, key: &PublicKey) -> Option<i64> {
+        self.not_after
+            .get(key)
+            .copied()
+            .or_else(|| self.delegations.expiry(key))
+    }
 }
 
 impl<T, R, C> Delegations for generic::Identity<T, R, C>
@@ -61,4 +108,16 @@ where
     fn quorum_threshold(&self) -> usize {
         self.doc.quorum_threshold()
     }
+
+    fn is_quorum(&self, votes: &BTreeSet<&PublicKey>) -> bool {
+        self.doc.is_quorum(votes)
+    }
+
+    fn revoked(&self) -> BTreeSet<&PublicKey> {
+        self.doc.revoked()
+    }
+
+    fn expiry(&self, key: &PublicKey) -> Option<i64> {
+        self.doc.expiry(key)
+    }
 }
@@ -6,6 +6,8 @@
 #![allow(clippy::type_complexity)]
 
 use std::{
+    collections::BTreeMap,
+    convert::Infallible,
     fmt::{Debug, Display},
     marker::PhantomData,
     ops::Deref,
@@ -13,7 +15,17 @@ use std::{
 
 use serde::ser::SerializeStruct;
 
-use super::{delegation::Delegations, payload::Payload, sealed, sign::Signatures, urn::Urn};
+use crate::keys::PublicKey;
+
+use super::{
+    delegation::Delegations,
+    payload::Payload,
+    quorum,
+    revocation::Revocation,
+    sealed,
+    sign::Signatures,
+    urn::Urn,
+};
 
 pub mod error;
 
@@ -33,6 +45,24 @@ pub struct Doc<T, D, Revision> {
     pub replaces: Option<Revision>,
     pub payload: T,
     pub delegations: D,
+    /// The [`quorum::Policy`] for this document, if it differs from the
+    /// default majority rule. Omitted from the serialised form if `None`.
+    #[serde(default)]
+    pub quorum: Option<quorum::Policy>,
+    /// Keys which have been revoked. Omitted from the serialised form if
+    /// empty.
+    #[serde(default)]
+    pub revocations: BTreeMap<PublicKey, Revocation<Revision>>,
+    /// The time (in seconds since the epoch) after which a delegate key is no
+    /// longer eligible to sign. Omitted from the serialised form if empty.
+    ///
+    /// Expiry is checked against the [`Identity::timestamp`], which is chosen
+    /// by the author of the attestation. Holders of an expired key can thus
+    /// circumvent the expiry by backdating, and other peers must decide
+    /// whether to trust the timestamps. Compromised keys should be revoked
+    /// instead.
+    #[serde(default, rename = "notAfter")]
+    pub not_after: BTreeMap<PublicKey, i64>,
 }
 
 impl<T, D, Revision> serde::Serialize for Doc<T, D, Revision>
@@ -45,11 +75,29 @@ where
     where
         S: serde::Serializer,
     {
-        let mut doc = serializer.serialize_struct("Doc", 4)?;
+        let len = 4
+            + self.quorum.is_some() as usize
+            + !self.revocations.is_empty() as usize
+            + !self.not_after.is_empty() as usize;
+        let mut doc = serializer.serialize_struct("Doc", len)?;
         doc.serialize_field("version", &0)?;
         doc.serialize_field("replaces", &self.replaces)?;
         doc.serialize_field("payload", &self.payload)?;
         doc.serialize_field("delegations", &self.delegations)?;
+        match &self.quorum {
+            Some(quorum) => doc.serialize_field("quorum", quorum)?,
+            None => doc.skip_field("quorum")?,
+        }
+        if self.revocations.is_empty() {
+            doc.skip_field("revocations")?;
+        } else {
+            doc.serialize_field("revocations", &self.revocations)?;
+        }
+        if self.not_after.is_empty() {
+            doc.skip_field("notAfter")?;
+        } else {
+            doc.serialize_field("notAfter", &self.not_after)?;
+        }
         doc.end()
     }
 }
@@ -68,6 +116,9 @@ impl<T, D, R> Doc<T, D, R> {
             replaces: self.replaces,
             payload: f(self.payload),
             delegations: g(self.delegations),
+            quorum: self.quorum,
+            revocations: self.revocations,
+            not_after: self.not_after,
         }
     }
 
@@ -100,6 +151,9 @@ impl<T, D, R> Doc<T, D, R> {
             replaces: doc.replaces,
             payload: doc.payload?,
             delegations: doc.delegations,
+            quorum: doc.quorum,
+            revocations: doc.revocations,
+            not_after: doc.not_after,
         })
     }
 
@@ -116,6 +170,9 @@ impl<T, D, R> Doc<T, D, R> {
             replaces: doc.replaces,
             payload: doc.payload,
             delegations: doc.delegations?,
+            quorum: doc.quorum,
+            revocations: doc.revocations,
+            not_after: doc.not_after,
         })
     }
 }
@@ -139,6 +196,12 @@ impl<T, D, R> sealed::Sealed for Doc<T, D, R> {}
 #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Identity<T, Revision, ContentId> {
     pub content_id: ContentId,
+    /// The time (in seconds since the epoch) of the attestation, if known.
+    ///
+    /// In `git`, this is the commit time. It is not part of the serialised
+    /// form.
+    #[serde(skip)]
+    pub timestamp: Option<i64>,
     pub root: Revision,
     pub revision: Revision,
     pub doc: T,
@@ -168,6 +231,7 @@ impl<T, R, C> Identity<T, R, C> {
     {
         Identity {
             content_id: self.content_id,
+            timestamp: self.timestamp,
             root: self.root,
             revision: self.revision,
             doc: f(self.doc),
@@ -185,6 +249,7 @@ impl<T, R, C, Error> Identity<Result<T, Error>, R, C> {
     pub fn transpose(self) -> Result<Identity<T, R, C>, Error> {
         Ok(Identity {
             content_id: self.content_id,
+            timestamp: self.timestamp,
             root: self.root,
             revision: self.revision,
             doc: self.doc?,
@@ -367,8 +432,10 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Signed> {
     ///
     /// # Errors
     ///
-    /// If the number of signatures does not reach the
-    /// [`Delegations::quorum_threshold`].
+    /// If the eligible signatures do not form a quorum according to
+    /// [`Delegations::is_quorum`]. Signatures by keys which have expired at
+    /// the [`Identity::timestamp`] (see [`Delegations::expiry`]) are not
+    /// eligible.
     pub fn quorum(self) -> Result<Verifying<Identity<T, R, C>, Quorum>, error::Verify<R, C>>
     where
         T: Delegations,
@@ -377,13 +444,13 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Signed> {
         R: Debug + Display,
         C: Debug + Display,
     {
-        let eligible = self
+        let mut eligible = self
             .doc
             .eligible(self.signatures.keys().collect())
-            .map_err(error::Verify::eligibility)?
-            .len();
+            .map_err(error::Verify::eligibility)?;
+        eligible.retain(|key| !is_expired(&self.doc, key, self.timestamp));
 
-        if eligible > self.doc.quorum_threshold() {
+        if self.doc.is_quorum(&eligible) {
             Ok(self.coerce())
         } else {
             Err(error::Verify::Quorum)
@@ -408,9 +475,13 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Quorum> {
     /// * the `parent` revision doesn't match `replaces`
     /// * `self`'s signatures do not reach a quorum of the `parent`'s
     ///   delegations. In other words,
-    ///   `parent.eligible(self.signatures.keys()).len() >
-    ///   parent.doc.quorum_threshold()`
+    ///   `parent.doc.is_quorum(&parent.eligible(self.signatures.keys()))` does
+    ///   not hold
     /// * `parent.eligible(self.signatures.keys())` returns an error
+    /// * signatures by keys which, according to `parent`, have expired at
+    ///   `self.timestamp` are not counted towards the quorum
+    /// * `self` is signed by a key revoked by `parent`, or does not retain all
+    ///   of the `parent`'s revocations
     pub fn verified(
         self,
         parent: Option<&Verifying<Identity<T, R, C>, Verified>>,
@@ -443,13 +514,21 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Quorum> {
                         actual: parent.revision.to_owned(),
                     })
                 } else {
-                    let votes = parent
+                    let revoked = parent.doc.revoked();
+                    if let Some(key) = self.signatures.keys().find(|key| revoked.contains(key)) {
+                        return Err(error::Verify::Revoked(*key));
+                    }
+                    if let Some(key) = revoked.difference(&self.doc.revoked()).next() {
+                        return Err(error::Verify::RevocationDropped(**key));
+                    }
+
+                    let mut votes = parent
                         .doc
                         .eligible(self.signatures.keys().collect())
-                        .map_err(error::Verify::eligibility)?
-                        .len();
+                        .map_err(error::Verify::eligibility)?;
+                    votes.retain(|key| !is_expired(&parent.doc, key, self.timestamp));
 
-                    if votes > parent.doc.quorum_threshold() {
+                    if parent.doc.is_quorum(&votes) {
                         Ok(self.coerce())
                     } else {
                         Err(error::Verify::ParentQuorum)
@@ -470,6 +549,181 @@ pub struct Folded<T, R, C> {
     pub parent: Option<Verifying<Identity<T, R, C>, Verified>>,
 }
 
+/// How an identity was treated by [`Folded::fold`].
+#[derive(Debug)]
+pub enum Step<R, C>
+where
+    R: Display + Debug + 'static,
+    C: Display + Debug + 'static,
+{
+    /// The identity was verified, and became the new head.
+    Verified,
+    /// The identity confirmed the current head, ie. it has the same revision,
+    /// and became the new head.
+    Confirmed,
+    /// The identity did not reach [`Quorum`], and was skipped. This is the
+    /// case for proposals.
+    NoQuorum(error::Verify<R, C>),
+    /// The identity reached [`Quorum`], but did not verify against the parent
+    /// of the current head, and was skipped.
+    Skipped(error::Verify<R, C>),
+    /// The identity failed verification, rendering the history invalid.
+    Invalid(error::Verify<R, C>),
+    /// The identity verified, but its document was rejected by the
+    /// validation passed to [`Folded::fold`]. The fold stops, and the current
+    /// head is the result.
+    Rejected(error::Verify<R, C>),
+}
+
+impl<T, R, C> Folded<T, R, C> {
+    /// Verify the hash-linked `history` of an identity, starting from its
+    /// root.
+    ///
+    /// Like [`Verifying::verify`], it is up to the caller to ensure that the
+    /// [`Iterator`] yields elements in reverse order. The first element is
+    /// verified as the root of the history.
+    ///
+    /// Every document which would become the new head is passed to
+    /// `validate`, which may reject it on grounds the verification itself is
+    /// not aware of (eg. its payload). A rejected root renders the history
+    /// invalid, otherwise the fold stops at the last valid revision.
+    ///
+    /// If given, `observe` is called with every identity in the `history`,
+    /// along with how it was treated. This includes the identity which failed
+    /// verification or validation, if any.
+    pub fn fold<E, V, VE, F>(
+        history: impl Iterator<Item = Result<Verifying<Identity<T, R, C>, Untrusted>, E>>,
+        validate: V,
+        observe: Option<F>,
+    ) -> Result<Self, error::Verify<R, C>>
+    where
+        T: Clone + Delegations + Replaces<Revision = R>,
+        T::Error: std::error::Error + Send + Sync + 'static,
+
+        R: Clone + Debug + Display + PartialEq + AsRef<[u8]>,
+        C: Clone + Debug + Display,
+
+        E: std::error::Error + Send + Sync + 'static,
+        V: Fn(&T) -> Result<(), VE>,
+        VE: std::error::Error + Send + Sync + 'static,
+        F: FnMut(&Identity<T, R, C>, &Step<R, C>),
+    {
+        Self::fold_from(None, history, validate, observe)?.ok_or(error::Verify::EmptyHistory)
+    }
+
+    fn fold_from<E, V, VE, F>(
+        mut acc: Option<Self>,
+        history: impl Iterator<Item = Result<Verifying<Identity<T, R, C>, Untrusted>, E>>,
+        validate: V,
+        mut observe: Option<F>,
+    ) -> Result<Option<Self>, error::Verify<R, C>>
+    where
+        T: Clone + Delegations + Replaces<Revision = R>,
+        T::Error: std::error::Error + Send + Sync + 'static,
+
+        R: Clone + Debug + Display + PartialEq + AsRef<[u8]>,
+        C: Clone + Debug + Display,
+
+        E: std::error::Error + Send + Sync + 'static,
+        V: Fn(&T) -> Result<(), VE>,
+        VE: std::error::Error + Send + Sync + 'static,
+        F: FnMut(&Identity<T, R, C>, &Step<R, C>),
+    {
+        for cur in history {
+            let cur = cur.map_err(error::Verify::history)?;
+            let observed = observe.as_ref().map(|_| Identity::clone(&cur));
+            let (next, step) = Self::step(acc, cur, &validate);
+            if let (Some(observe), Some(observed)) = (observe.as_mut(), observed) {
+                observe(&observed, &step)
+            }
+            acc = next;
+            match step {
+                Step::Invalid(e) => return Err(e),
+                Step::Rejected(_) => break,
+                _ => {},
+            }
+        }
+
+        Ok(acc)
+    }
+
+    /// Advance the fold by one identity, where `acc` is `None` if `cur` is the
+    /// root of the history.
+    fn step<V, VE>(
+        acc: Option<Self>,
+        cur: Verifying<Identity<T, R, C>, Untrusted>,
+        validate: &V,
+    ) -> (Option<Self>, Step<R, C>)
+    where
+        T: Delegations + Replaces<Revision = R>,
+        T::Error: std::error::Error + Send + Sync + 'static,
+
+        R: Clone + Debug + Display + PartialEq + AsRef<[u8]>,
+        C: Clone + Debug + Display,
+
+        V: Fn(&T) -> Result<(), VE>,
+        VE: std::error::Error + Send + Sync + 'static,
+    {
+        // Not signed is an error
+        let signed = match cur.signed() {
+            Err(e) => return (acc, Step::Invalid(e)),
+            Ok(signed) => signed,
+        };
+        let quorum = match signed.quorum() {
+            // The root must reach quorum
+            Err(e) if acc.is_none() => return (acc, Step::Invalid(e)),
+            // Not reaching quorum is ok otherwise, skip
+            Err(e) => return (acc, Step::NoQuorum(e)),
+            Ok(quorum) => quorum,
+        };
+
+        match acc {
+            None => match quorum.verified(None) {
+                Err(e) => (None, Step::Invalid(e)),
+                Ok(head) => match validate(&head.doc) {
+                    Err(e) => (None, Step::Invalid(error::Verify::payload(e))),
+                    Ok(()) => (Some(Self { head, parent: None }), Step::Verified),
+                },
+            },
+
+            // A confirmation of `head` is ok, but `parent` stays the same then.
+            // We need to be careful to not let a current quorum invalidate our
+            // already-confirmed state -- so skip if this doesn't pass
+            // `verified`, instead of returning an error (which would render
+            // this history invalid).
+            Some(acc)
+                if quorum.revision == acc.head.revision
+                    && quorum.doc.replaces() == acc.head.doc.replaces() =>
+            {
+                match quorum.verified(acc.parent.as_ref()) {
+                    Err(e) => (Some(acc), Step::Skipped(e)),
+                    Ok(head) => (
+                        Some(Self {
+                            head,
+                            parent: acc.parent,
+                        }),
+                        Step::Confirmed,
+                    ),
+                }
+            }
+
+            Some(acc) => match quorum.verified(Some(&acc.head)) {
+                Err(e) => (Some(acc), Step::Invalid(e)),
+                Ok(head) => match validate(&head.doc) {
+                    Err(e) => (Some(acc), Step::Rejected(error::Verify::payload(e))),
+                    Ok(()) => (
+                        Some(Self {
+                            head,
+                            parent: Some(acc.head),
+                        }),
+                        Step::Verified,
+                    ),
+                },
+            },
+        }
+    }
+}
+
 impl<T, R, C> Verifying<Identity<T, R, C>, Verified> {
     /// Starting from a [`Verified`] base [`Identity`], and its progeny, attempt
     /// to verify each identity in the progeny until either verification
@@ -484,12 +738,14 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Verified> {
     ///
     /// [`Signed`] identities in the progeny, which do not pass [`Quorum`] are
     /// skipped. This is to allow proposals to be made over the same protocol.
+    ///
+    /// See [`Folded::fold`] for verifying a history including its root.
     pub fn verify<E>(
         self,
-        mut progeny: impl Iterator<Item = Result<Verifying<Identity<T, R, C>, Untrusted>, E>>,
+        progeny: impl Iterator<Item = Result<Verifying<Identity<T, R, C>, Untrusted>, E>>,
     ) -> Result<Folded<T, R, C>, error::Verify<R, C>>
     where
-        T: Delegations + Replaces<Revision = R>,
+        T: Clone + Delegations + Replaces<Revision = R>,
         T::Error: std::error::Error + Send + Sync + 'static,
 
         R: Clone + Debug + Display + PartialEq + AsRef<[u8]>,
@@ -497,43 +753,27 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Verified> {
 
         E: std::error::Error + Send + Sync + 'static,
     {
-        progeny.try_fold(
-            Folded {
-                head: self,
-                parent: None,
-            },
-            |acc, cur| {
-                // Not signed is an error
-                let signed = cur.map_err(error::Verify::history)?.signed()?;
-                match signed.quorum() {
-                    // Not reaching quorum is ok, skip
-                    Err(_) => Ok(acc),
-                    Ok(quorum) => {
-                        // A confirmation of `self` is ok, but `parent` stays
-                        // the same then. We need to be careful to not let a
-                        // current quorum invalidate our already-confirmed state
-                        // -- so skip if this doesn't pass `verified`, instead
-                        // of returning an error (which would render this
-                        // history invalid).
-                        if quorum.revision == acc.head.revision
-                            && quorum.doc.replaces() == acc.head.doc.replaces()
-                        {
-                            match quorum.verified(acc.parent.as_ref()) {
-                                Err(_) => Ok(acc),
-                                Ok(verified) => Ok(Folded {
-                                    head: verified,
-                                    parent: acc.parent,
-                                }),
-                            }
-                        } else {
-                            quorum.verified(Some(&acc.head)).map(|verified| Folded {
-                                head: verified,
-                                parent: Some(acc.head),
-                            })
-                        }
-                    },
-                }
-            },
+        let base = Folded {
+            head: self,
+            parent: None,
+        };
+        Folded::fold_from(
+            Some(base),
+            progeny,
+            |_: &T| Ok::<_, Infallible>(()),
+            None::<fn(&Identity<T, R, C>, &Step<R, C>)>,
         )
+        .map(|folded| folded.expect("base is `Some`"))
+    }
+}
+
+/// Whether `key` has expired at time `at`, according to `delegations`.
+///
+/// If `at` is not known, keys which have an expiry are considered expired.
+fn is_expired<D: Delegations>(delegations: &D, key: &PublicKey, at: Option<i64>) -> bool {
+    match (delegations.expiry(key), at) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(not_after), Some(at)) => at > not_after,
     }
 }
@@ -7,6 +7,8 @@ use std::fmt::{Debug, Display};
 
 use thiserror::Error;
 
+use crate::keys::PublicKey;
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Verify<Revision, ContentId>
@@ -26,6 +28,12 @@ where
     #[error("quorum on parent not reached")]
     ParentQuorum,
 
+    #[error("signed by revoked key {0}")]
+    Revoked(PublicKey),
+
+    #[error("revocation of key {0} was dropped")]
+    RevocationDropped(PublicKey),
+
     #[error("expected parent {expected}, found {actual}")]
     ParentMismatch {
         expected: Revision,
@@ -52,6 +60,9 @@ where
 
     #[error("error traversing the identity history")]
     History(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("invalid payload")]
+    Payload(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
 }
 
 impl<R, C> Verify<R, C>
@@ -72,4 +83,11 @@ where
     {
         Self::History(Box::new(e))
     }
+
+    pub fn payload<E>(e: E) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Self::Payload(Box::new(e))
+    }
 }
@@ -136,6 +136,7 @@ where
 {
     Identity {
         content_id: Boring,
+        timestamp: None,
         root: Boring,
         revision: Boring,
         doc: Doc {
@@ -143,6 +144,9 @@ where
             replaces: None,
             payload: Boring,
             delegations,
+            quorum: None,
+            revocations: BTreeMap::new(),
+            not_after: BTreeMap::new(),
         },
         signatures,
     }
@@ -184,6 +188,7 @@ where
         .prop_map(
             |((root, revision, replaces), (signatures, delegations))| Identity {
                 content_id: Boring,
+                timestamp: None,
                 root,
                 revision,
                 doc: Doc {
@@ -191,6 +196,9 @@ where
                     replaces,
                     payload: Boring,
                     delegations,
+                    quorum: None,
+                    revocations: BTreeMap::new(),
+                    not_after: BTreeMap::new(),
                 },
                 signatures,
             },
@@ -324,6 +332,7 @@ where
             .unwrap();
         let inner = Identity {
             content_id: Boring,
+            timestamp: None,
             root: inner_root,
             revision: inner_revision,
             doc: Doc {
@@ -331,6 +340,9 @@ where
                 replaces: inner_replaces,
                 payload: Boring,
                 delegations,
+                quorum: None,
+                revocations: BTreeMap::new(),
+                not_after: BTreeMap::new(),
             },
             signatures,
         };
@@ -187,3 +187,125 @@ fn signed_no_signatures() {
         Err(error::Verify::NoSignatures)
     )
 }
+
+#[test]
+fn quorum_policy() {
+    use crate::{identities::quorum, keys::SecretKey};
+
+    let keys = vec![SecretKey::new(), SecretKey::new(), SecretKey::new()];
+    let identity = |signers: &[SecretKey], policy: Option<quorum::Policy>| {
+        boring(
+            keys.iter()
+                .map(|key| key.public())
+                .collect::<delegation::Direct>(),
+            signers
+                .iter()
+                .map(|key| (key.public(), key.sign(Boring.as_ref())))
+                .collect(),
+        )
+        .map(|doc| Doc {
+            quorum: policy,
+            ..doc
+        })
+    };
+
+    assert_matches!(
+        Verifying::from(identity(&keys[..1], None)).quorum(),
+        Err(error::Verify::Quorum)
+    );
+    assert!(
+        Verifying::from(identity(&keys[..1], Some(quorum::Policy::MinVotes(1))))
+            .quorum()
+            .is_ok()
+    );
+    assert_matches!(
+        Verifying::from(identity(&keys[..2], Some(quorum::Policy::MinVotes(3)))).quorum(),
+        Err(error::Verify::Quorum)
+    );
+}
+
+#[test]
+fn verified_revoked() {
+    use crate::keys::SecretKey;
+
+    let revoked = SecretKey::new();
+    let delegate = SecretKey::new();
+    let identity = |replaces: Option<Boring>, signers: &[&SecretKey], revoke: bool| {
+        boring(
+            Some(delegate.public())
+                .into_iter()
+                .collect::<delegation::Direct>(),
+            signers
+                .iter()
+                .map(|key| (key.public(), key.sign(Boring.as_ref())))
+                .collect(),
+        )
+        .map(|doc| Doc {
+            replaces,
+            revocations: if revoke {
+                Some((
+                    revoked.public(),
+                    Revocation {
+                        after: Boring,
+                        replaced_by: None,
+                        reason: None,
+                    },
+                ))
+                .into_iter()
+                .collect()
+            } else {
+                BTreeMap::new()
+            },
+            ..doc
+        })
+    };
+
+    let parent = Verifying::from(identity(None, &[&delegate, &revoked], true))
+        .verified(None)
+        .unwrap();
+
+    assert!(Verifying::from(identity(Some(Boring), &[&delegate], true))
+        .verified(Some(&parent))
+        .is_ok());
+    assert_matches!(
+        Verifying::from(identity(Some(Boring), &[&delegate, &revoked], true))
+            .verified(Some(&parent)),
+        Err(error::Verify::Revoked(key)) if key == revoked.public()
+    );
+    assert_matches!(
+        Verifying::from(identity(Some(Boring), &[&delegate], false)).verified(Some(&parent)),
+        Err(error::Verify::RevocationDropped(key)) if key == revoked.public()
+    );
+}
+
+#[test]
+fn quorum_expired() {
+    use crate::keys::SecretKey;
+
+    let keys = vec![SecretKey::new(), SecretKey::new()];
+    let identity = |timestamp: Option<i64>| {
+        let id = boring(
+            keys.iter()
+                .map(|key| key.public())
+                .collect::<delegation::Direct>(),
+            keys.iter()
+                .map(|key| (key.public(), key.sign(Boring.as_ref())))
+                .collect(),
+        )
+        .map(|doc| Doc {
+            not_after: Some((keys[0].public(), 1000)).into_iter().collect(),
+            ..doc
+        });
+        Identity { timestamp, ..id }
+    };
+
+    assert!(Verifying::from(identity(Some(1000))).quorum().is_ok());
+    assert_matches!(
+        Verifying::from(identity(Some(1001))).quorum(),
+        Err(error::Verify::Quorum)
+    );
+    assert_matches!(
+        Verifying::from(identity(None)).quorum(),
+        Err(error::Verify::Quorum)
+    );
+}
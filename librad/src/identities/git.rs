@@ -3,7 +3,11 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{convert::TryFrom, marker::PhantomData};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    marker::PhantomData,
+};
 
 use either::*;
 use futures::executor::block_on;
@@ -15,7 +19,9 @@ use crate::{
     identities::{
         delegation::{self, Delegations},
         generic::{self, Signed, Verified},
-        payload::{self, PersonPayload, ProjectPayload},
+        payload::{self, DelegatePayload, OrgPayload, PersonPayload, ProjectPayload},
+        quorum,
+        revocation::Revocation,
         sign::{Signature, Signatures},
         urn,
     },
@@ -25,6 +31,8 @@ use crate::{
 };
 
 pub mod error;
+pub mod fork;
+pub mod history;
 pub mod iter;
 
 pub use generic::Verifying;
@@ -37,6 +45,7 @@ pub(crate) mod tests;
 
 use iter::Iter;
 use load::ByOid;
+pub use load::FromBackend;
 
 pub type Urn = urn::Urn<Revision>;
 
@@ -51,15 +60,18 @@ pub type VerifiedIdentity<T> = generic::Verifying<Identity<T>, Verified>;
 
 pub type PersonDoc = Doc<PersonPayload, delegation::Direct>;
 pub type ProjectDoc = Doc<ProjectPayload, IndirectDelegation>;
+pub type OrgDoc = Doc<OrgPayload, IndirectDelegation>;
 
 pub type Person = Identity<PersonDoc>;
 pub type Project = Identity<ProjectDoc>;
+pub type Org = Identity<OrgDoc>;
 
 #[non_exhaustive]
 #[derive(Clone)]
 pub enum SomeIdentity {
     Person(Person),
     Project(Project),
+    Org(Org),
 }
 
 impl SomeIdentity {
@@ -76,21 +88,69 @@ impl SomeIdentity {
             _ => None,
         }
     }
+
+    pub fn org(self) -> Option<Org> {
+        match self {
+            Self::Org(org) => Some(org),
+            _ => None,
+        }
+    }
+
+    pub fn urn(&self) -> Urn {
+        match self {
+            Self::Person(person) => person.urn(),
+            Self::Project(project) => project.urn(),
+            Self::Org(org) => org.urn(),
+        }
+    }
 }
 
 pub type SignedPerson = SignedIdentity<PersonDoc>;
 pub type SignedProject = SignedIdentity<ProjectDoc>;
+pub type SignedOrg = SignedIdentity<OrgDoc>;
 
 pub type VerifiedPerson = VerifiedIdentity<PersonDoc>;
 pub type VerifiedProject = VerifiedIdentity<ProjectDoc>;
+pub type VerifiedOrg = VerifiedIdentity<OrgDoc>;
 
 pub type VerificationError = generic::error::Verify<Revision, ContentId>;
 
-pub type IndirectDelegation = delegation::Indirect<PersonPayload, Revision, ContentId>;
+pub type IndirectDelegation = delegation::Indirect<DelegatePayload, Revision, ContentId>;
+
+/// The target of an indirect delegation: either a [`Person`], or an [`Org`].
+///
+/// An [`Org`] is represented with its membership resolved to the keys of its
+/// delegates, as of the revision of the [`Org`]. All of them count as a
+/// single vote, just like the keys of a [`Person`].
+pub type Delegate =
+    delegation::indirect::IndirectlyDelegating<DelegatePayload, Revision, ContentId>;
+
+impl From<Person> for Delegate {
+    fn from(person: Person) -> Self {
+        person.map(|doc| doc.first(DelegatePayload::Person))
+    }
+}
+
+impl From<Org> for Delegate {
+    fn from(org: Org) -> Self {
+        org.map(|doc| {
+            doc.bimap(DelegatePayload::Org, |delegations| {
+                delegation_keys(&delegations).into_iter().copied().collect()
+            })
+        })
+    }
+}
+
+impl From<Person> for IndirectDelegation {
+    fn from(person: Person) -> Self {
+        Self::from(Delegate::from(person))
+    }
+}
 
 #[derive(Clone)]
 pub struct Identities<'a, T> {
     repo: &'a git2::Repository,
+    registry: &'a payload::ext::Registry,
     _marker: PhantomData<T>,
 }
 
@@ -98,16 +158,26 @@ impl<'a, T: 'a> From<&'a git2::Repository> for Identities<'a, T> {
     fn from(repo: &'a git2::Repository) -> Self {
         Self {
             repo,
+            registry: &payload::ext::DEFAULT_REGISTRY,
             _marker: PhantomData,
         }
     }
 }
 
 impl<'a, T: 'a> Identities<'a, T> {
+    /// Use `registry` to validate payload extensions when creating, updating,
+    /// or verifying identities.
+    ///
+    /// By default, the [`payload::ext::Registry::default`] is used.
+    pub fn with_registry(self, registry: &'a payload::ext::Registry) -> Self {
+        Self { registry, ..self }
+    }
+
     /// Convenience to specialise `T` to [`Person`].
     pub fn as_person(&self) -> Identities<'_, Person> {
         Identities {
             repo: self.repo,
+            registry: self.registry,
             _marker: PhantomData,
         }
     }
@@ -116,6 +186,7 @@ impl<'a, T: 'a> Identities<'a, T> {
     pub fn as_project(&self) -> Identities<'_, Project> {
         Identities {
             repo: self.repo,
+            registry: self.registry,
             _marker: PhantomData,
         }
     }
@@ -124,6 +195,7 @@ impl<'a, T: 'a> Identities<'a, T> {
     pub fn as_verified_person(&self) -> Identities<'_, VerifiedPerson> {
         Identities {
             repo: self.repo,
+            registry: self.registry,
             _marker: PhantomData,
         }
     }
@@ -132,6 +204,25 @@ impl<'a, T: 'a> Identities<'a, T> {
     pub fn as_verified_project(&self) -> Identities<'_, VerifiedProject> {
         Identities {
             repo: self.repo,
+            registry: self.registry,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Convenience to specialise `T` to [`Org`].
+    pub fn as_org(&self) -> Identities<'_, Org> {
+        Identities {
+            repo: self.repo,
+            registry: self.registry,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Convenience to specialise `T` to [`VerifiedOrg`].
+    pub fn as_verified_org(&self) -> Identities<'_, VerifiedOrg> {
+        Identities {
+            repo: self.repo,
+            registry: self.registry,
             _marker: PhantomData,
         }
     }
@@ -170,42 +261,46 @@ impl<'a, T: 'a> Identities<'a, T> {
         T::try_from(self.by_oid(oid))
     }
 
-    fn verify_generic<Doc>(
+    fn verify_generic<S, D>(
         &self,
         head: git2::Oid,
-    ) -> Result<VerifiedIdentity<Doc>, VerificationError>
+    ) -> Result<VerifiedIdentity<Doc<payload::Payload<S>, D>>, VerificationError>
     where
-        Doc: Delegations + generic::Replaces<Revision = Revision>,
-        <Doc as Delegations>::Error: std::error::Error + Send + Sync + 'static,
+        S: Clone + payload::Subject,
+        D: Clone,
+        Doc<payload::Payload<S>, D>: Delegations + generic::Replaces<Revision = Revision>,
+        <Doc<payload::Payload<S>, D> as Delegations>::Error:
+            std::error::Error + Send + Sync + 'static,
 
-        Identity<Doc>: TryFrom<ByOid<'a>, Error = error::Load>,
+        Identity<Doc<payload::Payload<S>, D>>: TryFrom<ByOid<'a>, Error = error::Load>,
     {
         self.fold_verify_generic(head).map(|folded| folded.head)
     }
 
-    fn fold_verify_generic<Doc>(
+    /// Fold the history with head commit `head`, validating the payload of
+    /// each revision against the [`payload::ext::Registry`].
+    fn fold_verify_generic<S, D>(
         &self,
         head: git2::Oid,
-    ) -> Result<generic::Folded<Doc, Revision, ContentId>, VerificationError>
+    ) -> Result<generic::Folded<Doc<payload::Payload<S>, D>, Revision, ContentId>, VerificationError>
     where
-        Doc: Delegations + generic::Replaces<Revision = Revision>,
-        <Doc as Delegations>::Error: std::error::Error + Send + Sync + 'static,
+        S: Clone + payload::Subject,
+        D: Clone,
+        Doc<payload::Payload<S>, D>: Delegations + generic::Replaces<Revision = Revision>,
+        <Doc<payload::Payload<S>, D> as Delegations>::Error:
+            std::error::Error + Send + Sync + 'static,
 
-        Identity<Doc>: TryFrom<ByOid<'a>, Error = error::Load>,
+        Identity<Doc<payload::Payload<S>, D>>: TryFrom<ByOid<'a>, Error = error::Load>,
     {
-        let mut progeny = Iter::<'_, Identity<Doc>>::new(self.repo, head)
+        let history = Iter::<'_, Identity<Doc<payload::Payload<S>, D>>>::new(self.repo, head)
             .map_err(generic::error::Verify::history)?;
 
         // TODO(kim): should we skip non-quorum commits at the beginning?
-        let root = progeny
-            .next()
-            .ok_or(generic::error::Verify::EmptyHistory)?
-            .map_err(generic::error::Verify::history)?
-            .signed()?
-            .quorum()?
-            .verified(None)?;
-
-        root.verify(progeny)
+        generic::Folded::fold(
+            history,
+            |doc: &Doc<payload::Payload<S>, D>| self.registry.validate(&doc.payload),
+            None::<fn(&Identity<Doc<payload::Payload<S>, D>>, &generic::Step<Revision, ContentId>)>,
+        )
     }
 
     //// Helpers ////
@@ -228,6 +323,46 @@ impl<'a, T: 'a> Identities<'a, T> {
         (self.repo, oid)
     }
 
+    fn updated_person<U>(
+        &self,
+        known: &Identity<U>,
+        latest_head: git2::Oid,
+    ) -> Result<VerifiedPerson, error::VerifyPerson> {
+        // Nb. technically we could coerce `known` into a `VerifiedPerson` if its
+        // `content_id` equals `latest_head`. Let's not introduce an unsafe
+        // coercion, but rely on caching to be implemented efficiently.
+        if self.is_in_ancestry_path(latest_head, known.revision.into())? {
+            self.as_person().verify(latest_head)
+        } else {
+            Err(error::VerifyPerson::NotInAncestryPath {
+                revision: known.revision,
+                root: known.root,
+                head: latest_head.into(),
+            })
+        }
+    }
+
+    fn updated_org<U, F, E>(
+        &self,
+        known: &Identity<U>,
+        latest_head: git2::Oid,
+        find_latest_head: &F,
+    ) -> Result<VerifiedOrg, error::VerifyOrg>
+    where
+        F: Fn(Urn) -> Result<git2::Oid, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        if self.is_in_ancestry_path(latest_head, known.revision.into())? {
+            self.as_org().verify(latest_head, find_latest_head)
+        } else {
+            Err(error::VerifyOrg::NotInAncestryPath {
+                revision: known.revision,
+                root: known.root,
+                head: latest_head.into(),
+            })
+        }
+    }
+
     fn is_in_ancestry_path(&self, commit: git2::Oid, tree: git2::Oid) -> Result<bool, git2::Error> {
         let mut revwalk = self.repo.revwalk()?;
         revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
@@ -265,7 +400,7 @@ where
                 sign(signer, theirs.revision).map_err(|e| error::Store::Signer(Box::new(e)))?;
             signatures.extend(Some(sig))
         }
-        let content_id = self.commit(
+        let (content_id, timestamp) = self.commit(
             &format!(
                 "Approved foreign identity {}, with content_id {} at revision {}",
                 theirs.root, theirs.content_id, theirs.revision
@@ -277,6 +412,7 @@ where
 
         Ok(Identity {
             content_id,
+            timestamp: Some(timestamp),
             signatures,
             ..theirs.into_inner()
         })
@@ -358,7 +494,7 @@ where
                 let mut signatures = ours.signatures.clone();
                 signatures.extend(theirs.signatures.clone());
 
-                let content_id = self.commit(
+                let (content_id, timestamp) = self.commit(
                     &format!("Updated signatures from {}", theirs.content_id),
                     &signatures,
                     ours.revision,
@@ -367,6 +503,7 @@ where
 
                 Ok(Identity {
                     content_id,
+                    timestamp: Some(timestamp),
                     signatures,
                     ..ours
                 })
@@ -379,7 +516,7 @@ where
                     signatures.extend(Some(sig))
                 }
 
-                let content_id = self.commit(
+                let (content_id, timestamp) = self.commit(
                     &format!(
                         "Approved new revision `{}` from {}",
                         theirs.revision, theirs.content_id
@@ -391,6 +528,7 @@ where
 
                 Ok(Identity {
                     content_id,
+                    timestamp: Some(timestamp),
                     signatures,
                     ..theirs
                 })
@@ -406,7 +544,7 @@ where
         signatures: &Signatures,
         revision: Revision,
         parents: &[&Identity<T>],
-    ) -> Result<ContentId, git2::Error> {
+    ) -> Result<(ContentId, i64), git2::Error> {
         let tree = self.repo.find_tree(*revision)?;
         let parents = parents
             .iter()
@@ -423,7 +561,7 @@ where
                 &tree,
                 parents.iter().collect::<Vec<_>>().as_slice(),
             )
-            .map(ContentId::from)
+            .map(|oid| (ContentId::from(oid), author.when().seconds()))
     }
 }
 
@@ -454,6 +592,9 @@ impl<'a> Identities<'a, Person> {
     ///
     /// The returned [`VerifiedPerson`] is the **most recent** identity for
     /// which the verification succeeded -- which may or may not be `head`.
+    /// Revisions whose payload extensions are invalid according to the
+    /// [`payload::ext::Registry`] end the history: the last valid revision is
+    /// returned.
     pub fn verify(&self, head: git2::Oid) -> Result<VerifiedPerson, error::VerifyPerson> {
         Ok(self.verify_generic(head)?)
     }
@@ -475,11 +616,15 @@ impl<'a> Identities<'a, Person> {
     where
         S: Signer,
     {
+        self.registry.validate(&payload)?;
         let doc = Doc {
             version: 0,
             replaces: None,
             payload,
             delegations: payload::PersonDelegations::from(delegations),
+            quorum: None,
+            revocations: Default::default(),
+            not_after: Default::default(),
         };
 
         let root: Revision = self.repo.blob(&Cjson(&doc).canonical_form()?)?.into();
@@ -491,7 +636,7 @@ impl<'a> Identities<'a, Person> {
         let signatures = sign(signer, revision)
             .map_err(|e| error::Store::Signer(Box::new(e)))?
             .into();
-        let content_id = self.commit(
+        let (content_id, timestamp) = self.commit(
             &format!("Initialised personal identity {}", root),
             &signatures,
             revision,
@@ -500,6 +645,7 @@ impl<'a> Identities<'a, Person> {
 
         Ok(Identity {
             content_id,
+            timestamp: Some(timestamp),
             root,
             revision,
             doc: doc.second(delegation::Direct::from),
@@ -524,25 +670,232 @@ impl<'a> Identities<'a, Person> {
     where
         S: Signer,
     {
-        let payload = payload.into();
-        let delegations = delegations.into();
+        self.update_doc(base, payload.into(), delegations.into(), None, None, signer)
+    }
+
+    /// Update an existing [`SignedPerson`] with a new [`quorum::Policy`], and
+    /// optionally new delegations.
+    ///
+    /// A `quorum` of `None` restores the default majority rule. The policy
+    /// must be valid for the resulting delegations, see
+    /// [`quorum::Policy::validate`].
+    ///
+    /// Otherwise, this behaves like [`Self::update`].
+    pub fn update_quorum<S>(
+        &self,
+        base: SignedPerson,
+        delegations: impl Into<Option<delegation::Direct>>,
+        quorum: Option<quorum::Policy>,
+        signer: &S,
+    ) -> Result<Person, error::Store>
+    where
+        S: Signer,
+    {
+        self.update_doc(base, None, delegations.into(), Some(quorum), None, signer)
+    }
+
+    /// Update an existing [`SignedPerson`] with new expiry times of its
+    /// delegations, see [`generic::Doc::not_after`].
+    ///
+    /// All keys in `not_after` must be delegates. Keys not in `not_after` don't
+    /// expire.
+    pub fn update_expiry<S>(
+        &self,
+        base: SignedPerson,
+        not_after: BTreeMap<PublicKey, i64>,
+        signer: &S,
+    ) -> Result<Person, error::Store>
+    where
+        S: Signer,
+    {
+        self.update_doc(base, None, None, None, Some(not_after), signer)
+    }
+
+    /// Revoke `key`, optionally replacing it by `replacement`.
+    ///
+    /// `key` is removed from the delegations, and a [`Revocation`] is recorded
+    /// in the document. Revisions following the resulting one may no longer be
+    /// signed by `key`, see [`generic::Verifying::verified`]. If the document
+    /// has a [`quorum::Policy`], `key` is substituted by `replacement` in it.
+    ///
+    /// The result is signed by `signer`. Note that it will, in general, need to
+    /// be confirmed by other delegates in order to reach a quorum.
+    pub fn revoke<S>(
+        &self,
+        base: SignedPerson,
+        key: PublicKey,
+        replacement: Option<PublicKey>,
+        reason: Option<String>,
+        signer: &S,
+    ) -> Result<Person, error::Store>
+    where
+        S: Signer,
+    {
+        self.revise(base, key, replacement, reason, |revision| {
+            sign(signer, revision)
+                .map(Signatures::from)
+                .map_err(|e| error::Store::Signer(Box::new(e)))
+        })
+    }
+
+    /// Replace the key of `old` by the key of `new`, revoking the former.
+    ///
+    /// This is like [`Self::revoke`], but the result is signed by both `old`
+    /// and `new`. For a person with a single delegation, this is sufficient to
+    /// form a quorum.
+    pub fn rotate<S, T>(
+        &self,
+        base: SignedPerson,
+        old: &S,
+        new: &T,
+        reason: Option<String>,
+    ) -> Result<Person, error::Store>
+    where
+        S: Signer,
+        T: Signer,
+    {
+        self.revise(
+            base,
+            old.public_key().into(),
+            Some(new.public_key().into()),
+            reason,
+            |revision| {
+                let mut signatures = Signatures::from(
+                    sign(old, revision).map_err(|e| error::Store::Signer(Box::new(e)))?,
+                );
+                signatures.extend(Some(
+                    sign(new, revision).map_err(|e| error::Store::Signer(Box::new(e)))?,
+                ));
+                Ok(signatures)
+            },
+        )
+    }
 
+    fn update_doc<S>(
+        &self,
+        base: SignedPerson,
+        payload: Option<PersonPayload>,
+        delegations: Option<delegation::Direct>,
+        quorum: Option<Option<quorum::Policy>>,
+        not_after: Option<BTreeMap<PublicKey, i64>>,
+        signer: &S,
+    ) -> Result<Person, error::Store>
+    where
+        S: Signer,
+    {
         // Fast path
-        if payload.is_none() && delegations.is_none() {
+        if payload.is_none() && delegations.is_none() && quorum.is_none() && not_after.is_none() {
             return Ok(base.into_inner());
         }
 
+        let delegations = delegations.unwrap_or_else(|| base.delegations().clone());
+        let not_after = not_after.unwrap_or_else(|| {
+            retain_delegates(base.doc.not_after.clone(), |key| delegations.contains(key))
+        });
         let doc = Doc {
             version: 0,
             replaces: Some(base.revision),
             payload: payload.unwrap_or_else(|| base.payload().clone()),
-            delegations: payload::PersonDelegations::from(
-                delegations.unwrap_or_else(|| base.delegations().clone()),
-            ),
+            delegations,
+            quorum: quorum.unwrap_or_else(|| base.doc.quorum.clone()),
+            revocations: base.doc.revocations.clone(),
+            not_after,
+        };
+
+        self.commit_update(base, doc, |revision| {
+            sign(signer, revision)
+                .map(Signatures::from)
+                .map_err(|e| error::Store::Signer(Box::new(e)))
+        })
+    }
+
+    fn revise<F>(
+        &self,
+        base: SignedPerson,
+        key: PublicKey,
+        replacement: Option<PublicKey>,
+        reason: Option<String>,
+        sign: F,
+    ) -> Result<Person, error::Store>
+    where
+        F: FnOnce(Revision) -> Result<Signatures, error::Store>,
+    {
+        if base.doc.revocations.contains_key(&key) {
+            return Err(error::Store::AlreadyRevoked(key));
+        }
+
+        let delegations = base
+            .delegations()
+            .iter()
+            .filter(|delegate| **delegate != key)
+            .copied()
+            .chain(replacement)
+            .collect::<delegation::Direct>();
+        let not_after =
+            retain_delegates(base.doc.not_after.clone(), |key| delegations.contains(key));
+        let quorum = base.doc.quorum.clone().map(|mut policy| {
+            if let Some(replacement) = replacement {
+                policy.replace_key(&key, replacement);
+            }
+            policy
+        });
+        let mut revocations = base.doc.revocations.clone();
+        revocations.insert(
+            key,
+            Revocation {
+                after: base.revision,
+                replaced_by: replacement,
+                reason,
+            },
+        );
+
+        let doc = Doc {
+            version: 0,
+            replaces: Some(base.revision),
+            payload: base.payload().clone(),
+            delegations,
+            quorum,
+            revocations,
+            not_after,
         };
 
+        self.commit_update(base, doc, sign)
+    }
+
+    fn commit_update<F>(
+        &self,
+        base: SignedPerson,
+        doc: PersonDoc,
+        sign: F,
+    ) -> Result<Person, error::Store>
+    where
+        F: FnOnce(Revision) -> Result<Signatures, error::Store>,
+    {
+        if doc.delegations.iter().next().is_none() {
+            return Err(error::Store::EmptyDelegations);
+        }
+        self.registry.validate(&doc.payload)?;
+        if let Some(key) = doc
+            .delegations
+            .iter()
+            .find(|key| doc.revocations.contains_key(key))
+        {
+            return Err(error::Store::RevokedDelegation(*key));
+        }
+        if let Some(policy) = &doc.quorum {
+            policy.validate(doc.delegations.iter())?;
+        }
+        if let Some(key) = doc
+            .not_after
+            .keys()
+            .find(|key| !doc.delegations.contains(key))
+        {
+            return Err(error::Store::NotADelegate(*key));
+        }
+
         let revision = {
-            let doc_blob = self.repo.blob(&Cjson(&doc).canonical_form()?)?;
+            let stored = doc.clone().second(payload::PersonDelegations::from);
+            let doc_blob = self.repo.blob(&Cjson(&stored).canonical_form()?)?;
             let base_tree = self.repo.find_tree(*base.revision)?;
             let mut builder = self.repo.treebuilder(Some(&base_tree))?;
             builder.insert(base.root.to_string(), doc_blob, 0o100_644)?;
@@ -553,10 +906,8 @@ impl<'a> Identities<'a, Person> {
             return Ok(base.into_inner());
         }
 
-        let signatures = sign(signer, revision)
-            .map_err(|e| error::Store::Signer(Box::new(e)))?
-            .into();
-        let content_id = self.commit(
+        let signatures = sign(revision)?;
+        let (content_id, timestamp) = self.commit(
             &format!("Updated to revision {}", revision),
             &signatures,
             revision,
@@ -565,9 +916,10 @@ impl<'a> Identities<'a, Person> {
 
         Ok(Identity {
             content_id,
+            timestamp: Some(timestamp),
             root: base.root,
             revision,
-            doc: doc.second(delegation::Direct::from),
+            doc,
             signatures,
         })
     }
@@ -588,6 +940,16 @@ impl<'a> Identities<'a, Project> {
     /// revocations or other circumstances which prevent [`Self::verify`] on the
     /// indirect delegation from succeeding.
     ///
+    /// Only `head` is verified against the latest heads of the indirect
+    /// delegations, the revisions leading up to it are verified against the
+    /// delegations inlined at the time they were made. For org delegations,
+    /// this means that the membership is taken from the latest head of the
+    /// org, not from the revision of the org the project refers to: members
+    /// added to the org later are delegates of the project and count towards
+    /// the quorum of `head`, while signatures of members removed later no
+    /// longer count. The latter may cause verification to fail until `head`
+    /// is signed by a remaining member.
+    ///
     /// The returned [`VerifiedProject`] is the **most recent** identity for
     /// which the verification succeeded -- which may or may not be `head`.
     pub fn verify<F, E>(
@@ -599,12 +961,126 @@ impl<'a> Identities<'a, Project> {
         F: Fn(Urn) -> Result<git2::Oid, E>,
         E: std::error::Error + Send + Sync + 'static,
     {
-        let generic::Folded { head, parent } = self.fold_verify_generic::<ProjectDoc>(head)?;
+        let folded = self.fold_verify_generic::<payload::Project, _>(head)?;
+        self.resolve_folded(folded, &find_latest_head)
+    }
+
+    pub fn latest_tip(&self, projects: NonEmpty<Project>) -> Result<git2::Oid, error::Store> {
+        Ok(self.latest_generic(projects.map(|proj| proj.content_id.into()))?)
+    }
+
+    /// Create a new [`Project`] from a payload and delegations.
+    ///
+    /// The returned [`Project`] (and the underlying commit) will not have any
+    /// parents, and will be signed by `signer`.
+    pub fn create<S>(
+        &self,
+        payload: ProjectPayload,
+        delegations: IndirectDelegation,
+        signer: &S,
+    ) -> Result<Project, error::Store>
+    where
+        S: Signer,
+    {
+        self.registry.validate(&payload)?;
+        self.create_indirect("project", payload, delegations, signer)
+    }
+
+    /// Update an existing [`SignedProject`] with a new payload and delegations.
+    ///
+    /// If both `payload` and `delegations` evaluate to `None`, or their values
+    /// result in the same revision as `base`, no new commit is made, and
+    /// the result is the unwrapped [`Project`] of the `base` argument.
+    ///
+    /// Otherwise, the result is a new [`Project`] whose parent is `base`.
+    pub fn update<S>(
+        &self,
+        base: SignedProject,
+        payload: impl Into<Option<ProjectPayload>>,
+        delegations: impl Into<Option<IndirectDelegation>>,
+        signer: &S,
+    ) -> Result<Project, error::Store>
+    where
+        S: Signer,
+    {
+        self.update_doc(base, payload.into(), delegations.into(), None, None, signer)
+    }
+
+    /// Update an existing [`SignedProject`] with a new [`quorum::Policy`], and
+    /// optionally new delegations.
+    ///
+    /// A `quorum` of `None` restores the default majority rule. The policy
+    /// must be valid for the keys of the resulting delegations (including the
+    /// keys of indirect delegations), see [`quorum::Policy::validate`].
+    ///
+    /// Otherwise, this behaves like [`Self::update`].
+    pub fn update_quorum<S>(
+        &self,
+        base: SignedProject,
+        delegations: impl Into<Option<IndirectDelegation>>,
+        quorum: Option<quorum::Policy>,
+        signer: &S,
+    ) -> Result<Project, error::Store>
+    where
+        S: Signer,
+    {
+        self.update_doc(base, None, delegations.into(), Some(quorum), None, signer)
+    }
+
+    /// Update an existing [`SignedProject`] with new expiry times of its
+    /// delegations, see [`generic::Doc::not_after`].
+    ///
+    /// All keys in `not_after` must be delegates, either directly or via an
+    /// indirect delegation. Keys not in `not_after` don't expire.
+    pub fn update_expiry<S>(
+        &self,
+        base: SignedProject,
+        not_after: BTreeMap<PublicKey, i64>,
+        signer: &S,
+    ) -> Result<Project, error::Store>
+    where
+        S: Signer,
+    {
+        self.update_doc(base, None, None, None, Some(not_after), signer)
+    }
+
+    fn update_doc<S>(
+        &self,
+        base: SignedProject,
+        payload: Option<ProjectPayload>,
+        delegations: Option<IndirectDelegation>,
+        quorum: Option<Option<quorum::Policy>>,
+        not_after: Option<BTreeMap<PublicKey, i64>>,
+        signer: &S,
+    ) -> Result<Project, error::Store>
+    where
+        S: Signer,
+    {
+        if let Some(payload) = &payload {
+            self.registry.validate(payload)?;
+        }
+        self.update_indirect(base, payload, delegations, quorum, not_after, signer)
+    }
+
+    //// Helpers ////
+
+    /// Re-verify the head of a folded project history against the latest
+    /// heads of its indirect delegations.
+    fn resolve_folded<F, E>(
+        &self,
+        folded: generic::Folded<ProjectDoc, Revision, ContentId>,
+        find_latest_head: &F,
+    ) -> Result<VerifiedProject, error::VerifyProject>
+    where
+        F: Fn(Urn) -> Result<git2::Oid, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let generic::Folded { head, parent } = folded;
         let head = head
             .into_inner()
             .map(|doc| {
                 doc.try_second(|delegations| {
-                    self.resolve_delegation_updates(delegations, &find_latest_head)
+                    self.resolve_delegation_updates(delegations, find_latest_head)
                 })
             })
             .transpose()?;
@@ -615,20 +1091,177 @@ impl<'a> Identities<'a, Project> {
             .verified(parent.as_ref())?)
     }
 
-    pub fn latest_tip(&self, projects: NonEmpty<Project>) -> Result<git2::Oid, error::Store> {
-        Ok(self.latest_generic(projects.map(|proj| proj.content_id.into()))?)
+    fn resolve_delegation_updates<I, F, E>(
+        &self,
+        current: I,
+        find_latest_head: &F,
+    ) -> Result<IndirectDelegation, error::VerifyProject>
+    where
+        I: IntoIterator<Item = Either<PublicKey, Delegate>>,
+        F: Fn(Urn) -> Result<git2::Oid, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let mut updated = Vec::new();
+        for delegation in current {
+            match delegation {
+                Right(id) => {
+                    let head = find_latest_head(id.urn())
+                        .map_err(|e| error::VerifyProject::Lookup(Box::new(e)))?;
+                    let delegate = match id.doc.payload {
+                        DelegatePayload::Person(_) => {
+                            Delegate::from(self.updated_person(&id, head)?.into_inner())
+                        },
+                        DelegatePayload::Org(_) => Delegate::from(
+                            self.updated_org(&id, head, find_latest_head)?.into_inner(),
+                        ),
+                    };
+                    updated.push(Right(delegate))
+                },
+
+                left => updated.push(left),
+            }
+        }
+
+        Ok(delegation::Indirect::try_from_iter(updated)?)
     }
+}
 
-    /// Create a new [`Project`] from a payload and delegations.
+impl<'a> Identities<'a, Org> {
+    /// Attempt to read an [`Org`] from commit `oid`, without verification.
+    pub fn get(&self, oid: git2::Oid) -> Result<Org, error::Load> {
+        self.get_generic(oid)
+    }
+
+    /// Verify the org history with head commit `head`.
     ///
-    /// The returned [`Project`] (and the underlying commit) will not have any
+    /// As for projects, the supplied [`Fn`] shall return the latest head commit
+    /// of the (personal) delegations of the org. The membership of the
+    /// returned [`VerifiedOrg`] reflects those heads.
+    pub fn verify<F, E>(
+        &self,
+        head: git2::Oid,
+        find_latest_head: F,
+    ) -> Result<VerifiedOrg, error::VerifyOrg>
+    where
+        F: Fn(Urn) -> Result<git2::Oid, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let generic::Folded { head, parent } = self.fold_verify_generic::<payload::Org, _>(head)?;
+        let head = head
+            .into_inner()
+            .map(|doc| {
+                doc.try_second(|delegations| {
+                    self.resolve_member_updates(delegations, &find_latest_head)
+                })
+            })
+            .transpose()?;
+
+        Ok(generic::Verifying::from(head)
+            .signed()?
+            .quorum()?
+            .verified(parent.as_ref())?)
+    }
+
+    pub fn latest_tip(&self, orgs: NonEmpty<Org>) -> Result<git2::Oid, error::Store> {
+        Ok(self.latest_generic(orgs.map(|org| org.content_id.into()))?)
+    }
+
+    /// Create a new [`Org`] from a payload and delegations.
+    ///
+    /// The delegations may be keys or [`Person`]s, but not other [`Org`]s.
+    ///
+    /// The returned [`Org`] (and the underlying commit) will not have any
     /// parents, and will be signed by `signer`.
     pub fn create<S>(
         &self,
-        payload: ProjectPayload,
+        payload: OrgPayload,
         delegations: IndirectDelegation,
         signer: &S,
-    ) -> Result<Project, error::Store>
+    ) -> Result<Org, error::Store>
+    where
+        S: Signer,
+    {
+        ensure_no_orgs(&delegations)?;
+        self.registry.validate(&payload)?;
+        self.create_indirect("org", payload, delegations, signer)
+    }
+
+    /// Update an existing [`SignedOrg`] with a new payload and members.
+    ///
+    /// This behaves like updating a [`Project`]. Note that projects which
+    /// delegate to the org pick up changes in its membership without having
+    /// to be updated themselves.
+    pub fn update<S>(
+        &self,
+        base: SignedOrg,
+        payload: impl Into<Option<OrgPayload>>,
+        delegations: impl Into<Option<IndirectDelegation>>,
+        signer: &S,
+    ) -> Result<Org, error::Store>
+    where
+        S: Signer,
+    {
+        let payload = payload.into();
+        if let Some(payload) = &payload {
+            self.registry.validate(payload)?;
+        }
+        let delegations = delegations.into();
+        if let Some(delegations) = &delegations {
+            ensure_no_orgs(delegations)?;
+        }
+        self.update_indirect(base, payload, delegations, None, None, signer)
+    }
+
+    //// Helpers ////
+
+    /// Replace the members of an org with their latest heads.
+    ///
+    /// Nb. when verifying a project which delegates to the org, this is
+    /// called on the org's latest head, so the membership is the current one
+    /// rather than the one at the revision the project refers to (see
+    /// `Identities<Project>::verify`).
+    fn resolve_member_updates<F, E>(
+        &self,
+        current: IndirectDelegation,
+        find_latest_head: &F,
+    ) -> Result<IndirectDelegation, error::VerifyOrg>
+    where
+        F: Fn(Urn) -> Result<git2::Oid, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let mut updated = Vec::new();
+        for delegation in current {
+            match delegation {
+                Right(id) => {
+                    if id.doc.payload.person().is_none() {
+                        return Err(error::VerifyOrg::NestedOrg(id.urn()));
+                    }
+                    let head = find_latest_head(id.urn())
+                        .map_err(|e| error::VerifyOrg::Lookup(Box::new(e)))?;
+                    let verified = self.updated_person(&id, head)?;
+                    updated.push(Right(Delegate::from(verified.into_inner())))
+                },
+
+                left => updated.push(left),
+            }
+        }
+
+        Ok(delegation::Indirect::try_from_iter(updated)?)
+    }
+}
+
+impl<'a, P: 'a> Identities<'a, Identity<Doc<P, IndirectDelegation>>>
+where
+    P: Clone + serde::Serialize,
+    Identity<Doc<P, IndirectDelegation>>: TryFrom<ByOid<'a>, Error = error::Load>,
+{
+    fn create_indirect<S>(
+        &self,
+        kind: &str,
+        payload: P,
+        delegations: IndirectDelegation,
+        signer: &S,
+    ) -> Result<Identity<Doc<P, IndirectDelegation>>, error::Store>
     where
         S: Signer,
     {
@@ -637,6 +1270,9 @@ impl<'a> Identities<'a, Project> {
             replaces: None,
             payload,
             delegations: payload::ProjectDelegations::from(delegations.clone()),
+            quorum: None,
+            revocations: Default::default(),
+            not_after: Default::default(),
         };
 
         let root: Revision = self.repo.blob(&Cjson(&doc).canonical_form()?)?.into();
@@ -649,8 +1285,8 @@ impl<'a> Identities<'a, Project> {
         let signatures = sign(signer, revision)
             .map_err(|e| error::Store::Signer(Box::new(e)))?
             .into();
-        let content_id = self.commit(
-            &format!("Initialised project identity {}", root),
+        let (content_id, timestamp) = self.commit(
+            &format!("Initialised {} identity {}", kind, root),
             &signatures,
             revision,
             &[],
@@ -658,6 +1294,7 @@ impl<'a> Identities<'a, Project> {
 
         Ok(Identity {
             content_id,
+            timestamp: Some(timestamp),
             root,
             revision,
             doc: doc.second(|_| delegations),
@@ -665,41 +1302,51 @@ impl<'a> Identities<'a, Project> {
         })
     }
 
-    /// Update an existing [`SignedProject`] with a new payload and delegations.
-    ///
-    /// If both `payload` and `delegations` evaluate to `None`, or their values
-    /// result in the same revision as `base`, no new commit is made, and
-    /// the result is the unwrapped [`Project`] of the `base` argument.
-    ///
-    /// Otherwise, the result is a new [`Project`] whose parent is `base`.
-    pub fn update<S>(
+    fn update_indirect<S>(
         &self,
-        base: SignedProject,
-        payload: impl Into<Option<ProjectPayload>>,
-        delegations: impl Into<Option<IndirectDelegation>>,
+        base: SignedIdentity<Doc<P, IndirectDelegation>>,
+        payload: Option<P>,
+        delegations: Option<IndirectDelegation>,
+        quorum: Option<Option<quorum::Policy>>,
+        not_after: Option<BTreeMap<PublicKey, i64>>,
         signer: &S,
-    ) -> Result<Project, error::Store>
+    ) -> Result<Identity<Doc<P, IndirectDelegation>>, error::Store>
     where
         S: Signer,
     {
-        let payload = payload.into();
-        let delegations = delegations.into();
-
         // Fast path
-        if payload.is_none() && delegations.is_none() {
+        if payload.is_none() && delegations.is_none() && quorum.is_none() && not_after.is_none() {
             return Ok(base.into_inner());
         }
 
+        let keys = delegation_keys(delegations.as_ref().unwrap_or_else(|| base.delegations()));
+        let quorum = quorum.unwrap_or_else(|| base.doc.quorum.clone());
+        if let Some(policy) = &quorum {
+            policy.validate(keys.iter().copied())?;
+        }
+        let not_after = match not_after {
+            None => retain_delegates(base.doc.not_after.clone(), |key| keys.contains(key)),
+            Some(not_after) => {
+                if let Some(key) = not_after.keys().find(|key| !keys.contains(key)) {
+                    return Err(error::Store::NotADelegate(*key));
+                }
+                not_after
+            },
+        };
+
         // FIXME: reorder stuff to avoid cloning
 
         let doc = Doc {
             version: 0,
             replaces: Some(base.revision),
-            payload: payload.unwrap_or_else(|| base.payload().clone()),
+            payload: payload.unwrap_or_else(|| base.doc.payload.clone()),
             delegations: delegations
                 .clone()
                 .map(payload::ProjectDelegations::from)
                 .unwrap_or_else(|| base.delegations().clone().into()),
+            quorum,
+            revocations: base.doc.revocations.clone(),
+            not_after,
         };
 
         let root = base.root;
@@ -707,9 +1354,10 @@ impl<'a> Identities<'a, Project> {
             // Create a fresh tree so we don't have to bother about stale
             // indirect delegations
             let mut builder = self.repo.treebuilder(None)?;
-            if let Some(ref indirect) = delegations {
-                self.inline_indirect(&mut builder, indirect)?;
-            }
+            self.inline_indirect(
+                &mut builder,
+                delegations.as_ref().unwrap_or_else(|| base.delegations()),
+            )?;
             let doc_blob = self.repo.blob(&Cjson(&doc).canonical_form()?)?;
             builder.insert(base.root.to_string(), doc_blob, 0o100_644)?;
             builder.write().map(Revision::from)
@@ -722,7 +1370,7 @@ impl<'a> Identities<'a, Project> {
         let signatures = sign(signer, revision)
             .map_err(|e| error::Store::Signer(Box::new(e)))?
             .into();
-        let content_id = self.commit(
+        let (content_id, timestamp) = self.commit(
             &format!("Updated to revision {}", revision),
             &signatures,
             revision,
@@ -731,6 +1379,7 @@ impl<'a> Identities<'a, Project> {
 
         Ok(Identity {
             content_id,
+            timestamp: Some(timestamp),
             root,
             revision,
             doc: doc.second(|_| delegations.unwrap_or_else(|| base.into_inner().doc.delegations)),
@@ -738,64 +1387,16 @@ impl<'a> Identities<'a, Project> {
         })
     }
 
-    //// Helpers ////
-
-    fn resolve_delegation_updates<I, F, E>(
-        &self,
-        current: I,
-        find_latest_head: &F,
-    ) -> Result<IndirectDelegation, error::VerifyProject>
-    where
-        I: IntoIterator<Item = Either<PublicKey, Person>>,
-        F: Fn(Urn) -> Result<git2::Oid, E>,
-        E: std::error::Error + Send + Sync + 'static,
-    {
-        let mut updated = Vec::new();
-        for delegation in current {
-            match delegation {
-                Right(id) => {
-                    let head = find_latest_head(id.urn())
-                        .map_err(|e| error::VerifyProject::Lookup(Box::new(e)))?;
-                    let verified = self.updated_person(id, head)?;
-                    updated.push(Right(verified.into_inner()))
-                },
-
-                left => updated.push(left),
-            }
-        }
-
-        Ok(delegation::Indirect::try_from_iter(updated)?)
-    }
-
-    fn updated_person(
-        &self,
-        known: Person,
-        latest_head: git2::Oid,
-    ) -> Result<VerifiedPerson, error::VerifyPerson> {
-        // Nb. technically we could coerce `known` into a `VerifiedPerson` if its
-        // `content_id` equals `latest_head`. Let's not introduce an unsafe
-        // coercion, but rely on caching to be implemented efficiently.
-        if self.is_in_ancestry_path(latest_head, known.revision.into())? {
-            self.as_person().verify(latest_head)
-        } else {
-            Err(error::VerifyPerson::NotInAncestryPath {
-                revision: known.revision,
-                root: known.root,
-                head: latest_head.into(),
-            })
-        }
-    }
-
     fn inline_indirect(
         &self,
         tree: &mut git2::TreeBuilder,
         delegations: &IndirectDelegation,
     ) -> Result<(), error::Store> {
         let mut builder = self.repo.treebuilder(None)?;
-        for person_delegation in delegations.iter().filter_map(|x| x.right()) {
+        for delegate in delegations.iter().filter_map(|x| x.right()) {
             let inlined = self.repo.blob(
                 &Cjson(
-                    &person_delegation
+                    &delegate
                         .clone()
                         .map(|doc| doc.second(payload::PersonDelegations::from)),
                 )
@@ -803,10 +1404,7 @@ impl<'a> Identities<'a, Project> {
             )?;
             builder.insert(
                 // TODO: factor out
-                multibase::encode(
-                    multibase::Base::Base32Z,
-                    Multihash::from(person_delegation.root),
-                ),
+                multibase::encode(multibase::Base::Base32Z, Multihash::from(delegate.root)),
                 inlined,
                 0o100_644,
             )?;
@@ -818,6 +1416,38 @@ impl<'a> Identities<'a, Project> {
     }
 }
 
+/// The keys of `delegations`, including those of indirect delegations.
+pub(crate) fn delegation_keys(delegations: &IndirectDelegation) -> BTreeSet<&PublicKey> {
+    delegations
+        .iter()
+        .flat_map(|d| d.either(|key| vec![key], |id| id.delegations().iter().collect()))
+        .collect()
+}
+
+/// Orgs can only delegate to keys and [`Person`]s.
+fn ensure_no_orgs(delegations: &IndirectDelegation) -> Result<(), error::Store> {
+    match delegations
+        .iter()
+        .indirect()
+        .find(|id| id.doc.payload.person().is_none())
+    {
+        Some(org) => Err(error::Store::NestedOrg(org.urn())),
+        None => Ok(()),
+    }
+}
+
+/// Drop the entries of `not_after` whose key is not a delegate anymore.
+fn retain_delegates<F>(
+    mut not_after: BTreeMap<PublicKey, i64>,
+    is_delegate: F,
+) -> BTreeMap<PublicKey, i64>
+where
+    F: Fn(&PublicKey) -> bool,
+{
+    not_after.retain(|key, _| is_delegate(key));
+    not_after
+}
+
 fn sign<S>(signer: &S, rev: Revision) -> Result<Signature, S::Error>
 where
     S: Signer,
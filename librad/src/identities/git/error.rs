@@ -11,16 +11,23 @@ use crate::{
     identities::{
         delegation::indirect::error::FromIter as DelegationsFromIterError,
         generic,
+        payload::ext,
+        quorum,
         sign,
+        urn::Urn,
         ContentId,
         Revision,
     },
     internal::canonical::CjsonError,
+    keys::PublicKey,
 };
 
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Load {
+    #[error("commit {0} not found")]
+    MissingCommit(git2::Oid),
+
     #[error("the identity document could not be resolved")]
     MissingDoc,
 
@@ -36,6 +43,9 @@ pub enum Load {
     #[error("expected blob at path `{0:?}`, got {1:?}")]
     NotABlob(PathBuf, Option<git2::ObjectType>),
 
+    #[error("inlined delegate at path `{0:?}` not found")]
+    MissingDelegate(PathBuf),
+
     #[error(transparent)]
     Delegation(#[from] DelegationsFromIterError<Revision>),
 
@@ -51,10 +61,22 @@ pub enum Load {
     #[error(transparent)]
     Json(#[from] serde_json::Error),
 
+    #[error("storage backend error")]
+    Backend(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
     #[error(transparent)]
     Git(#[from] git2::Error),
 }
 
+impl Load {
+    pub(crate) fn backend<E>(e: E) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Self::Backend(Box::new(e))
+    }
+}
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Store {
@@ -64,11 +86,32 @@ pub enum Store {
     #[error("failed to produce a signature")]
     Signer(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
 
+    #[error("invalid quorum policy")]
+    Quorum(#[from] quorum::error::Invalid),
+
+    #[error("delegations must not be empty")]
+    EmptyDelegations,
+
+    #[error("key {0} is revoked, and can not be delegated to")]
+    RevokedDelegation(PublicKey),
+
+    #[error("key {0} is already revoked")]
+    AlreadyRevoked(PublicKey),
+
+    #[error("key {0} is not a delegate")]
+    NotADelegate(PublicKey),
+
+    #[error("org {0} can not be a member of another org")]
+    NestedOrg(Urn<Revision>),
+
     #[error(transparent)]
     Cjson(#[from] CjsonError),
 
     #[error(transparent)]
     Git(#[from] git2::Error),
+
+    #[error("invalid payload extension")]
+    Extension(#[from] ext::ValidationError),
 }
 
 #[derive(Debug, Error)]
@@ -118,6 +161,41 @@ pub enum VerifyProject {
     #[error(transparent)]
     VerifyPerson(#[from] self::VerifyPerson),
 
+    #[error(transparent)]
+    VerifyOrg(#[from] self::VerifyOrg),
+
+    #[error(transparent)]
+    Delegation(#[from] DelegationsFromIterError<Revision>),
+
+    #[error(transparent)]
+    Load(#[from] self::Load),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum VerifyOrg {
+    #[error("Revision {revision} of {root} not in ancestry path of {head}")]
+    NotInAncestryPath {
+        revision: Revision,
+        root: Revision,
+        head: ContentId,
+    },
+
+    #[error("org {0} can not be a member of another org")]
+    NestedOrg(Urn<Revision>),
+
+    #[error("error resolving latest head")]
+    Lookup(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error(transparent)]
+    Verification(#[from] generic::error::Verify<Revision, ContentId>),
+
+    #[error(transparent)]
+    VerifyPerson(#[from] self::VerifyPerson),
+
     #[error(transparent)]
     Delegation(#[from] DelegationsFromIterError<Revision>),
 
@@ -153,4 +231,7 @@ pub enum Verify {
 
     #[error(transparent)]
     Person(#[from] VerifyPerson),
+
+    #[error(transparent)]
+    Org(#[from] VerifyOrg),
 }
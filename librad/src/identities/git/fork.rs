@@ -0,0 +1,160 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Inspecting and merging diverged identity histories.
+//!
+//! Two histories of the same identity are forked if neither contains the
+//! latest verified revision of the other, see [`Identities::is_fork`].
+
+use std::{collections::BTreeSet, convert::TryFrom};
+
+use git_ext::is_not_found_err;
+
+use super::{
+    error,
+    generic,
+    sign,
+    ContentId,
+    Delegations,
+    Identities,
+    Identity,
+    Revision,
+    Signatures,
+    SignedIdentity,
+    Signer,
+};
+use crate::keys::PublicKey;
+
+/// Two diverged histories of the same identity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fork {
+    /// The most recent commit both histories have in common, if any.
+    pub base: Option<ContentId>,
+    pub mine: Branch,
+    pub theirs: Branch,
+}
+
+/// The commits of one side of a [`Fork`], oldest first.
+///
+/// The [`Fork::base`] is not included.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Branch {
+    pub head: ContentId,
+    pub commits: Vec<Commit>,
+}
+
+impl Branch {
+    /// All keys which signed any of the commits on this branch.
+    pub fn signers(&self) -> BTreeSet<&PublicKey> {
+        self.commits
+            .iter()
+            .flat_map(|commit| commit.signers.iter())
+            .collect()
+    }
+}
+
+/// A commit on a [`Branch`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Commit {
+    pub content_id: ContentId,
+    pub revision: Revision,
+    pub signers: BTreeSet<PublicKey>,
+}
+
+impl<'a, T: 'a> Identities<'a, T> {
+    /// Explain how the histories with head commits `mine` and `theirs` have
+    /// diverged.
+    pub fn fork(&self, mine: git2::Oid, theirs: git2::Oid) -> Result<Fork, error::Load> {
+        let base = match self.repo.merge_base(mine, theirs) {
+            Ok(base) => Some(base),
+            Err(e) if is_not_found_err(&e) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Fork {
+            base: base.map(ContentId::from),
+            mine: self.branch(mine, base)?,
+            theirs: self.branch(theirs, base)?,
+        })
+    }
+
+    fn branch(&self, head: git2::Oid, base: Option<git2::Oid>) -> Result<Branch, error::Load> {
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+        revwalk.push(head)?;
+        if let Some(base) = base {
+            revwalk.hide(base)?;
+        }
+
+        let commits = revwalk
+            .map(|oid| {
+                let commit = self.repo.find_commit(oid?)?;
+                Ok(Commit {
+                    content_id: commit.id().into(),
+                    revision: commit.tree_id().into(),
+                    signers: Signatures::try_from(&commit)?.keys().copied().collect(),
+                })
+            })
+            .collect::<Result<Vec<_>, error::Load>>()?;
+
+        Ok(Branch {
+            head: head.into(),
+            commits,
+        })
+    }
+}
+
+impl<'a, T: 'a> Identities<'a, Identity<T>>
+where
+    T: Delegations + generic::Replaces<Revision = Revision>,
+    T::Error: std::error::Error + 'static,
+{
+    /// Record `theirs` as merged into `ours`, and sign the result.
+    ///
+    /// The result is a commit with both `content_id`s as parents, but with the
+    /// revision of `ours`. This does not change the verification status of
+    /// `ours`: the history of `theirs` is only linked, not applied. It does,
+    /// however, mean that the revision of `theirs` is now in the ancestry path
+    /// of `ours`, and `theirs` can fast-forward to the result.
+    ///
+    /// If the content of `theirs` should be retained, apply it as an update to
+    /// `ours` before merging.
+    pub fn merge_fork<S>(
+        &self,
+        ours: SignedIdentity<T>,
+        theirs: SignedIdentity<T>,
+        signer: &S,
+    ) -> Result<Identity<T>, error::Merge>
+    where
+        S: Signer,
+    {
+        if ours.root != theirs.root {
+            return Err(error::Merge::RootMismatch);
+        }
+
+        let ours = ours.into_inner();
+        let mut signatures = ours.signatures.clone();
+        {
+            let sig = sign(signer, ours.revision).map_err(|e| error::Merge::Signer(Box::new(e)))?;
+            signatures.extend(Some(sig))
+        }
+        let (content_id, timestamp) = self.commit(
+            &format!(
+                "Merged fork {} at revision {}",
+                theirs.content_id, theirs.revision
+            ),
+            &signatures,
+            ours.revision,
+            &[&ours, &*theirs],
+        )?;
+
+        Ok(Identity {
+            content_id,
+            timestamp: Some(timestamp),
+            signatures,
+            ..ours
+        })
+    }
+}
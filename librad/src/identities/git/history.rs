@@ -0,0 +1,302 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Audit log of identity histories.
+//!
+//! [`Identities::verify`] folds the history of an identity into its most
+//! recent verified state. The [`History`] returned by
+//! [`Identities::history`] drives the same fold, but records the outcome of
+//! verification for every revision, along with who signed it and what it
+//! changed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::{Infallible, TryFrom},
+};
+
+use serde::Serialize;
+
+use super::{
+    delegation_keys,
+    error,
+    generic,
+    ByOid,
+    ContentId,
+    Delegations,
+    Identities,
+    Identity,
+    Iter,
+    Person,
+    Project,
+    Revision,
+    Urn,
+};
+use crate::keys::PublicKey;
+
+/// The revision log of an identity, oldest first.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct History {
+    /// The content id of the most recent verified revision, if the history is
+    /// valid.
+    pub head: Option<ContentId>,
+    pub entries: Vec<Entry>,
+}
+
+impl History {
+    /// Whether the history passes verification.
+    pub fn is_valid(&self) -> bool {
+        self.head.is_some()
+    }
+
+    /// Export the log as (pretty-printed) JSON.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+/// A single commit in the history of an identity.
+///
+/// Changes are relative to the preceding entry. For the first entry, all
+/// delegates and payload fields are reported as added.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub content_id: ContentId,
+    pub revision: Revision,
+    /// The commit time, in seconds since the epoch.
+    pub timestamp: Option<i64>,
+    pub signers: BTreeSet<PublicKey>,
+    pub delegates_added: BTreeSet<PublicKey>,
+    pub delegates_removed: BTreeSet<PublicKey>,
+    /// Changed payload fields, keyed by their namespace.
+    pub payload: BTreeMap<String, Change>,
+    /// Whether the signatures form a quorum of the delegations of this
+    /// revision.
+    pub quorum: bool,
+    pub status: Status,
+}
+
+/// The value of a payload field before and after a revision.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Change {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<serde_json::Value>,
+}
+
+/// The outcome of verifying an [`Entry`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Status {
+    /// The revision is verified, and becomes the new head.
+    Verified,
+    /// The revision adds signatures to the current head.
+    Confirmed,
+    /// The revision did not reach quorum, and was skipped. This is the case
+    /// for proposals.
+    NoQuorum,
+    /// The revision reached quorum, but did not verify against the parent of
+    /// the current head, and was skipped.
+    Skipped { reason: String },
+    /// The revision renders the history invalid. This is either the last
+    /// entry, or the most recent verified revision if it is rejected after
+    /// the fact, eg. because a project delegate's latest head doesn't verify.
+    Invalid { reason: String },
+    /// The revision reached quorum and verified, but its payload is invalid.
+    /// The history ends at the previous revision.
+    Rejected { reason: String },
+}
+
+impl<'a> Identities<'a, Person> {
+    /// Build the [`History`] of the person with head commit `head`.
+    pub fn history(&self, head: git2::Oid) -> Result<History, error::Load> {
+        history(
+            self.repo,
+            head,
+            |delegations| delegations.iter().copied().collect(),
+            |payload| self.registry.validate(payload),
+            |folded| Ok::<_, Infallible>(folded.head.content_id),
+        )
+    }
+}
+
+impl<'a> Identities<'a, Project> {
+    /// Build the [`History`] of the project with head commit `head`.
+    ///
+    /// Delegates are reported as the keys of the direct and indirect
+    /// delegations, as they were inlined at each revision. As for
+    /// [`Identities::verify`], the supplied [`Fn`] shall return the latest
+    /// head commit of any indirect delegations: if the head of the history
+    /// does not verify against those, it is marked as
+    /// [`Status::Invalid`].
+    pub fn history<F, E>(
+        &self,
+        head: git2::Oid,
+        find_latest_head: F,
+    ) -> Result<History, error::Load>
+    where
+        F: Fn(Urn) -> Result<git2::Oid, E>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        history(
+            self.repo,
+            head,
+            |delegations| delegation_keys(delegations).into_iter().copied().collect(),
+            |payload| self.registry.validate(payload),
+            |folded| {
+                self.resolve_folded(folded, &find_latest_head)
+                    .map(|verified| verified.content_id)
+            },
+        )
+    }
+}
+
+/// Fold the history with head commit `tip`, recording an [`Entry`] for every
+/// revision. The payload of every revision is passed to `validate`, see
+/// [`generic::Folded::fold`]. The head of the fold is passed to `finish`,
+/// which may reject it on grounds the fold itself is not aware of.
+fn history<'a, T, D, K, P, PE, V, E>(
+    repo: &'a git2::Repository,
+    tip: git2::Oid,
+    keys: K,
+    validate: P,
+    finish: V,
+) -> Result<History, error::Load>
+where
+    T: Clone + Serialize,
+    D: Clone,
+    generic::Doc<T, D, Revision>: Delegations + generic::Replaces<Revision = Revision>,
+    <generic::Doc<T, D, Revision> as Delegations>::Error: std::error::Error + Send + Sync + 'static,
+    Identity<generic::Doc<T, D, Revision>>: TryFrom<ByOid<'a>, Error = error::Load>,
+    K: Fn(&D) -> BTreeSet<PublicKey>,
+    P: Fn(&T) -> Result<(), PE>,
+    PE: std::error::Error + Send + Sync + 'static,
+    V: FnOnce(
+        generic::Folded<generic::Doc<T, D, Revision>, Revision, ContentId>,
+    ) -> Result<ContentId, E>,
+    E: std::error::Error,
+{
+    // Load errors are not a matter of verification, so don't let them end up
+    // in the log.
+    let revisions = Iter::<'_, Identity<generic::Doc<T, D, Revision>>>::new(repo, tip)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut entries = Vec::with_capacity(revisions.len());
+    let mut prev: Option<(BTreeSet<PublicKey>, serde_json::Value)> = None;
+    let mut load_error = None;
+    let folded = generic::Folded::fold(
+        revisions.into_iter().map(Ok::<_, error::Load>),
+        |doc: &generic::Doc<T, D, Revision>| validate(&doc.payload),
+        Some(
+            |cur: &Identity<generic::Doc<T, D, Revision>>,
+             step: &generic::Step<Revision, ContentId>| {
+                let delegates = keys(&cur.doc.delegations);
+                let payload = match serde_json::to_value(&cur.doc.payload) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        load_error.get_or_insert(e);
+                        serde_json::Value::Null
+                    },
+                };
+                let (delegates_added, delegates_removed, payload_changes) = match &prev {
+                    None => (
+                        delegates.clone(),
+                        BTreeSet::new(),
+                        diff(&serde_json::Value::Null, &payload),
+                    ),
+                    Some((prev_delegates, prev_payload)) => (
+                        delegates.difference(prev_delegates).copied().collect(),
+                        prev_delegates.difference(&delegates).copied().collect(),
+                        diff(prev_payload, &payload),
+                    ),
+                };
+
+                entries.push(Entry {
+                    content_id: cur.content_id,
+                    revision: cur.revision,
+                    timestamp: cur.timestamp,
+                    signers: cur.signatures.keys().copied().collect(),
+                    delegates_added,
+                    delegates_removed,
+                    payload: payload_changes,
+                    quorum: generic::Verifying::from(cur.clone()).quorum().is_ok(),
+                    status: Status::from(step),
+                });
+                prev = Some((delegates, payload));
+            },
+        ),
+    );
+    if let Some(e) = load_error {
+        return Err(e.into());
+    }
+
+    let head = match folded {
+        Err(_) => None,
+        Ok(folded) => {
+            let head = folded.head.content_id;
+            match finish(folded) {
+                Ok(content_id) => Some(content_id),
+                Err(e) => {
+                    if let Some(entry) = entries.iter_mut().rev().find(|entry| {
+                        entry.content_id == head
+                            && matches!(entry.status, Status::Verified | Status::Confirmed)
+                    }) {
+                        entry.status = Status::Invalid {
+                            reason: e.to_string(),
+                        }
+                    }
+                    None
+                },
+            }
+        },
+    };
+
+    Ok(History { head, entries })
+}
+
+impl From<&generic::Step<Revision, ContentId>> for Status {
+    fn from(step: &generic::Step<Revision, ContentId>) -> Self {
+        use generic::Step;
+
+        match step {
+            Step::Verified => Self::Verified,
+            Step::Confirmed => Self::Confirmed,
+            Step::NoQuorum(_) => Self::NoQuorum,
+            Step::Skipped(e) => Self::Skipped {
+                reason: e.to_string(),
+            },
+            Step::Invalid(e) => Self::Invalid {
+                reason: e.to_string(),
+            },
+            Step::Rejected(e) => Self::Rejected {
+                reason: e.to_string(),
+            },
+        }
+    }
+}
+
+/// Compare the top-level fields of two JSON objects.
+fn diff(before: &serde_json::Value, after: &serde_json::Value) -> BTreeMap<String, Change> {
+    let empty = serde_json::Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    before
+        .keys()
+        .chain(after.keys())
+        .filter(|key| before.get(*key) != after.get(*key))
+        .map(|key| {
+            (
+                key.clone(),
+                Change {
+                    before: before.get(key).cloned(),
+                    after: after.get(key).cloned(),
+                },
+            )
+        })
+        .collect()
+}
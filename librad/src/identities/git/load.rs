@@ -6,14 +6,16 @@
 use std::{convert::TryFrom, path::PathBuf};
 
 use either::Either;
-use git_ext::{self as ext, is_not_found_err};
-use std_ext::result::ResultExt as _;
+use git_ext as ext;
 
 use crate::{
+    git::storage::backend::ReadObjects,
     identities::{
         delegation,
         generic,
         payload::{
+            DelegatePayload,
+            OrgPayload,
             PersonDelegations,
             PersonPayload,
             ProjectDelegations,
@@ -27,15 +29,39 @@ use crate::{
     internal::canonical::Cjson,
 };
 
-use super::{error, ContentId, Doc, Identity, Person, Project, Revision, SomeIdentity};
+use super::{
+    error,
+    ContentId,
+    Delegate,
+    Doc,
+    Identity,
+    IndirectDelegation,
+    Org,
+    Person,
+    Project,
+    Revision,
+    SomeIdentity,
+};
 
 pub type ByOid<'a> = (&'a git2::Repository, git2::Oid);
 
+/// Identities which can be loaded from any [`ReadObjects`] backend.
+pub trait FromBackend: Sized {
+    /// Load the identity stored at commit `oid`.
+    ///
+    /// The only guarantee about the returned value is that it is well-formed
+    /// -- it may or may not pass verification.
+    fn from_backend<B>(backend: &B, oid: git2::Oid) -> Result<Self, error::Load>
+    where
+        B: ReadObjects;
+}
+
 #[derive(Debug, serde::Serialize)]
 #[serde(untagged)]
 enum SomeDoc {
     Person(Doc<PersonPayload, PersonDelegations>),
     Project(Doc<ProjectPayload, ProjectDelegations<Revision>>),
+    Org(Doc<OrgPayload, ProjectDelegations<Revision>>),
 }
 
 impl<'de> serde::Deserialize<'de> for SomeDoc {
@@ -53,6 +79,9 @@ impl<'de> serde::Deserialize<'de> for SomeDoc {
                     replaces: doc.replaces,
                     payload,
                     delegations,
+                    quorum: doc.quorum,
+                    revocations: doc.revocations,
+                    not_after: doc.not_after,
                 }))
             },
 
@@ -62,6 +91,9 @@ impl<'de> serde::Deserialize<'de> for SomeDoc {
                     replaces: doc.replaces,
                     payload,
                     delegations,
+                    quorum: doc.quorum,
+                    revocations: doc.revocations,
+                    not_after: doc.not_after,
                 }))
             },
 
@@ -71,6 +103,33 @@ impl<'de> serde::Deserialize<'de> for SomeDoc {
                     replaces: doc.replaces,
                     payload,
                     delegations: (*delegations).iter().copied().map(Either::Left).collect(),
+                    quorum: doc.quorum,
+                    revocations: doc.revocations,
+                    not_after: doc.not_after,
+                }))
+            },
+
+            (SomePayload::Org(payload), SomeDelegations::Project(delegations)) => {
+                Ok(Self::Org(Doc {
+                    version: doc.version,
+                    replaces: doc.replaces,
+                    payload,
+                    delegations,
+                    quorum: doc.quorum,
+                    revocations: doc.revocations,
+                    not_after: doc.not_after,
+                }))
+            },
+
+            (SomePayload::Org(payload), SomeDelegations::Person(delegations)) => {
+                Ok(Self::Org(Doc {
+                    version: doc.version,
+                    replaces: doc.replaces,
+                    payload,
+                    delegations: (*delegations).iter().copied().map(Either::Left).collect(),
+                    quorum: doc.quorum,
+                    revocations: doc.revocations,
+                    not_after: doc.not_after,
                 }))
             },
 
@@ -79,138 +138,168 @@ impl<'de> serde::Deserialize<'de> for SomeDoc {
     }
 }
 
-struct Any<'a, Doc> {
-    repo: &'a git2::Repository,
-    tree: git2::Tree<'a>,
+struct Any<'a, B, Doc> {
+    backend: &'a B,
+    tree: ext::Oid,
     identity: generic::Identity<Doc, Revision, ContentId>,
 }
 
-type AnyPerson<'a> = Any<'a, Doc<PersonPayload, PersonDelegations>>;
-type AnyProject<'a> = Any<'a, Doc<ProjectPayload, ProjectDelegations<Revision>>>;
+type AnyPerson<'a, B> = Any<'a, B, Doc<PersonPayload, PersonDelegations>>;
+type AnyProject<'a, B> = Any<'a, B, Doc<ProjectPayload, ProjectDelegations<Revision>>>;
+type AnyOrg<'a, B> = Any<'a, B, Doc<OrgPayload, ProjectDelegations<Revision>>>;
 
-impl<'a> From<AnyPerson<'a>> for Person {
-    fn from(any: AnyPerson<'a>) -> Self {
+impl<'a, B> From<AnyPerson<'a, B>> for Person {
+    fn from(any: AnyPerson<'a, B>) -> Self {
         any.identity.map(|doc| doc.second(delegation::Direct::from))
     }
 }
 
-impl<'a> TryFrom<AnyProject<'a>> for Project {
+impl<'a, B: ReadObjects> TryFrom<AnyProject<'a, B>> for Project {
     type Error = error::Load;
 
-    fn try_from(any: AnyProject<'a>) -> Result<Self, Self::Error> {
-        let Any {
-            repo,
-            tree,
-            identity,
-        } = any;
-
-        identity
-            .map(|doc| {
-                doc.try_second(|delegations| {
-                    let delegations = delegations
-                        .into_iter()
-                        .map(|d| match d.into() {
-                            Either::Left(key) => Ok(Either::Left(key)),
-                            Either::Right(urn) => {
-                                resolve_inlined_person(repo, &tree, urn).map(Either::Right)
-                            },
-                        })
-                        .collect::<Result<Vec<Either<_, _>>, _>>()?;
-
-                    delegation::Indirect::try_from_iter(delegations).map_err(error::Load::from)
-                })
-            })
-            .transpose()
+    fn try_from(any: AnyProject<'a, B>) -> Result<Self, Self::Error> {
+        resolve_indirect(any)
     }
 }
 
-impl<'a, Doc> TryFrom<ByOid<'a>> for Any<'a, Doc>
+impl<'a, B: ReadObjects> TryFrom<AnyOrg<'a, B>> for Org {
+    type Error = error::Load;
+
+    fn try_from(any: AnyOrg<'a, B>) -> Result<Self, Self::Error> {
+        resolve_indirect(any)
+    }
+}
+
+fn resolve_indirect<B, T>(
+    any: Any<'_, B, Doc<T, ProjectDelegations<Revision>>>,
+) -> Result<Identity<Doc<T, IndirectDelegation>>, error::Load>
 where
-    Doc: serde::Serialize + serde::de::DeserializeOwned,
+    B: ReadObjects,
 {
-    type Error = error::Load;
+    let Any {
+        backend,
+        tree,
+        identity,
+    } = any;
+
+    identity
+        .map(|doc| {
+            doc.try_second(|delegations| {
+                let delegations = delegations
+                    .into_iter()
+                    .map(|d| match d.into() {
+                        Either::Left(key) => Ok(Either::Left(key)),
+                        Either::Right(urn) => {
+                            resolve_inlined_delegate(backend, tree, urn).map(Either::Right)
+                        },
+                    })
+                    .collect::<Result<Vec<Either<_, _>>, _>>()?;
+
+                delegation::Indirect::try_from_iter(delegations).map_err(error::Load::from)
+            })
+        })
+        .transpose()
+}
 
-    fn try_from((repo, oid): ByOid<'a>) -> Result<Self, Self::Error> {
-        let commit = repo.find_commit(oid)?;
-        let tree = commit.tree()?;
-
-        let (root, doc_blob) = {
-            // borrowck insists we drop this before returning
-            let first_blob_entry = tree
-                .iter()
-                .find(|entry| entry.kind() == Some(git2::ObjectType::Blob))
-                .ok_or(error::Load::MissingDoc)?;
-
-            let name = String::from_utf8_lossy(first_blob_entry.name_bytes());
-            let root = git2::Oid::from_str(&name)?;
-            let blob = first_blob_entry
-                .to_object(repo)?
-                .into_blob()
-                .expect("we filtered by git2::ObjectType::Blob, so this must be a blob. qed");
-
-            Ok::<_, Self::Error>((root, blob))
-        }?;
+impl<'a, B, Doc> Any<'a, B, Doc>
+where
+    B: ReadObjects,
+    Doc: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn load(backend: &'a B, oid: git2::Oid) -> Result<Self, error::Load> {
+        let commit = backend
+            .read_commit(oid.into())
+            .map_err(error::Load::backend)?
+            .ok_or(error::Load::MissingCommit(oid))?;
+        let tree = commit.tree;
+
+        let first_blob_entry = backend
+            .read_tree(tree)
+            .map_err(error::Load::backend)?
+            .and_then(|entries| {
+                entries
+                    .into_iter()
+                    .find(|entry| entry.kind == Some(git2::ObjectType::Blob))
+            })
+            .ok_or(error::Load::MissingDoc)?;
+        let root = git2::Oid::from_str(&first_blob_entry.name)?;
+        let doc_blob = backend
+            .read_blob(first_blob_entry.oid)
+            .map_err(error::Load::backend)?
+            .ok_or(error::Load::MissingDoc)?;
 
         // Check that the root doc exists
+        if backend
+            .read_blob(root.into())
+            .map_err(error::Load::backend)?
+            .is_none()
         {
-            let _root_doc = repo
-                .find_blob(root)
-                .or_matches(is_not_found_err, || Err(error::Load::MissingRoot))?;
+            return Err(error::Load::MissingRoot);
         }
 
-        let doc: Doc = Cjson::<Doc>::from_slice(doc_blob.content())?.into_inner();
+        let doc: Doc = Cjson::<Doc>::from_slice(&doc_blob)?.into_inner();
 
         // Verify that the doc is in canonical form (ie. the git hash is stable)
         {
             let canonical = Cjson(&doc).canonical_form()?;
             let hash = git2::Oid::hash_object(git2::ObjectType::Blob, &canonical)?;
-            if hash != doc_blob.id() {
+            if hash != *first_blob_entry.oid {
                 return Err(error::Load::DigestMismatch);
             }
         }
 
+        let signatures = commit
+            .message
+            .as_deref()
+            .ok_or(error::Signatures::Utf8)
+            .and_then(|msg| Signatures::from_trailers(msg).map_err(error::Signatures::from))?;
+
         let identity = generic::Identity {
-            content_id: commit.id().into(),
+            content_id: oid.into(),
+            timestamp: Some(commit.time),
             root: root.into(),
-            revision: tree.id().into(),
+            revision: tree,
             doc,
-            signatures: Signatures::try_from(&commit)?,
+            signatures,
         };
 
         Ok(Self {
-            repo,
+            backend,
             tree,
             identity,
         })
     }
 }
 
-impl<'a> TryFrom<ByOid<'a>> for SomeIdentity {
-    type Error = error::Load;
-
-    fn try_from((repo, oid): ByOid<'a>) -> Result<Self, Self::Error> {
+impl FromBackend for SomeIdentity {
+    fn from_backend<B>(backend: &B, oid: git2::Oid) -> Result<Self, error::Load>
+    where
+        B: ReadObjects,
+    {
         // Lighting a scent stick for Applicative
 
         let Any {
-            repo,
+            backend,
             tree,
             identity:
                 generic::Identity {
                     content_id,
+                    timestamp,
                     root,
                     revision,
                     doc,
                     signatures,
                 },
-        } = Any::<'a, SomeDoc>::try_from((repo, oid))?;
+        } = Any::<'_, B, SomeDoc>::load(backend, oid)?;
 
         match doc {
             SomeDoc::Person(person) => {
                 let person = Person::from(Any {
-                    repo,
+                    backend,
                     tree,
                     identity: Identity {
                         content_id,
+                        timestamp,
                         root,
                         revision,
                         doc: person,
@@ -222,10 +311,11 @@ impl<'a> TryFrom<ByOid<'a>> for SomeIdentity {
 
             SomeDoc::Project(project) => {
                 let project = Project::try_from(Any {
-                    repo,
+                    backend,
                     tree,
                     identity: Identity {
                         content_id,
+                        timestamp,
                         root,
                         revision,
                         doc: project,
@@ -234,42 +324,113 @@ impl<'a> TryFrom<ByOid<'a>> for SomeIdentity {
                 })?;
                 Ok(SomeIdentity::Project(project))
             },
+
+            SomeDoc::Org(org) => {
+                let org = Org::try_from(Any {
+                    backend,
+                    tree,
+                    identity: Identity {
+                        content_id,
+                        timestamp,
+                        root,
+                        revision,
+                        doc: org,
+                        signatures,
+                    },
+                })?;
+                Ok(SomeIdentity::Org(org))
+            },
         }
     }
 }
 
+impl FromBackend for Person {
+    fn from_backend<B>(backend: &B, oid: git2::Oid) -> Result<Self, error::Load>
+    where
+        B: ReadObjects,
+    {
+        Ok(Person::from(AnyPerson::load(backend, oid)?))
+    }
+}
+
+impl FromBackend for Project {
+    fn from_backend<B>(backend: &B, oid: git2::Oid) -> Result<Self, error::Load>
+    where
+        B: ReadObjects,
+    {
+        Project::try_from(AnyProject::load(backend, oid)?)
+    }
+}
+
+impl FromBackend for Org {
+    fn from_backend<B>(backend: &B, oid: git2::Oid) -> Result<Self, error::Load>
+    where
+        B: ReadObjects,
+    {
+        Org::try_from(AnyOrg::load(backend, oid)?)
+    }
+}
+
+impl<'a> TryFrom<ByOid<'a>> for SomeIdentity {
+    type Error = error::Load;
+
+    fn try_from((repo, oid): ByOid<'a>) -> Result<Self, Self::Error> {
+        Self::from_backend(repo, oid)
+    }
+}
+
 impl<'a> TryFrom<ByOid<'a>> for Person {
     type Error = error::Load;
 
-    fn try_from(git: ByOid<'a>) -> Result<Self, Self::Error> {
-        Ok(Person::from(Any::try_from(git)?))
+    fn try_from((repo, oid): ByOid<'a>) -> Result<Self, Self::Error> {
+        Self::from_backend(repo, oid)
     }
 }
 
 impl<'a> TryFrom<ByOid<'a>> for Project {
     type Error = error::Load;
 
-    fn try_from(git: ByOid<'a>) -> Result<Self, Self::Error> {
-        Project::try_from(Any::try_from(git)?)
+    fn try_from((repo, oid): ByOid<'a>) -> Result<Self, Self::Error> {
+        Self::from_backend(repo, oid)
+    }
+}
+
+impl<'a> TryFrom<ByOid<'a>> for Org {
+    type Error = error::Load;
+
+    fn try_from((repo, oid): ByOid<'a>) -> Result<Self, Self::Error> {
+        Self::from_backend(repo, oid)
     }
 }
 
-type InlinedPerson = generic::Identity<Doc<PersonPayload, PersonDelegations>, Revision, ContentId>;
+/// An inlined [`Delegate`]. For orgs, the delegations are the keys of all
+/// members at the inlined revision.
+type InlinedDelegate =
+    generic::Identity<Doc<DelegatePayload, PersonDelegations>, Revision, ContentId>;
 
-#[tracing::instrument(level = "debug", skip(repo, tree), err)]
-fn resolve_inlined_person(
-    repo: &git2::Repository,
-    tree: &git2::Tree,
+#[tracing::instrument(level = "debug", skip(backend), err)]
+fn resolve_inlined_delegate<B>(
+    backend: &B,
+    tree: ext::Oid,
     urn: Urn<Revision>,
-) -> Result<Person, error::Load> {
+) -> Result<Delegate, error::Load>
+where
+    B: ReadObjects,
+{
     let path = PathBuf::from(format!("delegations/{}", urn.encode_id()));
-    let blob = tree
-        .get_path(&path)?
-        .to_object(repo)?
-        .into_blob()
-        .map_err(|obj| error::Load::NotABlob(path, obj.kind()))?;
-
-    Ok(Cjson::<InlinedPerson>::from_slice(blob.content())?
+    let entry = backend
+        .tree_entry(tree, &path)
+        .map_err(error::Load::backend)?
+        .ok_or_else(|| error::Load::MissingDelegate(path.clone()))?;
+    let blob = match entry.kind {
+        Some(git2::ObjectType::Blob) => backend
+            .read_blob(entry.oid)
+            .map_err(error::Load::backend)?
+            .ok_or_else(|| error::Load::MissingDelegate(path.clone()))?,
+        kind => return Err(error::Load::NotABlob(path, kind)),
+    };
+
+    Ok(Cjson::<InlinedDelegate>::from_slice(&blob)?
         .into_inner()
         .map(|doc| doc.second(delegation::Direct::from)))
 }
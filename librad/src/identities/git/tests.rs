@@ -6,5 +6,6 @@
 use super::*;
 
 mod common;
+mod org;
 mod person;
 mod project;
@@ -3,17 +3,60 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::io;
+use std::{collections::BTreeMap, io};
 
 use anyhow::anyhow;
 
 use super::*;
-use crate::keys::SecretKey;
+use crate::{
+    git::storage::backend::{Memory, ReadObjects as _, WriteObjects as _},
+    keys::{PublicKey, SecretKey},
+};
 
 use librad_test::tempdir::WithTmpDir;
 
+pub(super) fn lookup(map: &BTreeMap<Urn, git2::Oid>) -> impl Fn(Urn) -> Result<git2::Oid, !> + '_ {
+    move |urn| Ok(*map.get(&urn).unwrap())
+}
+
 type TmpRepo = WithTmpDir<git2::Repository>;
 
+/// Copy the identity commit `oid` from `repo` to the in-memory `backend`,
+/// returning the oid of the copy.
+pub(super) fn copy_to_memory(
+    repo: &git2::Repository,
+    backend: &Memory,
+    oid: git2::Oid,
+) -> anyhow::Result<git2::Oid> {
+    fn copy_tree(
+        repo: &git2::Repository,
+        backend: &Memory,
+        tree: git_ext::Oid,
+    ) -> anyhow::Result<git_ext::Oid> {
+        let entries = repo
+            .read_tree(tree)?
+            .ok_or_else(|| anyhow!("missing tree"))?;
+        for entry in &entries {
+            if entry.kind == Some(git2::ObjectType::Tree) {
+                copy_tree(repo, backend, entry.oid)?;
+            } else {
+                let blob = repo
+                    .read_blob(entry.oid)?
+                    .ok_or_else(|| anyhow!("missing blob"))?;
+                backend.write_blob(&blob)?;
+            }
+        }
+        Ok(backend.write_tree(&entries)?)
+    }
+
+    let commit = repo
+        .read_commit(oid.into())?
+        .ok_or_else(|| anyhow!("missing commit"))?;
+    let tree = copy_tree(repo, backend, commit.tree)?;
+    let copy = backend.write_commit(tree, &[], commit.message.as_deref().unwrap_or_default())?;
+    Ok(copy.into())
+}
+
 pub(super) fn repo() -> anyhow::Result<TmpRepo> {
     Ok(WithTmpDir::new(|path| {
         let setup = || {
@@ -92,6 +135,56 @@ impl<'a> Device<'a> {
         Ok(Self { cur, ..self })
     }
 
+    pub fn update_quorum(
+        self,
+        delegations: impl Into<Option<delegation::Direct>>,
+        quorum: Option<quorum::Policy>,
+    ) -> anyhow::Result<Self> {
+        let cur = self.git.update_quorum(
+            Verifying::from(self.cur).signed()?,
+            delegations,
+            quorum,
+            self.key,
+        )?;
+
+        Ok(Self { cur, ..self })
+    }
+
+    pub fn update_expiry(self, not_after: BTreeMap<PublicKey, i64>) -> anyhow::Result<Self> {
+        let cur =
+            self.git
+                .update_expiry(Verifying::from(self.cur).signed()?, not_after, self.key)?;
+
+        Ok(Self { cur, ..self })
+    }
+
+    pub fn revoke(self, key: PublicKey, replacement: Option<PublicKey>) -> anyhow::Result<Self> {
+        let cur = self.git.revoke(
+            Verifying::from(self.cur).signed()?,
+            key,
+            replacement,
+            None,
+            self.key,
+        )?;
+
+        Ok(Self { cur, ..self })
+    }
+
+    pub fn rotate(self, new: &'a SecretKey) -> anyhow::Result<Self> {
+        let cur = self.git.rotate(
+            Verifying::from(self.cur).signed()?,
+            self.key,
+            new,
+            Some("rotated in tests".to_owned()),
+        )?;
+
+        Ok(Self {
+            key: new,
+            cur,
+            ..self
+        })
+    }
+
     pub fn update_from(self, other: &Device<'a>) -> anyhow::Result<Self> {
         let cur = self.git.update_from(
             Verifying::from(self.cur).signed()?,
@@ -102,6 +195,24 @@ impl<'a> Device<'a> {
         Ok(Self { cur, ..self })
     }
 
+    pub fn fork(&self, other: &Device<'a>) -> Result<fork::Fork, error::Load> {
+        self.git.fork(*self.cur.content_id, *other.cur.content_id)
+    }
+
+    pub fn merge_fork(self, other: &Device<'a>) -> anyhow::Result<Self> {
+        let cur = self.git.merge_fork(
+            Verifying::from(self.cur).signed()?,
+            Verifying::from(other.cur.clone()).signed()?,
+            self.key,
+        )?;
+
+        Ok(Self { cur, ..self })
+    }
+
+    pub fn history(&self) -> Result<history::History, error::Load> {
+        self.git.history(*self.cur.content_id)
+    }
+
     pub fn verify(&self) -> Result<VerifiedPerson, error::VerifyPerson> {
         Ok(self.git.verify(*self.cur.content_id)?)
     }
@@ -150,7 +261,7 @@ impl<'a> Project<'a> {
                 default_branch: Some("\u{1F32F}".into()),
             }
             .into(),
-            IndirectDelegation::try_from_iter(Some(Right(dev.cur.clone())))?,
+            IndirectDelegation::try_from_iter(Some(Right(Delegate::from(dev.cur.clone()))))?,
             dev.key,
         )?;
 
@@ -224,6 +335,16 @@ impl<'a> Project<'a> {
             .verify(*self.cur.content_id, lookup)?)
     }
 
+    pub fn history<F>(&self, lookup: F) -> Result<history::History, error::Load>
+    where
+        F: Fn(Urn) -> Result<git2::Oid, !>,
+    {
+        self.dev
+            .git
+            .as_project()
+            .history(*self.cur.content_id, lookup)
+    }
+
     pub fn assert_verifies<F>(&self, lookup: F) -> anyhow::Result<()>
     where
         F: Fn(Urn) -> Result<git2::Oid, !>,
@@ -0,0 +1,297 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::collections::BTreeMap;
+
+use super::{common::lookup, *};
+use crate::keys::SecretKey;
+
+lazy_static! {
+    static ref ALICE: SecretKey = SecretKey::from_seed([
+        33, 102, 91, 173, 18, 94, 230, 74, 203, 6, 150, 129, 218, 2, 58, 77, 140, 11, 125, 199, 64,
+        17, 248, 30, 155, 53, 112, 246, 9, 162, 84, 187
+    ]);
+    static ref BOB: SecretKey = SecretKey::from_seed([
+        120, 7, 231, 45, 166, 13, 89, 200, 51, 174, 92, 3, 117, 240, 61, 148, 27, 209, 70, 135,
+        182, 42, 99, 16, 223, 158, 80, 37, 194, 111, 6, 251
+    ]);
+}
+
+#[test]
+fn membership_propagates() -> anyhow::Result<()> {
+    let repo = common::repo()?;
+    {
+        let git = Identities::<Org>::from(&*repo);
+        let alice = common::Device::new(&*ALICE, Identities::from(&*repo))?;
+        let bob = common::Device::new(&*BOB, Identities::from(&*repo))?;
+
+        let org = git.create(
+            payload::Org {
+                name: "radicle".into(),
+            }
+            .into(),
+            IndirectDelegation::try_from_iter(Some(Right(Delegate::from(
+                alice.current().clone(),
+            ))))?,
+            &*ALICE,
+        )?;
+        let project = git.as_project().create(
+            payload::Project {
+                name: "radicle-link".into(),
+                description: None,
+                default_branch: None,
+            }
+            .into(),
+            IndirectDelegation::try_from_iter(Some(Right(Delegate::from(org.clone()))))?,
+            &*ALICE,
+        )?;
+
+        let mut heads = BTreeMap::new();
+        heads.insert(alice.current().urn(), *alice.current().content_id);
+        heads.insert(bob.current().urn(), *bob.current().content_id);
+        heads.insert(org.urn(), *org.content_id);
+
+        let verified = git
+            .as_project()
+            .verify(*project.content_id, lookup(&heads))?;
+        assert!(verified.delegations().owner(&BOB.public()).is_none());
+
+        // Adding bob to the org makes him a delegate of the project, without
+        // updating the project
+        let org = git.update(
+            Verifying::from(org).signed()?,
+            None,
+            IndirectDelegation::try_from_iter(vec![
+                Right(Delegate::from(alice.current().clone())),
+                Right(Delegate::from(bob.current().clone())),
+            ])?,
+            &*ALICE,
+        )?;
+        let org = git.create_from(Verifying::from(org).signed()?, &*BOB)?;
+        heads.insert(org.urn(), *org.content_id);
+
+        let verified = git
+            .as_project()
+            .verify(*project.content_id, lookup(&heads))?;
+        assert_eq!(verified.content_id, project.content_id);
+        let owner = verified
+            .delegations()
+            .owner(&BOB.public())
+            .expect("bob should be a delegate via the org");
+        assert_eq!(owner.urn(), org.urn());
+        assert!(owner.doc.payload.org().is_some());
+        assert_eq!(
+            verified
+                .delegations()
+                .owner(&ALICE.public())
+                .map(|id| id.urn()),
+            Some(org.urn())
+        );
+
+        Ok(())
+    }
+}
+
+#[test]
+fn member_added_later() -> anyhow::Result<()> {
+    let repo = common::repo()?;
+    {
+        let git = Identities::<Org>::from(&*repo);
+        let alice = common::Device::new(&*ALICE, Identities::from(&*repo))?;
+        let bob = common::Device::new(&*BOB, Identities::from(&*repo))?;
+
+        let org = git.create(
+            payload::Org {
+                name: "radicle".into(),
+            }
+            .into(),
+            IndirectDelegation::try_from_iter(Some(Right(Delegate::from(
+                alice.current().clone(),
+            ))))?,
+            &*ALICE,
+        )?;
+        let project = git.as_project().create(
+            payload::Project {
+                name: "radicle-link".into(),
+                description: None,
+                default_branch: None,
+            }
+            .into(),
+            IndirectDelegation::try_from_iter(Some(Right(Delegate::from(org.clone()))))?,
+            &*ALICE,
+        )?;
+        let org = git.update(
+            Verifying::from(org).signed()?,
+            None,
+            IndirectDelegation::try_from_iter(vec![
+                Right(Delegate::from(alice.current().clone())),
+                Right(Delegate::from(bob.current().clone())),
+            ])?,
+            &*ALICE,
+        )?;
+        let org = git.create_from(Verifying::from(org).signed()?, &*BOB)?;
+
+        let mut heads = BTreeMap::new();
+        heads.insert(alice.current().urn(), *alice.current().content_id);
+        heads.insert(bob.current().urn(), *bob.current().content_id);
+        heads.insert(org.urn(), *org.content_id);
+
+        // Bob is a delegate of the project head, but his update is verified
+        // against the membership inlined in the project, which doesn't
+        // include him
+        let update = git.as_project().update(
+            Verifying::from(project.clone()).signed()?,
+            payload::Project {
+                name: "radicle-link-bob".into(),
+                description: None,
+                default_branch: None,
+            }
+            .into(),
+            None,
+            &*BOB,
+        )?;
+        let verified = git
+            .as_project()
+            .verify(*update.content_id, lookup(&heads))?;
+        assert_eq!(verified.content_id, project.content_id);
+        assert_eq!(
+            verified
+                .delegations()
+                .owner(&BOB.public())
+                .map(|id| id.urn()),
+            Some(org.urn())
+        );
+
+        Ok(())
+    }
+}
+
+#[test]
+fn member_removed_later() -> anyhow::Result<()> {
+    let repo = common::repo()?;
+    {
+        let git = Identities::<Org>::from(&*repo);
+        let alice = common::Device::new(&*ALICE, Identities::from(&*repo))?;
+        let bob = common::Device::new(&*BOB, Identities::from(&*repo))?;
+
+        let org = git.create(
+            payload::Org {
+                name: "radicle".into(),
+            }
+            .into(),
+            IndirectDelegation::try_from_iter(Some(Right(Delegate::from(
+                alice.current().clone(),
+            ))))?,
+            &*ALICE,
+        )?;
+        let org = git.update(
+            Verifying::from(org).signed()?,
+            None,
+            IndirectDelegation::try_from_iter(vec![
+                Right(Delegate::from(alice.current().clone())),
+                Right(Delegate::from(bob.current().clone())),
+            ])?,
+            &*ALICE,
+        )?;
+        let org = git.create_from(Verifying::from(org).signed()?, &*BOB)?;
+        let project = git.as_project().create(
+            payload::Project {
+                name: "radicle-link".into(),
+                description: None,
+                default_branch: None,
+            }
+            .into(),
+            IndirectDelegation::try_from_iter(Some(Right(Delegate::from(org.clone()))))?,
+            &*ALICE,
+        )?;
+        let update = git.as_project().update(
+            Verifying::from(project).signed()?,
+            payload::Project {
+                name: "radicle-link-bob".into(),
+                description: None,
+                default_branch: None,
+            }
+            .into(),
+            None,
+            &*BOB,
+        )?;
+
+        let mut heads = BTreeMap::new();
+        heads.insert(alice.current().urn(), *alice.current().content_id);
+        heads.insert(bob.current().urn(), *bob.current().content_id);
+        heads.insert(org.urn(), *org.content_id);
+
+        let verified = git
+            .as_project()
+            .verify(*update.content_id, lookup(&heads))?;
+        assert_eq!(verified.content_id, update.content_id);
+
+        // Removing bob from the org invalidates his signature on the project
+        // head. Nb. bob signs first, as the removal needs the quorum of the
+        // previous membership
+        let org = git.update(
+            Verifying::from(org).signed()?,
+            None,
+            IndirectDelegation::try_from_iter(Some(Right(Delegate::from(
+                alice.current().clone(),
+            ))))?,
+            &*BOB,
+        )?;
+        let org = git.create_from(Verifying::from(org).signed()?, &*ALICE)?;
+        heads.insert(org.urn(), *org.content_id);
+
+        assert_matches!(
+            git.as_project().verify(*update.content_id, lookup(&heads)),
+            Err(error::VerifyProject::Verification(
+                generic::error::Verify::Quorum
+            ))
+        );
+
+        // Until a remaining member signs it
+        let confirmed = git
+            .as_project()
+            .create_from(Verifying::from(update).signed()?, &*ALICE)?;
+        let verified = git
+            .as_project()
+            .verify(*confirmed.content_id, lookup(&heads))?;
+        assert_eq!(verified.content_id, confirmed.content_id);
+        assert!(verified.delegations().owner(&BOB.public()).is_none());
+
+        Ok(())
+    }
+}
+
+#[test]
+fn no_nested_orgs() -> anyhow::Result<()> {
+    let repo = common::repo()?;
+    {
+        let git = Identities::<Org>::from(&*repo);
+        let alice = common::Device::new(&*ALICE, Identities::from(&*repo))?;
+
+        let org = git.create(
+            payload::Org {
+                name: "radicle".into(),
+            }
+            .into(),
+            IndirectDelegation::try_from_iter(Some(Right(Delegate::from(
+                alice.current().clone(),
+            ))))?,
+            &*ALICE,
+        )?;
+        assert_matches!(
+            git.create(
+                payload::Org {
+                    name: "radicle-link".into(),
+                }
+                .into(),
+                IndirectDelegation::try_from_iter(Some(Right(Delegate::from(org.clone()))))?,
+                &*ALICE,
+            ),
+            Err(error::Store::NestedOrg(urn)) if urn == org.urn()
+        );
+
+        Ok(())
+    }
+}
@@ -4,7 +4,7 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 use super::{common::*, *};
-use crate::keys::SecretKey;
+use crate::{git::storage::backend::Memory, keys::SecretKey};
 
 lazy_static! {
     static ref DESKTOP: SecretKey = SecretKey::from_seed([
@@ -29,6 +29,25 @@ fn create() -> anyhow::Result<()> {
     }
 }
 
+#[test]
+fn load_from_memory() -> anyhow::Result<()> {
+    let repo = repo()?;
+    {
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?;
+        let person = desktop.current();
+
+        let memory = Memory::new();
+        let head = copy_to_memory(&*repo, &memory, *person.content_id)?;
+        let loaded = Person::from_backend(&memory, head)?;
+        assert_eq!(loaded.revision, person.revision);
+        assert_eq!(loaded.root, person.root);
+        assert_eq!(loaded.doc, person.doc);
+        assert_eq!(loaded.signatures, person.signatures);
+
+        Ok(())
+    }
+}
+
 #[test]
 fn update() -> anyhow::Result<()> {
     let repo = repo()?;
@@ -49,6 +68,110 @@ fn update() -> anyhow::Result<()> {
     }
 }
 
+#[test]
+fn history() -> anyhow::Result<()> {
+    let repo = repo()?;
+    {
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?.update(Some(
+            vec![DESKTOP.public(), LAPTOP.public()]
+                .into_iter()
+                .collect(),
+        ))?;
+        let laptop = Device::create_from(&*LAPTOP, &desktop)?;
+
+        let history = laptop.history()?;
+        assert!(history.is_valid());
+        assert_eq!(history.head, Some(laptop.current().content_id));
+        assert_eq!(
+            history
+                .entries
+                .iter()
+                .map(|entry| entry.status.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                history::Status::Verified,
+                history::Status::NoQuorum,
+                history::Status::Verified
+            ]
+        );
+
+        let proposal = &history.entries[1];
+        assert!(!proposal.quorum);
+        assert!(proposal.payload.is_empty());
+        assert!(proposal.delegates_removed.is_empty());
+        assert_eq!(
+            proposal.delegates_added,
+            Some(LAPTOP.public()).into_iter().collect()
+        );
+
+        let confirmation = &history.entries[2];
+        assert!(confirmation.quorum);
+        assert_eq!(
+            confirmation.signers,
+            vec![DESKTOP.public(), LAPTOP.public()]
+                .into_iter()
+                .collect()
+        );
+
+        let json = serde_json::from_str::<serde_json::Value>(&history.to_json()?)?;
+        assert_eq!(json["entries"][1]["status"]["type"], "noQuorum");
+
+        Ok(())
+    }
+}
+
+#[test]
+fn fork() -> anyhow::Result<()> {
+    let repo = repo()?;
+    {
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?.update_quorum(
+            Some(
+                vec![DESKTOP.public(), LAPTOP.public()]
+                    .into_iter()
+                    .collect(),
+            ),
+            Some(quorum::Policy::MinVotes(1)),
+        )?;
+        let base = desktop.current().content_id;
+
+        // Either device can update unilaterally, so they can diverge
+        let laptop = Device::create_from(&*LAPTOP, &desktop)?
+            .update(Some(Some(LAPTOP.public()).into_iter().collect()))?;
+        let desktop = desktop.update(Some(
+            vec![DESKTOP.public(), LAPTOP.public(), PALMTOP.public()]
+                .into_iter()
+                .collect(),
+        ))?;
+        desktop.assert_verifies()?;
+        laptop.assert_verifies()?;
+
+        let fork = laptop.fork(&desktop)?;
+        assert_eq!(fork.base, Some(base));
+        assert_eq!(fork.mine.head, laptop.current().content_id);
+        assert_eq!(fork.mine.commits.len(), 2);
+        assert_eq!(fork.theirs.head, desktop.current().content_id);
+        assert_eq!(fork.theirs.commits.len(), 1);
+        assert_eq!(
+            fork.mine.signers(),
+            Some(&LAPTOP.public()).into_iter().collect()
+        );
+        assert_eq!(
+            fork.theirs.signers(),
+            Some(&DESKTOP.public()).into_iter().collect()
+        );
+
+        // Merging keeps the laptop's revision, but links the desktop's history
+        let merged = laptop.clone().merge_fork(&desktop)?;
+        merged.assert_verifies()?;
+        assert_eq!(merged.current().revision, laptop.current().revision);
+        assert!(
+            repo.graph_descendant_of(*merged.current().content_id, *desktop.current().content_id)?
+        );
+
+        Ok(())
+    }
+}
+
 #[test]
 fn revoke_a_deux() -> anyhow::Result<()> {
     let repo = repo()?;
@@ -114,3 +237,241 @@ fn revoke_a_trois() -> anyhow::Result<()> {
         desktop.assert_verifies()
     }
 }
+
+#[test]
+fn quorum_policy() -> anyhow::Result<()> {
+    let repo = repo()?;
+    {
+        // Any one device is enough
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?.update_quorum(
+            Some(
+                vec![DESKTOP.public(), LAPTOP.public(), PALMTOP.public()]
+                    .into_iter()
+                    .collect(),
+            ),
+            Some(quorum::Policy::MinVotes(1)),
+        )?;
+        desktop.assert_verifies()?;
+
+        // So laptop can kick out palmtop unilaterally
+        let laptop = Device::create_from(&*LAPTOP, &desktop)?.update(Some(
+            vec![DESKTOP.public(), LAPTOP.public()]
+                .into_iter()
+                .collect(),
+        ))?;
+        laptop.assert_verifies()?;
+
+        // Back to majority, which now requires both
+        laptop.update_quorum(None, None)?.assert_no_quorum()
+    }
+}
+
+#[test]
+fn quorum_policy_invalid() -> anyhow::Result<()> {
+    let repo = repo()?;
+    {
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?;
+        assert_matches!(
+            desktop
+                .update_quorum(None, Some(quorum::Policy::MinVotes(2)))
+                .map(|_| ())
+                .map_err(|e| e.downcast::<error::Store>()),
+            Err(Ok(error::Store::Quorum(
+                quorum::error::Invalid::Unsatisfiable { .. }
+            )))
+        );
+
+        Ok(())
+    }
+}
+
+#[test]
+fn expiry() -> anyhow::Result<()> {
+    let repo = repo()?;
+    {
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?
+            .update_quorum(
+                Some(
+                    vec![DESKTOP.public(), LAPTOP.public()]
+                        .into_iter()
+                        .collect(),
+                ),
+                Some(quorum::Policy::MinVotes(1)),
+            )?
+            .update_expiry(Some((LAPTOP.public(), 0)).into_iter().collect())?;
+        desktop.assert_verifies()?;
+
+        // Laptop's delegation has expired, so it can't update on its own
+        let laptop = Device::create_from(&*LAPTOP, &desktop)?
+            .update(Some(Some(LAPTOP.public()).into_iter().collect()))?;
+        laptop.assert_no_quorum()?;
+
+        // Only delegates can expire
+        assert_matches!(
+            desktop
+                .update_expiry(Some((PALMTOP.public(), 0)).into_iter().collect())
+                .map(|_| ())
+                .map_err(|e| e.downcast::<error::Store>()),
+            Err(Ok(error::Store::NotADelegate(key))) if key == PALMTOP.public()
+        );
+
+        Ok(())
+    }
+}
+
+#[test]
+fn rotate() -> anyhow::Result<()> {
+    let repo = repo()?;
+    {
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?;
+        let rotated = desktop.clone().rotate(&*LAPTOP)?;
+        rotated.assert_verifies()?;
+
+        let revocation = &rotated.current().doc.revocations[&DESKTOP.public()];
+        assert_eq!(revocation.after, desktop.current().revision);
+        assert_eq!(revocation.replaced_by, Some(LAPTOP.public()));
+
+        // The revocation sticks
+        let laptop = rotated.update_quorum(None, Some(quorum::Policy::MinVotes(1)))?;
+        laptop.assert_verifies()?;
+        assert!(laptop
+            .current()
+            .doc
+            .revocations
+            .contains_key(&DESKTOP.public()));
+
+        // Desktop can't sign anymore
+        let desktop = Device::create_from(&*DESKTOP, &laptop)?;
+        assert_eq!(desktop.verify()?.content_id, laptop.current().content_id);
+
+        // And can't be delegated to, either
+        assert_matches!(
+            laptop
+                .update(Some(
+                    vec![LAPTOP.public(), DESKTOP.public()]
+                        .into_iter()
+                        .collect()
+                ))
+                .map(|_| ())
+                .map_err(|e| e.downcast::<error::Store>()),
+            Err(Ok(error::Store::RevokedDelegation(_)))
+        );
+
+        Ok(())
+    }
+}
+
+#[test]
+fn revoke() -> anyhow::Result<()> {
+    let repo = repo()?;
+    {
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?.update(Some(
+            vec![DESKTOP.public(), LAPTOP.public(), PALMTOP.public()]
+                .into_iter()
+                .collect(),
+        ))?;
+        let laptop = Device::create_from(&*LAPTOP, &desktop)?;
+        laptop.assert_verifies()?;
+
+        // Palmtop got stolen
+        let laptop_revokes_palmtop = laptop.clone().revoke(PALMTOP.public(), None)?;
+        let desktop = desktop
+            .update_from(&laptop)?
+            .update_from(&laptop_revokes_palmtop)?;
+        desktop.assert_verifies()?;
+        assert!(!desktop.current().delegations().contains(&PALMTOP.public()));
+
+        assert_matches!(
+            desktop
+                .revoke(PALMTOP.public(), None)
+                .map(|_| ())
+                .map_err(|e| e.downcast::<error::Store>()),
+            Err(Ok(error::Store::AlreadyRevoked(_)))
+        );
+
+        Ok(())
+    }
+}
+
+#[test]
+fn invalid_extension() -> anyhow::Result<()> {
+    let repo = repo()?;
+    {
+        let git = Identities::<Person>::from(&*repo);
+        let payload = payload::PersonPayload::new(payload::Person {
+            name: "dylan".into(),
+        })
+        .with_ext(payload::ext::Largefiles {
+            url: "ipfs://{SHA256_CID".into(),
+        })?;
+        let delegations = Some(DESKTOP.public())
+            .into_iter()
+            .collect::<delegation::Direct>();
+
+        assert_matches!(
+            git.create(payload.clone(), delegations.clone(), &*DESKTOP),
+            Err(error::Store::Extension(_))
+        );
+
+        // Not validated if the registry doesn't know about the extension
+        let registry = payload::ext::Registry::empty();
+        let person = Identities::<Person>::from(&*repo)
+            .with_registry(&registry)
+            .create(payload, delegations, &*DESKTOP)?;
+        assert_matches!(
+            git.verify(*person.content_id),
+            Err(error::VerifyPerson::Verification(generic::error::Verify::Payload(_)))
+        );
+
+        Ok(())
+    }
+}
+
+#[test]
+fn invalid_extension_update() -> anyhow::Result<()> {
+    let repo = repo()?;
+    {
+        let git = Identities::<Person>::from(&*repo);
+        let payload = payload::PersonPayload::new(payload::Person {
+            name: "dylan".into(),
+        });
+        let valid = git.create(
+            payload.clone(),
+            Some(DESKTOP.public()).into_iter().collect(),
+            &*DESKTOP,
+        )?;
+
+        // Sneak in an invalid extension
+        let registry = payload::ext::Registry::empty();
+        let invalid = Identities::<Person>::from(&*repo)
+            .with_registry(&registry)
+            .update(
+                Verifying::from(valid.clone()).signed()?,
+                payload.with_ext(payload::ext::Largefiles {
+                    url: "ipfs://{SHA256_CID".into(),
+                })?,
+                None,
+                &*DESKTOP,
+            )?;
+
+        // Verification stops at the last valid revision
+        assert_eq!(
+            git.verify(*invalid.content_id)?.content_id,
+            valid.content_id
+        );
+
+        let history = git.history(*invalid.content_id)?;
+        assert_eq!(history.head, Some(valid.content_id));
+        assert_matches!(
+            history
+                .entries
+                .iter()
+                .map(|entry| entry.status.clone())
+                .collect::<Vec<_>>()
+                .as_slice(),
+            [history::Status::Verified, history::Status::Rejected { .. }]
+        );
+
+        Ok(())
+    }
+}
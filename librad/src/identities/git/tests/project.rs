@@ -5,8 +5,8 @@
 
 use std::collections::BTreeMap;
 
-use super::*;
-use crate::keys::SecretKey;
+use super::{common::lookup, *};
+use crate::{git::storage::backend::Memory, keys::SecretKey};
 
 lazy_static! {
     static ref CHEYENNE_DESKTOP: SecretKey = SecretKey::from_seed([
@@ -42,6 +42,26 @@ fn create() -> anyhow::Result<()> {
     }
 }
 
+#[test]
+fn load_from_memory() -> anyhow::Result<()> {
+    let repo = common::repo()?;
+    {
+        let dylan = common::Device::new(&*DYLAN, Identities::from(&*repo))?;
+        let project = common::Project::new(dylan)?;
+        let project = project.current();
+
+        let memory = Memory::new();
+        let head = common::copy_to_memory(&*repo, &memory, *project.content_id)?;
+        let loaded = Project::from_backend(&memory, head)?;
+        assert_eq!(loaded.revision, project.revision);
+        assert_eq!(loaded.root, project.root);
+        assert_eq!(loaded.doc, project.doc);
+        assert_eq!(loaded.signatures, project.signatures);
+
+        Ok(())
+    }
+}
+
 #[test]
 fn update() -> anyhow::Result<()> {
     let repo = common::repo()?;
@@ -54,8 +74,8 @@ fn update() -> anyhow::Result<()> {
         // Cheyenne's view
         let project = {
             let update = IndirectDelegation::try_from_iter(vec![
-                Right(cheyenne.current().clone()),
-                Right(dylan.current().clone()),
+                Right(Delegate::from(cheyenne.current().clone())),
+                Right(Delegate::from(dylan.current().clone())),
             ])?;
             common::Project::new(cheyenne)?.update(update)
         }?;
@@ -89,8 +109,8 @@ fn revoke() -> anyhow::Result<()> {
 
         let cheyennes = {
             let update = IndirectDelegation::try_from_iter(vec![
-                Right(cheyenne.current().clone()),
-                Right(dylan.current().clone()),
+                Right(Delegate::from(cheyenne.current().clone())),
+                Right(Delegate::from(dylan.current().clone())),
             ])?;
             common::Project::new(cheyenne.clone())?.update(update)
         }?;
@@ -105,7 +125,7 @@ fn revoke() -> anyhow::Result<()> {
             cheyennes
                 .update_from(&dylans)?
                 .update(IndirectDelegation::try_from_iter(Some(Right(
-                    cheyenne.current().clone(),
+                    Delegate::from(cheyenne.current().clone()),
                 )))?)?;
         assert_matches!(
             cheyennes.verify(lookup(&heads)),
@@ -155,8 +175,8 @@ fn revoke_indirect() -> anyhow::Result<()> {
 
         let cheyenne_project = {
             let update = IndirectDelegation::try_from_iter(vec![
-                Right(cheyenne_desktop.current().clone()),
-                Right(dylan.current().clone()),
+                Right(Delegate::from(cheyenne_desktop.current().clone())),
+                Right(Delegate::from(dylan.current().clone())),
             ])?;
             common::Project::new(cheyenne_desktop.clone())?.update(update)
         }?;
@@ -192,6 +212,61 @@ fn revoke_indirect() -> anyhow::Result<()> {
     }
 }
 
+#[test]
+fn history() -> anyhow::Result<()> {
+    let repo = common::repo()?;
+    {
+        let cheyenne_desktop = common::Device::new(&*CHEYENNE_DESKTOP, Identities::from(&*repo))?
+            .update(Some(
+            vec![CHEYENNE_DESKTOP.public(), CHEYENNE_LAPTOP.public()]
+                .into_iter()
+                .collect(),
+        ))?;
+        let cheyenne_laptop = common::Device::create_from(&*CHEYENNE_LAPTOP, &cheyenne_desktop)?;
+        let cheyenne_desktop = cheyenne_desktop.update_from(&cheyenne_laptop)?;
+
+        let project = common::Project::new(cheyenne_desktop.clone())?.change_description()?;
+
+        let heads = current_heads_from(vec![&cheyenne_desktop]);
+        let history = project.history(lookup(&heads))?;
+        assert_eq!(history.head, Some(project.current().content_id));
+        assert_eq!(
+            history
+                .entries
+                .iter()
+                .map(|entry| entry.status.clone())
+                .collect::<Vec<_>>(),
+            vec![history::Status::Verified, history::Status::Verified]
+        );
+        assert_eq!(
+            history.entries[1].payload.keys().collect::<Vec<_>>(),
+            vec!["https://radicle.xyz/link/identities/project/v1"]
+        );
+
+        // Swap lap with palm, which doesn't check out
+        let cheyenne_desktop = cheyenne_desktop.update(Some(
+            vec![CHEYENNE_DESKTOP.public(), CHEYENNE_PALMTOP.public()]
+                .into_iter()
+                .collect(),
+        ))?;
+        let cheyenne_palmtop = common::Device::create_from(&*CHEYENNE_PALMTOP, &cheyenne_desktop)?;
+
+        // The revisions are unchanged, but the latest head of the delegation
+        // now invalidates the project
+        let heads = current_heads_from(vec![&cheyenne_palmtop]);
+        let history = project.history(lookup(&heads))?;
+        assert!(!history.is_valid());
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(history.entries[0].status, history::Status::Verified);
+        assert_matches!(
+            history.entries[1].status,
+            history::Status::Invalid { .. }
+        );
+
+        Ok(())
+    }
+}
+
 #[test]
 fn double_vote() -> anyhow::Result<()> {
     let repo = common::repo()?;
@@ -223,8 +298,8 @@ fn double_vote() -> anyhow::Result<()> {
 
         let cheyenne_project = {
             let update = IndirectDelegation::try_from_iter(vec![
-                Right(cheyenne_desktop.current().clone()),
-                Right(dylan.current().clone()),
+                Right(Delegate::from(cheyenne_desktop.current().clone())),
+                Right(Delegate::from(dylan.current().clone())),
             ])?;
             common::Project::new(cheyenne_desktop.clone())?.update(update)
         }?;
@@ -234,7 +309,9 @@ fn double_vote() -> anyhow::Result<()> {
         dylan_project.assert_verifies(lookup(&heads))?;
 
         let cheyenne_project = cheyenne_project.update_from(&dylan_project)?.update(
-            IndirectDelegation::try_from_iter(vec![Right(cheyenne_desktop.current().clone())])?,
+            IndirectDelegation::try_from_iter(vec![Right(Delegate::from(
+                cheyenne_desktop.current().clone(),
+            ))])?,
         )?;
         // That doesn't pass parent-quorum
         assert_matches!(
@@ -288,8 +365,8 @@ fn fork() -> anyhow::Result<()> {
 
         let cheyenne_project = {
             let update = IndirectDelegation::try_from_iter(vec![
-                Right(cheyenne.current().clone()),
-                Right(dylan.current().clone()),
+                Right(Delegate::from(cheyenne.current().clone())),
+                Right(Delegate::from(dylan.current().clone())),
             ])?;
             common::Project::new(cheyenne.clone())?.update(update)
         }?;
@@ -303,8 +380,8 @@ fn fork() -> anyhow::Result<()> {
         // Same project, different day
         let cheyenne_other = {
             let update = IndirectDelegation::try_from_iter(vec![
-                Right(cheyenne.current().clone()),
-                Right(dylan.current().clone()),
+                Right(Delegate::from(cheyenne.current().clone())),
+                Right(Delegate::from(dylan.current().clone())),
             ])?;
             common::Project::new(cheyenne.clone())?.update(update)
         }?;
@@ -326,7 +403,3 @@ fn current_heads_from<'a>(
         })
         .collect()
 }
-
-fn lookup(map: &BTreeMap<Urn, git2::Oid>) -> impl Fn(Urn) -> Result<git2::Oid, !> + '_ {
-    move |urn| Ok(*map.get(&urn).unwrap())
-}
@@ -29,6 +29,8 @@ use super::{
 #[cfg(test)]
 use proptest::prelude::*;
 
+pub mod ext;
+
 lazy_static! {
     /// Base [`Url`] for [`Person`]
     static ref PERSON_NAMESPACE_BASE: Url =
@@ -51,6 +53,17 @@ lazy_static! {
         base.path_segments_mut().unwrap().extend(&["v1"]);
         base
     };
+
+    /// Base [`Url`] for [`Org`]
+    static ref ORG_NAMESPACE_BASE: Url =
+        Url::parse("https://radicle.xyz/link/identities/org").unwrap();
+
+    /// Versioned [`Url`] for [`Org`], version 1
+    static ref ORG_NAMESPACE_V1: Url = {
+        let mut base = ORG_NAMESPACE_BASE.clone();
+        base.path_segments_mut().unwrap().extend(&["v1"]);
+        base
+    };
 }
 
 /// Structure `radicle-link` expects to be part of a [`Payload`] describing a
@@ -113,6 +126,25 @@ impl Arbitrary for Project {
     }
 }
 
+/// Structure `radicle-link` expects to be part of a [`Payload`] describing an
+/// organisation identity.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Org {
+    pub name: Cstring,
+}
+
+impl sealed::Sealed for Org {}
+
+#[cfg(test)]
+impl Arbitrary for Org {
+    type Parameters = ();
+    type Strategy = prop::strategy::Map<<Cstring as Arbitrary>::Strategy, fn(Cstring) -> Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        any::<Cstring>().prop_map(|name| Org { name })
+    }
+}
+
 /// Namespace attached to a member type of the [`Payload`] "open" coproduct.
 ///
 /// This is morally a constant -- we cannot, however, construct a [`Url`] in
@@ -154,6 +186,12 @@ impl HasNamespace for Project {
     }
 }
 
+impl HasNamespace for Org {
+    fn namespace() -> &'static Url {
+        &ORG_NAMESPACE_V1
+    }
+}
+
 /// Internal trait which helps deal with future versions
 pub trait Subject: HasNamespace + sealed::Sealed {
     fn namespace_matches(url: &Url) -> bool;
@@ -171,8 +209,15 @@ impl Subject for Project {
     }
 }
 
+impl Subject for Org {
+    fn namespace_matches(url: &Url) -> bool {
+        url.as_str().starts_with(ORG_NAMESPACE_BASE.as_str())
+    }
+}
+
 pub type PersonPayload = Payload<Person>;
 pub type ProjectPayload = Payload<Project>;
+pub type OrgPayload = Payload<Org>;
 
 /// [`Payload`] for which the type is not known statically.
 #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -181,6 +226,81 @@ pub type ProjectPayload = Payload<Project>;
 pub enum SomePayload {
     Person(PersonPayload),
     Project(ProjectPayload),
+    Org(OrgPayload),
+}
+
+impl SomePayload {
+    /// Get the extension `U`, regardless of the type of the payload.
+    pub fn get_ext<U>(&self) -> Result<Option<U>, serde_json::Error>
+    where
+        U: HasNamespace + serde::de::DeserializeOwned,
+    {
+        self.ext()
+            .get(U::namespace())
+            .map(|val| serde_json::from_value(val.clone()))
+            .transpose()
+    }
+
+    pub fn query_ext<R>(&self, range: R) -> impl Iterator<Item = (&Url, &serde_json::Value)>
+    where
+        R: RangeBounds<Url>,
+    {
+        self.ext().range(range)
+    }
+
+    fn ext(&self) -> &BTreeMap<Url, serde_json::Value> {
+        match self {
+            Self::Person(person) => &person.ext,
+            Self::Project(project) => &project.ext,
+            Self::Org(org) => &org.ext,
+        }
+    }
+}
+
+/// [`Payload`] of an identity which can be the target of an indirect
+/// delegation, see [`delegation::Indirect`].
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+#[non_exhaustive]
+pub enum DelegatePayload {
+    Person(PersonPayload),
+    Org(OrgPayload),
+}
+
+impl DelegatePayload {
+    pub fn person(&self) -> Option<&PersonPayload> {
+        match self {
+            Self::Person(person) => Some(person),
+            _ => None,
+        }
+    }
+
+    pub fn org(&self) -> Option<&OrgPayload> {
+        match self {
+            Self::Org(org) => Some(org),
+            _ => None,
+        }
+    }
+
+    /// The name of the person or org.
+    pub fn name(&self) -> &Cstring {
+        match self {
+            Self::Person(person) => &person.subject.name,
+            Self::Org(org) => &org.subject.name,
+        }
+    }
+}
+
+impl From<PersonPayload> for DelegatePayload {
+    fn from(person: PersonPayload) -> Self {
+        Self::Person(person)
+    }
+}
+
+impl From<OrgPayload> for DelegatePayload {
+    fn from(org: OrgPayload) -> Self {
+        Self::Org(org)
+    }
 }
 
 /// Payload of an identity document.
@@ -188,7 +308,8 @@ pub enum SomePayload {
 /// This type is a simple formulation of an "open sum", where the type of one
 /// member is known. Additional members (extensions) are represented as
 /// [`serde_json::Value`]s. Every member is namespaced by a [`Url`], as
-/// described by its [`HasNamespace`] impl.
+/// described by its [`HasNamespace`] impl. Extensions with a known namespace
+/// are validated against an [`ext::Registry`].
 ///
 /// Note that it is an error during deserialisation if duplicate namespaces are
 /// found in the input -- this is unlike normal JSON deserialisation, which
@@ -667,10 +788,15 @@ mod tests {
             })
     }
 
+    fn gen_org_payload() -> impl Strategy<Value = OrgPayload> {
+        any::<Org>().prop_map(OrgPayload::new)
+    }
+
     fn gen_payload() -> impl Strategy<Value = SomePayload> {
         prop_oneof![
             gen_person_payload().prop_map(SomePayload::Person),
-            gen_project_payload().prop_map(SomePayload::Project)
+            gen_project_payload().prop_map(SomePayload::Project),
+            gen_org_payload().prop_map(SomePayload::Org)
         ]
     }
 
@@ -754,6 +880,28 @@ nom is a parser combinators library written in Rust.";
         cjson_roundtrip(payload)
     }
 
+    #[test]
+    fn org_example() {
+        let payload = OrgPayload::new(Org {
+            name: "radicle".into(),
+        });
+
+        let json_pretty = r#"{
+  "https://radicle.xyz/link/identities/org/v1": {
+    "name": "radicle"
+  }
+}"#;
+
+        assert_eq!(
+            serde_json::to_string_pretty(&payload).unwrap(),
+            json_pretty.to_owned()
+        );
+        assert!(matches!(
+            serde_json::from_str::<DelegatePayload>(json_pretty),
+            Ok(DelegatePayload::Org(org)) if org == payload
+        ))
+    }
+
     fn duplicate_delegation<T>()
     where
         T: serde::Serialize + serde::de::DeserializeOwned,
@@ -0,0 +1,485 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Extensions of a [`Payload`], and validation thereof.
+//!
+//! A [`Payload`] may carry arbitrary extensions, namespaced by a [`Url`]. A
+//! [`Registry`] knows about a set of extension namespaces, and how to validate
+//! the values found under them. Values under namespaces the [`Registry`] does
+//! not know about are not validated.
+//!
+//! The [`Default`] [`Registry`] knows about the contributor metadata described
+//! in the spec: [`Profile`], [`Largefiles`], and [`AlternateUrls`].
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use thiserror::Error;
+use url::Url;
+
+use crate::internal::canonical::Cstring;
+
+use super::{HasNamespace, Payload, SomePayload, Subject};
+
+lazy_static! {
+    /// The [`Default`] [`Registry`].
+    pub static ref DEFAULT_REGISTRY: Registry = Registry::default();
+
+    /// Versioned [`Url`] for [`Profile`], version 1
+    static ref PROFILE_NAMESPACE_V1: Url =
+        Url::parse("https://radicle.xyz/link/identities/ext/profile/v1").unwrap();
+
+    /// Versioned [`Url`] for [`Largefiles`], version 1
+    static ref LARGEFILES_NAMESPACE_V1: Url =
+        Url::parse("https://radicle.xyz/link/identities/ext/largefiles/v1").unwrap();
+
+    /// Versioned [`Url`] for [`AlternateUrls`], version 1
+    static ref ALTERNATE_URLS_NAMESPACE_V1: Url =
+        Url::parse("https://radicle.xyz/link/identities/ext/alternate-urls/v1").unwrap();
+}
+
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct Invalid(pub String);
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ValidationError {
+    #[error("extension `{namespace}` does not match its schema")]
+    Schema {
+        namespace: Url,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("invalid extension `{namespace}`")]
+    Invalid {
+        namespace: Url,
+        #[source]
+        source: Invalid,
+    },
+}
+
+/// A [`Payload`] extension known to a [`Registry`].
+pub trait Extension: HasNamespace + serde::de::DeserializeOwned {
+    /// Validate constraints which are not expressed by the type itself.
+    fn validate(&self) -> Result<(), Invalid> {
+        Ok(())
+    }
+}
+
+type Validator = fn(&serde_json::Value) -> Result<(), Failure>;
+
+/// Either the value does not deserialise, or it is [`Invalid`].
+enum Failure {
+    Schema(serde_json::Error),
+    Invalid(Invalid),
+}
+
+fn validate_as<U: Extension>(val: &serde_json::Value) -> Result<(), Failure> {
+    let ext = U::deserialize(val).map_err(Failure::Schema)?;
+    ext.validate().map_err(Failure::Invalid)
+}
+
+/// A set of known extension namespaces.
+#[derive(Clone)]
+pub struct Registry {
+    known: BTreeMap<Url, Validator>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register::<Profile>()
+            .register::<Largefiles>()
+            .register::<AlternateUrls>();
+        registry
+    }
+}
+
+impl Registry {
+    /// A [`Registry`] which doesn't know about any extensions.
+    pub fn empty() -> Self {
+        Self {
+            known: BTreeMap::new(),
+        }
+    }
+
+    /// Register the extension `U`, replacing any previous registration of its
+    /// namespace.
+    pub fn register<U: Extension>(&mut self) -> &mut Self {
+        self.known.insert(U::namespace().clone(), validate_as::<U>);
+        self
+    }
+
+    pub fn is_known(&self, namespace: &Url) -> bool {
+        self.known.contains_key(namespace)
+    }
+
+    pub fn namespaces(&self) -> impl Iterator<Item = &Url> {
+        self.known.keys()
+    }
+
+    /// Validate all extensions of `payload` whose namespace is known.
+    pub fn validate<T>(&self, payload: &Payload<T>) -> Result<(), ValidationError>
+    where
+        T: Subject,
+    {
+        self.validate_ext(payload.query_ext(..))
+    }
+
+    /// Like [`Self::validate`], for a payload whose type is not known
+    /// statically.
+    pub fn validate_some(&self, payload: &SomePayload) -> Result<(), ValidationError> {
+        self.validate_ext(payload.query_ext(..))
+    }
+
+    fn validate_ext<'a>(
+        &self,
+        ext: impl Iterator<Item = (&'a Url, &'a serde_json::Value)>,
+    ) -> Result<(), ValidationError> {
+        for (namespace, val) in ext {
+            if let Some(validate) = self.known.get(namespace) {
+                validate(val).map_err(|e| match e {
+                    Failure::Schema(source) => ValidationError::Schema {
+                        namespace: namespace.clone(),
+                        source,
+                    },
+                    Failure::Invalid(source) => ValidationError::Invalid {
+                        namespace: namespace.clone(),
+                        source,
+                    },
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Profile information of a contributor, see `user_profile` in the spec.
+///
+/// The `geo` field of the spec is not supported, as floating point numbers
+/// can not be represented in canonical JSON.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    pub nickname: Cstring,
+    /// Path or http(s) URL of an image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub img: Option<Cstring>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<Cstring>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bio: Option<Cstring>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<Cstring>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub urls: BTreeMap<Cstring, Url>,
+}
+
+impl HasNamespace for Profile {
+    fn namespace() -> &'static Url {
+        &PROFILE_NAMESPACE_V1
+    }
+}
+
+impl Extension for Profile {
+    fn validate(&self) -> Result<(), Invalid> {
+        max_len("nickname", &self.nickname, 32)?;
+        if let Some(img) = &self.img {
+            if let Ok(url) = Url::parse(img) {
+                http_url("img", &url)?;
+            }
+        }
+        if let Some(full_name) = &self.full_name {
+            max_len("full-name", full_name, 64)?;
+        }
+        if let Some(bio) = &self.bio {
+            max_len("bio", bio, 255)?;
+        }
+        if let Some(email) = &self.email {
+            max_len("email", email, 320)?;
+            match email.rsplitn(2, '@').collect::<Vec<_>>().as_slice() {
+                [domain, local]
+                    if !local.is_empty()
+                        && !domain.is_empty()
+                        && local.len() <= 64
+                        && domain.len() <= 255 => {},
+                _ => return Err(Invalid(format!("malformed email `{}`", email))),
+            }
+        }
+        if self.urls.len() > 5 {
+            return Err(Invalid("at most 5 urls are allowed".to_owned()));
+        }
+        for name in self.urls.keys() {
+            max_len("urls", name, 16)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Endpoint to fetch large files from, see "Large Objects" in the spec.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Largefiles {
+    /// An [RFC 6570] URL template.
+    ///
+    /// [RFC 6570]: https://tools.ietf.org/html/rfc6570
+    pub url: Cstring,
+}
+
+impl HasNamespace for Largefiles {
+    fn namespace() -> &'static Url {
+        &LARGEFILES_NAMESPACE_V1
+    }
+}
+
+impl Extension for Largefiles {
+    fn validate(&self) -> Result<(), Invalid> {
+        let malformed = || Invalid(format!("malformed URL template `{}`", self.url));
+
+        // Replace all template expressions by a placeholder, and require the
+        // result to be a valid URL
+        let mut expanded = String::with_capacity(self.url.len());
+        let mut in_expr = false;
+        for c in self.url.chars() {
+            match c {
+                '{' if !in_expr => in_expr = true,
+                '}' if in_expr => {
+                    in_expr = false;
+                    expanded.push('x')
+                },
+                '{' | '}' => return Err(malformed()),
+                c if !in_expr => expanded.push(c),
+                _ => {},
+            }
+        }
+        if in_expr {
+            return Err(malformed());
+        }
+
+        Url::parse(&expanded)
+            .map(|_| ())
+            .map_err(|e| Invalid(format!("malformed URL template `{}`: {}", self.url, e)))
+    }
+}
+
+/// URLs under which the identity's repository is also available.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlternateUrls {
+    pub urls: BTreeSet<Url>,
+}
+
+impl HasNamespace for AlternateUrls {
+    fn namespace() -> &'static Url {
+        &ALTERNATE_URLS_NAMESPACE_V1
+    }
+}
+
+impl Extension for AlternateUrls {
+    fn validate(&self) -> Result<(), Invalid> {
+        if self.urls.is_empty() {
+            return Err(Invalid("no alternate urls".to_owned()));
+        }
+
+        Ok(())
+    }
+}
+
+fn max_len(field: &str, val: &str, max: usize) -> Result<(), Invalid> {
+    if val.len() > max {
+        Err(Invalid(format!(
+            "`{}` exceeds the maximum length of {} bytes",
+            field, max
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn http_url(field: &str, url: &Url) -> Result<(), Invalid> {
+    match url.scheme() {
+        "http" | "https" => Ok(()),
+        scheme => Err(Invalid(format!(
+            "`{}` must be an http(s) URL, not `{}`",
+            field, scheme
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    use crate::identities::payload::{Person, PersonPayload};
+
+    fn profile() -> Profile {
+        Profile {
+            nickname: "cloudhead".into(),
+            img: Some("https://radicle.xyz/cloudhead.png".into()),
+            full_name: None,
+            bio: None,
+            email: Some("cloudhead@radicle.xyz".into()),
+            urls: vec![("home".into(), Url::parse("https://cloudhead.io").unwrap())]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn payload() -> PersonPayload {
+        PersonPayload::new(Person {
+            name: "cloudhead".into(),
+        })
+    }
+
+    #[test]
+    fn valid_profile() {
+        assert!(profile().validate().is_ok())
+    }
+
+    #[test]
+    fn invalid_profile() {
+        let too_long = Profile {
+            nickname: "x".repeat(33).into(),
+            ..profile()
+        };
+        assert!(too_long.validate().is_err());
+
+        let bad_email = Profile {
+            email: Some("cloudhead".into()),
+            ..profile()
+        };
+        assert!(bad_email.validate().is_err());
+
+        let bad_img = Profile {
+            img: Some("ftp://radicle.xyz/cloudhead.png".into()),
+            ..profile()
+        };
+        assert!(bad_img.validate().is_err());
+
+        let path_img = Profile {
+            img: Some("img/cloudhead.png".into()),
+            ..profile()
+        };
+        assert!(path_img.validate().is_ok());
+    }
+
+    #[test]
+    fn largefiles_templates() {
+        for url in &[
+            "https://github.com/me/rad-mirror.git/info/lfs/objects/batch",
+            "ipfs://{SHA256_CID}",
+            "dat://778f8d955175c92e4ced5e4f5563f69bfec0c86cc6f670352c457943666fe639/{SHA256}",
+        ] {
+            assert!(
+                Largefiles { url: (*url).into() }.validate().is_ok(),
+                "{} should be valid",
+                url
+            )
+        }
+
+        for url in &["ipfs://{SHA256_CID", "ipfs://SHA256_CID}", "{SHA256}"] {
+            assert!(
+                Largefiles { url: (*url).into() }.validate().is_err(),
+                "{} should be invalid",
+                url
+            )
+        }
+    }
+
+    #[test]
+    fn registry_validates_known() {
+        let registry = Registry::default();
+
+        let valid = payload().with_ext(profile()).unwrap();
+        assert!(registry.validate(&valid).is_ok());
+
+        let invalid = payload()
+            .with_ext(Profile {
+                nickname: "x".repeat(33).into(),
+                ..profile()
+            })
+            .unwrap();
+        assert_matches!(
+            registry.validate(&invalid),
+            Err(ValidationError::Invalid { namespace, .. }) if &namespace == Profile::namespace()
+        );
+        assert!(Registry::empty().validate(&invalid).is_ok());
+    }
+
+    #[test]
+    fn some_payload_ext() {
+        let json = serde_json::to_value(payload().with_ext(profile()).unwrap()).unwrap();
+        let some: SomePayload = serde_json::from_value(json).unwrap();
+        assert_matches!(some, SomePayload::Person(_));
+        assert_eq!(some.get_ext::<Profile>().unwrap(), Some(profile()));
+        assert_eq!(some.get_ext::<Largefiles>().unwrap(), None);
+        assert!(Registry::default().validate_some(&some).is_ok());
+
+        let invalid = payload()
+            .with_ext(AlternateUrls {
+                urls: BTreeSet::new(),
+            })
+            .unwrap();
+        assert_matches!(
+            Registry::default().validate_some(&SomePayload::Person(invalid)),
+            Err(ValidationError::Invalid { namespace, .. }) if &namespace == AlternateUrls::namespace()
+        );
+    }
+
+    #[test]
+    fn registry_rejects_schema_mismatch() {
+        #[derive(serde::Serialize)]
+        struct NotAProfile {
+            nick: String,
+        }
+
+        impl HasNamespace for NotAProfile {
+            fn namespace() -> &'static Url {
+                Profile::namespace()
+            }
+        }
+
+        let payload = payload()
+            .with_ext(NotAProfile {
+                nick: "cloudhead".to_owned(),
+            })
+            .unwrap();
+        assert_matches!(
+            Registry::default().validate(&payload),
+            Err(ValidationError::Schema { .. })
+        )
+    }
+
+    #[test]
+    fn registry_ignores_unknown() {
+        lazy_static! {
+            static ref UNKNOWN: Url = Url::parse("https://semantic.me/unknown/v1").unwrap();
+        }
+
+        struct Unknown;
+
+        impl HasNamespace for Unknown {
+            fn namespace() -> &'static Url {
+                &UNKNOWN
+            }
+        }
+
+        impl serde::Serialize for Unknown {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                json!({ "anything": "goes" }).serialize(serializer)
+            }
+        }
+
+        let payload = payload().with_ext(Unknown).unwrap();
+        assert!(!Registry::default().is_known(Unknown::namespace()));
+        assert!(Registry::default().validate(&payload).is_ok())
+    }
+}
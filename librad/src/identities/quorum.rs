@@ -0,0 +1,300 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Quorum policies for identity documents.
+//!
+//! By default, an identity update requires the signatures of a simple majority
+//! of the delegations (see
+//! [`super::delegation::Delegations::quorum_threshold`]). A [`Policy`] stored
+//! in the identity [`super::generic::Doc`] replaces this rule.
+//!
+//! Policies refer to the [`PublicKey`]s of the delegates. For indirect
+//! delegations, this means the keys of the delegated identity, which need to
+//! be updated when that identity rotates its keys.
+//!
+//! Note that peers which don't know about policies will continue to apply the
+//! majority rule.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::keys::PublicKey;
+
+pub mod error {
+    use thiserror::Error;
+
+    use super::PublicKey;
+
+    #[derive(Debug, Error, Eq, PartialEq)]
+    #[non_exhaustive]
+    pub enum Invalid {
+        #[error("the required number of votes must be at least one")]
+        Zero,
+
+        #[error("group `{0}` has no members")]
+        EmptyGroup(String),
+
+        #[error("policy has no groups")]
+        NoGroups,
+
+        #[error("policy can never be satisfied: {required} required, {available} available")]
+        Unsatisfiable { required: u64, available: u64 },
+
+        #[error("key `{0}` is not a delegate")]
+        NotADelegate(PublicKey),
+    }
+}
+
+/// A rule determining which sets of eligible votes form a quorum.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Policy {
+    /// At least this many votes are required.
+    ///
+    /// Nb. unlike [`super::delegation::Delegations::quorum_threshold`], which
+    /// must be exceeded, this is the minimum number of votes: `MinVotes(2)`
+    /// is satisfied by two votes.
+    MinVotes(usize),
+
+    /// The sum of the weights of the votes must be at least `min`.
+    ///
+    /// Keys which are not listed in `weights` have a weight of one.
+    Weighted {
+        weights: BTreeMap<PublicKey, u32>,
+        min: u64,
+    },
+
+    /// Every group must reach its own minimum number of votes.
+    Groups(BTreeMap<String, Group>),
+}
+
+/// A named set of delegates, of which at least `min` must vote.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    pub members: BTreeSet<PublicKey>,
+    pub min: usize,
+}
+
+impl Policy {
+    /// Whether the `votes` form a quorum under this policy.
+    ///
+    /// The `votes` are expected to be eligible, ie. already filtered by
+    /// [`super::delegation::Delegations::eligible`]. An empty set of votes is
+    /// never a quorum.
+    pub fn is_quorum(&self, votes: &BTreeSet<&PublicKey>) -> bool {
+        if votes.is_empty() {
+            return false;
+        }
+
+        match self {
+            Self::MinVotes(min) => votes.len() >= *min,
+            Self::Weighted { weights, min } => {
+                let weight: u64 = votes
+                    .iter()
+                    .map(|key| weights.get(*key).copied().unwrap_or(1) as u64)
+                    .sum();
+                weight >= *min
+            },
+            Self::Groups(groups) => groups.values().all(|group| {
+                group
+                    .members
+                    .iter()
+                    .filter(|key| votes.contains(key))
+                    .count()
+                    >= group.min
+            }),
+        }
+    }
+
+    /// Substitute `new` for `old` wherever the policy refers to `old`.
+    pub fn replace_key(&mut self, old: &PublicKey, new: PublicKey) {
+        match self {
+            Self::MinVotes(_) => {},
+            Self::Weighted { weights, .. } => {
+                if let Some(weight) = weights.remove(old) {
+                    weights.insert(new, weight);
+                }
+            },
+            Self::Groups(groups) => {
+                for group in groups.values_mut() {
+                    if group.members.remove(old) {
+                        group.members.insert(new);
+                    }
+                }
+            },
+        }
+    }
+
+    /// Check that this policy is well-formed, and can be satisfied by the
+    /// `delegates`.
+    ///
+    /// # Errors
+    ///
+    /// * If a required number of votes is zero
+    /// * If a key referred to by the policy is not in `delegates`
+    /// * If the votes required by the policy exceed what the `delegates` (or
+    ///   members of a group) could vote
+    pub fn validate<'a, I>(&self, delegates: I) -> Result<(), error::Invalid>
+    where
+        I: IntoIterator<Item = &'a PublicKey>,
+    {
+        use error::Invalid::*;
+
+        let delegates = delegates.into_iter().collect::<BTreeSet<_>>();
+        let check = |required: u64, available: u64| {
+            if required == 0 {
+                Err(Zero)
+            } else if required > available {
+                Err(Unsatisfiable {
+                    required,
+                    available,
+                })
+            } else {
+                Ok(())
+            }
+        };
+        let known = |key: &PublicKey| {
+            if delegates.contains(&key) {
+                Ok(())
+            } else {
+                Err(NotADelegate(*key))
+            }
+        };
+
+        match self {
+            Self::MinVotes(min) => check(*min as u64, delegates.len() as u64),
+            Self::Weighted { weights, min } => {
+                weights.keys().try_for_each(known)?;
+                let available = delegates
+                    .iter()
+                    .map(|key| weights.get(*key).copied().unwrap_or(1) as u64)
+                    .sum();
+                check(*min, available)
+            },
+            Self::Groups(groups) => {
+                if groups.is_empty() {
+                    return Err(NoGroups);
+                }
+                for (name, group) in groups {
+                    if group.members.is_empty() {
+                        return Err(EmptyGroup(name.clone()));
+                    }
+                    group.members.iter().try_for_each(known)?;
+                    check(group.min as u64, group.members.len() as u64)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::keys::SecretKey;
+
+    fn keys(n: usize) -> Vec<PublicKey> {
+        (0..n).map(|_| SecretKey::new().public()).collect()
+    }
+
+    #[test]
+    fn min_votes() {
+        let keys = keys(3);
+        let policy = Policy::MinVotes(2);
+
+        assert!(!policy.is_quorum(&keys[..1].iter().collect()));
+        assert!(policy.is_quorum(&keys[..2].iter().collect()));
+        assert_eq!(policy.validate(&keys), Ok(()));
+        assert_eq!(
+            Policy::MinVotes(4).validate(&keys),
+            Err(error::Invalid::Unsatisfiable {
+                required: 4,
+                available: 3
+            })
+        );
+        assert_eq!(
+            Policy::MinVotes(0).validate(&keys),
+            Err(error::Invalid::Zero)
+        );
+    }
+
+    #[test]
+    fn weighted() {
+        let keys = keys(3);
+        let policy = Policy::Weighted {
+            weights: Some((keys[0], 2)).into_iter().collect(),
+            min: 2,
+        };
+
+        assert!(policy.is_quorum(&keys[..1].iter().collect()));
+        assert!(!policy.is_quorum(&keys[1..2].iter().collect()));
+        assert!(policy.is_quorum(&keys[1..].iter().collect()));
+        assert_eq!(policy.validate(&keys), Ok(()));
+        assert_eq!(
+            policy.validate(&keys[1..]),
+            Err(error::Invalid::NotADelegate(keys[0]))
+        );
+    }
+
+    #[test]
+    fn groups() {
+        let keys = keys(5);
+        let policy = Policy::Groups(
+            vec![
+                (
+                    "core".to_owned(),
+                    Group {
+                        members: keys[..3].iter().copied().collect(),
+                        min: 2,
+                    },
+                ),
+                (
+                    "release".to_owned(),
+                    Group {
+                        members: keys[3..].iter().copied().collect(),
+                        min: 1,
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        assert!(!policy.is_quorum(&keys[..3].iter().collect()));
+        assert!(!policy.is_quorum(&keys[2..].iter().collect()));
+        assert!(policy.is_quorum(&vec![&keys[0], &keys[2], &keys[4]].into_iter().collect()));
+        assert_eq!(policy.validate(&keys), Ok(()));
+        assert_eq!(
+            Policy::Groups(BTreeMap::new()).validate(&keys),
+            Err(error::Invalid::NoGroups)
+        );
+    }
+
+    #[test]
+    fn no_votes() {
+        assert!(!Policy::MinVotes(0).is_quorum(&BTreeSet::new()))
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let keys = keys(2);
+        let policy = Policy::Weighted {
+            weights: keys.iter().map(|key| (*key, 3)).collect(),
+            min: 4,
+        };
+        let json = serde_json::to_value(&policy).unwrap();
+
+        assert!(json.get("weighted").is_some());
+        assert_eq!(serde_json::from_value::<Policy>(json).unwrap(), policy);
+
+        let policy = Policy::MinVotes(2);
+        let json = serde_json::to_value(&policy).unwrap();
+
+        assert_eq!(json.get("minVotes"), Some(&serde_json::json!(2)));
+        assert_eq!(serde_json::from_value::<Policy>(json).unwrap(), policy)
+    }
+}
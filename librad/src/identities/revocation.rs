@@ -0,0 +1,35 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Key revocation records.
+//!
+//! Removing a key from the delegations of an identity only means that it can
+//! no longer vote on future revisions. A [`Revocation`] stored in the identity
+//! [`super::generic::Doc`] additionally marks the key as compromised: once it
+//! is revoked, signatures made by it are rejected by verification, instead of
+//! merely not counting towards a quorum.
+//!
+//! The revision which introduces a revocation may still be signed by the
+//! revoked key, which allows a device to rotate its own key. All subsequent
+//! revisions carry the revocation forward, and may not be signed by it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::keys::PublicKey;
+
+/// A record of a revoked key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revocation<R> {
+    /// The last revision of the identity document in which the key was valid,
+    /// ie. the revision replaced by the one introducing this revocation.
+    pub after: R,
+    /// The key which replaces the revoked key, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaced_by: Option<PublicKey>,
+    /// Human-readable reason for the revocation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
@@ -3,12 +3,11 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{net::SocketAddr, panic, time::Duration};
+use std::{net::SocketAddr, time::Duration};
 
 use futures::{future, StreamExt as _, TryFutureExt as _, TryStreamExt as _};
 use futures_timer::Delay;
 use thiserror::Error;
-use tokio::task::spawn_blocking;
 
 use super::protocol::{self, gossip};
 use crate::{
@@ -36,7 +35,8 @@ pub struct Config<Signer> {
 #[derive(Clone, Copy)]
 pub struct PoolSizes {
     /// Number of [`git::storage::Storage`] instances to pool for [`Peer`]
-    /// consumers.
+    /// consumers. The same number of [`git::storage::ReadOnly`] instances is
+    /// pooled separately.
     ///
     /// Default: the number of physical cores available
     pub user: usize,
@@ -76,12 +76,24 @@ impl From<PoolError<git::storage::Error>> for StorageError {
     }
 }
 
+impl From<git::storage::pool::WithStorageError> for StorageError {
+    fn from(e: git::storage::pool::WithStorageError) -> Self {
+        use git::storage::pool::WithStorageError;
+
+        match e {
+            WithStorageError::Cancelled => Self::Cancelled,
+            WithStorageError::Pool(e) => Self::Pool(e),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Peer<S> {
     config: Config<S>,
     phone: protocol::TinCans,
     peer_store: PeerStorage,
     git_store: git::storage::Pool,
+    read_store: git::storage::ReadOnlyPool,
 }
 
 impl<S> Peer<S>
@@ -98,18 +110,23 @@ where
                 ),
                 config.storage_pools.protocol,
             ),
-            config.protocol.replication,
+            config.protocol.replication.clone(),
         );
         let git_store = git::storage::Pool::new(
             git::storage::pool::Config::new(config.protocol.paths.clone(), config.signer.clone()),
             config.storage_pools.user,
         );
+        let read_store = git::storage::ReadOnlyPool::new(
+            git::storage::pool::ReadOnlyConfig::new(config.protocol.paths.clone()),
+            config.storage_pools.user,
+        );
 
         Self {
             config,
             phone,
             peer_store,
             git_store,
+            read_store,
         }
     }
 
@@ -199,19 +216,27 @@ where
         F: FnOnce(&git::storage::Storage) -> A + Send + 'static,
         A: Send + 'static,
     {
-        let storage = self.git_store.get().await?;
-        match spawn_blocking(move || blocking(&storage)).await {
-            Ok(a) => Ok(a),
-            Err(e) => {
-                if e.is_cancelled() {
-                    Err(StorageError::Cancelled)
-                } else if e.is_panic() {
-                    panic::resume_unwind(e.into_panic())
-                } else {
-                    panic!("unknown error awaiting spawned blocking task: {:?}", e)
-                }
-            },
-        }
+        Ok(git::storage::pool::with_storage(&self.git_store, blocking).await?)
+    }
+
+    /// Like [`Peer::using_storage`], but with a [`git::storage::ReadOnly`]
+    /// handle.
+    ///
+    /// Read-only handles are pooled separately, so readers don't have to wait
+    /// for writers. Note that the storage must have been initialised before,
+    /// eg. by a prior call to [`Peer::using_storage`].
+    pub async fn using_read_only<F, A>(&self, blocking: F) -> Result<A, StorageError>
+    where
+        F: FnOnce(&git::storage::ReadOnly) -> A + Send + 'static,
+        A: Send + 'static,
+    {
+        Ok(git::storage::pool::with_storage(&self.read_store, blocking).await?)
+    }
+
+    /// Saturation metrics of the pool used by [`Peer::using_storage`] and
+    /// [`Peer::storage`].
+    pub fn storage_saturation(&self) -> git::storage::pool::Saturation {
+        git::storage::pool::Saturation::from(&self.git_store)
     }
 
     pub async fn storage(
@@ -238,15 +263,19 @@ impl<S> git::local::transport::CanOpenStorage for Peer<S>
 where
     S: Signer + Clone,
 {
+    /// Open a fresh, unpooled [`git::storage::Storage`].
+    ///
+    /// This is called from synchronous code, which may itself run on the
+    /// async executor. Waiting for a pooled handle could thus deadlock, so we
+    /// open the storage directly instead.
     fn open_storage(
         &self,
     ) -> Result<
         Box<dyn AsRef<git::storage::Storage>>,
         Box<dyn std::error::Error + Send + Sync + 'static>,
     > {
-        match futures::executor::block_on(self.storage()) {
-            Err(e) => Err(Box::new(e)),
-            Ok(s) => Ok(Box::new(s)),
-        }
+        let storage =
+            git::storage::Storage::open(&self.config.protocol.paths, self.config.signer.clone())?;
+        Ok(Box::new(storage))
     }
 }
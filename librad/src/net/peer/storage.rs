@@ -43,7 +43,7 @@ impl Storage {
         let urn = urn_context(*git.peer_id(), urn);
         let head = head.into().map(ext::Oid::from);
         let (remote_peer, addr_hints) = from.into();
-        let config = self.config;
+        let config = self.config.clone();
 
         spawn_blocking(move || {
             if let Some(head) = head {
@@ -85,11 +85,22 @@ impl Storage {
     async fn is_tracked(&self, urn: Urn, peer: PeerId) -> Result<bool, Error> {
         let git = self.inner.get().await?;
         Ok(
-            spawn_blocking(move || tracking::is_tracked(&git, &urn, peer))
+            spawn_blocking(move || tracking::is_tracked(&*git, &urn, peer))
                 .await
                 .expect("`Storage::is_tracked` panicked")?,
         )
     }
+
+    /// Start tracking `peer` in the context of `urn` if it is trusted, see
+    /// [`tracking::track_if_trusted`].
+    async fn track_if_trusted(&self, urn: Urn, peer: PeerId) -> Result<bool, Error> {
+        let git = self.inner.get().await?;
+        Ok(
+            spawn_blocking(move || tracking::track_if_trusted(&git, &urn, peer))
+                .await
+                .expect("`Storage::track_if_trusted` panicked")?,
+        )
+    }
 }
 
 /// If applicable, map the `path` of the given [`Urn`] to
@@ -144,6 +155,10 @@ impl broadcast::LocalStorage<SocketAddr> for Storage {
         // branch, assume we want the `provider`'s.
         let origin = has.origin.unwrap_or(provider);
         let is_tracked = match self.is_tracked(has.urn.clone(), origin).await {
+            Ok(false) => self.track_if_trusted(has.urn.clone(), origin).await,
+            x => x,
+        };
+        let is_tracked = match is_tracked {
             Ok(b) => b,
             Err(e) => {
                 tracing::error!(err = %e, "error determining tracking status");
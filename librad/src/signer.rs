@@ -5,13 +5,17 @@
 
 //!
 
-use std::error::Error;
+use std::{error::Error, panic};
 
 use futures::executor::block_on;
 use keystore::sign;
 
 use crate::{keys, peer::PeerId};
 
+pub mod external;
+#[cfg(unix)]
+pub mod ssh_agent;
+
 /// A blanket trait over [`sign::Signer`] that can be shared safely among
 /// threads.
 pub trait Signer:
@@ -33,6 +37,26 @@ where
 {
 }
 
+/// Run the blocking `f` on the blocking threadpool of the current `tokio`
+/// runtime, propagating panics.
+///
+/// If there is no runtime (eg. because the signer is driven by [`block_on`]),
+/// `f` is run in place.
+async fn unblock<F, A>(f: F) -> A
+where
+    F: FnOnce() -> A + Send + 'static,
+    A: Send + 'static,
+{
+    match tokio::runtime::Handle::try_current() {
+        Err(_) => f(),
+        Ok(handle) => match handle.spawn_blocking(f).await {
+            Ok(a) => a,
+            Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+            Err(e) => panic!("blocking signer task failed: {}", e),
+        },
+    }
+}
+
 // Here be Dragons...
 
 /// A boxed [`Error`] that is used as the associated `Error` type for
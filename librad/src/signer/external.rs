@@ -0,0 +1,206 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! [`Signer`] delegating to an external command.
+//!
+//! For every signature, the command is spawned with the data to sign written
+//! to its `stdin`. It is expected to write the raw 64 bytes of the ed25519
+//! signature to its `stdout`, and exit successfully. The signature is
+//! verified against the configured public key before it is returned.
+//!
+//! [`Signer`]: crate::signer::Signer
+
+use std::{
+    convert::TryFrom,
+    ffi::OsString,
+    io::{self, Write},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use keystore::sign;
+use thiserror::Error;
+
+use crate::keys::{PublicKey, Signature};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("failed to run signer command")]
+    Spawn(#[source] io::Error),
+
+    #[error("signer command exited with {status}: {stderr}")]
+    Exit { status: ExitStatus, stderr: String },
+
+    #[error("expected a signature of 64 bytes, got {0} bytes")]
+    Length(usize),
+
+    #[error("signer command produced an invalid signature")]
+    Verification,
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// A [`sign::Signer`] which runs `program` with `args` to sign data on behalf
+/// of `public_key`.
+#[derive(Clone)]
+pub struct External {
+    program: OsString,
+    args: Vec<OsString>,
+    public_key: PublicKey,
+}
+
+impl External {
+    pub fn new<P, A, S>(program: P, args: A, public_key: PublicKey) -> Self
+    where
+        P: Into<OsString>,
+        A: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        Self {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            public_key,
+        }
+    }
+
+    pub fn sign_blocking(&self, data: &[u8]) -> Result<sign::Signature, Error> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(Error::Spawn)?;
+        {
+            // Dropping `stdin` closes it, signalling the end of the data
+            let mut stdin = child.stdin.take().expect("stdin is piped");
+            stdin.write_all(data)?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(Error::Exit {
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            });
+        }
+
+        let sig = <[u8; 64]>::try_from(output.stdout.as_slice())
+            .map_err(|_| Error::Length(output.stdout.len()))?;
+        if !self
+            .public_key
+            .verify(&Signature::from(sign::Signature(sig)), data)
+        {
+            return Err(Error::Verification);
+        }
+
+        Ok(sign::Signature(sig))
+    }
+}
+
+#[async_trait]
+impl sign::Signer for External {
+    type Error = Error;
+
+    fn public_key(&self) -> sign::PublicKey {
+        self.public_key.into()
+    }
+
+    async fn sign(&self, data: &[u8]) -> Result<sign::Signature, Self::Error> {
+        let this = self.clone();
+        let data = data.to_vec();
+        super::unblock(move || this.sign_blocking(&data)).await
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use crate::keys::SecretKey;
+
+    /// Runs `sh`, which discards its input and outputs the contents of `file`.
+    fn cat(file: &std::path::Path, key: PublicKey) -> External {
+        External::new(
+            "sh",
+            vec![
+                OsString::from("-c"),
+                OsString::from("cat >/dev/null && cat \"$0\""),
+                file.as_os_str().to_owned(),
+            ],
+            key,
+        )
+    }
+
+    #[test]
+    fn sign() {
+        let tmp = tempdir().unwrap();
+        let key = SecretKey::new();
+        let file = tmp.path().join("sig");
+        fs::write(&file, &<[u8; 64]>::from(key.sign(b"cheyenne"))[..]).unwrap();
+
+        let signer = cat(&file, key.public());
+        let sig = signer.sign_blocking(b"cheyenne").unwrap();
+        assert!(key.public().verify(&Signature::from(sig), b"cheyenne"))
+    }
+
+    #[tokio::test]
+    async fn sign_on_runtime() {
+        let tmp = tempdir().unwrap();
+        let key = SecretKey::new();
+        let file = tmp.path().join("sig");
+        fs::write(&file, &<[u8; 64]>::from(key.sign(b"cheyenne"))[..]).unwrap();
+
+        let signer = cat(&file, key.public());
+        let sig = sign::Signer::sign(&signer, b"cheyenne").await.unwrap();
+        assert!(key.public().verify(&Signature::from(sig), b"cheyenne"))
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let tmp = tempdir().unwrap();
+        let key = SecretKey::new();
+        let file = tmp.path().join("sig");
+        fs::write(&file, &<[u8; 64]>::from(key.sign(b"cheyenne"))[..]).unwrap();
+
+        assert_matches!(
+            cat(&file, key.public()).sign_blocking(b"dylan"),
+            Err(Error::Verification)
+        );
+        assert_matches!(
+            cat(&file, SecretKey::new().public()).sign_blocking(b"cheyenne"),
+            Err(Error::Verification)
+        )
+    }
+
+    #[test]
+    fn malformed_output() {
+        let tmp = tempdir().unwrap();
+        let file = tmp.path().join("sig");
+        fs::write(&file, b"not a signature").unwrap();
+
+        assert_matches!(
+            cat(&file, SecretKey::new().public()).sign_blocking(b"cheyenne"),
+            Err(Error::Length(15))
+        )
+    }
+
+    #[test]
+    fn command_fails() {
+        let signer = External::new(
+            "sh",
+            vec!["-c", "cat >/dev/null; echo nope >&2; exit 1"],
+            SecretKey::new().public(),
+        );
+        assert_matches!(
+            signer.sign_blocking(b"cheyenne"),
+            Err(Error::Exit { stderr, .. }) if stderr == "nope"
+        )
+    }
+}
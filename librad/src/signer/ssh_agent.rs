@@ -0,0 +1,347 @@
+// Copyright © 2019-2020 The Radicle Foundation <hello@radicle.foundation>
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! [`Signer`] using an ed25519 key held by an `ssh-agent`.
+//!
+//! Only the subset of the agent protocol needed for signing is implemented,
+//! see [draft-miller-ssh-agent].
+//!
+//! [`Signer`]: crate::signer::Signer
+//! [draft-miller-ssh-agent]: https://tools.ietf.org/html/draft-miller-ssh-agent-04
+
+use std::{
+    convert::TryFrom,
+    env,
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+};
+
+use keystore::sign;
+use thiserror::Error;
+
+use crate::keys::{PublicKey, Signature};
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+const SSH_ED25519: &[u8] = b"ssh-ed25519";
+
+/// Upper bound for the size of a message we accept from the agent.
+const MAX_MESSAGE_LEN: usize = 256 * 1024;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("`SSH_AUTH_SOCK` is not set")]
+    NoSocket,
+
+    #[error("the key {0} is not held by the agent")]
+    KeyNotFound(PublicKey),
+
+    #[error("the agent refused the request")]
+    Failure,
+
+    #[error("unexpected response type {0} from the agent")]
+    UnexpectedResponse(u8),
+
+    #[error("malformed message from the agent: {0}")]
+    Malformed(&'static str),
+
+    #[error("the agent produced an invalid signature")]
+    Verification,
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// A [`sign::Signer`] which asks an `ssh-agent` to sign using the key
+/// corresponding to `public_key`.
+///
+/// A new connection to the agent is made for every request.
+#[derive(Clone)]
+pub struct SshAgent {
+    path: PathBuf,
+    public_key: PublicKey,
+}
+
+impl SshAgent {
+    /// Use the agent listening on `$SSH_AUTH_SOCK`.
+    ///
+    /// See [`Self::new`].
+    pub fn from_env(public_key: PublicKey) -> Result<Self, Error> {
+        let path = env::var_os("SSH_AUTH_SOCK").ok_or(Error::NoSocket)?;
+        Self::new(path, public_key)
+    }
+
+    /// Use the agent listening on the Unix socket at `path`.
+    ///
+    /// It is an error if the agent does not hold `public_key`.
+    pub fn new(path: impl Into<PathBuf>, public_key: PublicKey) -> Result<Self, Error> {
+        let path = path.into();
+        if !list(&path)?.contains(&public_key) {
+            return Err(Error::KeyNotFound(public_key));
+        }
+
+        Ok(Self { path, public_key })
+    }
+
+    /// Ask the agent to sign `data`.
+    ///
+    /// The signature is verified against the public key before it is
+    /// returned.
+    pub fn sign_blocking(&self, data: &[u8]) -> Result<sign::Signature, Error> {
+        let mut req = vec![SSH_AGENTC_SIGN_REQUEST];
+        put_string(&mut req, &key_blob(&self.public_key));
+        put_string(&mut req, data);
+        put_u32(&mut req, 0);
+
+        let resp = request(&self.path, &req)?;
+        let mut resp = expect(&resp, SSH_AGENT_SIGN_RESPONSE)?;
+        let mut blob = get_string(&mut resp)?;
+        if get_string(&mut blob)? != SSH_ED25519 {
+            return Err(Error::Malformed("not an ed25519 signature"));
+        }
+        let sig = <[u8; 64]>::try_from(get_string(&mut blob)?)
+            .map_err(|_| Error::Malformed("invalid signature length"))?;
+        if !self
+            .public_key
+            .verify(&Signature::from(sign::Signature(sig)), data)
+        {
+            return Err(Error::Verification);
+        }
+
+        Ok(sign::Signature(sig))
+    }
+}
+
+#[async_trait]
+impl sign::Signer for SshAgent {
+    type Error = Error;
+
+    fn public_key(&self) -> sign::PublicKey {
+        self.public_key.into()
+    }
+
+    async fn sign(&self, data: &[u8]) -> Result<sign::Signature, Self::Error> {
+        let this = self.clone();
+        let data = data.to_vec();
+        super::unblock(move || this.sign_blocking(&data)).await
+    }
+}
+
+/// List the ed25519 keys held by the agent listening on `path`.
+///
+/// Keys of other types are skipped.
+pub fn list(path: &Path) -> Result<Vec<PublicKey>, Error> {
+    let resp = request(path, &[SSH_AGENTC_REQUEST_IDENTITIES])?;
+    let mut resp = expect(&resp, SSH_AGENT_IDENTITIES_ANSWER)?;
+
+    let n = get_u32(&mut resp)?;
+    let mut keys = Vec::new();
+    for _ in 0..n {
+        let mut blob = get_string(&mut resp)?;
+        let _comment = get_string(&mut resp)?;
+        if get_string(&mut blob)? == SSH_ED25519 {
+            let key = PublicKey::from_slice(get_string(&mut blob)?)
+                .ok_or(Error::Malformed("invalid ed25519 key"))?;
+            keys.push(key);
+        }
+    }
+
+    Ok(keys)
+}
+
+fn request(path: &Path, msg: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut sock = UnixStream::connect(path)?;
+
+    let mut framed = Vec::with_capacity(msg.len() + 4);
+    put_string(&mut framed, msg);
+    sock.write_all(&framed)?;
+
+    let mut len = [0; 4];
+    sock.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(Error::Malformed("message too large"));
+    }
+    let mut resp = vec![0; len];
+    sock.read_exact(&mut resp)?;
+
+    Ok(resp)
+}
+
+fn expect(resp: &[u8], typ: u8) -> Result<&[u8], Error> {
+    match resp.split_first() {
+        Some((t, rest)) if *t == typ => Ok(rest),
+        Some((&SSH_AGENT_FAILURE, _)) => Err(Error::Failure),
+        Some((t, _)) => Err(Error::UnexpectedResponse(*t)),
+        None => Err(Error::Malformed("empty message")),
+    }
+}
+
+fn key_blob(key: &PublicKey) -> Vec<u8> {
+    let mut blob = Vec::new();
+    put_string(&mut blob, SSH_ED25519);
+    put_string(&mut blob, key.as_ref());
+    blob
+}
+
+fn put_u32(buf: &mut Vec<u8>, n: u32) {
+    buf.extend_from_slice(&n.to_be_bytes())
+}
+
+fn put_string(buf: &mut Vec<u8>, s: &[u8]) {
+    put_u32(buf, s.len() as u32);
+    buf.extend_from_slice(s)
+}
+
+fn get_u32(buf: &mut &[u8]) -> Result<u32, Error> {
+    if buf.len() < 4 {
+        return Err(Error::Malformed("truncated message"));
+    }
+    let (n, rest) = buf.split_at(4);
+    *buf = rest;
+    Ok(u32::from_be_bytes(<[u8; 4]>::try_from(n).unwrap()))
+}
+
+fn get_string<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let len = get_u32(buf)? as usize;
+    if buf.len() < len {
+        return Err(Error::Malformed("truncated message"));
+    }
+    let (s, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{os::unix::net::UnixListener, thread};
+
+    use futures::executor::block_on;
+    use tempfile::tempdir;
+
+    use crate::keys::SecretKey;
+
+    /// Serve `n` requests of the agent protocol on `path`, signing with `key`.
+    fn stand_in_agent(path: &Path, key: SecretKey, n: usize) -> thread::JoinHandle<()> {
+        stand_in_agent_signing_with(path, key.clone(), key, n)
+    }
+
+    /// Like [`stand_in_agent`], but sign with `signing_key` on behalf of
+    /// `key`.
+    fn stand_in_agent_signing_with(
+        path: &Path,
+        key: SecretKey,
+        signing_key: SecretKey,
+        n: usize,
+    ) -> thread::JoinHandle<()> {
+        let listener = UnixListener::bind(path).unwrap();
+        thread::spawn(move || {
+            for sock in listener.incoming().take(n) {
+                let mut sock = sock.unwrap();
+                let mut len = [0; 4];
+                sock.read_exact(&mut len).unwrap();
+                let mut req = vec![0; u32::from_be_bytes(len) as usize];
+                sock.read_exact(&mut req).unwrap();
+
+                let mut resp = Vec::new();
+                match req.split_first().unwrap() {
+                    (&SSH_AGENTC_REQUEST_IDENTITIES, _) => {
+                        resp.push(SSH_AGENT_IDENTITIES_ANSWER);
+                        put_u32(&mut resp, 1);
+                        put_string(&mut resp, &key_blob(&key.public()));
+                        put_string(&mut resp, b"stand-in");
+                    },
+                    (&SSH_AGENTC_SIGN_REQUEST, mut rest) => {
+                        let blob = get_string(&mut rest).unwrap();
+                        if blob == key_blob(&key.public()).as_slice() {
+                            let data = get_string(&mut rest).unwrap();
+                            let sig: [u8; 64] = signing_key.sign(data).into();
+                            let mut sig_blob = Vec::new();
+                            put_string(&mut sig_blob, SSH_ED25519);
+                            put_string(&mut sig_blob, &sig);
+                            resp.push(SSH_AGENT_SIGN_RESPONSE);
+                            put_string(&mut resp, &sig_blob);
+                        } else {
+                            resp.push(SSH_AGENT_FAILURE);
+                        }
+                    },
+                    _ => resp.push(SSH_AGENT_FAILURE),
+                }
+
+                let mut framed = Vec::new();
+                put_string(&mut framed, &resp);
+                sock.write_all(&framed).unwrap();
+            }
+        })
+    }
+
+    #[test]
+    fn sign() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("agent.sock");
+        let key = SecretKey::new();
+        let agent = stand_in_agent(&path, key.clone(), 2);
+
+        let signer = SshAgent::new(&path, key.public()).unwrap();
+        assert_eq!(
+            PublicKey::from(sign::Signer::public_key(&signer)),
+            key.public()
+        );
+        let sig = block_on(sign::Signer::sign(&signer, b"cheyenne")).unwrap();
+        assert!(key.public().verify(&Signature::from(sig), b"cheyenne"));
+
+        agent.join().unwrap()
+    }
+
+    #[tokio::test]
+    async fn sign_on_runtime() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("agent.sock");
+        let key = SecretKey::new();
+        let agent = stand_in_agent(&path, key.clone(), 2);
+
+        let signer = SshAgent::new(&path, key.public()).unwrap();
+        let sig = sign::Signer::sign(&signer, b"cheyenne").await.unwrap();
+        assert!(key.public().verify(&Signature::from(sig), b"cheyenne"));
+
+        agent.join().unwrap()
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("agent.sock");
+        let key = SecretKey::new();
+        let agent = stand_in_agent_signing_with(&path, key.clone(), SecretKey::new(), 2);
+
+        let signer = SshAgent::new(&path, key.public()).unwrap();
+        assert_matches!(signer.sign_blocking(b"cheyenne"), Err(Error::Verification));
+
+        agent.join().unwrap()
+    }
+
+    #[test]
+    fn key_not_found() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("agent.sock");
+        let agent = stand_in_agent(&path, SecretKey::new(), 1);
+
+        let other = SecretKey::new().public();
+        assert_matches!(
+            SshAgent::new(&path, other),
+            Err(Error::KeyNotFound(key)) if key == other
+        );
+
+        agent.join().unwrap()
+    }
+}
@@ -50,8 +50,11 @@ async fn can_add_maintainer() {
                         None,
                         None,
                         librad::identities::delegation::Indirect::try_from_iter(
-                            vec![either::Either::Left(key), either::Either::Right(owner)]
-                                .into_iter(),
+                            vec![
+                                either::Either::Left(key),
+                                either::Either::Right(librad::identities::Delegate::from(owner)),
+                            ]
+                            .into_iter(),
                         )
                         .unwrap(),
                     )?;
@@ -3,15 +3,25 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+use either::Either;
 use librad::{
     self,
     git::{
         identities,
+        refs::Refs,
         replication,
-        types::{Namespace, Reference},
+        tracking,
+        types::{Namespace, Reference, RefsCategory},
     },
+    git_ext::RefLike,
+    identities::{
+        git::{Delegate, IndirectDelegation},
+        payload,
+    },
+    reflike,
 };
 use librad_test::{
+    git::create_commit,
     logging,
     rad::{
         identities::TestProject,
@@ -58,6 +68,222 @@ async fn when_disconnected_and_no_addr_hints() {
     .await;
 }
 
+#[tokio::test]
+async fn from_many_providers() {
+    logging::init();
+
+    const NUM_PEERS: usize = 3;
+
+    let peers = testnet::setup(NUM_PEERS).await.unwrap();
+    testnet::run_on_testnet(peers, NUM_PEERS, |mut peers| async move {
+        let host = Host::init(peers.pop().unwrap()).await;
+        let mirror = peers.pop().unwrap();
+        let leecher = peers.pop().unwrap();
+
+        let urn = host.project.project.urn();
+        let host_id = host.peer.peer_id();
+        let next = Reference::head(Namespace::from(&urn), None, reflike!("next"));
+
+        // The host publishes a branch, which the mirror does not replicate
+        host.peer
+            .using_storage({
+                let urn = urn.clone();
+                let next = next.clone();
+                move |storage| {
+                    let repo = git2::Repository::open(storage.path()).unwrap();
+                    create_commit(&repo, RefLike::from(next)).unwrap();
+                    Refs::update(&storage, &urn).unwrap();
+                }
+            })
+            .await
+            .unwrap();
+        mirror
+            .using_storage({
+                let urn = urn.clone();
+                move |storage| {
+                    let policy = tracking::Policy {
+                        categories: Some(RefsCategory::Rad).into_iter().collect(),
+                        ..tracking::Policy::default()
+                    };
+                    tracking::track_with(&storage, &urn, host_id, &policy).unwrap();
+                }
+            })
+            .await
+            .unwrap();
+        host.project.pull(&host.peer, &mirror).await.ok().unwrap();
+
+        let cfg = leecher.protocol_config().replication.clone();
+        let providers = vec![
+            (mirror.peer_id(), mirror.listen_addrs().to_vec()),
+            (host_id, host.peer.listen_addrs().to_vec()),
+        ];
+        let mirror_id = mirror.peer_id();
+        leecher
+            .using_storage(move |storage| {
+                let res = replication::replicate_many(&storage, cfg, None, urn.clone(), providers)
+                    .unwrap();
+
+                assert_eq!(mirror_id, res.primary);
+                assert!(res.failed.is_empty(), "failed: {:?}", res.failed);
+                // Only the host's branch was missing, which is fetched from the
+                // host itself
+                assert_eq!(
+                    res.fetched,
+                    Some((host_id, Some(host_id).into_iter().collect()))
+                        .into_iter()
+                        .collect()
+                );
+                assert!(
+                    res.unverified.is_empty(),
+                    "unverified: {:?}",
+                    res.unverified
+                );
+                assert!(
+                    storage
+                        .has_ref(&Reference::rad_self(Namespace::from(&urn), host_id))
+                        .unwrap(),
+                    "`refs/remotes/<host>/rad/self` should exist"
+                );
+                assert!(
+                    storage.has_ref(&next.with_remote(host_id)).unwrap(),
+                    "`refs/remotes/<host>/heads/next` should exist"
+                )
+            })
+            .await
+            .unwrap();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn dry_run() {
+    logging::init();
+
+    let peers = testnet::setup(NUM_PEERS).await.unwrap();
+    testnet::run_on_testnet(peers, NUM_PEERS, |mut peers| async move {
+        let host = Host::init(peers.pop().unwrap()).await;
+        let leecher = peers.pop().unwrap();
+
+        let cfg = leecher.protocol_config().replication.clone();
+        let urn = host.project.project.urn();
+        let host_id = host.peer.peer_id();
+        let addrs = host.peer.listen_addrs().to_vec();
+        leecher
+            .using_storage(move |storage| {
+                let plan = replication::plan(&storage, &cfg, urn.clone(), host_id, addrs).unwrap();
+
+                assert!(plan.peers.contains(&host_id));
+                assert!(plan.updates.contains_key(&RefLike::from(
+                    Reference::rad_id(Namespace::from(&urn)).with_remote(host_id)
+                )));
+                assert!(
+                    !storage.has_urn(&urn).unwrap(),
+                    "dry run should not replicate the project"
+                );
+            })
+            .await
+            .unwrap();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn project_delegating_to_org() {
+    logging::init();
+
+    let peers = testnet::setup(NUM_PEERS).await.unwrap();
+    testnet::run_on_testnet(peers, NUM_PEERS, |mut peers| async move {
+        let host = peers.pop().unwrap();
+        let leecher = peers.pop().unwrap();
+
+        let (owner, org, project) = host
+            .using_storage(move |storage| -> anyhow::Result<_> {
+                let owner = identities::person::create(
+                    &storage,
+                    payload::Person {
+                        name: "alice".into(),
+                    },
+                    Some(*storage.peer_id().as_public_key())
+                        .into_iter()
+                        .collect(),
+                )?;
+                let local_id = identities::local::load(&storage, owner.urn())?
+                    .expect("local id must exist as we just created it");
+                let org = identities::org::create(
+                    &storage,
+                    local_id.clone(),
+                    payload::Org {
+                        name: "radicle".into(),
+                    },
+                    IndirectDelegation::try_from_iter(Some(Either::Right(Delegate::from(
+                        owner.clone(),
+                    ))))?,
+                )?;
+                let project = identities::project::create(
+                    &storage,
+                    local_id,
+                    payload::Project {
+                        name: "radicle-link".into(),
+                        description: None,
+                        default_branch: None,
+                    },
+                    IndirectDelegation::try_from_iter(Some(Either::Right(Delegate::from(
+                        org.clone(),
+                    ))))?,
+                )?;
+
+                Ok((owner, org, project))
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let cfg = leecher.protocol_config().replication.clone();
+        let host_id = host.peer_id();
+        let addrs = host.listen_addrs().to_vec();
+        leecher
+            .using_storage(move |storage| {
+                let urn = project.urn();
+                replication::replicate(
+                    &storage,
+                    cfg.clone(),
+                    None,
+                    urn.clone(),
+                    host_id,
+                    addrs.clone(),
+                )
+                .unwrap();
+
+                assert!(
+                    identities::project::verify(&storage, &urn)
+                        .unwrap()
+                        .is_some(),
+                    "the project should verify against the members of the org"
+                );
+                assert!(tracking::is_tracked(&storage, &urn, host_id).unwrap());
+                assert_eq!(
+                    Some(org.clone()),
+                    identities::org::get(&storage, &org.urn()).unwrap(),
+                    "the org should be a first class citizen"
+                );
+                assert_eq!(
+                    Some(owner.clone()),
+                    identities::person::get(&storage, &owner.urn()).unwrap(),
+                    "alice should be a first class citizen"
+                );
+
+                // The org itself can now be fetched from the host
+                replication::replicate(&storage, cfg, None, org.urn(), host_id, addrs).unwrap();
+                assert!(identities::org::verify(&storage, &org.urn())
+                    .unwrap()
+                    .is_some());
+            })
+            .await
+            .unwrap();
+    })
+    .await;
+}
+
 struct Host {
     project: TestProject,
     peer: RunningTestPeer,
@@ -79,11 +305,11 @@ struct Leecher(RunningTestPeer);
 
 impl Leecher {
     async fn clone_from(&self, host: Host, supply_addr_hints: bool) {
-        let cfg = self.0.protocol_config().replication;
+        let cfg = self.0.protocol_config().replication.clone();
         self.0
             .using_storage(move |storage| {
                 let urn = host.project.project.urn();
-                replication::replicate(
+                let res = replication::replicate(
                     &storage,
                     cfg,
                     None,
@@ -96,6 +322,15 @@ impl Leecher {
                 )
                 .unwrap();
 
+                // everything we got is new
+                assert!(res
+                    .updated
+                    .values()
+                    .all(|updated| matches!(updated, replication::Updated::New { .. })));
+                assert!(res.updated.contains_key(&RefLike::from(
+                    Reference::rad_id(Namespace::from(&urn)).with_remote(host.peer.peer_id())
+                )));
+
                 // check rad/self of peer1 exists
                 assert!(
                     storage
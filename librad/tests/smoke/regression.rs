@@ -36,8 +36,8 @@ async fn list_identities_returns_only_local_projects() {
         proj.pull(&peer2, &peer3).await.ok().unwrap();
 
         let all_identities = peer3
-            .using_storage(move |storage| -> Result<Vec<SomeIdentity>, anyhow::Error> {
-                Ok(identities::any::list(&storage)?.collect::<Result<Vec<_>, _>>()?)
+            .using_read_only(move |storage| -> Result<Vec<SomeIdentity>, anyhow::Error> {
+                Ok(identities::any::list(storage)?.collect::<Result<Vec<_>, _>>()?)
             })
             .await
             .unwrap()
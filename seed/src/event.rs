@@ -31,9 +31,9 @@ impl Event {
         api: &Peer<Signer>,
     ) -> Result<Self, Error> {
         let proj = api
-            .using_storage({
+            .using_read_only({
                 let urn = Urn::new(urn.id);
-                move |s| identities::project::get(&s, &urn)
+                move |s| identities::project::view(s, &urn)
             })
             .await??;
 
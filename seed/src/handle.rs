@@ -6,7 +6,7 @@
 use futures::{channel::mpsc as chan, sink::SinkExt as _, stream::StreamExt as _};
 use thiserror::Error;
 
-use librad::peer::PeerId;
+use librad::{git::identities::query::Page, peer::PeerId};
 
 use crate::Project;
 
@@ -28,11 +28,13 @@ impl NodeHandle {
         Self { channel }
     }
 
-    /// Get all local projects.
-    pub async fn get_projects(&mut self) -> Result<Vec<Project>, NodeError> {
+    /// Get the local projects on `page`.
+    ///
+    /// See [`crate::project::get_projects`].
+    pub async fn get_projects(&mut self, page: Page) -> Result<Vec<Project>, NodeError> {
         let (tx, mut rx) = chan::channel(1);
         self.channel
-            .send(Request::GetProjects(tx))
+            .send(Request::GetProjects(page, tx))
             .await
             .map_err(|_| NodeError::RequestFailed)?;
 
@@ -53,8 +55,8 @@ impl NodeHandle {
 
 /// User request to the seed node.
 pub enum Request {
-    /// Get local projects.
-    GetProjects(chan::Sender<Vec<Project>>),
+    /// Get the local projects on the given page.
+    GetProjects(Page, chan::Sender<Vec<Project>>),
     /// Get connected peers.
     GetPeers(chan::Sender<Vec<PeerId>>),
 }
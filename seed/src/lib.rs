@@ -172,6 +172,8 @@ impl Node {
     /// occurs.
     pub async fn run(self, mut transmit: chan::Sender<Event>) -> Result<(), Error> {
         let peer = Peer::new(self.peer_config);
+        // Read-only handles can only be opened once the storage was initialised.
+        peer.using_storage(|_| ()).await?;
         let mut events = peer.subscribe().boxed().fuse();
         let mut requests = self.requests;
         let mode = &self.config.mode;
@@ -236,8 +238,8 @@ impl Node {
                 let peers = api.connected_peers().await;
                 reply.send(peers).await?;
             },
-            Request::GetProjects(mut reply) => {
-                let projs = project::get_projects(api).await?;
+            Request::GetProjects(page, mut reply) => {
+                let projs = project::get_projects(api, page).await?;
                 reply.send(projs).await?;
             },
         }
@@ -304,7 +306,7 @@ impl Node {
         let addr_hints = peer_info.seen_addrs.iter().copied().collect::<Vec<_>>();
 
         let result = {
-            let cfg = api.protocol_config().replication;
+            let cfg = api.protocol_config().replication.clone();
             let urn = urn.clone();
             api.using_storage(move |storage| {
                 replication::replicate(&storage, cfg, None, urn.clone(), peer_id, addr_hints)?;
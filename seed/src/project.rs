@@ -7,7 +7,7 @@ use std::collections::HashSet;
 
 use librad::{
     git::{
-        identities::{self, SomeIdentity},
+        identities::{self, query},
         Urn,
     },
     net::peer::Peer,
@@ -23,39 +23,49 @@ pub struct Project {
     pub maintainers: HashSet<Urn>,
 }
 
-impl From<identities::Project> for Project {
-    fn from(proj: identities::Project) -> Self {
+impl From<identities::project::View> for Project {
+    fn from(view: identities::project::View) -> Self {
+        let proj = view.project;
         Self {
             urn: proj.urn(),
             name: proj.subject().name.to_string(),
             description: proj
-                .doc
-                .payload
-                .subject
+                .subject()
                 .description
+                .as_ref()
                 .map(|desc| desc.to_string()),
-            maintainers: proj
-                .doc
-                .delegations
-                .into_iter()
-                .indirect()
-                .map(|id| id.urn())
+            maintainers: view
+                .maintainers
+                .iter()
+                .map(|maintainer| maintainer.urn())
                 .collect(),
         }
     }
 }
 
-/// Get all local projects.
-pub async fn get_projects(api: &Peer<Signer>) -> Result<Vec<Project>, Error> {
-    api.using_storage(|s| {
-        identities::any::list(&s)?
-            .filter_map(|res| {
-                res.map(|id| match id {
-                    SomeIdentity::Project(proj) => Some(Project::from(proj)),
-                    _ => None,
-                })
-                .map_err(Error::from)
-                .transpose()
+/// Get the local projects on `page`.
+///
+/// To obtain the next page, pass the [`Urn`] of the last project returned as
+/// [`query::Page::after`]. The on-disk [`query::Index`] is used if the storage
+/// has one.
+pub async fn get_projects(api: &Peer<Signer>, page: query::Page) -> Result<Vec<Project>, Error> {
+    api.using_read_only(move |s| {
+        let filter = query::Filter {
+            kind: Some(query::Kind::Project),
+            ..query::Filter::default()
+        };
+        let listing = match query::Index::open(s)? {
+            Some(index) => index.list(&filter, &page),
+            None => query::list(s, &filter, &page)?,
+        };
+        listing
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                identities::project::view(s, &entry.urn)
+                    .map(|view| view.map(Project::from))
+                    .map_err(Error::from)
+                    .transpose()
             })
             .collect::<Result<Vec<_>, _>>()
     })